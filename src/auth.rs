@@ -0,0 +1,175 @@
+// API keys for the HTTP interface, kept in `--api-keys <file>`. Each key has a scope, and a
+// key is good for the calls of its scope and every scope below it:
+//
+// - `read`, calls querying the chain
+// - `wallet`, calls submitting transactions
+// - `admin`, calls managing the API keys and approving deep reorgs
+//
+// Clients send their key as `Authorization: Bearer <key>`. As long as there are no keys every
+// call but the admin ones is open to anyone. Once there is one, calls without a good key are
+// turned down. Keys are added to the file by hand or with the admin calls of `rpc`.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    hash::Hash256,
+    schema::{self, Migration},
+};
+
+// Migrations of the API keys file, see `schema`.
+const MIGRATIONS: &[Migration] = &[];
+
+// `Scope` What an API key may be used for, each scope allowing the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    Read,
+    Wallet,
+    Admin,
+}
+
+impl Scope {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "read" => Some(Scope::Read),
+            "wallet" => Some(Scope::Wallet),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Scope::Read => "read",
+            Scope::Wallet => "wallet",
+            Scope::Admin => "admin",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// `ApiKey` A key along with its scope and a name telling who it was handed to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub key: String,
+    pub name: String,
+    pub scope: Scope,
+}
+
+// `ApiKeys` The API keys of the node, persisted to `path` if set.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys {
+    path: Option<String>,
+    keys: Vec<ApiKey>,
+}
+
+impl ApiKeys {
+    pub fn load(path: &str) -> Self {
+        let keys = match schema::load_json(path, MIGRATIONS) {
+            Ok(Some(data)) => serde_json::from_value(data).unwrap_or_else(|err| {
+                println!("can't parse api keys in {}: {}", path, err);
+                Vec::new()
+            }),
+            Ok(None) => Vec::new(),
+            Err(err) => {
+                println!("can't load api keys from {}: {}", path, err);
+                Vec::new()
+            }
+        };
+
+        ApiKeys {
+            path: Some(path.to_string()),
+            keys,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ApiKey> {
+        self.keys.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    // Check that `key` is good for calls of `scope`.
+    pub fn authorize(&self, key: Option<&str>, scope: Scope) -> Result<(), String> {
+        if self.keys.is_empty() {
+            return match scope {
+                Scope::Admin => Err("admin calls need an api key, there are none".to_string()),
+                _ => Ok(()),
+            };
+        }
+
+        let Some(key) = key else {
+            return Err("calls need an api key".to_string());
+        };
+        match self
+            .keys
+            .iter()
+            .find(|api_key| keys_match(&api_key.key, key))
+        {
+            Some(api_key) if api_key.scope >= scope => Ok(()),
+            Some(api_key) => Err(format!(
+                "api key {} is limited to {} calls",
+                api_key.name, api_key.scope
+            )),
+            None => Err("unknown api key".to_string()),
+        }
+    }
+
+    // Create a key named `name` for calls of `scope`.
+    pub fn create(&mut self, name: &str, scope: Scope) -> Result<ApiKey, String> {
+        if self.path.is_none() {
+            return Err("api keys can't be saved, start the node with --api-keys <file>".into());
+        }
+        if self.keys.iter().any(|api_key| api_key.name == name) {
+            return Err(format!("there already is an api key named {}", name));
+        }
+
+        let api_key = ApiKey {
+            key: Hash256(rand::random()).to_string(),
+            name: name.to_string(),
+            scope,
+        };
+        self.keys.push(api_key.clone());
+        self.save();
+        Ok(api_key)
+    }
+
+    // Revoke the key named `name`.
+    pub fn revoke(&mut self, name: &str) -> Result<(), String> {
+        let count = self.keys.len();
+        self.keys.retain(|api_key| api_key.name != name);
+        if self.keys.len() == count {
+            return Err(format!("there is no api key named {}", name));
+        }
+
+        self.save();
+        Ok(())
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let version = MIGRATIONS.len() as u32;
+        if let Err(err) = schema::save_json(path, version, &self.keys) {
+            println!("can't save api keys to {}: {}", path, err);
+        }
+    }
+}
+
+// Compare `key` with `other` in time independent of where they differ, so a client can't guess
+// a key byte by byte from how long it takes to be turned down.
+fn keys_match(key: &str, other: &str) -> bool {
+    key.len() == other.len()
+        && key
+            .bytes()
+            .zip(other.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
@@ -0,0 +1,165 @@
+// Comparison of two exported chains, e.g. of nodes that split. Finds the first block the chains
+// disagree on and prints the headers and transactions around it, lines only in the first chain
+// marked `-` and lines only in the second marked `+`. Exits with 1 if the chains differ.
+//
+// Usage: diff-chain <left.json> <right.json> [--context N]
+
+use std::{collections::HashSet, fmt::Display, fs, process, sync::Arc};
+
+use blockchain::models::{block::Block, hash::Hash256, transaction::Transaction};
+
+// Blocks shown before and after the fork point unless `--context` says otherwise.
+const DEFAULT_CONTEXT: usize = 2;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut paths = Vec::new();
+    let mut context = DEFAULT_CONTEXT;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--context" => {
+                context = args
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            file if paths.len() < 2 && !file.starts_with("--") => paths.push(file.to_string()),
+            _ => usage(),
+        }
+    }
+
+    let [left_path, right_path] = paths.as_slice() else {
+        usage();
+    };
+    let left = load(left_path);
+    let right = load(right_path);
+
+    let fork = left
+        .iter()
+        .zip(right.iter())
+        .position(|(left, right)| left.header.hash != right.header.hash)
+        .unwrap_or(left.len().min(right.len()));
+
+    if fork == left.len() && fork == right.len() {
+        println!("chains are identical up to #{}", fork.saturating_sub(1));
+        return;
+    }
+
+    match fork.checked_sub(1).and_then(|index| left.get(index)) {
+        Some(block) if block.header.hash.is_zero() => {
+            println!("chains agree up to the genesis block")
+        }
+        Some(block) => println!(
+            "chains agree up to #{} {}",
+            block.header.index, block.header.hash
+        ),
+        None => println!("chains share no blocks"),
+    }
+    println!("--- {} ({} blocks)", left_path, left.len());
+    println!("+++ {} ({} blocks)", right_path, right.len());
+
+    let end = (fork + context + 1).min(left.len().max(right.len()));
+    for position in fork.saturating_sub(context)..end {
+        println!();
+        print_block(position, left.get(position), right.get(position));
+    }
+
+    process::exit(1);
+}
+
+fn usage() -> ! {
+    println!("usage: diff-chain <left.json> <right.json> [--context N]");
+    process::exit(2);
+}
+
+fn load(path: &str) -> Vec<Arc<Block>> {
+    let data = fs::read_to_string(path).unwrap_or_else(|err| {
+        println!("can't read {}: {}", path, err);
+        process::exit(2);
+    });
+
+    serde_json::from_str(&data).unwrap_or_else(|err| {
+        println!("can't parse {}: {}", path, err);
+        process::exit(2);
+    })
+}
+
+// Print the block at `position` of both chains, either of which may have ended.
+fn print_block(position: usize, left: Option<&Arc<Block>>, right: Option<&Arc<Block>>) {
+    println!("@@ block {} @@", position);
+
+    print_field(
+        "index",
+        left.map(|block| block.header.index),
+        right.map(|block| block.header.index),
+    );
+    print_field(
+        "timestamp",
+        left.map(|block| block.header.timestamp),
+        right.map(|block| block.header.timestamp),
+    );
+    print_field(
+        "previous_hash",
+        left.map(|block| block.header.previous_hash),
+        right.map(|block| block.header.previous_hash),
+    );
+    print_field(
+        "proof_of_work",
+        left.map(|block| block.header.proof_of_work),
+        right.map(|block| block.header.proof_of_work),
+    );
+    print_field(
+        "hash",
+        left.map(|block| block.header.hash),
+        right.map(|block| block.header.hash),
+    );
+
+    let left = transactions(left);
+    let right = transactions(right);
+    let left_ids: HashSet<Hash256> = left.iter().map(|(id, _)| *id).collect();
+    let right_ids: HashSet<Hash256> = right.iter().map(|(id, _)| *id).collect();
+
+    println!(" transactions:");
+    for (id, transaction) in left.iter() {
+        let marker = if right_ids.contains(id) { ' ' } else { '-' };
+        print_transaction(marker, id, transaction);
+    }
+    for (id, transaction) in right.iter().filter(|(id, _)| !left_ids.contains(id)) {
+        print_transaction('+', id, transaction);
+    }
+}
+
+fn print_field<T: PartialEq + Display>(name: &str, left: Option<T>, right: Option<T>) {
+    match (left, right) {
+        (Some(left), Some(right)) if left == right => println!("  {}: {}", name, left),
+        (left, right) => {
+            if let Some(left) = left {
+                println!("- {}: {}", name, left);
+            }
+            if let Some(right) = right {
+                println!("+ {}: {}", name, right);
+            }
+        }
+    }
+}
+
+fn transactions(block: Option<&Arc<Block>>) -> Vec<(Hash256, &Transaction)> {
+    block
+        .map(|block| {
+            block
+                .body
+                .transactions
+                .iter()
+                .map(|transaction| (transaction.id(), transaction))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn print_transaction(marker: char, id: &Hash256, transaction: &Transaction) {
+    println!(
+        "{}   {} {} -> {} {}",
+        marker, id, transaction.sender, transaction.receiver, transaction.amount
+    );
+}
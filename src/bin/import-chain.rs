@@ -0,0 +1,230 @@
+// Migration of demo data from other educational blockchains. Replays the transfers of a foreign
+// chain export and writes a chain config whose genesis block allocates the resulting balances.
+// How the foreign format is laid out is described by a JSON mapping, e.g.
+//
+//     {
+//         "blocks": "chain",
+//         "transactions": "data",
+//         "sender": "from",
+//         "receiver": "to",
+//         "amount": "amount",
+//         "mint_senders": ["0", "MINING_REWARD"],
+//         "scale": 100000000
+//     }
+//
+// Fields are found by dot separated paths, an empty `blocks` path means the export is the list
+// of blocks. Transfers from a mint sender create coins, foreign amounts are multiplied by
+// `scale` to get units.
+//
+// Usage: import-chain <export.json> --mapping <path> [--chain-config <path>] [--out <path>]
+
+use std::{collections::BTreeMap, fs, process};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use blockchain::models::{
+    address::Address,
+    amount::Amount,
+    params::{Allocation, ChainParams},
+};
+
+// `Mapping` Where the fields of the foreign format are.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct Mapping {
+    blocks: String,
+    transactions: String,
+    sender: String,
+    receiver: String,
+    amount: String,
+    // Senders whose transfers create coins rather than move them.
+    mint_senders: Vec<String>,
+    // Units of the native coin per unit of the foreign one.
+    scale: f64,
+}
+
+impl Default for Mapping {
+    fn default() -> Self {
+        Mapping {
+            blocks: String::new(),
+            transactions: "transactions".to_string(),
+            sender: "sender".to_string(),
+            receiver: "receiver".to_string(),
+            amount: "amount".to_string(),
+            mint_senders: Vec::new(),
+            scale: 1.0,
+        }
+    }
+}
+
+// `Import` Balances replayed from a foreign chain.
+#[derive(Debug, Default)]
+struct Import {
+    balances: BTreeMap<Address, Amount>,
+    transfers: usize,
+    // Transfers left out since a field is missing or can't be mapped.
+    skipped: usize,
+    // Transfers spending more than the sender had, replayed as spending what it had.
+    overdrafts: usize,
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    let mut mapping_path = None;
+    let mut params = ChainParams::default();
+    let mut out = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--mapping" => mapping_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--chain-config" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                params = ChainParams::load(&path).unwrap_or_else(|err| fail(err));
+            }
+            "--out" => out = Some(args.next().unwrap_or_else(|| usage())),
+            file if path.is_none() && !file.starts_with("--") => path = Some(file.to_string()),
+            _ => usage(),
+        }
+    }
+
+    let path = path.unwrap_or_else(|| usage());
+    let mapping_path = mapping_path.unwrap_or_else(|| usage());
+    let mapping: Mapping = serde_json::from_value(read_json(&mapping_path))
+        .unwrap_or_else(|err| fail(format!("can't parse {}: {}", mapping_path, err)));
+    let export = read_json(&path);
+
+    let import = replay(&export, &mapping).unwrap_or_else(|err| fail(err));
+    params.genesis_allocations = import
+        .balances
+        .iter()
+        .filter(|(_, amount)| !amount.is_zero())
+        .map(|(address, amount)| Allocation {
+            address: address.clone(),
+            amount: *amount,
+        })
+        .collect();
+    let total = params
+        .genesis_allocations
+        .iter()
+        .fold(Amount::ZERO, |total, allocation| {
+            total.saturating_add(allocation.amount)
+        });
+
+    println!("transfers: {}", import.transfers);
+    println!("skipped: {}", import.skipped);
+    println!("overdrafts: {}", import.overdrafts);
+    println!(
+        "allocations: {} totalling {}",
+        params.genesis_allocations.len(),
+        total
+    );
+
+    let json = serde_json::to_string_pretty(&params).expect("can jsonify chain config");
+    match out {
+        Some(out) => {
+            fs::write(&out, json)
+                .unwrap_or_else(|err| fail(format!("can't write {}: {}", out, err)));
+            println!("chain config written to {}", out);
+        }
+        None => println!("{}", json),
+    }
+}
+
+fn usage() -> ! {
+    println!(
+        "usage: import-chain <export.json> --mapping <path> [--chain-config <path>] [--out <path>]"
+    );
+    process::exit(2);
+}
+
+fn fail(err: String) -> ! {
+    println!("{}", err);
+    process::exit(2);
+}
+
+fn read_json(path: &str) -> Value {
+    let data = fs::read_to_string(path)
+        .unwrap_or_else(|err| fail(format!("can't read {}: {}", path, err)));
+
+    serde_json::from_str(&data).unwrap_or_else(|err| fail(format!("can't parse {}: {}", path, err)))
+}
+
+// Replay every transfer of `export` in order.
+fn replay(export: &Value, mapping: &Mapping) -> Result<Import, String> {
+    let blocks = field(export, &mapping.blocks)
+        .and_then(Value::as_array)
+        .ok_or_else(|| format!("export has no list of blocks at {:?}", mapping.blocks))?;
+
+    let mut import = Import::default();
+    for block in blocks {
+        let Some(transactions) = field(block, &mapping.transactions).and_then(Value::as_array)
+        else {
+            continue;
+        };
+
+        for transaction in transactions {
+            import.transfers += 1;
+
+            let Some((sender, receiver, amount)) = transfer(transaction, mapping) else {
+                import.skipped += 1;
+                continue;
+            };
+
+            if !mapping.mint_senders.contains(&sender) {
+                let Ok(sender) = Address::new(sender) else {
+                    import.skipped += 1;
+                    continue;
+                };
+                let balance = import.balances.entry(sender).or_default();
+                if *balance < amount {
+                    import.overdrafts += 1;
+                }
+                *balance = balance.saturating_sub(amount);
+            }
+
+            let balance = import.balances.entry(receiver).or_default();
+            *balance = balance.saturating_add(amount);
+        }
+    }
+
+    Ok(import)
+}
+
+// The sender, receiver and amount of a foreign transaction.
+fn transfer(transaction: &Value, mapping: &Mapping) -> Option<(String, Address, Amount)> {
+    let sender = text(field(transaction, &mapping.sender)?)?;
+    let receiver = Address::new(text(field(transaction, &mapping.receiver)?)?).ok()?;
+
+    let amount = match field(transaction, &mapping.amount)? {
+        Value::Number(number) => number.as_f64()?,
+        Value::String(number) => number.parse().ok()?,
+        _ => return None,
+    };
+    let units = (amount * mapping.scale).round();
+    if !(0.0..=u64::MAX as f64).contains(&units) {
+        return None;
+    }
+
+    Some((sender, receiver, Amount(units as u64)))
+}
+
+// The value at a dot separated `path`, array elements are indexed by number.
+fn field<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|key| !key.is_empty())
+        .try_fold(value, |value, key| match value {
+            Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+            _ => value.get(key),
+        })
+}
+
+// Addresses may be strings or, in some formats, numbers.
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,155 @@
+// Offline verification of an exported chain, without any networking.
+//
+// Usage: verify-chain <chain.json> [--chain-config <path>] [--difficulty N]
+//                     [--pow sha256|memory-hard] [--genesis <hash>]
+
+use std::{collections::HashSet, fs, process, sync::Arc};
+
+use blockchain::models::{
+    amount::Amount, block::Block, blockchain::Blockchain, coinbase, consensus::PowAlgorithm,
+    hash::Hash256, params::ChainParams, state::State, work,
+};
+
+// `ChainConfig` Consensus settings the exported chain is checked against.
+struct ChainConfig {
+    params: ChainParams,
+    // Expected hash of the genesis block, any genesis block is accepted if unset.
+    genesis_hash: Option<Hash256>,
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut path = None;
+    let mut config = ChainConfig {
+        params: ChainParams::default(),
+        genesis_hash: None,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--chain-config" => {
+                let path = args.next().unwrap_or_else(|| usage());
+                config.params = ChainParams::load(&path).unwrap_or_else(|err| {
+                    println!("{}", err);
+                    process::exit(2);
+                })
+            }
+            "--difficulty" => {
+                config.params.difficulty = args
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            "--pow" => {
+                config.params.pow = match args.next().as_deref() {
+                    Some("sha256") => PowAlgorithm::Sha256,
+                    Some("memory-hard") => PowAlgorithm::MemoryHard,
+                    _ => usage(),
+                }
+            }
+            "--genesis" => {
+                config.genesis_hash = Some(
+                    args.next()
+                        .and_then(|value| value.parse().ok())
+                        .unwrap_or_else(|| usage()),
+                )
+            }
+            file if path.is_none() && !file.starts_with("--") => path = Some(file.to_string()),
+            _ => usage(),
+        }
+    }
+
+    let path = path.unwrap_or_else(|| usage());
+    let data = fs::read_to_string(&path).unwrap_or_else(|err| {
+        println!("can't read {}: {}", path, err);
+        process::exit(2);
+    });
+    let chain: Vec<Arc<Block>> = serde_json::from_str(&data).unwrap_or_else(|err| {
+        println!("can't parse {}: {}", path, err);
+        process::exit(2);
+    });
+
+    let valid = verify(&chain, &config);
+    print_stats(&chain);
+
+    if valid {
+        println!("verdict: VALID");
+    } else {
+        println!("verdict: INVALID");
+        process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    println!(
+        "usage: verify-chain <chain.json> [--chain-config <path>] [--difficulty N] [--pow sha256|memory-hard] [--genesis <hash>]"
+    );
+    process::exit(2);
+}
+
+fn verify(chain: &[Arc<Block>], config: &ChainConfig) -> bool {
+    let Some(genesis) = chain.first() else {
+        println!("chain is empty");
+        return false;
+    };
+
+    if genesis.header.index != 0 || !genesis.header.previous_hash.is_zero() {
+        println!("first block is not a genesis block");
+        return false;
+    }
+    if let Some(hash) = &config.genesis_hash
+        && &genesis.header.hash != hash
+    {
+        println!(
+            "genesis block has hash {}, expected {}",
+            genesis.header.hash, hash
+        );
+        return false;
+    }
+
+    Blockchain::new(config.params.clone()).is_chain_valid(chain)
+}
+
+fn print_stats(chain: &[Arc<Block>]) {
+    let transactions = chain
+        .iter()
+        .flat_map(|block| block.body.transactions.iter());
+    let transaction_count = transactions.clone().count();
+    let issued = transactions
+        .clone()
+        .filter(|transaction| coinbase::is_coinbase(transaction))
+        .fold(Amount::ZERO, |issued, transaction| {
+            issued.saturating_add(transaction.amount)
+        });
+    let addresses: HashSet<&str> = transactions
+        .flat_map(|transaction| [transaction.sender.as_str(), transaction.receiver.as_str()])
+        .filter(|address| *address != coinbase::COINBASE_SENDER)
+        .collect();
+
+    let mut state = State::default();
+    chain.iter().for_each(|block| state.apply_block(block));
+    let funded = state
+        .balances
+        .values()
+        .filter(|balance| !balance.is_zero())
+        .count();
+
+    let work = work::cumulative_work(chain);
+
+    println!("blocks: {}", chain.len());
+    println!("total work: {}", work);
+    println!("transactions: {}", transaction_count);
+    println!("addresses: {} ({} with a balance)", addresses.len(), funded);
+    println!("coins issued: {}", issued);
+
+    if let (Some(first), Some(last)) = (chain.first(), chain.last()) {
+        if chain.len() > 1 {
+            let span = last.header.timestamp.saturating_sub(first.header.timestamp) as f64 / 1000.0;
+            println!(
+                "average block interval: {:.1}s",
+                span / (chain.len() - 1) as f64
+            );
+        }
+        println!("tip: #{} {}", last.header.index, last.header.hash);
+    }
+}
@@ -0,0 +1,39 @@
+// Offline check of a message signed with `wallet sign-message`, proving whoever signed it
+// controls the address.
+//
+// Usage: verify-message <address> <signature> <message>...
+
+use std::process;
+
+use blockchain::models::{address::Address, message};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [address, signature, words @ ..] = args.as_slice() else {
+        usage();
+    };
+    if words.is_empty() {
+        usage();
+    }
+
+    let address = Address::new(address.as_str()).unwrap_or_else(|err| {
+        println!("{}", err);
+        process::exit(2);
+    });
+    // Separate arguments are one message, the way `wallet sign-message` reads it.
+    let text = words.join(" ");
+
+    match message::verify(&address, &text, signature) {
+        Ok(()) => println!("verdict: VALID"),
+        Err(err) => {
+            println!("{}", err);
+            println!("verdict: INVALID");
+            process::exit(1);
+        }
+    }
+}
+
+fn usage() -> ! {
+    println!("usage: verify-message <address> <signature> <message>...");
+    process::exit(2);
+}
@@ -0,0 +1,72 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{gossip::Topic, models::hash::Hash256};
+
+// How often unacknowledged broadcasts are published again.
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+// Broadcasts nobody acknowledged for this long are dropped.
+const STALE_AFTER: Duration = Duration::from_secs(120);
+
+struct Pending {
+    topic: Topic,
+    kind: &'static str,
+    data: Vec<u8>,
+    queued_at: Instant,
+}
+
+// `OutboundQueue` Broadcasts waiting for at least one peer to acknowledge them, keyed by the
+// hash of the published block or the id of the published transaction.
+#[derive(Default)]
+pub struct OutboundQueue {
+    pending: HashMap<Hash256, Pending>,
+}
+
+impl OutboundQueue {
+    // Queue an item for broadcasting. Returns `false` if it's already queued, so the caller
+    // doesn't publish it twice.
+    pub fn push(&mut self, hash: Hash256, topic: Topic, kind: &'static str, data: Vec<u8>) -> bool {
+        if self.pending.contains_key(&hash) {
+            return false;
+        }
+
+        self.pending.insert(
+            hash,
+            Pending {
+                topic,
+                kind,
+                data,
+                queued_at: Instant::now(),
+            },
+        );
+        true
+    }
+
+    // A peer has seen the item, stop broadcasting it. Returns how long ago it was queued, or
+    // `None` if it wasn't.
+    pub fn acknowledge(&mut self, hash: &Hash256) -> Option<Duration> {
+        self.pending
+            .remove(hash)
+            .map(|pending| pending.queued_at.elapsed())
+    }
+
+    // Drop stale items and return the ones to publish again along with their hash and kind.
+    pub fn retry(&mut self) -> Vec<(Hash256, Topic, &'static str, Vec<u8>)> {
+        self.pending
+            .retain(|_, pending| pending.queued_at.elapsed() < STALE_AFTER);
+
+        self.pending
+            .iter()
+            .map(|(hash, pending)| {
+                (
+                    *hash,
+                    pending.topic.clone(),
+                    pending.kind,
+                    pending.data.clone(),
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,133 @@
+// Fault injection for checking that nodes recover before deploying them. Every fault applies
+// to the fraction of inbound gossip messages or mining runs given on the command line, e.g.
+// `--chaos-drop 0.1 --chaos-delay 0.2 --chaos-kill-miner 0.05`. Running it on every node of a
+// testbed makes each link lossy, slow and unreliable.
+
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+use crate::{
+    gossip::GossipMessage,
+    miner::{NonceSearcher, Work},
+};
+
+// Longest a delayed message is held back.
+pub const MAX_DELAY: Duration = Duration::from_secs(10);
+// How often delayed messages are checked for release.
+pub const RELEASE_INTERVAL: Duration = Duration::from_secs(1);
+
+// `ChaosSettings` Fraction of messages or mining runs every fault is injected into.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ChaosSettings {
+    pub drop: f64,
+    pub delay: f64,
+    pub duplicate: f64,
+    pub corrupt: f64,
+    pub kill_miner: f64,
+}
+
+impl ChaosSettings {
+    pub fn is_enabled(&self) -> bool {
+        [
+            self.drop,
+            self.delay,
+            self.duplicate,
+            self.corrupt,
+            self.kill_miner,
+        ]
+        .iter()
+        .any(|fraction| *fraction > 0.0)
+    }
+}
+
+// `Chaos` Injects faults into inbound gossip before it is handled.
+#[derive(Debug, Default)]
+pub struct Chaos {
+    settings: ChaosSettings,
+    // Held back messages and when to release them.
+    delayed: Vec<(Instant, GossipMessage)>,
+}
+
+impl Chaos {
+    pub fn new(settings: ChaosSettings) -> Self {
+        Chaos {
+            settings,
+            delayed: Vec::new(),
+        }
+    }
+
+    // Messages to handle in place of `msg`: none if it was dropped or delayed, two if it was
+    // duplicated.
+    pub fn inbound(&mut self, mut msg: GossipMessage) -> Vec<GossipMessage> {
+        if happens(self.settings.drop) {
+            println!("chaos: dropped message from {}", msg.source);
+            return Vec::new();
+        }
+
+        if happens(self.settings.corrupt) && !msg.data.is_empty() {
+            let index = rand::random::<usize>() % msg.data.len();
+            msg.data[index] ^= 1 << (rand::random::<u8>() % 8);
+            println!("chaos: corrupted message from {}", msg.source);
+        }
+
+        if happens(self.settings.delay) {
+            let delay = MAX_DELAY.mul_f64(rand::random());
+            println!("chaos: delayed message from {} by {:?}", msg.source, delay);
+            self.delayed.push((Instant::now() + delay, msg));
+            return Vec::new();
+        }
+
+        if happens(self.settings.duplicate) {
+            println!("chaos: duplicated message from {}", msg.source);
+            return vec![msg.clone(), msg];
+        }
+
+        vec![msg]
+    }
+
+    // Delayed messages that are due.
+    pub fn release(&mut self) -> Vec<GossipMessage> {
+        let now = Instant::now();
+        let (due, delayed) = self.delayed.drain(..).partition(|(at, _)| *at <= now);
+        self.delayed = delayed;
+
+        due.into_iter().map(|(_, msg)| msg).collect()
+    }
+}
+
+// `ChaosSearcher` Kills a fraction of the nonce searches, the way a crashing miner would, and
+// restarts the miner for the next one.
+pub struct ChaosSearcher {
+    searcher: Box<dyn NonceSearcher + Send>,
+    start: Box<dyn FnMut() -> Box<dyn NonceSearcher + Send> + Send>,
+    kill_rate: f64,
+}
+
+impl ChaosSearcher {
+    pub fn new(
+        kill_rate: f64,
+        mut start: impl FnMut() -> Box<dyn NonceSearcher + Send> + Send + 'static,
+    ) -> Self {
+        ChaosSearcher {
+            searcher: start(),
+            start: Box::new(start),
+            kill_rate,
+        }
+    }
+}
+
+impl NonceSearcher for ChaosSearcher {
+    fn search(&mut self, work: &Work, cancel: &AtomicBool) -> Option<u64> {
+        if happens(self.kill_rate) {
+            println!("chaos: killed the miner, restarting it");
+            self.searcher = (self.start)();
+            return None;
+        }
+
+        self.searcher.search(work, cancel)
+    }
+}
+
+fn happens(fraction: f64) -> bool {
+    fraction > 0.0 && rand::random::<f64>() < fraction
+}
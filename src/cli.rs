@@ -0,0 +1,562 @@
+// Command line of the node. Every option is declared here, so misspelled or unknown options and
+// options missing their value stop the node instead of being ignored. The settings of every
+// service are built from the parsed values, see the notes at the top of their modules.
+
+use std::{env, net::IpAddr, path::PathBuf, thread, time::Duration};
+
+use clap::{Parser, builder::RangedU64ValueParser};
+use libp2p::{Multiaddr, PeerId};
+
+use crate::{
+    auth::ApiKeys,
+    chaos::ChaosSettings,
+    config::NodeConfig,
+    discovery::{self, DiscoverySettings},
+    faucet::{self, FaucetSettings},
+    gossip::{self, GossipSettings},
+    health::{self, HealthSettings},
+    http::{self, RateLimits},
+    metrics::{self, MetricsSettings},
+    miner::MinerSettings,
+    models::{
+        address::Address,
+        amount::Amount,
+        anchor::AnchorRule,
+        coinbase::{Payout, PayoutSplit},
+        index::IndexSettings,
+        params::{ChainParams, MAX_DIFFICULTY},
+    },
+    rpc::RpcSettings,
+    schedule::{BlockSchedule, BlockScheduler},
+    session::SessionRecorder,
+    trace::PropagationTrace,
+    ws::WsSettings,
+};
+
+// `NodeArgs` Options of the node. Options given twice keep the last value, so the command line
+// overrides the configuration file, see `NodeArgs::with_config`.
+#[derive(Debug, Clone, Parser)]
+#[command(
+    name = "blockchain",
+    args_override_self = true,
+    after_help = "Once the node runs, `help` lists the commands it reads from the console."
+)]
+pub struct NodeArgs {
+    #[arg(
+        long,
+        value_name = "path",
+        help = "Read options from <path>, node.toml by default"
+    )]
+    pub config: Option<String>,
+    #[arg(
+        long,
+        value_name = "path",
+        help = "Keep the chain and the other files in <path>"
+    )]
+    pub data_dir: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "path",
+        help = "Identify the node with the secret key in <path>"
+    )]
+    pub key_file: Option<String>,
+    #[cfg(feature = "test-network")]
+    #[arg(
+        long,
+        value_name = "nodes",
+        help = "Run the in-process test network and exit"
+    )]
+    pub test_network: Option<usize>,
+
+    #[arg(
+        long,
+        visible_alias = "p2p-port",
+        value_name = "port",
+        help = "Listen on <port>, every further chain on the ports after"
+    )]
+    pub port: Option<u16>,
+    #[arg(
+        long,
+        visible_alias = "bootstrap",
+        value_name = "multiaddr",
+        value_parser = parse_peer,
+        help = "Join the network through <multiaddr>/p2p/<peer id>"
+    )]
+    pub bootstrap_peer: Vec<(PeerId, Multiaddr)>,
+    #[arg(
+        long,
+        value_name = "multiaddr",
+        help = "Address peers can reach the node at"
+    )]
+    pub external_address: Vec<Multiaddr>,
+    #[arg(
+        long,
+        value_name = "seconds",
+        value_parser = clap::value_parser!(u64).range(1..),
+        default_value_t = discovery::DEFAULT_REFRESH_INTERVAL.as_secs(),
+        help = "Seconds between DHT routing table refreshes"
+    )]
+    pub kad_refresh: u64,
+
+    #[arg(
+        long,
+        value_name = "digits",
+        value_parser = RangedU64ValueParser::<usize>::new().range(..=MAX_DIFFICULTY as u64),
+        help = "Leading zero hex digits block hashes need"
+    )]
+    pub difficulty: Option<usize>,
+    #[arg(long, value_name = "path", help = "Host the chain <path> configures")]
+    pub chain_config: Vec<String>,
+    #[arg(
+        long,
+        value_name = "chain>:<anchored>[:<blocks>",
+        help = "Anchor the tip of the hosted chain <anchored> in <chain> every <blocks> blocks"
+    )]
+    pub anchor: Vec<AnchorRule>,
+
+    #[arg(
+        long,
+        help = "Mine blocks continuously, same as --block-schedule continuous"
+    )]
+    pub mine: bool,
+    #[arg(
+        long,
+        value_name = "schedule",
+        help = "on-demand, continuous or interval:<seconds>"
+    )]
+    pub block_schedule: Option<BlockSchedule>,
+    #[arg(
+        long,
+        value_name = "seconds",
+        help = "Mine an empty block if there was none for <seconds>"
+    )]
+    pub empty_block_heartbeat: Option<u64>,
+    #[arg(long, value_name = "threads", help = "Threads searching for nonces")]
+    pub mine_threads: Option<usize>,
+    #[arg(
+        long,
+        value_name = "percent",
+        default_value_t = 100,
+        help = "Share of the time the mining threads run"
+    )]
+    pub mine_throttle: usize,
+    #[arg(long, value_name = "address", help = "Address block rewards go to")]
+    pub payout: Option<Address>,
+    #[arg(
+        long,
+        value_name = "address>:<percent",
+        help = "Send <percent> of every block reward to <address>"
+    )]
+    pub payout_split: Vec<PayoutSplit>,
+
+    #[arg(long, value_name = "port", help = "Serve the JSON-RPC API on <port>")]
+    pub rpc_port: Option<u16>,
+    #[arg(
+        long,
+        value_name = "address",
+        help = "Serve the JSON-RPC API on <address> only"
+    )]
+    pub rpc_bind: Option<IpAddr>,
+    #[arg(
+        long,
+        value_name = "port",
+        help = "Push chain events over WebSocket on <port>"
+    )]
+    pub ws_port: Option<u16>,
+    #[arg(
+        long,
+        value_name = "file",
+        help = "Require one of the API keys in <file>"
+    )]
+    pub api_keys: Option<String>,
+    #[arg(
+        long,
+        value_name = "requests",
+        default_value_t = http::DEFAULT_IP_QUOTA,
+        help = "Requests per minute every IP address may make"
+    )]
+    pub rate_limit: u32,
+    #[arg(
+        long,
+        value_name = "requests",
+        default_value_t = http::DEFAULT_TOKEN_QUOTA,
+        help = "Requests per minute every API key may make"
+    )]
+    pub token_rate_limit: u32,
+
+    #[arg(
+        long,
+        value_name = "address",
+        help = "Pay faucet requests from <address>"
+    )]
+    pub faucet: Option<Address>,
+    #[arg(
+        long,
+        value_name = "port",
+        default_value_t = faucet::DEFAULT_PORT,
+        help = "Serve the faucet on <port>"
+    )]
+    pub faucet_port: u16,
+    #[arg(
+        long,
+        value_name = "units",
+        value_parser = clap::value_parser!(u64).range(1..),
+        default_value_t = faucet::DEFAULT_AMOUNT.0,
+        help = "Units the faucet pays out per request"
+    )]
+    pub faucet_amount: u64,
+
+    #[arg(
+        long,
+        value_name = "peers",
+        default_value_t = gossip::DEFAULT_MESH_N,
+        help = "Peers gossip aims to forward to"
+    )]
+    pub mesh_n: usize,
+    #[arg(
+        long,
+        value_name = "peers",
+        default_value_t = gossip::DEFAULT_MESH_N_LOW,
+        help = "Fewest peers gossip forwards to"
+    )]
+    pub mesh_n_low: usize,
+    #[arg(
+        long,
+        value_name = "peers",
+        default_value_t = gossip::DEFAULT_MESH_N_HIGH,
+        help = "Most peers gossip forwards to"
+    )]
+    pub mesh_n_high: usize,
+    #[arg(
+        long,
+        value_name = "ms",
+        value_parser = clap::value_parser!(u64).range(1..),
+        default_value_t = gossip::DEFAULT_HEARTBEAT.as_millis() as u64,
+        help = "Milliseconds between gossip heartbeats"
+    )]
+    pub gossip_heartbeat: u64,
+    #[arg(
+        long,
+        help = "Announce headers and let peers fetch the blocks they miss"
+    )]
+    pub header_relay: bool,
+
+    #[arg(
+        long,
+        value_name = "blocks",
+        default_value_t = health::DEFAULT_MAX_LAG,
+        help = "Blocks the node may lag behind its peers and be ready"
+    )]
+    pub ready_max_lag: u64,
+    #[arg(long, help = "Report ready without peers")]
+    pub standalone: bool,
+    #[arg(
+        long,
+        value_name = "seconds",
+        value_parser = clap::value_parser!(u64).range(1..),
+        default_value_t = health::DEFAULT_STALE_TIMEOUT.as_secs(),
+        help = "Seconds without a new block after which the tip is stale"
+    )]
+    pub stale_tip_timeout: u64,
+    #[arg(
+        long,
+        value_name = "seconds",
+        value_parser = clap::value_parser!(u64).range(1..),
+        default_value_t = metrics::DEFAULT_METRICS_INTERVAL.as_secs(),
+        help = "Seconds between metrics samples"
+    )]
+    pub metrics_interval: u64,
+    #[arg(
+        long,
+        value_name = "samples",
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..),
+        default_value_t = metrics::DEFAULT_METRICS_HISTORY,
+        help = "Metrics samples kept"
+    )]
+    pub metrics_history: usize,
+
+    #[arg(long, help = "Keep no per-address statistics")]
+    pub no_address_index: bool,
+    #[arg(long, help = "Keep no spend links of transactions")]
+    pub no_tx_index: bool,
+    #[arg(
+        long,
+        value_name = "blocks",
+        help = "Drop index entries deeper than <blocks>"
+    )]
+    pub index_depth: Option<u64>,
+    #[arg(
+        long,
+        value_name = "seconds",
+        help = "Drop index entries older than <seconds>"
+    )]
+    pub index_max_age: Option<u64>,
+
+    #[arg(long, value_name = "path", help = "Append inbound gossip to <path>")]
+    pub record_session: Option<String>,
+    #[arg(
+        long,
+        value_name = "path",
+        help = "Replay the session in <path> instead of joining the network"
+    )]
+    pub replay_session: Option<String>,
+    #[arg(long, help = "Record how blocks propagate, see `trace export`")]
+    pub trace_propagation: bool,
+    #[arg(
+        long,
+        value_name = "fraction",
+        value_parser = parse_fraction,
+        default_value_t = 0.0,
+        help = "Fraction of inbound gossip to drop"
+    )]
+    pub chaos_drop: f64,
+    #[arg(
+        long,
+        value_name = "fraction",
+        value_parser = parse_fraction,
+        default_value_t = 0.0,
+        help = "Fraction of inbound gossip to delay"
+    )]
+    pub chaos_delay: f64,
+    #[arg(
+        long,
+        value_name = "fraction",
+        value_parser = parse_fraction,
+        default_value_t = 0.0,
+        help = "Fraction of inbound gossip to handle twice"
+    )]
+    pub chaos_duplicate: f64,
+    #[arg(
+        long,
+        value_name = "fraction",
+        value_parser = parse_fraction,
+        default_value_t = 0.0,
+        help = "Fraction of inbound gossip to corrupt"
+    )]
+    pub chaos_corrupt: f64,
+    #[arg(
+        long,
+        value_name = "fraction",
+        value_parser = parse_fraction,
+        default_value_t = 0.0,
+        help = "Fraction of nonce searches to kill"
+    )]
+    pub chaos_kill_miner: f64,
+}
+
+impl NodeArgs {
+    // Parse the command line, preceded by the options of the configuration file it names.
+    // Prints the help or the mistake and exits if the command line doesn't parse.
+    pub fn load() -> Result<Self, String> {
+        let args: Vec<String> = env::args().collect();
+        let command_line = NodeArgs::parse_from(&args);
+        let config = NodeConfig::find(command_line.config.as_deref())?;
+
+        NodeArgs::with_config(args, &config)
+    }
+
+    // Parse `args`, the program name and the options, with the options `config` stands for put
+    // first so the ones in `args` override them.
+    pub fn with_config(args: Vec<String>, config: &NodeConfig) -> Result<Self, String> {
+        let mut args = args.into_iter();
+        let program = args.next();
+        let args = program.into_iter().chain(config.args()).chain(args);
+
+        NodeArgs::try_parse_from(args).map_err(|err| err.to_string())
+    }
+
+    // Directory the node keeps its files in, the working directory unless set.
+    pub fn data_dir(&self) -> PathBuf {
+        self.data_dir.clone().unwrap_or_default()
+    }
+
+    pub fn chain_params(&self) -> Result<Vec<ChainParams>, String> {
+        ChainParams::configured(&self.chain_config, self.difficulty)
+    }
+
+    pub fn miner_settings(&self) -> MinerSettings {
+        let threads = self
+            .mine_threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |count| count.get()));
+
+        MinerSettings::new(threads, self.mine_throttle)
+    }
+
+    // Where rewards go, `default_address` unless `--payout` is given.
+    pub fn payout(&self, default_address: Address) -> Result<Payout, String> {
+        let address = self.payout.clone().unwrap_or(default_address);
+        Payout::new(address, self.payout_split.clone())
+    }
+
+    // Blocks are produced on demand unless a schedule is given.
+    pub fn block_scheduler(&self) -> BlockScheduler {
+        let schedule = match self.mine {
+            true => BlockSchedule::Continuous,
+            false => self.block_schedule.unwrap_or_default(),
+        };
+        let heartbeat = self.empty_block_heartbeat.map(Duration::from_secs);
+
+        BlockScheduler::new(schedule, heartbeat)
+    }
+
+    pub fn index_settings(&self) -> IndexSettings {
+        IndexSettings {
+            address_index: !self.no_address_index,
+            tx_index: !self.no_tx_index,
+            depth: self.index_depth,
+            max_age: self.index_max_age,
+        }
+    }
+
+    pub fn discovery_settings(&self) -> DiscoverySettings {
+        DiscoverySettings {
+            bootstrap: self.bootstrap_peer.clone(),
+            port: self.port.unwrap_or_default(),
+            external_addresses: self.external_address.clone(),
+            refresh: Duration::from_secs(self.kad_refresh),
+        }
+    }
+
+    pub fn gossip_settings(&self) -> GossipSettings {
+        GossipSettings {
+            mesh_n: self.mesh_n,
+            mesh_n_low: self.mesh_n_low,
+            mesh_n_high: self.mesh_n_high,
+            heartbeat: Duration::from_millis(self.gossip_heartbeat),
+            header_relay: self.header_relay,
+        }
+    }
+
+    pub fn health_settings(&self) -> HealthSettings {
+        HealthSettings {
+            max_lag: self.ready_max_lag,
+            standalone: self.standalone,
+            stale_timeout: Duration::from_secs(self.stale_tip_timeout),
+        }
+    }
+
+    pub fn metrics_settings(&self) -> MetricsSettings {
+        MetricsSettings {
+            interval: Duration::from_secs(self.metrics_interval),
+            history: self.metrics_history,
+        }
+    }
+
+    pub fn rate_limits(&self) -> RateLimits {
+        RateLimits {
+            per_ip: self.rate_limit,
+            per_token: self.token_rate_limit,
+        }
+    }
+
+    // The faucet only runs if it has an address to pay from.
+    pub fn faucet_settings(&self) -> Option<FaucetSettings> {
+        Some(FaucetSettings {
+            address: self.faucet.clone()?,
+            port: self.faucet_port,
+            amount: Amount(self.faucet_amount),
+        })
+    }
+
+    pub fn api_keys(&self) -> ApiKeys {
+        match &self.api_keys {
+            Some(path) => ApiKeys::load(path),
+            None => ApiKeys::default(),
+        }
+    }
+
+    // The JSON-RPC API only runs if it has a port.
+    pub fn rpc_settings(&self, keys: &ApiKeys) -> Option<RpcSettings> {
+        Some(RpcSettings::new(self.rpc_port?, self.rpc_bind, keys))
+    }
+
+    // The WebSocket server only runs if it has a port.
+    pub fn ws_settings(&self) -> Option<WsSettings> {
+        Some(WsSettings {
+            port: self.ws_port?,
+        })
+    }
+
+    pub fn chaos_settings(&self) -> ChaosSettings {
+        ChaosSettings {
+            drop: self.chaos_drop,
+            delay: self.chaos_delay,
+            duplicate: self.chaos_duplicate,
+            corrupt: self.chaos_corrupt,
+            kill_miner: self.chaos_kill_miner,
+        }
+    }
+
+    pub fn propagation_trace(&self) -> PropagationTrace {
+        PropagationTrace::new(self.trace_propagation)
+    }
+
+    pub fn session_recorder(&self, peer_id: &PeerId) -> SessionRecorder {
+        match &self.record_session {
+            Some(path) => SessionRecorder::open(path, peer_id),
+            None => SessionRecorder::default(),
+        }
+    }
+}
+
+fn parse_peer(value: &str) -> Result<(PeerId, Multiaddr), String> {
+    discovery::peer_address(value)
+        .ok_or_else(|| format!("{} isn't a multiaddr ending in /p2p/<peer id>", value))
+}
+
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|fraction| (0.0..=1.0).contains(fraction))
+        .ok_or_else(|| format!("{} isn't a fraction between 0 and 1", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<NodeArgs, String> {
+        let args = ["blockchain"].iter().chain(args).map(|arg| arg.to_string());
+        NodeArgs::with_config(args.collect(), &NodeConfig::default())
+    }
+
+    #[test]
+    fn unknown_options_and_missing_values_are_rejected() {
+        assert!(parse(&["--dificulty", "5"]).is_err());
+        assert!(parse(&["--data-dir"]).is_err());
+        assert!(parse(&["--difficulty", "five"]).is_err());
+        assert!(parse(&["--difficulty", &(MAX_DIFFICULTY + 1).to_string()]).is_err());
+        assert!(parse(&["--chaos-drop", "1.5"]).is_err());
+
+        let args = parse(&["--difficulty", "5", "--data-dir", "node1"]).expect("options parse");
+        assert_eq!(args.difficulty, Some(5));
+        assert_eq!(args.data_dir(), PathBuf::from("node1"));
+    }
+
+    #[test]
+    fn command_line_overrides_configuration_file() {
+        let peer = |host: &str| format!("/ip4/{}/tcp/4001/p2p/{}", host, PeerId::random());
+        let config = NodeConfig::parse(&format!(
+            "data_dir = \"node1\"\n[network]\nbootstrap = [\"{}\"]\n[mining]\nmine = true\ndifficulty = 3\n",
+            peer("10.0.0.1")
+        ))
+        .expect("config parses");
+        let args = [
+            "blockchain",
+            "--difficulty",
+            "5",
+            "--mine",
+            "--bootstrap-peer",
+        ];
+        let args = args.iter().map(|arg| arg.to_string());
+
+        let args = NodeArgs::with_config(args.chain([peer("10.0.0.2")]).collect(), &config)
+            .expect("options parse");
+        assert_eq!(args.difficulty, Some(5));
+        assert_eq!(args.data_dir(), PathBuf::from("node1"));
+        assert!(args.mine);
+        // Repeatable options keep the values of both.
+        assert_eq!(args.bootstrap_peer.len(), 2);
+    }
+}
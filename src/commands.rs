@@ -0,0 +1,973 @@
+// Commands typed into the node's console. `parse` reads the ones below into a `Command`, and a
+// line it can't make sense of gets a message naming what is wrong and how the command is used,
+// instead of being run half understood. Lines that aren't one of them go to the older commands
+// (`create t`, `ls c`, `wallet`, ...), all of which `help` lists. The `handle_*` functions run
+// the commands on the node's swarm.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    str::FromStr,
+    time::Duration,
+};
+
+use libp2p::{Multiaddr, PeerId, Swarm};
+use serde::Serializer;
+
+use crate::{
+    dump,
+    models::{
+        accumulator::{self, InclusionProof},
+        address::Address,
+        amount::Amount,
+        blockchain::Blockchain,
+        hash::Hash256,
+        history::{DEFAULT_HISTORY_SAMPLES, MAX_HISTORY_SAMPLES},
+        mempool::TransactionPackage,
+        message,
+        transaction::Transaction,
+    },
+    p2p::{BlockchainBehaviour, CheckpointRequest, EpochRequest, HistoryRequest, get_list_peers},
+    schedule::BlockSchedule,
+    vanity::{self, VanitySearch},
+};
+
+pub const HELP: &str = "\
+commands:
+  help                                  this text
+  tx send <to> <amount> [fee]           pay <amount> units from this node's address
+  tx <id>                               print a transaction of the chain
+  balance <address> [height]            balance of <address>, at the tip or at <height>
+  chain info                            height, tip and difficulty of the chain
+  chain watch                           print new blocks as they arrive, again to stop
+  peer list                             peers found on the local network
+  peer dial <multiaddr>                 connect to a peer
+  mine start | mine stop                mine blocks one after another, or only when asked to
+  mine [threads <count> | throttle <percent>]
+  schedule [on-demand | continuous | interval:<seconds> | heartbeat <seconds>|off]
+  create b [transactions json]          mine a block, of the pending transactions by default
+  create t <transaction json>           submit a transaction
+  create p <transactions json>          submit transactions depending on each other
+  test_accept <transaction json>        check a transaction without submitting it
+  ls c | ls m | ls p                    print the chain, the mempool or the peers
+  wallet address | wallet sign-message <address> <message>
+  wallet sign-transaction <json> | wallet vanity <prefix> [seed] | status | cancel
+  invalidate <block hash> | reconsider <block hash>
+  stats <address> | state <height> | prove <transaction id> | verify <proof json>
+  checkpoints | checkpoint <peer> [height] | epochs [<peer> [from epoch]]
+  sample <peer> [blocks] | relay [block hash] | coop join | leave | mine <transactions>
+  metrics [seconds] | export <path> | debug dump [path] | trace export <path>
+  chains | use <chain id> | @<chain id> <command> | anchors";
+
+// `Command` A console command and its arguments.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Help,
+    TxSend {
+        to: Address,
+        amount: Amount,
+        fee: Amount,
+    },
+    Balance {
+        address: Address,
+        height: Option<u64>,
+    },
+    ChainInfo,
+    PeerList,
+    PeerDial(Multiaddr),
+    MineStart,
+    MineStop,
+}
+
+// Read `line` as one of the commands, or `None` if it is none of them and left to the older
+// commands.
+pub fn parse(line: &str) -> Option<Result<Command, String>> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    let command = match words.as_slice() {
+        ["help"] => Ok(Command::Help),
+        ["tx", "send", args @ ..] => tx_send(args),
+        ["balance", args @ ..] => balance(args),
+        ["chain", "info"] => Ok(Command::ChainInfo),
+        ["peer", args @ ..] => peer(args),
+        ["mine", "start"] => Ok(Command::MineStart),
+        ["mine", "stop"] => Ok(Command::MineStop),
+        _ => return None,
+    };
+
+    Some(command)
+}
+
+fn tx_send(args: &[&str]) -> Result<Command, String> {
+    let usage = "usage: tx send <to> <amount> [fee]";
+    let (to, amount, fee) = match args {
+        [to, amount] => (to, amount, None),
+        [to, amount, fee] => (to, amount, Some(fee)),
+        _ => return Err(usage.to_string()),
+    };
+
+    Ok(Command::TxSend {
+        to: address(to, usage)?,
+        amount: units(amount, "amount", usage)?,
+        fee: match fee {
+            Some(fee) => units(fee, "fee", usage)?,
+            None => Amount::ZERO,
+        },
+    })
+}
+
+fn balance(args: &[&str]) -> Result<Command, String> {
+    let usage = "usage: balance <address> [height]";
+    let (address_arg, height) = match args {
+        [address] => (address, None),
+        [address, height] => (address, Some(height)),
+        _ => return Err(usage.to_string()),
+    };
+
+    Ok(Command::Balance {
+        address: address(address_arg, usage)?,
+        height: height
+            .map(|height| {
+                height
+                    .parse()
+                    .map_err(|_| format!("{} is not a block height\n{}", height, usage))
+            })
+            .transpose()?,
+    })
+}
+
+fn peer(args: &[&str]) -> Result<Command, String> {
+    let usage = "usage: peer list | peer dial <multiaddr>";
+
+    match args {
+        ["list"] => Ok(Command::PeerList),
+        ["dial", address] => Multiaddr::from_str(address)
+            .map(Command::PeerDial)
+            .map_err(|err| format!("{} is not a multiaddr: {}\n{}", address, err, usage)),
+        _ => Err(usage.to_string()),
+    }
+}
+
+fn address(value: &str, usage: &str) -> Result<Address, String> {
+    Address::from_str(value)
+        .map_err(|err| format!("{} is not an address: {}\n{}", value, err, usage))
+}
+
+fn units(value: &str, name: &str, usage: &str) -> Result<Amount, String> {
+    value.parse().map(Amount).map_err(|_| {
+        format!(
+            "{} expects a number of units, not {}\n{}",
+            name, value, usage
+        )
+    })
+}
+
+pub fn handle_print_peers(swarm: &Swarm<BlockchainBehaviour>) {
+    let peers = get_list_peers(swarm);
+    let selector = &swarm.behaviour().peers;
+
+    peers.iter().for_each(|peer| {
+        if selector.is_outbound(peer) {
+            println!("{} (outbound)", peer);
+        } else {
+            println!("{}", peer);
+        }
+    });
+}
+
+pub fn handle_dial_peer(address: Multiaddr, swarm: &mut Swarm<BlockchainBehaviour>) {
+    match Swarm::dial_addr(swarm, address.clone()) {
+        Ok(()) => println!("dialing {}", address),
+        Err(err) => println!("can't dial {}: {}", address, err),
+    }
+}
+
+pub fn handle_print_chain_info(swarm: &Swarm<BlockchainBehaviour>) {
+    let behaviour = swarm.behaviour();
+    let blockchain = &behaviour.blockchain;
+    let tip = blockchain
+        .chain
+        .last()
+        .expect("there is at least one block");
+
+    println!("chain {}", blockchain.params.chain_id);
+    println!("height {}, tip {}", tip.header.index, tip.header.hash);
+    println!(
+        "difficulty {} for the next block, blocks produced {}",
+        blockchain.next_difficulty(),
+        behaviour.scheduler
+    );
+    println!(
+        "{} pending transactions, {} peers",
+        behaviour.mempool.len(),
+        behaviour.gossipsub.all_peers().count()
+    );
+}
+
+pub fn handle_print_chain(swarm: &Swarm<BlockchainBehaviour>) {
+    println!("local blockchain");
+
+    let pretty_json = serde_json::to_string_pretty(&swarm.behaviour().blockchain.chain)
+        .expect("can jsonify blocks");
+
+    println!("{}", pretty_json);
+}
+
+pub fn handle_export_chain(cmd: &str, swarm: &Swarm<BlockchainBehaviour>) {
+    let Some(path) = cmd.split_whitespace().nth(1) else {
+        println!("usage: export <path>");
+        return;
+    };
+
+    match write_blocks(path, &swarm.behaviour().blockchain) {
+        Ok(()) => println!("exported chain to {}", path),
+        Err(err) => println!("can't export chain to {}: {}", path, err),
+    }
+}
+
+// Write the chain to `path` as a JSON array one block at a time, without building the whole
+// document in memory.
+fn write_blocks(path: &str, blockchain: &Blockchain) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::Serializer::pretty(&mut writer).collect_seq(blockchain.iter_blocks(..))?;
+
+    writer.flush()
+}
+
+// Mine a block of the given transactions, or of the pending ones if none are given.
+pub fn handle_create_block(cmd: &str, swarm: &mut Swarm<BlockchainBehaviour>) {
+    if let Some(data) = cmd.strip_prefix("create b") {
+        let behaviour = swarm.behaviour_mut();
+
+        let transactions = if data.trim().is_empty() {
+            behaviour.pending_transactions()
+        } else {
+            match serde_json::from_str::<Vec<Transaction>>(data) {
+                Ok(mut transactions) => {
+                    for transaction in transactions.iter_mut() {
+                        behaviour.sign_own(transaction);
+                    }
+                    transactions
+                }
+                Err(err) => {
+                    println!("can't parse transactions: {}", err);
+                    println!("usage: create b [transactions json]");
+                    return;
+                }
+            }
+        };
+        let block = behaviour.next_block(transactions);
+
+        println!(
+            "mining new block with {} transactions",
+            block.body.transactions.len()
+        );
+
+        behaviour.update_chain(|behaviour| behaviour.mine_block(block));
+    }
+}
+
+// Add a transaction to the mempool and pass it on to peers.
+pub fn handle_create_transaction(cmd: &str, swarm: &mut Swarm<BlockchainBehaviour>) {
+    let data = cmd.strip_prefix("create t").unwrap_or_default();
+    let transaction: Transaction = match serde_json::from_str(data) {
+        Ok(transaction) => transaction,
+        Err(err) => {
+            println!("can't parse transaction: {}", err);
+            println!("usage: create t <transaction json>");
+            return;
+        }
+    };
+
+    send_transaction(transaction, swarm.behaviour_mut());
+}
+
+// Pay `amount` from this node's address to `to`.
+pub fn handle_send(
+    to: Address,
+    amount: Amount,
+    fee: Amount,
+    swarm: &mut Swarm<BlockchainBehaviour>,
+) {
+    let behaviour = swarm.behaviour_mut();
+    let Some(keypair) = behaviour.signing_keypair() else {
+        println!("this node has no ed25519 key to send with");
+        return;
+    };
+
+    let sender = Address::from_public_key(&keypair.public);
+    let mut transaction = Transaction::new(sender.clone(), to, amount);
+    transaction.nonce = behaviour.next_nonce(&sender);
+    transaction.fee = fee;
+    send_transaction(transaction, behaviour);
+}
+
+// Sign `transaction` if it is this node's, add it to the mempool and pass it on to peers.
+fn send_transaction(mut transaction: Transaction, behaviour: &mut BlockchainBehaviour) {
+    behaviour.sign_own(&mut transaction);
+    let json = serde_json::to_string(&transaction).expect("can jsonify transaction");
+    match behaviour.submit_transaction(transaction) {
+        Ok(id) => {
+            behaviour.broadcast_transaction("transaction", id, json);
+            println!(
+                "transaction {} is pending, {} in the mempool",
+                id,
+                behaviour.mempool.len()
+            );
+        }
+        Err(err) => println!("transaction not accepted: {}", err),
+    }
+}
+
+// Add transactions that depend on each other to the mempool as a package and pass it on to peers.
+pub fn handle_create_package(cmd: &str, swarm: &mut Swarm<BlockchainBehaviour>) {
+    let data = cmd.strip_prefix("create p").unwrap_or_default();
+    let transactions: Vec<Transaction> = match serde_json::from_str(data) {
+        Ok(transactions) => transactions,
+        Err(err) => {
+            println!("can't parse transactions: {}", err);
+            println!("usage: create p <transactions json>");
+            return;
+        }
+    };
+
+    let behaviour = swarm.behaviour_mut();
+    let mut package = TransactionPackage { transactions };
+    for transaction in package.transactions.iter_mut() {
+        behaviour.sign_own(transaction);
+    }
+    let json = serde_json::to_string(&package).expect("can jsonify package");
+    let id = package
+        .transactions
+        .first()
+        .map(|transaction| transaction.id());
+    match behaviour.submit_package(package) {
+        Ok(ids) => {
+            if let Some(id) = id {
+                behaviour.broadcast_transaction("package", id, json);
+            }
+            println!(
+                "{} transactions of the package are pending, {} in the mempool",
+                ids.len(),
+                behaviour.mempool.len()
+            );
+        }
+        Err(err) => println!("package not accepted: {}", err),
+    }
+}
+
+pub fn handle_print_mempool(swarm: &Swarm<BlockchainBehaviour>) {
+    let mempool = &swarm.behaviour().mempool;
+    println!("{} pending transactions", mempool.len());
+
+    for transaction in mempool.by_fee_rate() {
+        println!(
+            "{} {} -> {} {} fee {}",
+            transaction.id(),
+            transaction.sender,
+            transaction.receiver,
+            transaction.amount,
+            transaction.fee
+        );
+    }
+}
+
+pub fn handle_schedule(cmd: &str, swarm: &mut Swarm<BlockchainBehaviour>) {
+    let scheduler = &mut swarm.behaviour_mut().scheduler;
+
+    let mut args = cmd.split_whitespace().skip(1);
+    let usage =
+        "usage: schedule [on-demand | continuous | interval:<seconds> | heartbeat <seconds>|off]";
+
+    match (args.next(), args.next()) {
+        (None, _) => {}
+        (Some("heartbeat"), Some("off")) => scheduler.heartbeat = None,
+        (Some("heartbeat"), Some(seconds)) => match seconds.parse() {
+            Ok(seconds) => scheduler.heartbeat = Some(Duration::from_secs(seconds)),
+            Err(_) => {
+                println!("{}", usage);
+                return;
+            }
+        },
+        (Some(schedule), None) => match schedule.parse::<BlockSchedule>() {
+            Ok(schedule) => scheduler.set(schedule),
+            Err(err) => {
+                println!("{}", err);
+                println!("{}", usage);
+                return;
+            }
+        },
+        _ => {
+            println!("{}", usage);
+            return;
+        }
+    }
+
+    println!("producing blocks {}", scheduler);
+}
+
+pub fn handle_set_schedule(schedule: BlockSchedule, swarm: &mut Swarm<BlockchainBehaviour>) {
+    let scheduler = &mut swarm.behaviour_mut().scheduler;
+    scheduler.set(schedule);
+
+    println!("producing blocks {}", scheduler);
+}
+
+pub fn handle_test_accept(cmd: &str, swarm: &Swarm<BlockchainBehaviour>) {
+    let data = cmd.strip_prefix("test_accept").unwrap_or_default();
+    let transaction: Transaction = match serde_json::from_str(data) {
+        Ok(transaction) => transaction,
+        Err(err) => {
+            println!("can't parse transaction: {}", err);
+            println!("usage: test_accept <transaction json>");
+            return;
+        }
+    };
+
+    let checks = swarm.behaviour().blockchain.test_accept(&transaction);
+    for (check, passed) in checks.iter() {
+        println!("{}: {}", check, if *passed { "ok" } else { "failed" });
+    }
+
+    let accepted = checks.iter().all(|(_, passed)| *passed);
+    println!(
+        "transaction {} would be {}",
+        transaction.id(),
+        if accepted { "accepted" } else { "rejected" }
+    );
+}
+
+pub fn handle_invalidate_block(cmd: &str, swarm: &mut Swarm<BlockchainBehaviour>) {
+    let Some(hash) = parse_hash_argument(cmd, "usage: invalidate <block hash>") else {
+        return;
+    };
+
+    let behaviour = swarm.behaviour_mut();
+    match behaviour.update_chain(|behaviour| behaviour.blockchain.invalidate_block(hash)) {
+        Ok(dropped) => println!(
+            "invalidated block {}, dropped {} blocks, tip is now #{}",
+            hash,
+            dropped,
+            behaviour.blockchain.chain.len() - 1
+        ),
+        Err(err) => println!("{}", err),
+    }
+}
+
+pub fn handle_reconsider_block(cmd: &str, swarm: &mut Swarm<BlockchainBehaviour>) {
+    let Some(hash) = parse_hash_argument(cmd, "usage: reconsider <block hash>") else {
+        return;
+    };
+
+    let restored = swarm
+        .behaviour_mut()
+        .update_chain(|behaviour| behaviour.blockchain.reconsider_block(hash));
+    match restored {
+        Ok(restored) => {
+            let tip = swarm.behaviour().blockchain.chain.len() - 1;
+            if restored {
+                println!("reconsidered block {}, restored chain up to #{}", hash, tip);
+            } else {
+                println!("reconsidered block {}, keeping chain up to #{}", hash, tip);
+            }
+
+            // Peers may have built on the block since it was invalidated.
+            let mut peers = get_list_peers(swarm);
+            if let Some(peer) = peers.pop() {
+                swarm.behaviour_mut().start_download(peer, peers);
+            }
+        }
+        Err(err) => println!("{}", err),
+    }
+}
+
+fn parse_hash_argument(cmd: &str, usage: &str) -> Option<Hash256> {
+    match cmd.split_whitespace().nth(1).map(str::parse::<Hash256>) {
+        Some(Ok(hash)) => Some(hash),
+        Some(Err(err)) => {
+            println!("can't parse hash: {}", err);
+            None
+        }
+        None => {
+            println!("{}", usage);
+            None
+        }
+    }
+}
+
+pub fn handle_coop(cmd: &str, swarm: &mut Swarm<BlockchainBehaviour>) {
+    let behaviour = swarm.behaviour_mut();
+
+    match cmd.split_whitespace().nth(1) {
+        Some(action @ ("join" | "leave")) => {
+            behaviour.coop.volunteering = action == "join";
+            behaviour.announce_volunteer();
+            if !behaviour.coop.volunteering {
+                behaviour.coop.cancel_search("stopped volunteering");
+            }
+
+            if behaviour.coop.volunteering {
+                println!("accepting cooperative mining work");
+            } else {
+                println!("stopped accepting cooperative mining work");
+            }
+        }
+        Some("mine") => {
+            let data = cmd.split_once("mine").map_or("", |(_, data)| data);
+            let transactions: Vec<Transaction> = match serde_json::from_str(data) {
+                Ok(transactions) => transactions,
+                Err(err) => {
+                    println!("can't parse transactions: {}", err);
+                    return;
+                }
+            };
+
+            let block = behaviour.next_block(transactions);
+            behaviour.start_coop_job(block);
+        }
+        _ => println!("usage: coop join | coop leave | coop mine <transactions>"),
+    }
+}
+
+pub fn handle_print_relay(cmd: &str, swarm: &Swarm<BlockchainBehaviour>) {
+    let behaviour = swarm.behaviour();
+
+    let Some(hash) = cmd.split_whitespace().nth(1) else {
+        println!("blocks first delivered by each peer");
+        for (peer, count) in behaviour.relay_log.first_deliveries() {
+            println!("{}: {}", peer, count);
+        }
+        return;
+    };
+
+    let hash: Hash256 = match hash.parse() {
+        Ok(hash) => hash,
+        Err(err) => {
+            println!("can't parse block hash: {}", err);
+            return;
+        }
+    };
+
+    match behaviour.relay_log.get(&hash) {
+        Some(record) => {
+            let block = behaviour.blockchain.get_block_by_hash(&hash);
+            let summary = serde_json::json!({
+                "hash": record.hash,
+                "peer": record.peer,
+                "received_at": record.received_at,
+                "delay_ms": block.map(|block| record.received_at.saturating_sub(block.header.timestamp)),
+                "in_chain": block.is_some(),
+            });
+            let pretty_json =
+                serde_json::to_string_pretty(&summary).expect("can jsonify relay record");
+            println!("{}", pretty_json);
+        }
+        None => println!("block {} was never received", hash),
+    }
+}
+
+pub fn handle_export_trace(cmd: &str, swarm: &Swarm<BlockchainBehaviour>) {
+    let Some(path) = cmd.split_whitespace().nth(2) else {
+        println!("usage: trace export <path>");
+        return;
+    };
+
+    match swarm.behaviour().trace.export(path) {
+        Ok(count) => println!("exported {} trace records to {}", count, path),
+        Err(err) => println!("can't export trace to {}: {}", path, err),
+    }
+}
+
+pub fn handle_debug_dump(cmd: &str, swarm: &Swarm<BlockchainBehaviour>) {
+    let path = cmd
+        .split_whitespace()
+        .nth(2)
+        .map_or_else(dump::default_path, str::to_string);
+
+    match dump::write(&path, swarm) {
+        Ok(()) => println!("wrote debug dump to {}", path),
+        Err(err) => println!("can't write debug dump to {}: {}", path, err),
+    }
+}
+
+pub fn handle_chain_watch(swarm: &mut Swarm<BlockchainBehaviour>) {
+    let behaviour = swarm.behaviour_mut();
+    behaviour.watching = !behaviour.watching;
+
+    if behaviour.watching {
+        println!("watching new blocks, run `chain watch` again to stop");
+    } else {
+        println!("stopped watching new blocks");
+    }
+}
+
+// Balance of `address` at `height`, or at the tip.
+pub fn handle_print_balance(
+    address: &Address,
+    height: Option<u64>,
+    swarm: &Swarm<BlockchainBehaviour>,
+) {
+    let blockchain = &swarm.behaviour().blockchain;
+    let height = height.unwrap_or(blockchain.chain.len() as u64 - 1);
+
+    match blockchain.get_balance_at(address.as_str(), height) {
+        Some(balance) => println!("{} at height {}: {}", address, height, balance),
+        None => println!("there is no block at height {}", height),
+    }
+}
+
+pub fn handle_print_state(cmd: &str, swarm: &Swarm<BlockchainBehaviour>) {
+    let Some(Ok(height)) = cmd.split_whitespace().nth(1).map(|height| height.parse()) else {
+        println!("usage: state <height>");
+        return;
+    };
+
+    match swarm.behaviour().blockchain.get_state_at(height) {
+        Some(state) => {
+            let pretty_json = serde_json::to_string_pretty(&state).expect("can jsonify state");
+            println!("{}", pretty_json);
+        }
+        None => println!("there is no block at height {}", height),
+    }
+}
+
+pub fn handle_print_address_stats(cmd: &str, swarm: &Swarm<BlockchainBehaviour>) {
+    let Some(address) = cmd.split_whitespace().nth(1) else {
+        println!("usage: stats <address>");
+        return;
+    };
+
+    let blockchain = &swarm.behaviour().blockchain;
+    match blockchain.address_stats(address) {
+        Some((stats, balance)) => {
+            let summary = serde_json::json!({
+                "address": address,
+                "first_seen": stats.first_seen,
+                "last_seen": stats.last_seen,
+                "total_received": stats.total_received,
+                "total_sent": stats.total_sent,
+                "transaction_count": stats.transaction_count,
+                "balance": balance,
+            });
+            let pretty_json =
+                serde_json::to_string_pretty(&summary).expect("can jsonify address stats");
+            println!("{}", pretty_json);
+        }
+        None if !blockchain.index.settings().address_index => {
+            println!("the address index is disabled, restart without --no-address-index")
+        }
+        None if blockchain.index.pruned_below() > 0 => println!(
+            "address {} has no transactions since block {}, older ones are pruned from the index",
+            address,
+            blockchain.index.pruned_below()
+        ),
+        None => println!("address {} has no transactions", address),
+    }
+}
+
+pub fn handle_wallet(cmd: &str, swarm: &mut Swarm<BlockchainBehaviour>) {
+    if cmd.starts_with("wallet vanity") {
+        handle_vanity(cmd, swarm);
+        return;
+    }
+
+    let behaviour = swarm.behaviour();
+    let Some(keypair) = behaviour.signing_keypair() else {
+        println!("this node has no ed25519 key");
+        return;
+    };
+    let own = Address::from_public_key(&keypair.public);
+
+    // Signed for the sender or the sponsor, whichever this node's address is, to be passed on to
+    // the other one.
+    if let Some(json) = cmd.strip_prefix("wallet sign-transaction ") {
+        match serde_json::from_str::<Transaction>(json) {
+            Ok(mut transaction) => {
+                behaviour.sign_own(&mut transaction);
+                println!(
+                    "{}",
+                    serde_json::to_string(&transaction).expect("can jsonify transaction")
+                );
+            }
+            Err(err) => println!("invalid transaction: {}", err),
+        }
+        return;
+    }
+
+    // The message is everything after the address, spaces included.
+    let mut args = cmd.splitn(4, ' ').skip(1);
+    match (args.next(), args.next(), args.next()) {
+        (Some("address"), None, None) => println!("{}", own),
+        (Some("sign-message"), Some(address), Some(text)) if !text.is_empty() => {
+            if address != own.as_str() {
+                println!("this node has no key for {}, only for {}", address, own);
+                return;
+            }
+            println!("{}", message::sign(&keypair, text));
+        }
+        _ => println!(
+            "usage: wallet address | wallet sign-message <address> <message> | wallet \
+             sign-transaction <json> | wallet vanity ..."
+        ),
+    }
+}
+
+fn handle_vanity(cmd: &str, swarm: &mut Swarm<BlockchainBehaviour>) {
+    let behaviour = swarm.behaviour_mut();
+    let running = behaviour.vanity.as_ref().filter(|search| !search.is_done());
+
+    let mut args = cmd.split_whitespace().skip(2);
+    match (args.next(), args.next(), running) {
+        (Some("status"), None, Some(search)) => println!("{}", search),
+        (Some("cancel"), None, Some(search)) => search.cancel(),
+        (Some("status" | "cancel"), None, None) => println!("no vanity search is running"),
+        (Some(_), _, Some(search)) => println!("{} is still running", search),
+        (Some(prefix), seed, None) => {
+            // Seeds are given as 64 hex digits, like the ones searches report, or any text.
+            let seed = match seed {
+                Some(seed) => seed
+                    .parse::<Hash256>()
+                    .unwrap_or_else(|_| Hash256::digest(seed)),
+                None => Hash256(rand::random()),
+            };
+
+            match VanitySearch::start(prefix, seed.0, behaviour.miner_settings.clone()) {
+                Ok(search) => {
+                    println!(
+                        "searching for {}{}, ~{:.0} attempts expected",
+                        vanity::ADDRESS_START,
+                        prefix,
+                        search.expected_attempts()
+                    );
+                    behaviour.vanity = Some(search);
+                }
+                Err(err) => println!("{}", err),
+            }
+        }
+        _ => println!("usage: wallet vanity <prefix> [seed] | wallet vanity status | cancel"),
+    }
+}
+
+pub fn handle_print_transaction(cmd: &str, swarm: &Swarm<BlockchainBehaviour>) {
+    let Some(id) = parse_hash_argument(cmd, "usage: tx <transaction id>") else {
+        return;
+    };
+
+    let blockchain = &swarm.behaviour().blockchain;
+    match blockchain.find_transaction(&id) {
+        Some((transaction, links)) => {
+            let summary = serde_json::json!({
+                "id": id,
+                "height": links.height,
+                "transaction": transaction,
+                "inputs": links.inputs,
+                "spent_by": links.spent_by,
+            });
+            let pretty_json =
+                serde_json::to_string_pretty(&summary).expect("can jsonify transaction");
+            println!("{}", pretty_json);
+        }
+        None if !blockchain.index.settings().tx_index => {
+            println!("the transaction index is disabled, restart without --no-tx-index")
+        }
+        None if blockchain.index.pruned_below() > 0 => println!(
+            "transaction {} is not in the chain since block {}, older ones are pruned from the index",
+            id,
+            blockchain.index.pruned_below()
+        ),
+        None => println!("transaction {} is not in the chain", id),
+    }
+}
+
+pub fn handle_prove_transaction(cmd: &str, swarm: &Swarm<BlockchainBehaviour>) {
+    if let Some(transaction_id) = cmd.strip_prefix("prove") {
+        let transaction_id: Hash256 = match transaction_id.trim().parse() {
+            Ok(transaction_id) => transaction_id,
+            Err(err) => {
+                println!("can't parse transaction id: {}", err);
+                return;
+            }
+        };
+
+        match accumulator::prove(&swarm.behaviour().blockchain.chain, &transaction_id) {
+            Some(proof) => {
+                let json = serde_json::to_string(&proof).expect("can jsonify proof");
+                println!("{}", json);
+            }
+            None => println!("transaction {} is not in the chain", transaction_id),
+        }
+    }
+}
+
+pub fn handle_verify_proof(cmd: &str, swarm: &Swarm<BlockchainBehaviour>) {
+    if let Some(data) = cmd.strip_prefix("verify") {
+        let proof: InclusionProof = match serde_json::from_str(data) {
+            Ok(proof) => proof,
+            Err(err) => {
+                println!("can't parse proof: {}", err);
+                return;
+            }
+        };
+
+        let latest_block = swarm
+            .behaviour()
+            .blockchain
+            .chain
+            .last()
+            .expect("there is at least one block");
+
+        if latest_block.header.accumulator.verify(&proof) {
+            println!("transaction {} is in the chain", proof.transaction_id);
+        } else {
+            println!("proof for {} is invalid", proof.transaction_id);
+        }
+    }
+}
+
+pub fn handle_print_checkpoints(swarm: &Swarm<BlockchainBehaviour>) {
+    println!("local checkpoints");
+
+    for checkpoint in swarm.behaviour().checkpoints.iter() {
+        println!(
+            "#{} {} state root: {}",
+            checkpoint.height, checkpoint.hash, checkpoint.state_root
+        );
+    }
+}
+
+pub fn handle_print_metrics(cmd: &str, swarm: &Swarm<BlockchainBehaviour>) {
+    let window = match cmd.split_whitespace().nth(1).map(|seconds| seconds.parse()) {
+        Some(Ok(seconds)) => Duration::from_secs(seconds),
+        Some(Err(_)) => {
+            println!("usage: metrics [seconds]");
+            return;
+        }
+        None => Duration::from_secs(u64::MAX),
+    };
+
+    println!("timestamp height mempool peers hashrate");
+    for sample in swarm.behaviour().metrics.window(window) {
+        println!(
+            "{} {} {} {} {:.0}/s",
+            sample.timestamp, sample.height, sample.mempool, sample.peers, sample.hashrate
+        );
+    }
+}
+
+pub fn handle_print_epochs(swarm: &Swarm<BlockchainBehaviour>) {
+    println!("local epoch summaries");
+
+    for summary in swarm.behaviour().epochs.iter() {
+        println!(
+            "epoch {} #{}..#{} {} work: {} txs: {} state root: {}",
+            summary.epoch,
+            summary.start,
+            summary.end,
+            summary.hash,
+            summary.work,
+            summary.transactions,
+            summary.state_root
+        );
+    }
+}
+
+pub fn handle_request_epochs(cmd: &str, swarm: &mut Swarm<BlockchainBehaviour>) {
+    let mut args = cmd.split_whitespace().skip(1);
+
+    let peer = match args.next().map(|peer| peer.parse::<PeerId>()) {
+        Some(Ok(peer)) => peer,
+        _ => {
+            println!("usage: epochs <peer> [from epoch]");
+            return;
+        }
+    };
+    let from = match args.next().map(|from| from.parse()) {
+        Some(Ok(from)) => from,
+        Some(Err(_)) => {
+            println!("usage: epochs <peer> [from epoch]");
+            return;
+        }
+        None => 1,
+    };
+
+    swarm
+        .behaviour_mut()
+        .epoch_sync
+        .send_request(&peer, EpochRequest { from });
+    println!("requested epoch summaries from {}", peer);
+}
+
+pub fn handle_request_history(cmd: &str, swarm: &mut Swarm<BlockchainBehaviour>) {
+    let mut args = cmd.split_whitespace().skip(1);
+
+    let peer = match args.next().map(|peer| peer.parse::<PeerId>()) {
+        Some(Ok(peer)) => peer,
+        _ => {
+            println!("usage: sample <peer> [blocks]");
+            return;
+        }
+    };
+    let samples = match args.next().map(|samples| samples.parse()) {
+        Some(Ok(samples)) => samples,
+        Some(Err(_)) => {
+            println!("usage: sample <peer> [blocks]");
+            return;
+        }
+        None => DEFAULT_HISTORY_SAMPLES,
+    };
+
+    let samples = samples.min(MAX_HISTORY_SAMPLES);
+    let behaviour = swarm.behaviour_mut();
+    let request_id = behaviour
+        .history_sync
+        .send_request(&peer, HistoryRequest { samples });
+    behaviour.history_requests.insert(request_id, samples);
+    println!("requested history proof from {}", peer);
+}
+
+pub fn handle_request_checkpoint(cmd: &str, swarm: &mut Swarm<BlockchainBehaviour>) {
+    let mut args = cmd.split_whitespace().skip(1);
+
+    let peer = match args.next().map(|peer| peer.parse::<PeerId>()) {
+        Some(Ok(peer)) => peer,
+        _ => {
+            println!("usage: checkpoint <peer> [height]");
+            return;
+        }
+    };
+    let height = match args.next().map(|height| height.parse()) {
+        Some(Ok(height)) => Some(height),
+        Some(Err(_)) => {
+            println!("usage: checkpoint <peer> [height]");
+            return;
+        }
+        None => None,
+    };
+
+    swarm
+        .behaviour_mut()
+        .checkpoint_sync
+        .send_request(&peer, CheckpointRequest { height });
+    println!("requested checkpoint from {}", peer);
+}
+
+pub fn handle_mine_settings(cmd: &str, swarm: &Swarm<BlockchainBehaviour>) {
+    let settings = &swarm.behaviour().miner_settings;
+    let mut args = cmd.split_whitespace().skip(1);
+
+    match (args.next(), args.next().map(|value| value.parse::<usize>())) {
+        (Some("threads"), Some(Ok(threads))) => settings.set_threads(threads),
+        (Some("throttle"), Some(Ok(percent))) => settings.set_throttle(percent),
+        (None, None) => {}
+        _ => {
+            println!("usage: mine [threads <count> | throttle <percent>]");
+            return;
+        }
+    }
+
+    println!(
+        "mining with {} threads at {}% throttle",
+        settings.threads(),
+        settings.throttle()
+    );
+}
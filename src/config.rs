@@ -0,0 +1,241 @@
+// Node configuration file, so nodes set up the same way don't need long command lines. The node
+// reads `node.toml` from the working directory if there is one, or the file given with
+// `--config <path>`:
+//
+//     data_dir = "node1"
+//
+//     [network]
+//     port = 4001
+//     bootstrap = ["/ip4/10.0.0.1/tcp/4001/p2p/12D3KooW..."]
+//     external_addresses = ["/ip4/203.0.113.7/tcp/4001"]
+//
+//     [mining]
+//     mine = true
+//     difficulty = 3
+//     threads = 4
+//     throttle = 50
+//     payout = "12D3KooW..."
+//
+//     [rpc]
+//     bind = "127.0.0.1"
+//     port = 8545
+//
+// Every setting stands for a command line option, see `NodeConfig::args`, and options given on
+// the command line override the file. Settings left out keep the defaults of their options.
+
+use std::{fs, net::IpAddr, path::Path};
+
+use toml_edit::{Document, TableLike};
+
+// File read unless `--config` names another one.
+pub const DEFAULT_CONFIG_FILE: &str = "node.toml";
+
+// `NodeConfig` Settings of the node as the configuration file gives them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeConfig {
+    pub data_dir: Option<String>,
+    pub network: NetworkConfig,
+    pub mining: MiningConfig,
+    pub rpc: RpcConfig,
+}
+
+// `NetworkConfig` Where the node listens and how it joins the network, see `discovery`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetworkConfig {
+    pub port: Option<u16>,
+    pub bootstrap: Vec<String>,
+    pub external_addresses: Vec<String>,
+}
+
+// `MiningConfig` Whether and how the node mines, see `miner` and `schedule`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MiningConfig {
+    pub mine: bool,
+    pub difficulty: Option<usize>,
+    pub threads: Option<usize>,
+    pub throttle: Option<usize>,
+    pub payout: Option<String>,
+}
+
+// `RpcConfig` Where the JSON-RPC server listens, see `rpc`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RpcConfig {
+    pub bind: Option<IpAddr>,
+    pub port: Option<u16>,
+}
+
+impl NodeConfig {
+    // Read the file `--config <path>` names, or `node.toml` if there is one. The defaults if
+    // there is no file.
+    pub fn find(path: Option<&str>) -> Result<Self, String> {
+        match path {
+            Some(path) => NodeConfig::load(path),
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                NodeConfig::load(DEFAULT_CONFIG_FILE)
+            }
+            None => Ok(NodeConfig::default()),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text =
+            fs::read_to_string(path).map_err(|err| format!("can't read {}: {}", path, err))?;
+        NodeConfig::parse(&text).map_err(|err| format!("can't parse {}: {}", path, err))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let document: Document = text.parse().map_err(|err| format!("{}", err))?;
+        let root = document.as_table();
+        check_keys(root, "", &["data_dir", "network", "mining", "rpc"])?;
+
+        let mut config = NodeConfig {
+            data_dir: string(root, "", "data_dir")?,
+            ..NodeConfig::default()
+        };
+        if let Some(network) = table(root, "network")? {
+            check_keys(
+                network,
+                "network.",
+                &["port", "bootstrap", "external_addresses"],
+            )?;
+            config.network = NetworkConfig {
+                port: number(network, "network.", "port")?,
+                bootstrap: strings(network, "network.", "bootstrap")?,
+                external_addresses: strings(network, "network.", "external_addresses")?,
+            };
+        }
+        if let Some(mining) = table(root, "mining")? {
+            check_keys(
+                mining,
+                "mining.",
+                &["mine", "difficulty", "threads", "throttle", "payout"],
+            )?;
+            config.mining = MiningConfig {
+                mine: match mining.get("mine") {
+                    None => false,
+                    Some(item) => item
+                        .as_bool()
+                        .ok_or_else(|| "mining.mine expects true or false".to_string())?,
+                },
+                difficulty: number(mining, "mining.", "difficulty")?,
+                threads: number(mining, "mining.", "threads")?,
+                throttle: number(mining, "mining.", "throttle")?,
+                payout: string(mining, "mining.", "payout")?,
+            };
+        }
+        if let Some(rpc) = table(root, "rpc")? {
+            check_keys(rpc, "rpc.", &["bind", "port"])?;
+            config.rpc = RpcConfig {
+                bind: string(rpc, "rpc.", "bind")?
+                    .map(|bind| {
+                        bind.parse()
+                            .map_err(|_| "rpc.bind expects an IP address".to_string())
+                    })
+                    .transpose()?,
+                port: number(rpc, "rpc.", "port")?,
+            };
+        }
+
+        Ok(config)
+    }
+
+    // The command line options the settings stand for.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let mut push = |flag: &str, value: String| args.extend([flag.to_string(), value]);
+
+        if let Some(data_dir) = &self.data_dir {
+            push("--data-dir", data_dir.clone());
+        }
+        if let Some(port) = self.network.port {
+            push("--port", port.to_string());
+        }
+        for peer in self.network.bootstrap.iter() {
+            push("--bootstrap", peer.clone());
+        }
+        for address in self.network.external_addresses.iter() {
+            push("--external-address", address.clone());
+        }
+        if let Some(difficulty) = self.mining.difficulty {
+            push("--difficulty", difficulty.to_string());
+        }
+        if let Some(threads) = self.mining.threads {
+            push("--mine-threads", threads.to_string());
+        }
+        if let Some(throttle) = self.mining.throttle {
+            push("--mine-throttle", throttle.to_string());
+        }
+        if let Some(payout) = &self.mining.payout {
+            push("--payout", payout.clone());
+        }
+        if let Some(bind) = self.rpc.bind {
+            push("--rpc-bind", bind.to_string());
+        }
+        if let Some(port) = self.rpc.port {
+            push("--rpc-port", port.to_string());
+        }
+        if self.mining.mine {
+            args.push("--mine".to_string());
+        }
+
+        args
+    }
+}
+
+fn check_keys(table: &dyn TableLike, prefix: &str, known: &[&str]) -> Result<(), String> {
+    match table.iter().find(|(key, _)| !known.contains(key)) {
+        Some((key, _)) => Err(format!("unknown setting {}{}", prefix, key)),
+        None => Ok(()),
+    }
+}
+
+fn table<'a>(table: &'a dyn TableLike, key: &str) -> Result<Option<&'a dyn TableLike>, String> {
+    table
+        .get(key)
+        .map(|item| {
+            item.as_table_like()
+                .ok_or_else(|| format!("{} expects a table", key))
+        })
+        .transpose()
+}
+
+fn string(table: &dyn TableLike, prefix: &str, key: &str) -> Result<Option<String>, String> {
+    table
+        .get(key)
+        .map(|item| {
+            item.as_str()
+                .map(str::to_string)
+                .ok_or_else(|| format!("{}{} expects a string", prefix, key))
+        })
+        .transpose()
+}
+
+fn strings(table: &dyn TableLike, prefix: &str, key: &str) -> Result<Vec<String>, String> {
+    let Some(item) = table.get(key) else {
+        return Ok(Vec::new());
+    };
+
+    item.as_array()
+        .and_then(|values| {
+            values
+                .iter()
+                .map(|value| value.as_str().map(str::to_string))
+                .collect()
+        })
+        .ok_or_else(|| format!("{}{} expects a list of strings", prefix, key))
+}
+
+fn number<T: TryFrom<i64>>(
+    table: &dyn TableLike,
+    prefix: &str,
+    key: &str,
+) -> Result<Option<T>, String> {
+    table
+        .get(key)
+        .map(|item| {
+            item.as_integer()
+                .and_then(|value| T::try_from(value).ok())
+                .ok_or_else(|| format!("{}{} is out of range or not a number", prefix, key))
+        })
+        .transpose()
+}
@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+
+use libp2p::{PeerId, identity};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    miner::{SearchJob, Work, WorkResult},
+    models::{address::Address, block::Block, hash::Hash256},
+};
+
+// Number of nonces in one work assignment, volunteers don't search larger ranges.
+pub const ASSIGNMENT_SIZE: u64 = 1 << 20;
+
+// Announces that a peer joins or leaves cooperative mining, along with the address its share
+// of the rewards is paid to. Signed by the peer, so nobody else can redirect its share.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CoopVolunteer {
+    pub address: Address,
+    pub available: bool,
+    // Protobuf encoding of the public key of the peer.
+    pub signer: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl CoopVolunteer {
+    pub fn new(keys: &identity::Keypair, address: Address, available: bool) -> Self {
+        let mut volunteer = CoopVolunteer {
+            address,
+            available,
+            signer: keys.public().into_protobuf_encoding(),
+            signature: Vec::new(),
+        };
+        volunteer.signature = keys
+            .sign(&volunteer.signing_data())
+            .expect("can sign volunteer");
+
+        volunteer
+    }
+
+    // Data covered by the signature.
+    pub fn signing_data(&self) -> Vec<u8> {
+        serde_json::to_vec(&(&self.address, self.available)).expect("can jsonify volunteer")
+    }
+
+    // Check that the announcement is signed by `peer`.
+    pub fn is_signed_by(&self, peer: &PeerId) -> bool {
+        identity::PublicKey::from_protobuf_encoding(&self.signer).is_ok_and(|key| {
+            PeerId::from(key.clone()) == *peer && key.verify(&self.signing_data(), &self.signature)
+        })
+    }
+}
+
+// A nonce range of a coordinator's block template, assigned to one volunteer.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CoopAssignment {
+    pub job_id: Hash256,
+    pub worker: String,
+    pub work: Work,
+}
+
+// A volunteer's answer to a `CoopAssignment`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CoopResult {
+    pub job_id: Hash256,
+    pub worker: String,
+    pub result: WorkResult,
+}
+
+// `CoopJob` A block template whose nonce space is being searched by volunteers.
+#[derive(Debug)]
+pub struct CoopJob {
+    pub id: Hash256,
+    pub block: Block,
+    // Start of the nonce range handed out next.
+    pub next_nonce: u64,
+}
+
+impl CoopJob {
+    // Take the next unassigned nonce range, `None` once the nonce space is exhausted.
+    pub fn next_range(&mut self) -> Option<(u64, u64)> {
+        let start = self.next_nonce;
+        if start == u64::MAX {
+            return None;
+        }
+
+        let end = start.saturating_add(ASSIGNMENT_SIZE);
+        self.next_nonce = end;
+        Some((start, end))
+    }
+}
+
+// `CoopSearched` The outcome of the search `search` this node ran for a coordinator.
+#[derive(Debug)]
+pub struct CoopSearched {
+    pub search: u64,
+    pub result: CoopResult,
+}
+
+// `Cooperation` Opt-in cooperative mining. Volunteers search the nonce ranges a coordinator
+// assigns them and are credited in the coinbase of the block they help mine.
+#[derive(Debug, Default)]
+pub struct Cooperation {
+    // Whether this node accepts work assignments.
+    pub volunteering: bool,
+    // Payout addresses of known volunteers by peer id.
+    pub volunteers: BTreeMap<String, Address>,
+    // Template this node is coordinating.
+    pub job: Option<CoopJob>,
+    // Assignment this node is searching in the background.
+    pub search: Option<SearchJob>,
+    // Searches started so far, the next search gets this id.
+    pub searches: u64,
+}
+
+impl Cooperation {
+    // Give up the assignment being searched, its coordinator hands the range to someone else.
+    pub fn cancel_search(&mut self, reason: &str) {
+        if let Some(search) = self.search.take() {
+            search.cancel();
+            println!("stopped searching assignment {}: {}", search.id, reason);
+        }
+    }
+}
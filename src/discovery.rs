@@ -0,0 +1,104 @@
+// Peer discovery beyond the local network over a Kademlia DHT, for nodes mDNS can't find.
+// Nodes join through the peers given with `--bootstrap <multiaddr>/p2p/<peer id>` (or
+// `--bootstrap-peer`), which may be repeated, and look themselves up again every `--kad-refresh
+// <seconds>` to keep their routing table fresh. The table is saved along with the chain, so a
+// restarted node finds its peers without the bootstrap nodes.
+//
+// Peers reachable from elsewhere listen on a known port with `--p2p-port <port>` (or `--port`),
+// every further chain on the port after, and announce the address others reach them at with
+// `--external-address <multiaddr>` when they are behind a NAT.
+
+use std::time::Duration;
+
+use libp2p::{Multiaddr, PeerId, multiaddr::Protocol};
+use serde::{Deserialize, Serialize};
+
+use crate::models::schema::{self, Migration};
+
+// Time between two lookups refreshing the routing table unless `--kad-refresh` says otherwise.
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+
+// Migrations of the routing table file, see `schema`.
+const MIGRATIONS: &[Migration] = &[];
+
+// `DiscoverySettings` How the node joins the DHT and where it can be reached.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoverySettings {
+    pub bootstrap: Vec<(PeerId, Multiaddr)>,
+    // Port of the first chain, 0 for any free one.
+    pub port: u16,
+    pub external_addresses: Vec<Multiaddr>,
+    pub refresh: Duration,
+}
+
+impl Default for DiscoverySettings {
+    fn default() -> Self {
+        DiscoverySettings {
+            bootstrap: Vec::new(),
+            port: 0,
+            external_addresses: Vec::new(),
+            refresh: DEFAULT_REFRESH_INTERVAL,
+        }
+    }
+}
+
+impl DiscoverySettings {
+    // Address the chain at `position` among the hosted ones listens on.
+    pub fn listen_address(&self, position: usize) -> Multiaddr {
+        let port = match self.port {
+            0 => 0,
+            port => port.saturating_add(position as u16),
+        };
+        format!("/ip4/0.0.0.0/tcp/{}", port)
+            .parse()
+            .expect("listen address is valid")
+    }
+}
+
+// Split `value`, a multiaddr ending in `/p2p/<peer id>`, into the peer and its address.
+pub fn peer_address(value: &str) -> Option<(PeerId, Multiaddr)> {
+    let mut address: Multiaddr = value.parse().ok()?;
+    match address.pop()? {
+        Protocol::P2p(hash) => Some((PeerId::from_multihash(hash).ok()?, address)),
+        _ => None,
+    }
+}
+
+// `RoutingEntry` A peer of the routing table along with the addresses it was reached at.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoutingEntry {
+    pub peer: String,
+    pub addresses: Vec<String>,
+}
+
+// Peers of the routing table saved to `path`, skipping the ones that don't parse.
+pub fn load_routing_table(path: &str) -> Vec<(PeerId, Multiaddr)> {
+    let entries: Vec<RoutingEntry> = match schema::load_json(path, MIGRATIONS) {
+        Ok(Some(data)) => serde_json::from_value(data).unwrap_or_else(|err| {
+            println!("can't parse routing table in {}: {}", path, err);
+            Vec::new()
+        }),
+        Ok(None) => Vec::new(),
+        Err(err) => {
+            println!("can't load routing table from {}: {}", path, err);
+            Vec::new()
+        }
+    };
+
+    entries
+        .into_iter()
+        .filter_map(|entry| Some((entry.peer.parse().ok()?, entry.addresses)))
+        .flat_map(|(peer, addresses)| {
+            addresses
+                .into_iter()
+                .filter_map(move |address| Some((peer, address.parse().ok()?)))
+        })
+        .collect()
+}
+
+pub fn save_routing_table(path: &str, entries: &[RoutingEntry]) {
+    let version = MIGRATIONS.len() as u32;
+    if let Err(err) = schema::save_json(path, version, &entries) {
+        println!("can't save routing table to {}: {}", path, err);
+    }
+}
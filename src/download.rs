@@ -0,0 +1,503 @@
+// Headers-first block download. A node syncing with a peer first sends it a locator, hashes of
+// its chain thinning out towards the genesis block, and the peer answers with the headers of its
+// blocks after the highest of them it has, `MAX_HEADERS` at a time. The last block the two chains
+// share is the one below the first header. Once the headers are in and their work beats the
+// local blocks after that ancestor, the block bodies are fetched in batches of `BATCH_SIZE`.
+//
+// Batches are spread over every connected peer, at most `MAX_REQUESTS_PER_PEER` at a time each,
+// and put back in order as they come. A peer whose request times out or that sends blocks other
+// than the headers announced is dropped from the download, and its batch is asked for again from
+// the others.
+//
+// Once all headers are in they are saved along with the ancestor, so a node restarted during the
+// download fetches the blocks after the ones its store holds from the peers it connects to next,
+// instead of starting over. Batches that were asked for or downloaded and not connected are
+// asked for again, as are the blocks of a fork, which were only staged.
+//
+// The download, the validation and the store are pipelined: batches past the ones being
+// connected are asked for ahead of time, and every block extending the local tip is validated and
+// stored as soon as it connects. At most `MAX_BATCHES_AHEAD` batches are asked for or waiting to
+// be connected, so a long chain is never held in memory twice while it syncs. Blocks of a fork
+// are validated batch by batch on top of the block the fork starts from and staged in the store,
+// see `ForkCheck`, until the fork is complete, when it is compared with the local chain as a
+// whole.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use libp2p::{PeerId, request_response::RequestId};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    block::{Block, BlockHeader},
+    blockchain::{Blockchain, ForkCheck},
+    consensus::PowAlgorithm,
+    hash::Hash256,
+    schema::{self, Migration},
+    work::Work,
+};
+
+// Blocks asked for in one request.
+pub const BATCH_SIZE: u64 = 100;
+// Batches asked for or downloaded and not connected yet.
+pub const MAX_BATCHES_AHEAD: usize = 8;
+// Batches asked of one peer at a time.
+pub const MAX_REQUESTS_PER_PEER: usize = 2;
+// Headers sent in one response.
+pub const MAX_HEADERS: u64 = 2000;
+// Hashes of the tip and the blocks below it in a locator before it starts skipping blocks.
+const LOCATOR_DENSE: usize = 10;
+
+// Migrations of the download file, see `schema`.
+const MIGRATIONS: &[Migration] = &[];
+
+// Ask for the headers after the first block of `locator` the peer has.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HeadersRequest {
+    pub locator: Vec<Hash256>,
+}
+
+// At most `MAX_HEADERS` headers asked for, along with the height of the peer's tip.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HeadersResponse {
+    pub headers: Vec<BlockHeader>,
+    pub tip: u64,
+}
+
+// Ask for the blocks at `from` and the `count - 1` after it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct BlocksRequest {
+    pub from: u64,
+    pub count: u64,
+}
+
+// The blocks asked for that the peer has.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BlocksResponse {
+    pub blocks: Vec<Arc<Block>>,
+}
+
+// `SavedDownload` What a restarted node needs to resume a download: the headers of the peer
+// after the ancestor.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedDownload {
+    pub peer: String,
+    pub ancestor: u64,
+    pub ancestor_hash: Hash256,
+    pub headers: Vec<BlockHeader>,
+}
+
+// The download saved to `path`, if there is one.
+pub fn load_download(path: &str) -> Option<SavedDownload> {
+    match schema::load_json(path, MIGRATIONS) {
+        Ok(Some(data)) => serde_json::from_value(data)
+            .map_err(|err| println!("can't parse download in {}: {}", path, err))
+            .ok(),
+        Ok(None) => None,
+        Err(err) => {
+            println!("can't load download from {}: {}", path, err);
+            None
+        }
+    }
+}
+
+pub fn save_download(path: &str, saved: &SavedDownload) {
+    let version = MIGRATIONS.len() as u32;
+    if let Err(err) = schema::save_json(path, version, saved) {
+        println!("can't save download to {}: {}", path, err);
+    }
+}
+
+// Forget the download saved to `path`, as it finished or can't be resumed.
+pub fn remove_download(path: &str) {
+    match std::fs::remove_file(path) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => println!("can't remove download {}: {}", path, err),
+    }
+}
+
+// Hashes of `chain` to find the last block it shares with a peer's by: the tip and the blocks
+// right below it, then every second, fourth, eighth... block, and the genesis block last.
+pub fn locator(chain: &[Arc<Block>]) -> Vec<Hash256> {
+    let mut locator = Vec::new();
+    let mut height = chain.len() - 1;
+    let mut step = 1;
+
+    while height > 0 {
+        locator.push(chain[height].header.hash);
+        if locator.len() >= LOCATOR_DENSE {
+            step *= 2;
+        }
+        height = height.saturating_sub(step);
+    }
+    locator.push(chain[0].header.hash);
+    locator
+}
+
+// `BlockDownload` A download of the blocks of `peer` after the last one it shares with the local
+// chain, the headers first. The blocks come from `peer` and any other peer that has them.
+#[derive(Debug)]
+pub struct BlockDownload {
+    pub peer: PeerId,
+    // Peers blocks are asked of.
+    peers: Vec<PeerId>,
+    // Height and hash of the last block the local chain shares with the peer's, once known.
+    ancestor: Option<(u64, Hash256)>,
+    // Headers of the peer's blocks after the ancestor.
+    headers: Vec<BlockHeader>,
+    headers_done: bool,
+    // Check of the blocks of a fork, which replace the local ones only once all of them were
+    // downloaded.
+    pub branch: Option<ForkCheck>,
+    // The download was saved before a restart, so the peer may have more blocks by now.
+    pub resumed: bool,
+    // Height of the next block to connect, and of the next one to ask for.
+    next_connect: u64,
+    next_request: u64,
+    requested: HashMap<RequestId, (PeerId, BlocksRequest)>,
+    // Batches to ask for again, keyed by the height they start at.
+    retry: BTreeMap<u64, BlocksRequest>,
+    // Batches waiting for the ones below them, keyed by the height they start at.
+    downloaded: BTreeMap<u64, Vec<Arc<Block>>>,
+}
+
+impl BlockDownload {
+    // Download the headers from `peer` and the blocks from it and `others`.
+    pub fn new(peer: PeerId, others: impl IntoIterator<Item = PeerId>) -> Self {
+        let mut peers = vec![peer];
+        peers.extend(others.into_iter().filter(|other| *other != peer));
+
+        BlockDownload {
+            peer,
+            peers,
+            ancestor: None,
+            headers: Vec::new(),
+            headers_done: false,
+            branch: None,
+            resumed: false,
+            next_connect: 0,
+            next_request: 0,
+            requested: HashMap::new(),
+            retry: BTreeMap::new(),
+            downloaded: BTreeMap::new(),
+        }
+    }
+
+    // Resume `saved` on top of `blockchain`, the blocks the store kept. Returns `None` unless
+    // the chain still holds the ancestor.
+    pub fn resume(saved: SavedDownload, blockchain: &Blockchain) -> Option<Self> {
+        let chain = &blockchain.chain;
+        let ancestor = chain.get(saved.ancestor as usize)?;
+        if ancestor.header.hash != saved.ancestor_hash {
+            return None;
+        }
+
+        let mut download = BlockDownload::new(saved.peer.parse().ok()?, []);
+        download.peers.clear();
+        download.set_ancestor(ancestor);
+        download.headers = saved.headers;
+        download.headers_done = true;
+        download.resumed = true;
+
+        // Blocks stored after the ancestor are either the peer's, connected before the restart,
+        // or the local side of a fork.
+        let stored = &chain[saved.ancestor as usize + 1..];
+        let connected = stored
+            .iter()
+            .zip(download.headers.iter())
+            .take_while(|(block, header)| block.header.hash == header.hash)
+            .count();
+        if connected < stored.len() {
+            download.branch = Some(blockchain.check_fork(saved.ancestor)?);
+        } else {
+            download.next_connect += connected as u64;
+            download.next_request += connected as u64;
+        }
+        Some(download)
+    }
+
+    // The download to save so it can be resumed, once all headers are in.
+    pub fn saved(&self) -> Option<SavedDownload> {
+        let (ancestor, ancestor_hash) = self.ancestor.filter(|_| self.headers_done)?;
+        Some(SavedDownload {
+            peer: self.peer.to_string(),
+            ancestor,
+            ancestor_hash,
+            headers: self.headers.clone(),
+        })
+    }
+
+    // Ask `peers` for blocks too, e.g. the ones connected after a restart.
+    pub fn add_peers(&mut self, peers: impl IntoIterator<Item = PeerId>) {
+        for peer in peers {
+            if !self.peers.contains(&peer) {
+                self.peers.push(peer);
+            }
+        }
+    }
+
+    // Height of the last block the local chain shares with the peer's.
+    pub fn ancestor(&self) -> Option<u64> {
+        self.ancestor.map(|(height, _)| height)
+    }
+
+    // Set the last block the local chain shares with the peer's, the one its headers follow.
+    pub fn set_ancestor(&mut self, ancestor: &Block) {
+        self.ancestor = Some((ancestor.header.index, ancestor.header.hash));
+        self.next_connect = ancestor.header.index + 1;
+        self.next_request = ancestor.header.index + 1;
+    }
+
+    // Take in the next headers of the peer. Headers have to follow the ancestor and each other
+    // and prove the work they claim, the bodies are checked against them once they come.
+    pub fn add_headers(
+        &mut self,
+        headers: Vec<BlockHeader>,
+        pow: PowAlgorithm,
+    ) -> Result<(), String> {
+        let engine = pow.engine();
+        let mut previous = self
+            .headers
+            .last()
+            .map(|header| (header.index, header.hash))
+            .or(self.ancestor)
+            .ok_or("the ancestor isn't known")?;
+
+        for header in headers.iter() {
+            if header.index != previous.0 + 1 || header.previous_hash != previous.1 {
+                return Err(format!(
+                    "header {} doesn't follow the one before",
+                    header.index
+                ));
+            }
+            if engine.hash(header) != header.hash
+                || !header.is_mined(Work::from_difficulty(header.difficulty))
+            {
+                return Err(format!("header {} isn't mined", header.index));
+            }
+            previous = (header.index, header.hash);
+        }
+
+        self.headers_done = (headers.len() as u64) < MAX_HEADERS;
+        self.headers.extend(headers);
+        Ok(())
+    }
+
+    // Locator asking for the headers after the ones received so far.
+    pub fn next_locator(&self) -> Option<Vec<Hash256>> {
+        match self.headers_done {
+            true => None,
+            false => self.headers.last().map(|header| vec![header.hash]),
+        }
+    }
+
+    // Work of the peer's blocks after the ancestor, by the difficulty their headers declare.
+    pub fn work(&self) -> Work {
+        self.headers
+            .iter()
+            .map(|header| Work::from_difficulty(header.difficulty))
+            .sum()
+    }
+
+    // Height of the peer's last block, as far as its headers came.
+    pub fn tip(&self) -> u64 {
+        self.ancestor().unwrap_or(0) + self.headers.len() as u64
+    }
+
+    fn header(&self, height: u64) -> Option<&BlockHeader> {
+        let ancestor = self.ancestor()?;
+        self.headers.get(height.checked_sub(ancestor + 1)? as usize)
+    }
+
+    // Peers blocks are still asked of.
+    pub fn peers(&self) -> &[PeerId] {
+        &self.peers
+    }
+
+    // The next batch to ask for once all headers came and the peer to ask, the one with the
+    // fewest requests, if there is room for it. Batches to ask for again come first.
+    pub fn next_request(&self) -> Option<(PeerId, BlocksRequest)> {
+        let ahead = self.requested.len() + self.downloaded.len();
+        if !self.headers_done || ahead >= MAX_BATCHES_AHEAD {
+            return None;
+        }
+        let (peer, requests) = self
+            .peers
+            .iter()
+            .map(|peer| (*peer, self.requests_of(peer)))
+            .min_by_key(|(_, requests)| *requests)?;
+        if requests >= MAX_REQUESTS_PER_PEER {
+            return None;
+        }
+
+        let request = match self.retry.values().next() {
+            Some(request) => *request,
+            None if self.next_request <= self.tip() => BlocksRequest {
+                from: self.next_request,
+                count: BATCH_SIZE.min(self.tip() - self.next_request + 1),
+            },
+            None => return None,
+        };
+        Some((peer, request))
+    }
+
+    fn requests_of(&self, peer: &PeerId) -> usize {
+        self.requested
+            .values()
+            .filter(|(requested, _)| requested == peer)
+            .count()
+    }
+
+    pub fn requested(&mut self, id: RequestId, peer: PeerId, request: BlocksRequest) {
+        self.requested.insert(id, (peer, request));
+        if self.retry.remove(&request.from).is_none() {
+            self.next_request = request.from + request.count;
+        }
+    }
+
+    // Take in the response to request `id`. Returns `false` if the download didn't ask for it,
+    // and an error unless the peer sent the blocks its headers announced, in which case it is
+    // dropped and the batch asked for again.
+    pub fn downloaded(&mut self, id: &RequestId, response: BlocksResponse) -> Result<bool, String> {
+        let Some((peer, request)) = self.requested.remove(id) else {
+            return Ok(false);
+        };
+
+        let mismatch = match response.blocks.len() as u64 == request.count {
+            true => (request.from..)
+                .zip(response.blocks.iter())
+                .find(|(height, block)| {
+                    self.header(*height) != Some(&block.header) || !block.is_merkle_root_valid()
+                })
+                .map(|(height, _)| format!("block {} doesn't match its header", height)),
+            false => Some(format!(
+                "sent {} of the {} blocks from {}",
+                response.blocks.len(),
+                request.count,
+                request.from
+            )),
+        };
+        if let Some(mismatch) = mismatch {
+            self.drop_peer(&peer, request);
+            return Err(mismatch);
+        }
+
+        self.downloaded.insert(request.from, response.blocks);
+        Ok(true)
+    }
+
+    // Request `id` failed, e.g. as the peer stalled. Drops the peer and asks for the batch again,
+    // returns `false` if the download didn't ask for it.
+    pub fn failed(&mut self, id: &RequestId) -> bool {
+        match self.requested.remove(id) {
+            Some((peer, request)) => {
+                self.drop_peer(&peer, request);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn drop_peer(&mut self, peer: &PeerId, request: BlocksRequest) {
+        self.peers.retain(|other| other != peer);
+        self.retry.insert(request.from, request);
+        let requests = self
+            .requested
+            .iter()
+            .filter(|(_, (requested, _))| requested == peer)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in requests {
+            if let Some((_, request)) = self.requested.remove(&id) {
+                self.retry.insert(request.from, request);
+            }
+        }
+    }
+
+    // The next batch to connect, if it was downloaded.
+    pub fn next_batch(&mut self) -> Option<Vec<Arc<Block>>> {
+        let blocks = self.downloaded.remove(&self.next_connect)?;
+        self.next_connect += blocks.len() as u64;
+        Some(blocks)
+    }
+
+    // Height of the last block downloaded, and of the tip of the peer.
+    pub fn progress(&self) -> (u64, u64) {
+        (self.next_connect.saturating_sub(1), self.tip())
+    }
+
+    // Whether every block up to the tip of the peer was connected.
+    pub fn is_done(&self) -> bool {
+        self.headers_done
+            && self.requested.is_empty()
+            && self.retry.is_empty()
+            && self.downloaded.is_empty()
+            && self.next_connect > self.tip()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::params::ChainParams;
+
+    // Blocks after the tip of `blockchain`, each pointing at the one before it. The download
+    // only compares them by hash, so they aren't mined.
+    fn blocks_after(blockchain: &Blockchain, count: u64) -> Vec<Arc<Block>> {
+        let tip = &blockchain
+            .chain
+            .last()
+            .expect("there is a genesis block")
+            .header;
+        let mut previous_hash = tip.hash;
+        (tip.index + 1..=tip.index + count)
+            .map(|index| {
+                let mut block = Block::new(index, previous_hash, Vec::new());
+                block.header.hash = Hash256::digest(index.to_be_bytes());
+                previous_hash = block.header.hash;
+                Arc::new(block)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resumed_download_asks_for_the_blocks_after_the_stored_ones() {
+        let mut blockchain = Blockchain::new(ChainParams::default());
+        let genesis_hash = blockchain.chain[0].header.hash;
+        let blocks = blocks_after(&blockchain, 5);
+        let saved = SavedDownload {
+            peer: PeerId::random().to_string(),
+            ancestor: 0,
+            ancestor_hash: genesis_hash,
+            headers: blocks.iter().map(|block| block.header.clone()).collect(),
+        };
+
+        // Two of the blocks were connected and stored before the restart.
+        blockchain.chain.extend(blocks[..2].iter().cloned());
+        let mut download =
+            BlockDownload::resume(saved.clone(), &blockchain).expect("ancestor is in the chain");
+        assert!(download.resumed);
+        assert!(download.branch.is_none());
+        assert_eq!(download.progress(), (2, 5));
+        assert_eq!(download.saved(), Some(saved.clone()));
+
+        // Blocks are only asked of the peers connected after the restart.
+        assert_eq!(download.next_request(), None);
+        let peer = PeerId::random();
+        download.add_peers([peer]);
+        assert_eq!(
+            download.next_request(),
+            Some((peer, BlocksRequest { from: 3, count: 3 }))
+        );
+
+        // A download from a block the chain no longer holds starts over.
+        let other_ancestor = SavedDownload {
+            ancestor_hash: Hash256::digest("another block"),
+            ..saved
+        };
+        assert!(BlockDownload::resume(other_ancestor, &blockchain).is_none());
+    }
+}
@@ -0,0 +1,88 @@
+// Snapshot of the node for bug reports, written with `debug dump [path]`. Bundles the tip of the
+// chain, recent validation failures, the mempool, the peer table and the configuration into one JSON file.
+// The node's keys are never included, only the peer id derived from them.
+
+use std::{collections::HashSet, fs, io};
+
+use chrono::Utc;
+use libp2p::{PeerId, Swarm};
+use serde_json::{Value, json};
+
+use crate::{models::amount::Amount, p2p::BlockchainBehaviour};
+
+// Where the dump is written unless a path is given.
+pub fn default_path() -> String {
+    format!("debug-dump-{}.json", Utc::now().timestamp())
+}
+
+pub fn write(path: &str, swarm: &Swarm<BlockchainBehaviour>) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(&collect(swarm))?;
+    fs::write(path, json)
+}
+
+fn collect(swarm: &Swarm<BlockchainBehaviour>) -> Value {
+    let behaviour = swarm.behaviour();
+    let blockchain = &behaviour.blockchain;
+    let tip = blockchain
+        .chain
+        .last()
+        .expect("there is at least one block");
+
+    // Peers are discovered once for every address they listen on.
+    let discovered: HashSet<&PeerId> = behaviour.mdns.discovered_nodes().collect();
+    let peers: Vec<Value> = discovered
+        .into_iter()
+        .map(|peer| {
+            json!({
+                "peer": peer.to_string(),
+                "outbound": behaviour.peers.is_outbound(peer),
+                "connected": swarm.is_connected(peer),
+            })
+        })
+        .collect();
+
+    let splits: Vec<Value> = behaviour
+        .payout
+        .splits
+        .iter()
+        .map(|split| json!({ "address": split.address, "percent": split.percent }))
+        .collect();
+
+    let fees = behaviour
+        .mempool
+        .iter()
+        .fold(Amount::ZERO, |fees, transaction| {
+            fees.saturating_add(transaction.fee)
+        });
+
+    json!({
+        "generated_at": Utc::now().timestamp_millis(),
+        "version": env!("CARGO_PKG_VERSION"),
+        "peer_id": behaviour.peer_id.to_string(),
+        "tip": {
+            "height": tip.header.index,
+            "hash": tip.header.hash,
+            "timestamp": tip.header.timestamp,
+            "work": blockchain.work().to_string(),
+            "next_difficulty": blockchain.next_difficulty(),
+            "blocks": blockchain.chain.len(),
+        },
+        "invalidated_blocks": blockchain.invalidated_blocks().collect::<Vec<_>>(),
+        "recent_failures": behaviour.trace.recent_failures(),
+        "mempool": {
+            "transactions": behaviour.mempool.len(),
+            "fees": fees.to_string(),
+        },
+        "peers": peers,
+        "config": {
+            "chain": blockchain.params,
+            "payout": { "address": behaviour.payout.address, "splits": splits },
+            "mining": {
+                "threads": behaviour.miner_settings.threads(),
+                "throttle": behaviour.miner_settings.throttle(),
+            },
+            "block_schedule": behaviour.scheduler.to_string(),
+            "watching": behaviour.watching,
+        },
+    })
+}
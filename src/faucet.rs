@@ -0,0 +1,275 @@
+// Faucet handing out coins on test networks, enabled with `--faucet <address>`. Anyone can ask
+// for coins with `POST /faucet/<address>` on `--faucet-port`, and every request is paid from the
+// faucet address in the next block the node mines for payouts, along with the requests that came
+// in while the one before was mined. Payouts are signed with the node's key, so the faucet
+// address has to be the node's own, its peer id. Each client IP and each receiving address gets at most
+// one payout per `COOLDOWN`, on top of the request quotas of every HTTP server, see `http`. Fund the faucet by sending coins to it or mining to it with
+// `--payout <address>`. It gives coins away, so only enable it on test networks.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    net::{TcpListener, TcpStream},
+    spawn,
+    sync::{mpsc, oneshot},
+};
+
+use crate::{
+    http::{self, RateLimiter, RateLimits},
+    models::{address::Address, amount::Amount},
+};
+
+pub const DEFAULT_PORT: u16 = 8090;
+// Amount paid out per request unless `--faucet-amount` says otherwise.
+pub const DEFAULT_AMOUNT: Amount = Amount::from_coins(10);
+// How long a client IP or receiving address has to wait between payouts.
+pub const COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+// `FaucetSettings` Where faucet payouts come from and how large they are.
+#[derive(Debug, Clone)]
+pub struct FaucetSettings {
+    pub address: Address,
+    pub port: u16,
+    pub amount: Amount,
+}
+
+// `FaucetRequest` A request that passed the rate limits and the cooldowns. It is paid out by the event loop,
+// which owns the chain, and the outcome is sent back through `reply`.
+#[derive(Debug)]
+pub struct FaucetRequest {
+    pub sender: Address,
+    pub receiver: Address,
+    pub amount: Amount,
+    pub reply: oneshot::Sender<Result<Amount, String>>,
+}
+
+// `Cooldowns` When every client IP and receiving address was last paid, and the ones with a
+// payout under way.
+#[derive(Debug, Default)]
+struct Cooldowns {
+    by_ip: HashMap<IpAddr, Instant>,
+    by_address: HashMap<Address, Instant>,
+    pending_ips: HashSet<IpAddr>,
+    pending_addresses: HashSet<Address>,
+}
+
+impl Cooldowns {
+    // Start a payout to `address` requested from `ip`. Returns how long to wait instead if
+    // either of them was paid within the cooldown or has a payout under way.
+    fn check(&mut self, ip: IpAddr, address: &Address) -> Result<(), Duration> {
+        self.by_ip.retain(|_, at| at.elapsed() < COOLDOWN);
+        self.by_address.retain(|_, at| at.elapsed() < COOLDOWN);
+
+        if self.pending_ips.contains(&ip) || self.pending_addresses.contains(address) {
+            return Err(COOLDOWN);
+        }
+        let last = [self.by_ip.get(&ip), self.by_address.get(address)]
+            .into_iter()
+            .flatten()
+            .max();
+        if let Some(last) = last {
+            return Err(COOLDOWN - last.elapsed());
+        }
+
+        self.pending_ips.insert(ip);
+        self.pending_addresses.insert(address.clone());
+        Ok(())
+    }
+
+    // Finish the payout started by `check`, the cooldown only starting if it was `paid`.
+    fn finish(&mut self, ip: IpAddr, address: &Address, paid: bool) {
+        self.pending_ips.remove(&ip);
+        self.pending_addresses.remove(address);
+
+        if paid {
+            let now = Instant::now();
+            self.by_ip.insert(ip, now);
+            self.by_address.insert(address.clone(), now);
+        }
+    }
+}
+
+// Accept faucet requests until the listener fails, forwarding the ones within `limits` and the
+// cooldowns to `requests`.
+pub async fn serve(
+    settings: FaucetSettings,
+    limits: RateLimits,
+    requests: mpsc::UnboundedSender<FaucetRequest>,
+) {
+    let listener = match TcpListener::bind(("0.0.0.0", settings.port)).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            println!("can't start faucet on port {}: {}", settings.port, err);
+            return;
+        }
+    };
+    println!(
+        "faucet paying {} from {} on port {}",
+        settings.amount, settings.address, settings.port
+    );
+
+    let limiter = Arc::new(Mutex::new(RateLimiter::new(limits)));
+    let cooldowns = Arc::new(Mutex::new(Cooldowns::default()));
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let settings = settings.clone();
+                let requests = requests.clone();
+                let (limiter, cooldowns) = (limiter.clone(), cooldowns.clone());
+                spawn(async move {
+                    if let Err(err) =
+                        handle(stream, peer, settings, requests, limiter, cooldowns).await
+                    {
+                        println!("faucet request from {} failed: {}", peer, err);
+                    }
+                });
+            }
+            Err(err) => println!("faucet can't accept connection: {}", err),
+        }
+    }
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    settings: FaucetSettings,
+    requests: mpsc::UnboundedSender<FaucetRequest>,
+    limiter: Arc<Mutex<RateLimiter>>,
+    cooldowns: Arc<Mutex<Cooldowns>>,
+) -> std::io::Result<()> {
+    let allowed = limiter
+        .lock()
+        .expect("faucet rate limiter isn't poisoned")
+        .check_ip(peer.ip());
+    if let Err(wait) = allowed {
+        return http::write_rate_limited(&mut stream, wait).await;
+    }
+
+    let request = http::read_request(&mut stream).await?;
+    let allowed = limiter
+        .lock()
+        .expect("faucet rate limiter isn't poisoned")
+        .check_token(request.token());
+    if let Err(wait) = allowed {
+        return http::write_rate_limited(&mut stream, wait).await;
+    }
+
+    let (status, body) = match parse_request(&request.method, &request.path) {
+        Err(status) => (status, serde_json::json!({ "error": status })),
+        Ok(receiver) => {
+            let allowed = cooldowns
+                .lock()
+                .expect("faucet cooldowns aren't poisoned")
+                .check(peer.ip(), &receiver);
+
+            match allowed {
+                Err(wait) => (
+                    "429 Too Many Requests",
+                    serde_json::json!({ "error": "rate limited", "retry_after_secs": wait.as_secs() }),
+                ),
+                Ok(()) => {
+                    let (status, body) = pay(&settings, receiver.clone(), &requests).await;
+                    cooldowns
+                        .lock()
+                        .expect("faucet cooldowns aren't poisoned")
+                        .finish(peer.ip(), &receiver, status == "200 OK");
+                    (status, body)
+                }
+            }
+        }
+    };
+
+    http::write_response(&mut stream, status, &[], &body).await
+}
+
+async fn pay(
+    settings: &FaucetSettings,
+    receiver: Address,
+    requests: &mpsc::UnboundedSender<FaucetRequest>,
+) -> (&'static str, serde_json::Value) {
+    let (reply, outcome) = oneshot::channel();
+    let request = FaucetRequest {
+        sender: settings.address.clone(),
+        receiver: receiver.clone(),
+        amount: settings.amount,
+        reply,
+    };
+
+    if requests.send(request).is_err() {
+        return (
+            "503 Service Unavailable",
+            serde_json::json!({ "error": "node is shutting down" }),
+        );
+    }
+
+    match outcome.await {
+        Ok(Ok(amount)) => (
+            "200 OK",
+            serde_json::json!({ "address": receiver, "amount": amount }),
+        ),
+        Ok(Err(err)) => (
+            "503 Service Unavailable",
+            serde_json::json!({ "error": err }),
+        ),
+        Err(_) => (
+            "503 Service Unavailable",
+            serde_json::json!({ "error": "request was dropped" }),
+        ),
+    }
+}
+
+// The receiving address of a `POST /faucet/<address>` request, or the status to answer
+// anything else with.
+fn parse_request(method: &str, path: &str) -> Result<Address, &'static str> {
+    if method.is_empty() || path.is_empty() {
+        return Err("400 Bad Request");
+    }
+    let Some(address) = path.strip_prefix("/faucet/") else {
+        return Err("404 Not Found");
+    };
+    if method != "POST" {
+        return Err("405 Method Not Allowed");
+    }
+
+    Address::new(address).map_err(|_| "400 Bad Request")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(name: &str) -> Address {
+        Address::new(name).expect("address is valid")
+    }
+
+    #[test]
+    fn failed_payout_starts_no_cooldown() {
+        let mut cooldowns = Cooldowns::default();
+        let ip = IpAddr::from([127, 0, 0, 1]);
+
+        assert!(cooldowns.check(ip, &address("receiver")).is_ok());
+        cooldowns.finish(ip, &address("receiver"), false);
+        assert!(cooldowns.check(ip, &address("receiver")).is_ok());
+    }
+
+    #[test]
+    fn paid_or_pending_payout_is_rate_limited() {
+        let mut cooldowns = Cooldowns::default();
+        let ip = IpAddr::from([127, 0, 0, 1]);
+
+        assert!(cooldowns.check(ip, &address("receiver")).is_ok());
+        assert!(cooldowns.check(ip, &address("other")).is_err());
+        cooldowns.finish(ip, &address("receiver"), true);
+        assert!(cooldowns.check(ip, &address("other")).is_err());
+        assert!(
+            cooldowns
+                .check(IpAddr::from([10, 0, 0, 1]), &address("receiver"))
+                .is_err()
+        );
+    }
+}
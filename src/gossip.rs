@@ -0,0 +1,93 @@
+// Gossipsub settings of the node. Blocks, transactions and the other broadcasts are relayed
+// over a mesh of `--mesh-n` peers per topic, kept between `--mesh-n-low` and `--mesh-n-high`
+// on every heartbeat of `--gossip-heartbeat <ms>`. Messages are signed by the key of the chain
+// they belong to and unsigned ones are dropped. Copies of a message reaching the node through
+// several peers are only handled once.
+//
+// Every new tip is announced on the tips topic by its header alone, and peers fetch the block
+// only if it adds to their work. With `--header-relay` mined blocks are only announced that way
+// instead of being flooded in full over the blocks topic.
+
+use std::time::Duration;
+
+use libp2p::{
+    PeerId,
+    gossipsub::{
+        GossipsubConfig, GossipsubConfigBuilder, GossipsubMessage, IdentTopic, TopicHash,
+        ValidationMode,
+    },
+};
+
+// Peers every topic is relayed to unless the mesh options say otherwise.
+pub const DEFAULT_MESH_N: usize = 6;
+pub const DEFAULT_MESH_N_LOW: usize = 5;
+pub const DEFAULT_MESH_N_HIGH: usize = 12;
+pub const DEFAULT_HEARTBEAT: Duration = Duration::from_secs(1);
+// Largest message relayed, well above the size of blocks full of transactions.
+const MAX_GOSSIP_MESSAGE_SIZE: usize = 1024 * 1024;
+
+// `Topic` A gossip topic, named after what's published on it.
+pub type Topic = IdentTopic;
+
+// `GossipSettings` Shape of the gossip mesh.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GossipSettings {
+    pub mesh_n: usize,
+    pub mesh_n_low: usize,
+    pub mesh_n_high: usize,
+    pub heartbeat: Duration,
+    pub header_relay: bool,
+}
+
+impl Default for GossipSettings {
+    fn default() -> Self {
+        GossipSettings {
+            mesh_n: DEFAULT_MESH_N,
+            mesh_n_low: DEFAULT_MESH_N_LOW,
+            mesh_n_high: DEFAULT_MESH_N_HIGH,
+            heartbeat: DEFAULT_HEARTBEAT,
+            header_relay: false,
+        }
+    }
+}
+
+impl GossipSettings {
+    // Gossipsub configuration of these settings, an error unless 0 < mesh-n-low <= mesh-n <=
+    // mesh-n-high.
+    pub fn config(&self) -> Result<GossipsubConfig, String> {
+        GossipsubConfigBuilder::default()
+            .mesh_n(self.mesh_n)
+            .mesh_n_low(self.mesh_n_low)
+            .mesh_n_high(self.mesh_n_high)
+            // Gossipsub keeps a few outbound peers in every mesh, which small meshes can't hold.
+            .mesh_outbound_min(
+                2.min(self.mesh_n / 2)
+                    .min(self.mesh_n_low.saturating_sub(1)),
+            )
+            .heartbeat_interval(self.heartbeat)
+            .max_transmit_size(MAX_GOSSIP_MESSAGE_SIZE)
+            .validation_mode(ValidationMode::Strict)
+            .build()
+            .map_err(|err| format!("invalid gossip settings: {}", err))
+    }
+}
+
+// `GossipMessage` A message received over gossip, `source` being the peer that published it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GossipMessage {
+    pub source: PeerId,
+    pub topic: TopicHash,
+    pub data: Vec<u8>,
+}
+
+impl GossipMessage {
+    // `message` as relayed by `propagation_source`. Strict validation only lets signed messages
+    // through, so they always have a source.
+    pub fn new(propagation_source: PeerId, message: GossipsubMessage) -> Self {
+        GossipMessage {
+            source: message.source.unwrap_or(propagation_source),
+            topic: message.topic,
+            data: message.data,
+        }
+    }
+}
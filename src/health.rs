@@ -0,0 +1,111 @@
+// Health and readiness of the node, for orchestration systems supervising it. The RPC server
+// answers `GET /health` as long as the process runs, and `GET /ready` with 200 once the node is
+// fit to serve and 503 until then. A node is ready when its store can be read, it has peers or
+// was started with `--standalone`, and its chain is within `--ready-max-lag <blocks>` of the
+// highest block its peers announced. Neither needs an API key.
+//
+// A node that saw neither a new block nor a message from a peer for `--stale-tip-timeout
+// <seconds>` warns that its tip may be stale, e.g. as it was cut off from the network or its
+// peers hold it back. It then dials the bootstrap nodes again, looks itself up in the DHT and asks
+// its peers for the blocks past its tip.
+
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::p2p::BlockchainBehaviour;
+
+// Blocks the chain may be behind the peers and still be ready, unless `--ready-max-lag` says
+// otherwise.
+pub const DEFAULT_MAX_LAG: u64 = 6;
+// Time without a new block or a peer message after which the tip counts as stale, unless
+// `--stale-tip-timeout` says otherwise.
+pub const DEFAULT_STALE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+// Time between two checks for a stale tip.
+pub const STALE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+// `HealthSettings` What the node has to meet to be ready.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HealthSettings {
+    pub max_lag: u64,
+    // The node runs on its own, so it's ready without peers.
+    pub standalone: bool,
+    pub stale_timeout: Duration,
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        HealthSettings {
+            max_lag: DEFAULT_MAX_LAG,
+            standalone: false,
+            stale_timeout: DEFAULT_STALE_TIMEOUT,
+        }
+    }
+}
+
+// `Check` Outcome of one of the readiness checks, `detail` telling why it failed or what it saw.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+// `Readiness` The readiness checks of the node, which is ready if all of them passed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<Check>,
+}
+
+impl Readiness {
+    pub fn new(checks: Vec<Check>) -> Self {
+        Readiness {
+            ready: checks.iter().all(|check| check.ok),
+            checks,
+        }
+    }
+}
+
+// Run the readiness checks against `behaviour`.
+pub fn readiness(behaviour: &BlockchainBehaviour) -> Readiness {
+    let settings = &behaviour.health;
+
+    let store = match behaviour.blockchain.check_store() {
+        Ok(()) => Check {
+            name: "store",
+            ok: true,
+            detail: "open".to_string(),
+        },
+        Err(err) => Check {
+            name: "store",
+            ok: false,
+            detail: err,
+        },
+    };
+
+    let peers = behaviour.gossipsub.all_peers().count();
+    let peers = Check {
+        name: "peers",
+        ok: peers > 0 || settings.standalone,
+        detail: match settings.standalone {
+            true => format!("{} connected, standalone", peers),
+            false => format!("{} connected", peers),
+        },
+    };
+
+    let height = behaviour.blockchain.chain.len() as u64 - 1;
+    let lag = behaviour.best_known_height.saturating_sub(height);
+    let sync = Check {
+        name: "sync",
+        ok: lag <= settings.max_lag,
+        detail: format!(
+            "at block {} of {}, at most {} behind",
+            height,
+            height.max(behaviour.best_known_height),
+            settings.max_lag
+        ),
+    };
+
+    Readiness::new(vec![store, peers, sync])
+}
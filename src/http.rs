@@ -0,0 +1,254 @@
+// HTTP plumbing shared by the node's HTTP servers: reading a request, writing a JSON response
+// and the rate limits every server applies before handling a request. Each client IP gets
+// `--rate-limit` requests per minute, counted before the request is read, and each token, sent
+// as `Authorization: Bearer <token>`, `--token-rate-limit` requests per minute. Clients over
+// either quota are answered with `429 Too Many Requests` and a `Retry-After` header. A request
+// that isn't sent within `READ_TIMEOUT` is dropped, so slow clients can't hold connections open.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+// Requests per minute a client IP may send unless `--rate-limit` says otherwise.
+pub const DEFAULT_IP_QUOTA: u32 = 60;
+// Requests per minute a token may be used for unless `--token-rate-limit` says otherwise.
+pub const DEFAULT_TOKEN_QUOTA: u32 = 600;
+// Period quotas are counted over.
+const QUOTA_PERIOD: Duration = Duration::from_secs(60);
+// Largest request head read.
+const MAX_HEAD_SIZE: usize = 8 * 1024;
+// Largest request body read.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+// Time a client has to send its whole request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+// `HttpRequest` A request read off a connection. Header names are lowercase.
+#[derive(Debug, Clone, Default)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Token of an `Authorization: Bearer <token>` header.
+    pub fn token(&self) -> Option<&str> {
+        self.header("authorization")?
+            .strip_prefix("Bearer ")
+            .map(str::trim)
+            .filter(|token| !token.is_empty())
+    }
+}
+
+// Read a request, its body up to its `Content-Length`, within `READ_TIMEOUT`.
+pub async fn read_request(stream: &mut TcpStream) -> std::io::Result<HttpRequest> {
+    timeout(READ_TIMEOUT, read_request_data(stream))
+        .await
+        .map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::TimedOut, "request wasn't sent in time")
+        })?
+}
+
+async fn read_request_data(stream: &mut TcpStream) -> std::io::Result<HttpRequest> {
+    let mut data = Vec::new();
+    let mut buffer = [0; 1024];
+
+    let head_end = loop {
+        if let Some(position) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+        if data.len() >= MAX_HEAD_SIZE {
+            return Err(invalid("request head is too large"));
+        }
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break data.len();
+        }
+        data.extend_from_slice(&buffer[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&data[..head_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let mut request = HttpRequest {
+        method: request_line.next().unwrap_or_default().to_string(),
+        path: request_line.next().unwrap_or_default().to_string(),
+        ..HttpRequest::default()
+    };
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            request
+                .headers
+                .push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let length = match request.header("content-length") {
+        Some(length) => length
+            .parse::<usize>()
+            .map_err(|_| invalid("Content-Length isn't a number"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_SIZE {
+        return Err(invalid("request body is too large"));
+    }
+
+    request.body = data[head_end..].to_vec();
+    while request.body.len() < length {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        request.body.extend_from_slice(&buffer[..read]);
+    }
+    request.body.truncate(length);
+
+    Ok(request)
+}
+
+// Write a JSON response with `status` and close the connection, `headers` being sent along
+// with the usual ones.
+pub async fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    headers: &[(&str, String)],
+    body: &serde_json::Value,
+) -> std::io::Result<()> {
+    let body = body.to_string();
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        body.len()
+    );
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str("\r\n");
+    response.push_str(&body);
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+// Answer a client over its quota, `wait` being how long until it may try again.
+pub async fn write_rate_limited(stream: &mut TcpStream, wait: Duration) -> std::io::Result<()> {
+    let retry_after = wait.as_secs().max(1);
+    write_response(
+        stream,
+        "429 Too Many Requests",
+        &[("Retry-After", retry_after.to_string())],
+        &serde_json::json!({ "error": "rate limited", "retry_after_secs": retry_after }),
+    )
+    .await
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+// `RateLimits` Requests per minute each client IP and each token may send, `0` for no limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimits {
+    pub per_ip: u32,
+    pub per_token: u32,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            per_ip: DEFAULT_IP_QUOTA,
+            per_token: DEFAULT_TOKEN_QUOTA,
+        }
+    }
+}
+
+// `Bucket` Requests a client has left, refilled over the quota period.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    left: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    // Take one request out of a bucket holding `quota`, returning how long to wait instead if
+    // it is empty.
+    fn take(&mut self, quota: u32) -> Result<(), Duration> {
+        let rate = quota as f64 / QUOTA_PERIOD.as_secs_f64();
+        self.left = (self.left + self.updated.elapsed().as_secs_f64() * rate).min(quota as f64);
+        self.updated = Instant::now();
+
+        if self.left < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - self.left) / rate));
+        }
+        self.left -= 1.0;
+        Ok(())
+    }
+}
+
+// `RateLimiter` Requests left to every client IP and token within their quotas.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    by_ip: HashMap<IpAddr, Bucket>,
+    by_token: HashMap<String, Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        RateLimiter {
+            limits,
+            ..RateLimiter::default()
+        }
+    }
+
+    // Count a request from `ip`, before it is read. Returns how long to wait instead if the IP is
+    // over its quota.
+    pub fn check_ip(&mut self, ip: IpAddr) -> Result<(), Duration> {
+        take(&mut self.by_ip, ip, self.limits.per_ip)
+    }
+
+    // Count a request using `token`, once it was read. Returns how long to wait instead if the
+    // token is over its quota.
+    pub fn check_token(&mut self, token: Option<&str>) -> Result<(), Duration> {
+        match token {
+            Some(token) => take(&mut self.by_token, token.to_string(), self.limits.per_token),
+            None => Ok(()),
+        }
+    }
+}
+
+// Take one request out of the bucket of `key` in `buckets`, a quota of `0` being no limit.
+fn take<K: Eq + Hash>(
+    buckets: &mut HashMap<K, Bucket>,
+    key: K,
+    quota: u32,
+) -> Result<(), Duration> {
+    if quota == 0 {
+        return Ok(());
+    }
+    buckets.retain(|_, bucket| bucket.updated.elapsed() < QUOTA_PERIOD);
+    buckets
+        .entry(key)
+        .or_insert_with(|| Bucket {
+            left: quota as f64,
+            updated: Instant::now(),
+        })
+        .take(quota)
+}
@@ -0,0 +1,68 @@
+// Blockchain node as a library. The models, i.e. blocks, transactions, the chain and its
+// validation, are always available. The networked node built on them, the p2p layer and the
+// services around it, needs the `p2p` feature, which is on by default.
+
+pub mod miner;
+pub mod models;
+pub mod vanity;
+
+#[cfg(feature = "p2p")]
+pub mod auth;
+#[cfg(feature = "p2p")]
+pub mod broadcast;
+#[cfg(feature = "p2p")]
+pub mod chaos;
+#[cfg(feature = "p2p")]
+pub mod cli;
+#[cfg(feature = "p2p")]
+pub mod commands;
+#[cfg(feature = "p2p")]
+pub mod config;
+#[cfg(feature = "p2p")]
+pub mod coop;
+#[cfg(feature = "p2p")]
+pub mod discovery;
+#[cfg(feature = "p2p")]
+pub mod download;
+#[cfg(feature = "p2p")]
+pub mod dump;
+#[cfg(feature = "p2p")]
+pub mod faucet;
+#[cfg(feature = "p2p")]
+pub mod gossip;
+#[cfg(feature = "p2p")]
+pub mod health;
+#[cfg(feature = "p2p")]
+pub mod http;
+#[cfg(feature = "p2p")]
+pub mod metrics;
+#[cfg(feature = "p2p")]
+pub mod openapi;
+#[cfg(feature = "p2p")]
+pub mod orphans;
+#[cfg(feature = "p2p")]
+pub mod p2p;
+#[cfg(feature = "p2p")]
+pub mod peers;
+#[cfg(feature = "p2p")]
+pub mod reconcile;
+#[cfg(feature = "p2p")]
+pub mod relay;
+#[cfg(feature = "p2p")]
+pub mod rpc;
+#[cfg(feature = "p2p")]
+pub mod schedule;
+#[cfg(feature = "p2p")]
+pub mod seen;
+#[cfg(feature = "p2p")]
+pub mod selfcheck;
+#[cfg(feature = "p2p")]
+pub mod session;
+#[cfg(feature = "test-network")]
+pub mod testnet;
+#[cfg(feature = "p2p")]
+pub mod trace;
+#[cfg(feature = "p2p")]
+pub mod ws;
+
+pub use models::{block::Block, blockchain::Blockchain, transaction::Transaction};
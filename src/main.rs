@@ -1,71 +1,265 @@
-extern crate chrono;
-extern crate serde;
-extern crate sha2;
-
-mod models;
-mod p2p;
-
-use std::time::Duration;
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use libp2p::{
-    Swarm, Transport,
+    PeerId, Swarm, Transport,
     core::upgrade,
-    futures::StreamExt,
+    futures::{StreamExt, future},
     mplex,
     noise::{Keypair, NoiseConfig, X25519Spec},
-    swarm::SwarmBuilder,
+    swarm::{AddressScore, SwarmBuilder},
     tcp::TokioTcpConfig,
 };
 use tokio::{
     io::{AsyncBufReadExt, BufReader, stdin},
     select, spawn,
     sync::mpsc,
-    time::sleep,
+    time::{Instant, interval, sleep, sleep_until},
+};
+
+#[cfg(feature = "test-network")]
+use blockchain::testnet;
+use blockchain::{
+    Blockchain, broadcast, chaos,
+    cli::NodeArgs,
+    commands::{self, Command},
+    coop, faucet, health, metrics, miner,
+    models::{
+        anchor::{self, Anchor, AnchorRule},
+        checkpoint::Checkpoints,
+        epoch::EpochSummaries,
+        journal::Journal,
+        params::{ChainParams, MAIN_CHAIN_ID},
+        storage,
+    },
+    p2p, peers, relay, rpc, schedule, selfcheck, session, ws,
 };
 
-use crate::models::blockchain;
+// Program the nonce search is delegated to, `None` mines in-process.
+const EXTERNAL_HASHER: Option<&str> = None;
+// File the checkpoints created by this node are kept in.
+const CHECKPOINT_FILE: &str = "checkpoints.json";
+// File the epoch summaries signed by this node are kept in.
+const EPOCH_FILE: &str = "epochs.json";
+// File recording which peer first delivered every block.
+const RELAY_LOG_FILE: &str = "relay.jsonl";
+// Database the chain is persisted in, so it survives restarts.
+const CHAIN_DB: &str = "chain.db";
+// Journal of the changes to the chain and the mempool, replayed after a crash.
+const JOURNAL_FILE: &str = "journal.jsonl";
+// Peers of the DHT routing table, so restarts don't depend on the bootstrap nodes.
+const ROUTING_FILE: &str = "routing.json";
+// Headers of the block download under way, so restarts don't start it over.
+const DOWNLOAD_FILE: &str = "download.json";
+// `HostedChain` One of the chains this node hosts. Every chain runs its own swarm with its
+// own keys, topics, store and mempool, so chains don't see each other's blocks.
+struct HostedChain {
+    id: String,
+    swarm: Swarm<p2p::BlockchainBehaviour>,
+    mined: mpsc::UnboundedReceiver<miner::MinedBlock>,
+    searched: mpsc::UnboundedReceiver<coop::CoopSearched>,
+}
+
+impl HostedChain {
+    // Wait for the next event of this chain. Swarm events are handled by the behaviour, they
+    // yield `None`.
+    async fn next_event(&mut self) -> Option<p2p::EventType> {
+        let next_block_at = self.swarm.behaviour().next_scheduled_block();
+
+        select! {
+            mined = self.mined.recv() => {
+                Some(p2p::EventType::Mined(mined.expect("the behaviour keeps a mined sender")))
+            },
+            searched = self.searched.recv() => {
+                Some(p2p::EventType::Searched(
+                    searched.expect("the behaviour keeps a searched sender"),
+                ))
+            },
+            _ = sleep_until(next_block_at.unwrap_or_else(Instant::now)), if next_block_at.is_some() => {
+                Some(p2p::EventType::ScheduledBlock)
+            }
+            _event = self.swarm.select_next_some() => None,
+        }
+    }
+}
 
-const MINING_DIFFICULTY: usize = 3;
+// Path of the file `name` of the chain with `chain_id` in `data_dir`. The main chain keeps the
+// plain names.
+fn chain_file(data_dir: &Path, chain_id: &str, name: &str) -> String {
+    let name = match chain_id {
+        MAIN_CHAIN_ID => name.to_string(),
+        _ => format!("{}-{}", chain_id, name),
+    };
+    data_dir.join(name).to_string_lossy().into_owned()
+}
 
 #[tokio::main]
 async fn main() {
-    println!("Peer Id {}", p2p::PEER_ID.clone());
+    // Options of the configuration file come first, so the command line overrides them.
+    let args = NodeArgs::load().unwrap_or_else(|err| {
+        println!("{}", err);
+        std::process::exit(1);
+    });
 
-    let (response_sender, mut response_rcv) = mpsc::unbounded_channel();
-    let (init_sender, mut init_rcv) = mpsc::unbounded_channel();
+    #[cfg(feature = "test-network")]
+    if let Some(nodes) = args.test_network {
+        let converged = testnet::self_test(nodes).await;
+        println!("test network converged: {}", converged);
+        std::process::exit(if converged { 0 } else { 1 });
+    }
 
-    let auth_keys = Keypair::<X25519Spec>::new()
-        .into_authentic(&p2p::KEYS)
-        .expect("can create auth keys");
+    if let Err(err) = selfcheck::run() {
+        println!("serialization self-check failed: {}", err);
+        std::process::exit(1);
+    }
 
-    let transp = TokioTcpConfig::new()
-        .upgrade(upgrade::Version::V1)
-        .authenticate(NoiseConfig::xx(auth_keys).into_authenticated())
-        .multiplex(mplex::MplexConfig::new())
-        .boxed();
+    if let Some(path) = &args.key_file {
+        let _ = p2p::KEY_FILE.set(path.clone());
+    }
+    let mut chains_params = args.chain_params().unwrap_or_else(|err| {
+        println!("{}", err);
+        std::process::exit(1);
+    });
+    let data_dir = args.data_dir();
+    if let Err(err) = fs::create_dir_all(&data_dir) {
+        println!("can't create {}: {}", data_dir.display(), err);
+        std::process::exit(1);
+    }
 
-    let behaviour = p2p::BlockchainBehaviour::new(
-        blockchain::Blockchain::new(MINING_DIFFICULTY),
-        response_sender,
-        init_sender.clone(),
-    )
-    .await;
+    // Sessions record a single chain, replays only host the first one.
+    let replay = args.replay_session.clone();
+    if replay.is_some() {
+        chains_params.truncate(1);
+    }
 
-    let mut swarm = SwarmBuilder::new(transp, behaviour, *p2p::PEER_ID)
-        .executor(Box::new(|fut| {
-            spawn(fut);
-        }))
-        .build();
+    let (init_sender, mut init_rcv) = mpsc::unbounded_channel();
+    let (faucet_sender, mut faucet_rcv) = mpsc::unbounded_channel();
+    let (rpc_sender, mut rpc_rcv) = mpsc::unbounded_channel();
+    let miner_settings = Arc::new(args.miner_settings());
+
+    let mut chains = Vec::new();
+    for (position, params) in chains_params.into_iter().enumerate() {
+        // Recording, tracing and fault injection follow the first chain only.
+        let is_first = position == 0;
+        let chain = host_chain(
+            params,
+            &args,
+            &data_dir,
+            is_first,
+            &replay,
+            miner_settings.clone(),
+        )
+        .await;
+        chains.push(chain);
+    }
+    let mut active = 0;
+
+    let mut anchor_rules = args.anchor.clone();
+    anchor_rules.retain(|rule| {
+        let hosted = [&rule.chain_id, &rule.anchored]
+            .iter()
+            .all(|id| chains.iter().any(|chain| chain.id == **id));
+        if !hosted {
+            println!(
+                "can't anchor {} in {}, this node doesn't host both",
+                rule.anchored, rule.chain_id
+            );
+        }
+        hosted
+    });
 
     let mut stdin = BufReader::new(stdin()).lines();
+    let mut console_open = true;
+
+    // Replays never join the network, the swarm is only there for the commands to inspect.
+    if let Some(path) = replay {
+        let swarm = &mut chains[0].swarm;
+        match session::load(&path) {
+            Ok(entries) => {
+                let count = session::replay(entries, swarm.behaviour_mut());
+                println!("replayed {} messages from {}", count, path);
+            }
+            Err(err) => {
+                println!("can't read session {}: {}", path, err);
+                return;
+            }
+        }
+
+        while let Ok(Some(line)) = stdin.next_line().await {
+            handle_input(&line, swarm);
+        }
+        return;
+    }
 
-    Swarm::listen_on(
-        &mut swarm,
-        "/ip4/0.0.0.0/tcp/0"
-            .parse()
-            .expect("can get a local socket"),
-    )
-    .expect("swarm can be started");
+    let mut retry = interval(broadcast::RETRY_INTERVAL);
+    let mut rotation = interval(peers::ROTATION_INTERVAL);
+    let discovery_settings = args.discovery_settings();
+    let mut discovery_refresh = interval(discovery_settings.refresh);
+    let mut release = interval(chaos::RELEASE_INTERVAL);
+    let metrics_settings = args.metrics_settings();
+    let health_settings = args.health_settings();
+    let gossip_settings = args.gossip_settings();
+    let mut sampling = interval(metrics_settings.interval);
+    let mut stale_check = interval(health::STALE_CHECK_INTERVAL);
+    for chain in chains.iter_mut() {
+        chain.swarm.behaviour_mut().metrics =
+            metrics::MetricsHistory::new(metrics_settings.history);
+        chain.swarm.behaviour_mut().health = health_settings;
+        chain.swarm.behaviour_mut().header_relay = gossip_settings.header_relay;
+    }
+
+    // Bootstrap nodes and external addresses are those of the first chain, the other chains
+    // have peer ids and ports of their own.
+    for (position, chain) in chains.iter_mut().enumerate() {
+        Swarm::listen_on(
+            &mut chain.swarm,
+            discovery_settings.listen_address(position),
+        )
+        .expect("swarm can be started");
+
+        let bootstrap: &[_] = match position {
+            0 => {
+                for address in discovery_settings.external_addresses.iter() {
+                    chain
+                        .swarm
+                        .add_external_address(address.clone(), AddressScore::Infinite);
+                }
+                &discovery_settings.bootstrap
+            }
+            _ => &[],
+        };
+        let routing_file = chain_file(&data_dir, &chain.id, ROUTING_FILE);
+        chain
+            .swarm
+            .behaviour_mut()
+            .start_discovery(bootstrap, routing_file);
+        let download_file = chain_file(&data_dir, &chain.id, DOWNLOAD_FILE);
+        chain.swarm.behaviour_mut().resume_download(download_file);
+    }
+
+    let limits = args.rate_limits();
+    if let Some(settings) = args.faucet_settings() {
+        spawn(faucet::serve(settings, limits, faucet_sender.clone()));
+    }
+    let keys = args.api_keys();
+    let rpc_settings = args.rpc_settings(&keys);
+    let keys = Arc::new(Mutex::new(keys));
+    if let Some(settings) = rpc_settings {
+        spawn(rpc::serve(
+            settings,
+            limits,
+            keys.clone(),
+            rpc_sender.clone(),
+        ));
+    }
+    if let Some(settings) = args.ws_settings() {
+        let events = chains[0].swarm.behaviour().events.clone();
+        spawn(ws::serve(settings, limits, keys.clone(), events));
+    }
 
     spawn(async move {
         sleep(Duration::from_secs(1)).await;
@@ -74,60 +268,416 @@ async fn main() {
     });
 
     loop {
-        let evt = {
+        // Events for one chain, or for every chain if unset.
+        let evt: Option<(Option<usize>, p2p::EventType)> = {
+            let chain_events =
+                future::select_all(chains.iter_mut().map(|chain| Box::pin(chain.next_event())));
+
             select! {
-                line = stdin.next_line() => Some(p2p::EventType::Input(line.expect("can get line").expect("can read line from stdin"))),
-                response = response_rcv.recv() => {
-                    Some(p2p::EventType::LocalChainResponse(response.expect("response exists")))
+                line = stdin.next_line(), if console_open => match line {
+                    Ok(Some(line)) => Some((Some(active), p2p::EventType::Input(line))),
+                    // Nodes run without a console keep running once their input ends.
+                    Ok(None) => {
+                        console_open = false;
+                        None
+                    }
+                    Err(err) => {
+                        println!("can't read command: {}", err);
+                        None
+                    }
                 },
+                (event, position, _) = chain_events => event.map(|event| (Some(position), event)),
+                request = faucet_rcv.recv() => {
+                    Some((Some(0), p2p::EventType::FaucetRequest(request.expect("faucet sender is kept alive"))))
+                }
+                call = rpc_rcv.recv() => {
+                    Some((Some(0), p2p::EventType::RpcCall(call.expect("rpc sender is kept alive"))))
+                }
                 _init = init_rcv.recv() => {
-                    Some(p2p::EventType::Init)
+                    Some((None, p2p::EventType::Init))
+                }
+                _retry = retry.tick() => {
+                    Some((None, p2p::EventType::Retry))
+                }
+                _rotation = rotation.tick() => {
+                    Some((None, p2p::EventType::RotatePeers))
                 }
-                event = swarm.select_next_some() => {
-                    // println!("Unhandled Swarm event: {:?}", event);
-                    None
+                _refresh = discovery_refresh.tick() => {
+                    Some((None, p2p::EventType::RefreshDiscovery))
+                }
+                _release = release.tick() => {
+                    Some((None, p2p::EventType::ReleaseDelayed))
+                }
+                _sampling = sampling.tick() => {
+                    Some((None, p2p::EventType::SampleMetrics))
+                }
+                _stale_check = stale_check.tick() => {
+                    Some((None, p2p::EventType::CheckStaleTip))
                 }
             }
         };
 
-        if let Some(event) = evt {
-            match event {
-                p2p::EventType::Init => {
-                    let peers = p2p::get_list_peers(&swarm);
-                    // swarm.behaviour_mut().blockchain.genesis_block();
-
-                    println!("connected nodes: {}", peers.len());
-                    if !peers.is_empty() {
-                        let req = p2p::LocalChainRequest {
-                            from_peer_id: peers
-                                .iter()
-                                .last()
-                                .expect("at least one peer")
-                                .to_string(),
-                        };
-                        let json = serde_json::to_string(&req).expect("can jsonify request");
-
-                        swarm
-                            .behaviour_mut()
-                            .floodsub
-                            .publish(p2p::CHAIN_TOPIC.clone(), json.as_bytes());
-                    }
+        match evt {
+            Some((_, p2p::EventType::Input(line))) => {
+                handle_chain_input(&line, &mut chains, &mut active)
+            }
+            Some((Some(position), event)) => handle_event(event, &mut chains[position].swarm),
+            Some((None, event)) => {
+                for chain in chains.iter_mut() {
+                    let event = match event {
+                        p2p::EventType::Init => p2p::EventType::Init,
+                        p2p::EventType::Retry => p2p::EventType::Retry,
+                        p2p::EventType::RotatePeers => p2p::EventType::RotatePeers,
+                        p2p::EventType::RefreshDiscovery => p2p::EventType::RefreshDiscovery,
+                        p2p::EventType::ReleaseDelayed => p2p::EventType::ReleaseDelayed,
+                        p2p::EventType::SampleMetrics => p2p::EventType::SampleMetrics,
+                        p2p::EventType::CheckStaleTip => p2p::EventType::CheckStaleTip,
+                        _ => unreachable!("only timer events go to every chain"),
+                    };
+                    handle_event(event, &mut chain.swarm);
                 }
-                p2p::EventType::LocalChainResponse(resp) => {
-                    let json = serde_json::to_string(&resp).expect("can jsonify response");
+            }
+            None => {}
+        }
 
-                    swarm
-                        .behaviour_mut()
-                        .floodsub
-                        .publish(p2p::CHAIN_TOPIC.clone(), json.as_bytes());
-                }
-                p2p::EventType::Input(line) => match line.as_str() {
-                    "ls p" => p2p::handle_print_peers(&swarm),
-                    cmd if cmd.starts_with("ls c") => p2p::handle_print_chain(&swarm),
-                    cmd if cmd.starts_with("create b") => p2p::handle_create_block(cmd, &mut swarm),
-                    _ => println!("Unknown command: {}", line),
-                },
-            };
+        anchor_chains(&mut chains, &mut anchor_rules);
+    }
+}
+
+// Submit the anchors `rules` are due for.
+fn anchor_chains(chains: &mut [HostedChain], rules: &mut [AnchorRule]) {
+    for rule in rules.iter_mut() {
+        let Some(anchored) = chains.iter().find(|chain| chain.id == rule.anchored) else {
+            continue;
+        };
+        let tip = anchored
+            .swarm
+            .behaviour()
+            .blockchain
+            .chain
+            .last()
+            .expect("there is at least one block");
+        if !rule.is_due(tip.header.index) {
+            continue;
+        }
+        let anchor = Anchor {
+            chain_id: rule.anchored.clone(),
+            height: tip.header.index,
+            hash: tip.header.hash,
+        };
+        rule.last_height = Some(anchor.height);
+
+        let Some(chain) = chains.iter_mut().find(|chain| chain.id == rule.chain_id) else {
+            continue;
+        };
+        let (height, hash) = (anchor.height, anchor.hash);
+        match chain.swarm.behaviour_mut().submit_anchor(anchor) {
+            Ok(id) => println!(
+                "anchoring {} block {} {} in {} with transaction {}",
+                rule.anchored, height, hash, rule.chain_id, id
+            ),
+            Err(err) => println!(
+                "can't anchor {} in {}: {}",
+                rule.anchored, rule.chain_id, err
+            ),
+        }
+    }
+}
+
+// List the anchors on chain `position`, checking them against the anchored chains this node
+// hosts.
+fn print_anchors(chains: &[HostedChain], position: usize) {
+    let chain = &chains[position].swarm.behaviour().blockchain.chain;
+
+    let mut count = 0;
+    for (height, anchor) in anchor::anchors(chain) {
+        let anchored = chains.iter().find(|chain| chain.id == anchor.chain_id);
+        let status = match anchored.map(|chain| {
+            chain
+                .swarm
+                .behaviour()
+                .blockchain
+                .get_block_by_height(anchor.height)
+        }) {
+            None => "not hosted",
+            Some(Some(block)) if block.header.hash == anchor.hash => "verified",
+            Some(Some(_)) => "MISMATCH",
+            Some(None) => "ahead of the local chain",
+        };
+        println!(
+            "block {}: {} block {} {} {}",
+            height, anchor.chain_id, anchor.height, anchor.hash, status
+        );
+        count += 1;
+    }
+
+    println!("{} anchors on {}", count, chains[position].id);
+}
+
+// Open the chain `params` describes and start a swarm for it. Replays start from an empty chain
+// every time, so they don't touch the stored one.
+async fn host_chain(
+    params: ChainParams,
+    args: &NodeArgs,
+    data_dir: &Path,
+    is_first: bool,
+    replay: &Option<String>,
+    miner_settings: Arc<miner::MinerSettings>,
+) -> HostedChain {
+    let id = params.chain_id.clone();
+    let keys = p2p::chain_keys(&id);
+    let peer_id = PeerId::from(keys.public());
+    if is_first {
+        println!("Peer Id {}", peer_id);
+    } else {
+        println!("hosting chain {} as {}", id, peer_id);
+    }
+
+    let (checkpoint_file, relay_log_file, epoch_file) = match replay {
+        Some(path) => session::scratch_files(path),
+        None => (
+            chain_file(data_dir, &id, CHECKPOINT_FILE),
+            chain_file(data_dir, &id, RELAY_LOG_FILE),
+            chain_file(data_dir, &id, EPOCH_FILE),
+        ),
+    };
+    // Replays are neither recorded again nor have faults injected, so they stay deterministic.
+    let (recorder, chaos_settings, trace) = match replay {
+        None if is_first => (
+            args.session_recorder(&peer_id),
+            args.chaos_settings(),
+            args.propagation_trace(),
+        ),
+        _ => Default::default(),
+    };
+
+    let blockchain = match replay {
+        Some(_) => Ok(Blockchain::new(params)),
+        None => storage::SledStore::open(&chain_file(data_dir, &id, CHAIN_DB))
+            .and_then(|store| Blockchain::open(params, Arc::new(store))),
+    };
+    let mut blockchain = blockchain.unwrap_or_else(|err| {
+        println!("{}: {}", id, err);
+        std::process::exit(1);
+    });
+    if chaos_settings.is_enabled() {
+        println!("injecting faults: {:?}", chaos_settings);
+    }
+    let gossip_config = args.gossip_settings().config().unwrap_or_else(|err| {
+        println!("{}", err);
+        std::process::exit(1);
+    });
+    let payout = args
+        .payout(p2p::peer_address(&peer_id))
+        .unwrap_or_else(|err| {
+            println!("{}", err);
+            std::process::exit(1);
+        });
+    let index_settings = args.index_settings();
+    if index_settings != *blockchain.index.settings() {
+        blockchain.set_index_settings(index_settings);
+    }
+
+    let (mined_sender, mined) = mpsc::unbounded_channel();
+    let (searched_sender, searched) = mpsc::unbounded_channel();
+
+    let auth_keys = Keypair::<X25519Spec>::new()
+        .into_authentic(&keys)
+        .expect("can create auth keys");
+
+    let transp = TokioTcpConfig::new()
+        .upgrade(upgrade::Version::V1)
+        .authenticate(NoiseConfig::xx(auth_keys).into_authenticated())
+        .multiplex(mplex::MplexConfig::new())
+        .boxed();
+
+    let start_searcher = {
+        let miner_settings = miner_settings.clone();
+        move || -> Box<dyn miner::NonceSearcher + Send> {
+            match EXTERNAL_HASHER {
+                Some(program) => Box::new(
+                    miner::ExternalHasher::spawn(program).expect("can start external hasher"),
+                ),
+                None => Box::new(miner::ThreadedHasher::new(miner_settings.clone())),
+            }
+        }
+    };
+
+    let searcher: Box<dyn miner::NonceSearcher + Send> = if chaos_settings.kill_miner > 0.0 {
+        Box::new(chaos::ChaosSearcher::new(
+            chaos_settings.kill_miner,
+            start_searcher,
+        ))
+    } else {
+        start_searcher()
+    };
+
+    let settings = p2p::NodeSettings {
+        keys,
+        blockchain,
+        searcher,
+        miner_settings,
+        payout,
+        scheduler: args.block_scheduler(),
+        checkpoints: Checkpoints::load(&checkpoint_file),
+        epochs: EpochSummaries::load(&epoch_file),
+        relay_log: relay::RelayLog::load(&relay_log_file),
+        trace,
+        session: recorder,
+        chaos: chaos::Chaos::new(chaos_settings),
+        gossip_config,
+    };
+    let senders = p2p::NodeSenders {
+        mined: mined_sender,
+        searched: searched_sender,
+    };
+    let mut behaviour = p2p::BlockchainBehaviour::new(settings, senders).await;
+    if replay.is_none() {
+        match Journal::open(&chain_file(data_dir, &id, JOURNAL_FILE)) {
+            Ok((journal, records)) => behaviour.attach_journal(journal, records),
+            Err(err) => {
+                println!("{}: {}", id, err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let swarm = SwarmBuilder::new(transp, behaviour, peer_id)
+        .executor(Box::new(|fut| {
+            spawn(fut);
+        }))
+        .build();
+
+    HostedChain {
+        id,
+        swarm,
+        mined,
+        searched,
+    }
+}
+
+fn handle_event(event: p2p::EventType, swarm: &mut Swarm<p2p::BlockchainBehaviour>) {
+    match event {
+        p2p::EventType::Init => {
+            let peers = p2p::get_list_peers(swarm);
+            // swarm.behaviour_mut().blockchain.genesis_block();
+
+            println!("connected nodes: {}", peers.len());
+            if let Some((peer, others)) = peers.split_last() {
+                swarm.behaviour_mut().start_download(*peer, others.to_vec());
+            }
         }
+        p2p::EventType::Retry => swarm.behaviour_mut().rebroadcast(),
+        p2p::EventType::RotatePeers => swarm.behaviour_mut().rotate_peers(),
+        p2p::EventType::RefreshDiscovery => swarm.behaviour_mut().refresh_discovery(),
+        p2p::EventType::ReleaseDelayed => swarm.behaviour_mut().release_delayed(),
+        p2p::EventType::SampleMetrics => swarm.behaviour_mut().sample_metrics(),
+        p2p::EventType::CheckStaleTip => swarm.behaviour_mut().check_stale_tip(),
+        p2p::EventType::Input(line) => handle_input(&line, swarm),
+        p2p::EventType::ScheduledBlock => p2p::handle_scheduled_block(swarm),
+        p2p::EventType::Mined(mined) => {
+            let behaviour = swarm.behaviour_mut();
+            behaviour.update_chain(|behaviour| behaviour.finish_mining(mined));
+        }
+        p2p::EventType::Searched(searched) => swarm.behaviour_mut().finish_search(searched),
+        p2p::EventType::FaucetRequest(request) => p2p::handle_faucet_request(request, swarm),
+        p2p::EventType::RpcCall(call) => p2p::handle_rpc_call(call, swarm),
+    };
+}
+
+// Run `line` on the chain it names with `@<chain id> <command>`, or on the active one. `use
+// <chain id>` changes the active chain, `chains` lists the hosted ones and `anchors` the anchors
+// on the active chain.
+fn handle_chain_input(line: &str, chains: &mut [HostedChain], active: &mut usize) {
+    let position = |id: &str| chains.iter().position(|chain| chain.id == id);
+
+    if line == "anchors" {
+        print_anchors(chains, *active);
+    } else if line == "chains" {
+        for (index, chain) in chains.iter().enumerate() {
+            let marker = if index == *active { "*" } else { " " };
+            let blockchain = &chain.swarm.behaviour().blockchain;
+            println!(
+                "{} {} height {} peer {}",
+                marker,
+                chain.id,
+                blockchain.chain.len() - 1,
+                chain.swarm.local_peer_id()
+            );
+        }
+    } else if let Some(id) = line.strip_prefix("use ") {
+        match position(id.trim()) {
+            Some(index) => {
+                *active = index;
+                println!("commands go to chain {}", id.trim());
+            }
+            None => println!("this node doesn't host chain {}", id.trim()),
+        }
+    } else if let Some(addressed) = line.strip_prefix('@') {
+        let (id, command) = addressed.split_once(' ').unwrap_or((addressed, ""));
+        match position(id) {
+            Some(index) => handle_input(command.trim(), &mut chains[index].swarm),
+            None => println!("this node doesn't host chain {}", id),
+        }
+    } else {
+        handle_input(line, &mut chains[*active].swarm);
+    }
+}
+
+fn handle_input(line: &str, swarm: &mut Swarm<p2p::BlockchainBehaviour>) {
+    if let Some(command) = commands::parse(line) {
+        match command {
+            Ok(Command::Help) => println!("{}", commands::HELP),
+            Ok(Command::TxSend { to, amount, fee }) => {
+                commands::handle_send(to, amount, fee, swarm)
+            }
+            Ok(Command::Balance { address, height }) => {
+                commands::handle_print_balance(&address, height, swarm)
+            }
+            Ok(Command::ChainInfo) => commands::handle_print_chain_info(swarm),
+            Ok(Command::PeerList) => commands::handle_print_peers(swarm),
+            Ok(Command::PeerDial(address)) => commands::handle_dial_peer(address, swarm),
+            Ok(Command::MineStart) => {
+                commands::handle_set_schedule(schedule::BlockSchedule::Continuous, swarm)
+            }
+            Ok(Command::MineStop) => {
+                commands::handle_set_schedule(schedule::BlockSchedule::OnDemand, swarm)
+            }
+            Err(err) => println!("{}", err),
+        }
+        return;
+    }
+
+    match line {
+        "ls p" => commands::handle_print_peers(swarm),
+        "chain watch" => commands::handle_chain_watch(swarm),
+        "checkpoints" => commands::handle_print_checkpoints(swarm),
+        "epochs" => commands::handle_print_epochs(swarm),
+        "ls m" => commands::handle_print_mempool(swarm),
+        cmd if cmd.starts_with("metrics") => commands::handle_print_metrics(cmd, swarm),
+        cmd if cmd.starts_with("ls c") => commands::handle_print_chain(swarm),
+        cmd if cmd.starts_with("export") => commands::handle_export_chain(cmd, swarm),
+        cmd if cmd.starts_with("create b") => commands::handle_create_block(cmd, swarm),
+        cmd if cmd.starts_with("create t") => commands::handle_create_transaction(cmd, swarm),
+        cmd if cmd.starts_with("create p") => commands::handle_create_package(cmd, swarm),
+        cmd if cmd.starts_with("coop") => commands::handle_coop(cmd, swarm),
+        cmd if cmd.starts_with("test_accept") => commands::handle_test_accept(cmd, swarm),
+        cmd if cmd.starts_with("invalidate") => commands::handle_invalidate_block(cmd, swarm),
+        cmd if cmd.starts_with("reconsider") => commands::handle_reconsider_block(cmd, swarm),
+        cmd if cmd.starts_with("schedule") => commands::handle_schedule(cmd, swarm),
+        cmd if cmd.starts_with("mine") => commands::handle_mine_settings(cmd, swarm),
+        cmd if cmd.starts_with("stats") => commands::handle_print_address_stats(cmd, swarm),
+        cmd if cmd.starts_with("state") => commands::handle_print_state(cmd, swarm),
+        cmd if cmd.starts_with("debug dump") => commands::handle_debug_dump(cmd, swarm),
+        cmd if cmd.starts_with("trace export") => commands::handle_export_trace(cmd, swarm),
+        cmd if cmd.starts_with("relay") => commands::handle_print_relay(cmd, swarm),
+        cmd if cmd.starts_with("wallet") => commands::handle_wallet(cmd, swarm),
+        cmd if cmd.starts_with("tx") => commands::handle_print_transaction(cmd, swarm),
+        cmd if cmd.starts_with("prove") => commands::handle_prove_transaction(cmd, swarm),
+        cmd if cmd.starts_with("sample") => commands::handle_request_history(cmd, swarm),
+        cmd if cmd.starts_with("epochs") => commands::handle_request_epochs(cmd, swarm),
+        cmd if cmd.starts_with("checkpoint") => commands::handle_request_checkpoint(cmd, swarm),
+        cmd if cmd.starts_with("verify") => commands::handle_verify_proof(cmd, swarm),
+        _ => println!("Unknown command: {}, see help", line),
     }
 }
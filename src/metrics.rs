@@ -0,0 +1,101 @@
+// Metrics of the node sampled into a ring buffer, so dashboards can show their recent history
+// without an external time series database. A sample is taken every `--metrics-interval
+// <seconds>` and the last `--metrics-history <samples>` are kept. They are read with `metrics
+// [seconds]` or the `get_metrics_history` call of `rpc`.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use serde::Serialize;
+
+// Time between two samples unless `--metrics-interval` says otherwise.
+pub const DEFAULT_METRICS_INTERVAL: Duration = Duration::from_secs(10);
+// Samples kept unless `--metrics-history` says otherwise, a day at the default interval.
+pub const DEFAULT_METRICS_HISTORY: usize = 8640;
+
+// `MetricsSettings` How often metrics are sampled and how many samples are kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetricsSettings {
+    pub interval: Duration,
+    pub history: usize,
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        MetricsSettings {
+            interval: DEFAULT_METRICS_INTERVAL,
+            history: DEFAULT_METRICS_HISTORY,
+        }
+    }
+}
+
+// `MetricsSample` The metrics of the node at `timestamp`, in milliseconds since the epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MetricsSample {
+    pub timestamp: u64,
+    pub height: u64,
+    pub mempool: usize,
+    pub peers: usize,
+    // Hashes per second the miner computed since the previous sample.
+    pub hashrate: f64,
+}
+
+// `MetricsHistory` The latest samples, oldest first.
+#[derive(Debug, Clone)]
+pub struct MetricsHistory {
+    samples: VecDeque<MetricsSample>,
+    capacity: usize,
+    // Hashes the miner had computed at the previous sample, and when it was taken.
+    last_hashes: Option<(u64, Instant)>,
+}
+
+impl Default for MetricsHistory {
+    fn default() -> Self {
+        MetricsHistory::new(DEFAULT_METRICS_HISTORY)
+    }
+}
+
+impl MetricsHistory {
+    pub fn new(capacity: usize) -> Self {
+        MetricsHistory {
+            samples: VecDeque::new(),
+            capacity: capacity.max(1),
+            last_hashes: None,
+        }
+    }
+
+    // Record a sample, `hashes` being the hashes the miner computed since the node started.
+    pub fn record(&mut self, height: u64, mempool: usize, peers: usize, hashes: u64) {
+        let now = Instant::now();
+        let hashrate = match self.last_hashes {
+            Some((last, at)) if now > at => {
+                hashes.saturating_sub(last) as f64 / now.duration_since(at).as_secs_f64()
+            }
+            _ => 0.0,
+        };
+        self.last_hashes = Some((hashes, now));
+
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(MetricsSample {
+            timestamp: Utc::now().timestamp_millis() as u64,
+            height,
+            mempool,
+            peers,
+            hashrate,
+        });
+    }
+
+    // Samples of the last `window`, oldest first.
+    pub fn window(&self, window: Duration) -> impl Iterator<Item = &MetricsSample> {
+        let window = u64::try_from(window.as_millis()).unwrap_or(u64::MAX);
+        let since = (Utc::now().timestamp_millis() as u64).saturating_sub(window);
+        self.samples
+            .iter()
+            .skip_while(move |sample| sample.timestamp < since)
+    }
+}
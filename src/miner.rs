@@ -0,0 +1,390 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    ops::Range,
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    block::{Block, BlockHeader},
+    consensus::PowAlgorithm,
+    hash::Hash256,
+    work,
+};
+
+// `Work` A nonce search request. The hash of a candidate nonce is the proof of work hash of
+// `prefix + nonce + suffix`, the nonce encoded as 8 big-endian bytes, so searchers don't need to
+// know the block format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Work {
+    pub algorithm: PowAlgorithm,
+    pub prefix: Vec<u8>,
+    pub suffix: Vec<u8>,
+    pub difficulty: usize,
+    pub start_nonce: u64,
+    pub end_nonce: u64,
+}
+
+// `WorkResult` The answer to a `Work` request, `None` if the range holds no valid nonce.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkResult {
+    pub nonce: Option<u64>,
+}
+
+impl Work {
+    pub fn new(block: &Block, algorithm: PowAlgorithm, nonces: Range<u64>) -> Self {
+        let data = block.header.hash_data();
+        let position = BlockHeader::NONCE_OFFSET;
+
+        Work {
+            algorithm,
+            prefix: data[..position].to_vec(),
+            suffix: data[position + 8..].to_vec(),
+            difficulty: block.header.difficulty,
+            start_nonce: nonces.start,
+            end_nonce: nonces.end,
+        }
+    }
+
+    // Encode the block data for a candidate nonce.
+    pub fn data(&self, nonce: u64) -> Vec<u8> {
+        [&self.prefix[..], &nonce.to_be_bytes(), &self.suffix[..]].concat()
+    }
+}
+
+// `NonceSearcher` Runs the inner loop of mining, searching a nonce range for a valid hash. The
+// search gives up once `cancel` is set, as soon as the searcher can tell.
+pub trait NonceSearcher {
+    fn search(&mut self, work: &Work, cancel: &AtomicBool) -> Option<u64>;
+}
+
+// `SharedSearcher` A searcher mining jobs borrow from the node in turn.
+pub type SharedSearcher = Arc<Mutex<Box<dyn NonceSearcher + Send>>>;
+
+// Number of hashes a worker computes between throttle pauses.
+const THROTTLE_BATCH: u64 = 1000;
+
+// `MinerSettings` Settings of the built-in miner, adjustable while the node runs.
+#[derive(Debug)]
+pub struct MinerSettings {
+    // Number of worker threads searching nonces.
+    threads: AtomicUsize,
+    // Share of the time workers spend hashing, in percent.
+    throttle: AtomicUsize,
+    // Hashes computed so far.
+    hashes: AtomicU64,
+}
+
+impl MinerSettings {
+    pub fn new(threads: usize, throttle: usize) -> Self {
+        let settings = MinerSettings {
+            threads: AtomicUsize::new(1),
+            throttle: AtomicUsize::new(100),
+            hashes: AtomicU64::new(0),
+        };
+        settings.set_threads(threads);
+        settings.set_throttle(throttle);
+
+        settings
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads.load(Ordering::Relaxed)
+    }
+
+    pub fn set_threads(&self, threads: usize) {
+        self.threads.store(threads.max(1), Ordering::Relaxed);
+    }
+
+    pub fn throttle(&self) -> usize {
+        self.throttle.load(Ordering::Relaxed)
+    }
+
+    pub fn set_throttle(&self, percent: usize) {
+        self.throttle
+            .store(percent.clamp(1, 100), Ordering::Relaxed);
+    }
+
+    pub fn hashes(&self) -> u64 {
+        self.hashes.load(Ordering::Relaxed)
+    }
+
+    // Sleep long enough after `busy` time of hashing to keep the CPU share at the throttle.
+    fn pause(&self, busy: Duration) {
+        let percent = self.throttle() as u32;
+        if percent < 100 {
+            thread::sleep(busy * (100 - percent) / percent);
+        }
+    }
+}
+
+// `ThreadedHasher` The built-in miner. Workers search interleaved nonces on their own threads
+// and all stop as soon as one of them finds a valid hash.
+pub struct ThreadedHasher {
+    settings: Arc<MinerSettings>,
+}
+
+impl ThreadedHasher {
+    pub fn new(settings: Arc<MinerSettings>) -> Self {
+        ThreadedHasher { settings }
+    }
+}
+
+impl NonceSearcher for ThreadedHasher {
+    fn search(&mut self, work: &Work, cancel: &AtomicBool) -> Option<u64> {
+        search_parallel(
+            &self.settings,
+            work.start_nonce..work.end_nonce,
+            cancel,
+            &self.settings.hashes,
+            |nonce| {
+                let engine = work.algorithm.engine();
+                let hash = engine.hash_data(&work.data(nonce));
+                engine.meets_difficulty(&hash, work.difficulty)
+            },
+        )
+    }
+}
+
+// Search `candidates` for one `is_match` accepts, on the worker threads of `settings` taking
+// interleaved candidates. Every worker stops once `stop` is set, which happens when one of them
+// finds a match but may also be done by the caller to cancel the search. The number of
+// candidates tried is added up in `attempts` as the search goes.
+pub fn search_parallel(
+    settings: &MinerSettings,
+    candidates: Range<u64>,
+    stop: &AtomicBool,
+    attempts: &AtomicU64,
+    is_match: impl Fn(u64) -> bool + Sync,
+) -> Option<u64> {
+    let threads = settings.threads() as u64;
+    let found = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for worker in 0..threads {
+            let (found, is_match, candidates) = (&found, &is_match, &candidates);
+
+            scope.spawn(move || {
+                let mut candidate = candidates.start.checked_add(worker);
+                let mut batch_start = Instant::now();
+                let mut batch = 0;
+
+                while let Some(current) =
+                    candidate.filter(|candidate| candidates.contains(candidate))
+                {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+
+                    if is_match(current) {
+                        stop.store(true, Ordering::Relaxed);
+                        *found.lock().unwrap() = Some(current);
+                        break;
+                    }
+
+                    batch += 1;
+                    if batch == THROTTLE_BATCH {
+                        attempts.fetch_add(batch, Ordering::Relaxed);
+                        settings.pause(batch_start.elapsed());
+                        batch_start = Instant::now();
+                        batch = 0;
+                    }
+
+                    candidate = current.checked_add(threads);
+                }
+
+                attempts.fetch_add(batch, Ordering::Relaxed);
+            });
+        }
+    });
+
+    found.into_inner().unwrap()
+}
+
+// `ExternalHasher` Delegates the nonce search to another program, e.g. a GPU miner. The
+// program reads one JSON encoded `Work` per line on stdin and answers each with one JSON
+// encoded `WorkResult` line on stdout.
+pub struct ExternalHasher {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ExternalHasher {
+    pub fn spawn(program: &str) -> io::Result<Self> {
+        let mut child = Command::new(program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let stdin = child.stdin.take().expect("child has stdin");
+        let stdout = BufReader::new(child.stdout.take().expect("child has stdout"));
+
+        Ok(ExternalHasher {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    fn request(&mut self, work: &Work) -> io::Result<WorkResult> {
+        let json = serde_json::to_string(work)?;
+        writeln!(self.stdin, "{}", json)?;
+        self.stdin.flush()?;
+
+        let mut line = String::new();
+        self.stdout.read_line(&mut line)?;
+
+        Ok(serde_json::from_str(&line)?)
+    }
+}
+
+impl NonceSearcher for ExternalHasher {
+    // The program can't be interrupted, a cancelled search is only skipped if it hasn't started.
+    fn search(&mut self, work: &Work, cancel: &AtomicBool) -> Option<u64> {
+        if cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        match self.request(work) {
+            Ok(result) => result.nonce,
+            Err(err) => {
+                println!("external hasher failed: {}", err);
+                None
+            }
+        }
+    }
+}
+
+impl Drop for ExternalHasher {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+    }
+}
+
+// Mine `block` by delegating the nonce search to `searcher`. The nonce it reports is checked
+// before it is accepted.
+pub fn mine_with(
+    block: &mut Block,
+    algorithm: PowAlgorithm,
+    searcher: &mut dyn NonceSearcher,
+    cancel: &AtomicBool,
+) -> bool {
+    let work = Work::new(block, algorithm, 0..u64::MAX);
+
+    let Some(nonce) = searcher.search(&work, cancel) else {
+        if !cancel.load(Ordering::Relaxed) {
+            println!("no valid nonce found");
+        }
+        return false;
+    };
+
+    block.header.proof_of_work = nonce;
+    block.header.hash = algorithm.engine().hash(&block.header);
+
+    if !block.is_mined(work::Work::from_difficulty(block.header.difficulty)) {
+        println!("hasher returned an invalid nonce: {}", nonce);
+        return false;
+    }
+
+    true
+}
+
+// `MinedBlock` Outcome of the mining job `job`, no block if the search failed or was cancelled.
+#[derive(Debug)]
+pub struct MinedBlock {
+    pub job: u64,
+    pub block: Option<Box<Block>>,
+}
+
+// `MiningJob` A block being mined on a background thread, so the node keeps handling events
+// meanwhile.
+#[derive(Debug)]
+pub struct MiningJob {
+    pub id: u64,
+    // Hash of the block the mined block extends.
+    pub previous_hash: Hash256,
+    cancel: Arc<AtomicBool>,
+}
+
+impl MiningJob {
+    // Mine `block` with `searcher` on a new thread, handing the outcome to `done`.
+    pub fn spawn(
+        id: u64,
+        mut block: Block,
+        algorithm: PowAlgorithm,
+        searcher: SharedSearcher,
+        done: impl FnOnce(MinedBlock) + Send + 'static,
+    ) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let job = MiningJob {
+            id,
+            previous_hash: block.header.previous_hash,
+            cancel: cancel.clone(),
+        };
+
+        thread::spawn(move || {
+            let mut searcher = searcher.lock().unwrap();
+            let mined = mine_with(&mut block, algorithm, searcher.as_mut(), &cancel);
+            drop(searcher);
+            done(MinedBlock {
+                job: id,
+                block: mined.then(|| Box::new(block)),
+            });
+        });
+
+        job
+    }
+
+    // Stop the search, the job then reports no block.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+// `SearchJob` A nonce range searched on a background thread for another node, so the node keeps
+// handling events meanwhile.
+#[derive(Debug)]
+pub struct SearchJob {
+    pub id: u64,
+    cancel: Arc<AtomicBool>,
+}
+
+impl SearchJob {
+    // Search `work` with `searcher` on a new thread, handing the nonce found, if any, to `done`.
+    // A cancelled search hands nothing.
+    pub fn spawn(
+        id: u64,
+        work: Work,
+        searcher: SharedSearcher,
+        done: impl FnOnce(Option<u64>) + Send + 'static,
+    ) -> Self {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let job = SearchJob {
+            id,
+            cancel: cancel.clone(),
+        };
+
+        thread::spawn(move || {
+            let mut searcher = searcher.lock().unwrap();
+            let nonce = searcher.search(&work, &cancel);
+            drop(searcher);
+            if !cancel.load(Ordering::Relaxed) {
+                done(nonce);
+            }
+        });
+
+        job
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
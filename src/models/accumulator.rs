@@ -0,0 +1,244 @@
+use super::block::Block;
+use super::hash::Hash256;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+// `Accumulator` Utreexo-style forest of perfect merkle trees over the ids of every transaction
+// in the chain. `roots[height]` is the root of the tree holding 2^height transactions, so a
+// node only needs the roots to check inclusion proofs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Accumulator {
+    pub roots: Vec<Option<Hash256>>,
+    // Number of transactions added so far.
+    pub leaves: u64,
+}
+
+// `InclusionProof` Sibling hashes from a transaction id up to one of the accumulator roots.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub transaction_id: Hash256,
+    // Position of the transaction among all transactions of the chain.
+    pub position: u64,
+    pub siblings: Vec<Hash256>,
+}
+
+impl Accumulator {
+    pub fn add_block(&mut self, block: &Block) {
+        for transaction in block.body.transactions.iter() {
+            self.add(transaction.id());
+        }
+    }
+
+    // Add a leaf, merging equally sized trees like carrying in a binary counter.
+    pub fn add(&mut self, leaf: Hash256) {
+        let mut node = leaf;
+        let mut height = 0;
+
+        loop {
+            if height == self.roots.len() {
+                self.roots.push(None);
+            }
+
+            match self.roots[height].take() {
+                Some(left) => {
+                    node = hash_pair(&left, &node);
+                    height += 1;
+                }
+                None => {
+                    self.roots[height] = Some(node);
+                    break;
+                }
+            }
+        }
+
+        self.leaves += 1;
+    }
+
+    // Check that `proof` leads to one of the roots.
+    pub fn verify(&self, proof: &InclusionProof) -> bool {
+        if proof.position >= self.leaves {
+            return false;
+        }
+
+        // Trees are ordered from the largest, find the one holding the position.
+        let mut offset = 0;
+        for height in (0..self.roots.len()).rev() {
+            let Some(root) = &self.roots[height] else {
+                continue;
+            };
+            let size = 1u64 << height;
+
+            if proof.position < offset + size {
+                return proof.siblings.len() == height
+                    && fold(
+                        &proof.transaction_id,
+                        proof.position - offset,
+                        &proof.siblings,
+                    ) == *root;
+            }
+            offset += size;
+        }
+
+        false
+    }
+}
+
+// Build the inclusion proof of a transaction from the full chain.
+pub fn prove(chain: &[Arc<Block>], transaction_id: &Hash256) -> Option<InclusionProof> {
+    let leaves: Vec<Hash256> = chain
+        .iter()
+        .flat_map(|block| block.body.transactions.iter())
+        .map(|transaction| transaction.id())
+        .collect();
+    let position = leaves.iter().position(|leaf| leaf == transaction_id)?;
+
+    Some(prove_leaf(&leaves, position))
+}
+
+// Build the inclusion proof of the leaf at `position` of an accumulator of `leaves`. The proof
+// names the leaf `transaction_id` whatever it stands for.
+pub fn prove_leaf(leaves: &[Hash256], position: usize) -> InclusionProof {
+    // Find the tree holding the position, the same way `Accumulator::verify` does.
+    let mut offset = 0;
+    let mut size = 1usize << (usize::BITS - 1 - leaves.len().leading_zeros());
+    while position >= offset + size || leaves.len() & size == 0 {
+        if leaves.len() & size != 0 {
+            offset += size;
+        }
+        size >>= 1;
+    }
+
+    let mut level: Vec<Hash256> = leaves[offset..offset + size].to_vec();
+    let mut index = position - offset;
+    let mut siblings = Vec::new();
+
+    while level.len() > 1 {
+        siblings.push(level[index ^ 1]);
+        level = level
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+        index >>= 1;
+    }
+
+    InclusionProof {
+        transaction_id: leaves[position],
+        position: position as u64,
+        siblings,
+    }
+}
+
+// Check that `block` commits to `accumulator`, the one of the chain it builds on, extended by its
+// transactions.
+pub fn is_commitment_valid(block: &Block, accumulator: &Accumulator) -> bool {
+    let mut accumulator = accumulator.clone();
+    accumulator.add_block(block);
+
+    if block.header.accumulator != accumulator {
+        println!(
+            "Block with id: {} has a wrong accumulator",
+            block.header.index
+        );
+        return false;
+    }
+
+    true
+}
+
+fn fold(leaf: &Hash256, mut index: u64, siblings: &[Hash256]) -> Hash256 {
+    let mut node = *leaf;
+
+    for sibling in siblings.iter() {
+        node = if index & 1 == 0 {
+            hash_pair(&node, sibling)
+        } else {
+            hash_pair(sibling, &node)
+        };
+        index >>= 1;
+    }
+
+    node
+}
+
+// Pairs are hashed over their hex encodings, so existing roots stay valid.
+fn hash_pair(left: &Hash256, right: &Hash256) -> Hash256 {
+    let mut hasher = Sha256::new();
+    hasher.update(left.hex());
+    hasher.update(right.hex());
+
+    Hash256(hasher.finalize().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::address::Address;
+    use crate::models::amount::Amount;
+    use crate::models::transaction::Transaction;
+
+    fn leaves(count: usize) -> Vec<Hash256> {
+        (0..count)
+            .map(|leaf| Hash256::digest(leaf.to_string()))
+            .collect()
+    }
+
+    fn accumulator(leaves: &[Hash256]) -> Accumulator {
+        let mut accumulator = Accumulator::default();
+        for leaf in leaves.iter() {
+            accumulator.add(*leaf);
+        }
+        accumulator
+    }
+
+    #[test]
+    fn proof_of_every_leaf_is_accepted() {
+        // 7 leaves make trees of 4, 2 and 1.
+        let leaves = leaves(7);
+        let accumulator = accumulator(&leaves);
+        assert_eq!(accumulator.leaves, 7);
+
+        for position in 0..leaves.len() {
+            assert!(accumulator.verify(&prove_leaf(&leaves, position)));
+        }
+    }
+
+    #[test]
+    fn forged_proof_is_rejected() {
+        let leaves = leaves(7);
+        let accumulator = accumulator(&leaves);
+
+        let mut other_leaf = prove_leaf(&leaves, 2);
+        other_leaf.transaction_id = Hash256::digest("other");
+        assert!(!accumulator.verify(&other_leaf));
+
+        let mut other_position = prove_leaf(&leaves, 2);
+        other_position.position = 3;
+        assert!(!accumulator.verify(&other_position));
+
+        let mut past_the_end = prove_leaf(&leaves, 6);
+        past_the_end.position = 7;
+        assert!(!accumulator.verify(&past_the_end));
+
+        let mut short = prove_leaf(&leaves, 2);
+        short.siblings.pop();
+        assert!(!accumulator.verify(&short));
+    }
+
+    #[test]
+    fn block_has_to_commit_to_its_transactions() {
+        let previous = accumulator(&leaves(3));
+        let transaction = Transaction::new(
+            Address::new("alice").expect("address is valid"),
+            Address::new("bob").expect("address is valid"),
+            Amount(1),
+        );
+
+        let mut block = Block::new(1, Hash256::ZERO, vec![transaction.clone()]);
+        block.header.accumulator = previous.clone();
+        assert!(!is_commitment_valid(&block, &previous));
+
+        block.header.accumulator.add(transaction.id());
+        assert!(is_commitment_valid(&block, &previous));
+    }
+}
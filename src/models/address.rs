@@ -0,0 +1,116 @@
+use ed25519_dalek::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::fmt;
+use std::str::FromStr;
+
+// Longest address accepted, well above the length of encoded peer ids.
+pub const MAX_ADDRESS_LENGTH: usize = 128;
+// Start of the peer id of an ed25519 key: an identity multihash of the protobuf encoded key,
+// where key type 1 is ed25519, followed by the 32 bytes of the key.
+const PUBLIC_KEY_PREFIX: [u8; 6] = [0x00, 0x24, 0x08, 0x01, 0x12, 0x20];
+
+// `Address` An account funds are sent from and to. Any run of 1 to `MAX_ADDRESS_LENGTH` ASCII
+// letters, digits, `-`, `_` and `.` is an address, so peer ids and names both work.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Address(String);
+
+impl Address {
+    pub fn new(address: impl Into<String>) -> Result<Self, String> {
+        let address = address.into();
+
+        if address.is_empty() || address.len() > MAX_ADDRESS_LENGTH {
+            return Err(format!(
+                "addresses have 1 to {} characters, got {}",
+                MAX_ADDRESS_LENGTH,
+                address.len()
+            ));
+        }
+        if !address
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.'))
+        {
+            return Err(format!("{:?} is not a valid address", address));
+        }
+
+        Ok(Address(address))
+    }
+
+    // Address controlled by `public_key`, encoded like the peer id of a node with that key so
+    // nodes can sign for what they mine.
+    pub fn from_public_key(public_key: &PublicKey) -> Self {
+        let mut peer_id = PUBLIC_KEY_PREFIX.to_vec();
+        peer_id.extend_from_slice(public_key.as_bytes());
+
+        Address(bs58::encode(peer_id).into_string())
+    }
+
+    // Key controlling the address, `None` for addresses not derived from one, e.g. names.
+    pub fn public_key(&self) -> Option<PublicKey> {
+        let peer_id = bs58::decode(&self.0).into_vec().ok()?;
+        let key = peer_id.strip_prefix(PUBLIC_KEY_PREFIX.as_slice())?;
+
+        PublicKey::from_bytes(key).ok()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        Address::new(address)
+    }
+}
+
+impl TryFrom<String> for Address {
+    type Error = String;
+
+    fn try_from(address: String) -> Result<Self, Self::Error> {
+        Address::new(address)
+    }
+}
+
+impl From<Address> for String {
+    fn from(address: Address) -> Self {
+        address.0
+    }
+}
+
+// Lets maps keyed by address be queried with plain strings.
+impl Borrow<str> for Address {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_peer_ids_are_addresses() {
+        assert!(Address::new("receiver").is_ok());
+        assert!(Address::new("cold-wallet_2.backup").is_ok());
+        assert!(Address::new("12D3KooWAbd4Fbpor5RqFjVHquPwXMHs9iXEhrFhnsafYg4zWEYX").is_ok());
+    }
+
+    #[test]
+    fn other_characters_are_rejected() {
+        for address in ["", "a b", "a\u{e9}", "<script>", "a/b", "a:b"] {
+            assert!(Address::new(address).is_err(), "{:?} was accepted", address);
+        }
+        assert!(Address::new("a".repeat(MAX_ADDRESS_LENGTH)).is_ok());
+        assert!(Address::new("a".repeat(MAX_ADDRESS_LENGTH + 1)).is_err());
+    }
+}
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Number of decimal places of a coin, amounts are counted in the smallest unit.
+pub const DECIMALS: u32 = 8;
+const UNITS_PER_COIN: u64 = 10u64.pow(DECIMALS);
+
+// `Amount` A number of the smallest units of the native coin. Arithmetic is checked or
+// saturating, there are no operators that could silently wrap around.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Amount(pub u64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    // `coins` whole coins, or the most there can be if they don't fit.
+    pub const fn from_coins(coins: u64) -> Amount {
+        Amount(coins.saturating_mul(UNITS_PER_COIN))
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_sub(other.0))
+    }
+
+    // `percent` percent of the amount, rounded down. Never overflows, the product is
+    // computed in 128 bits.
+    pub fn percent(self, percent: u64) -> Amount {
+        let share = self.0 as u128 * percent as u128 / 100;
+        Amount(share.min(u64::MAX as u128) as u64)
+    }
+}
+
+// Formats the amount in coins, e.g. `12.50000000`.
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:0width$}",
+            self.0 / UNITS_PER_COIN,
+            self.0 % UNITS_PER_COIN,
+            width = DECIMALS as usize
+        )
+    }
+}
@@ -0,0 +1,117 @@
+use super::block::Block;
+use super::condition::Condition;
+use super::hash::Hash256;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Blocks an anchored chain grows by between two anchors, unless `--anchor` sets another interval.
+pub const DEFAULT_ANCHOR_INTERVAL: u64 = 10;
+
+// `Anchor` Commitment of one chain to the block another chain had at `height`. Consensus only
+// knows its own chain, so anchors aren't checked against the anchored chain when they are mined.
+// Nodes hosting both chains can check them later, which makes rewriting either chain past an
+// anchor evident.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Anchor {
+    pub chain_id: String,
+    pub height: u64,
+    pub hash: Hash256,
+}
+
+// `AnchorRule` Let the hosted chain `chain_id` commit the tip of the hosted chain `anchored`
+// every `interval` blocks of it, set with `--anchor <chain id>:<anchored chain id>[:<blocks>]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnchorRule {
+    pub chain_id: String,
+    pub anchored: String,
+    pub interval: u64,
+    // Height of the anchored chain the last anchor committed to.
+    pub last_height: Option<u64>,
+}
+
+impl AnchorRule {
+    // Whether an anchored chain with its tip at `height` is due to be anchored again.
+    pub fn is_due(&self, height: u64) -> bool {
+        match self.last_height {
+            Some(last_height) => height >= last_height.saturating_add(self.interval),
+            None => height > 0,
+        }
+    }
+}
+
+impl FromStr for AnchorRule {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("{} isn't <chain id>:<anchored chain id>[:<blocks>]", value);
+        let mut parts = value.split(':');
+        let chain_id = parts
+            .next()
+            .filter(|id| !id.is_empty())
+            .ok_or_else(invalid)?;
+        let anchored = parts
+            .next()
+            .filter(|id| !id.is_empty() && *id != chain_id)
+            .ok_or_else(invalid)?;
+        let interval = match parts.next() {
+            Some(interval) => interval
+                .parse()
+                .ok()
+                .filter(|interval| *interval > 0)
+                .ok_or_else(invalid)?,
+            None => DEFAULT_ANCHOR_INTERVAL,
+        };
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(AnchorRule {
+            chain_id: chain_id.to_string(),
+            anchored: anchored.to_string(),
+            interval,
+            last_height: None,
+        })
+    }
+}
+
+// Check that the anchors in `block` of the chain `chain_id` move no coins and commit to other
+// chains.
+pub fn are_anchors_valid(block: &Block, chain_id: &str) -> bool {
+    for transaction in block.body.transactions.iter() {
+        let Some(Condition::Anchor(anchor)) = &transaction.condition else {
+            continue;
+        };
+
+        if !transaction.amount.is_zero() || !transaction.assets.is_empty() {
+            println!(
+                "Anchor in block with id: {} moves coins",
+                block.header.index
+            );
+            return false;
+        }
+        if anchor.chain_id == chain_id {
+            println!(
+                "Anchor in block with id: {} commits to its own chain",
+                block.header.index
+            );
+            return false;
+        }
+    }
+
+    true
+}
+
+// Anchors committed in `chain`, along with the height of the block including them.
+pub fn anchors(chain: &[Arc<Block>]) -> impl Iterator<Item = (u64, &Anchor)> {
+    chain.iter().flat_map(|block| {
+        block
+            .body
+            .transactions
+            .iter()
+            .filter_map(move |transaction| match &transaction.condition {
+                Some(Condition::Anchor(anchor)) => Some((block.header.index, anchor)),
+                _ => None,
+            })
+    })
+}
@@ -0,0 +1,285 @@
+// Proptest strategies for property tests of the validation logic, enabled with the `arbitrary`
+// feature. Generated values are checked against `blockchain()`, e.g.
+// `any_with::<ArbitraryChain>(Validity::Valid)` always passes `blockchain().is_chain_valid`.
+#![allow(dead_code)]
+
+use std::{collections::HashMap, sync::Arc};
+
+use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+use proptest::{collection::vec, prelude::*};
+
+use super::address::{Address, MAX_ADDRESS_LENGTH};
+use super::amount::Amount;
+use super::block::{Block, BlockBody, BlockHeader};
+use super::blockchain::Blockchain;
+use super::coinbase::{self, COINBASE_SENDER, Payout};
+use super::hash::Hash256;
+use super::params::{Allocation, ChainParams};
+use super::state::State;
+use super::transaction::Transaction;
+
+// Difficulty generated chains are mined at, low so generating them stays fast.
+pub const ARBITRARY_DIFFICULTY: usize = 1;
+// Most blocks a generated chain extends the genesis block by.
+pub const MAX_ARBITRARY_BLOCKS: usize = 8;
+// Number of keys valid transactions are sent from, each funded by the genesis block with far
+// more than generated chains can spend.
+const FUNDED_KEYS: u8 = 4;
+const FUNDING: Amount = Amount(1_000_000_000_000);
+
+// `Validity` How much of the validation generated values pass.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Validity {
+    // Passes every consensus check.
+    #[default]
+    Valid,
+    // Well formed and linked, with correct hashes, but the content breaks consensus rules,
+    // e.g. unmined blocks, misplaced coinbases or invalid asset transfers.
+    StructurallyValid,
+    // Every field is arbitrary.
+    Garbage,
+}
+
+// `ArbitraryChain` Blocks starting from the genesis block, generated together since they only
+// make sense in order.
+#[derive(Debug, Clone)]
+pub struct ArbitraryChain(pub Vec<Arc<Block>>);
+
+// The empty chain generated values are built on and validated against.
+pub fn blockchain() -> Blockchain {
+    let genesis_allocations = (0..FUNDED_KEYS)
+        .map(|key| Allocation {
+            address: Address::from_public_key(&funded_keypair(key).public),
+            amount: FUNDING,
+        })
+        .collect();
+
+    Blockchain::new(ChainParams {
+        difficulty: ARBITRARY_DIFFICULTY,
+        genesis_allocations,
+        ..ChainParams::default()
+    })
+}
+
+impl Arbitrary for Transaction {
+    type Parameters = Validity;
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(validity: Validity) -> Self::Strategy {
+        match validity {
+            Validity::Valid => (0..FUNDED_KEYS, address(), 0..1_000_000u64)
+                .prop_map(|(key, receiver, amount)| {
+                    let keypair = funded_keypair(key);
+                    let sender = Address::from_public_key(&keypair.public);
+                    let mut transaction = Transaction::new(sender, receiver, Amount(amount));
+                    transaction.sign(&keypair);
+                    transaction
+                })
+                .boxed(),
+            Validity::StructurallyValid => (
+                prop_oneof![address(), Just(COINBASE_SENDER).prop_map(to_address)],
+                address(),
+                any::<u32>(),
+                vec(("native|[a-z]{1,3}", 0..100u64), 0..3),
+            )
+                .prop_map(|(sender, receiver, amount, assets)| {
+                    let mut transaction = Transaction::new(sender, receiver, Amount(amount as u64));
+                    transaction.assets = assets
+                        .into_iter()
+                        .map(|(asset, amount)| super::asset::AssetTransfer { asset, amount })
+                        .collect();
+                    transaction
+                })
+                .boxed(),
+            Validity::Garbage => (
+                any_address(),
+                any_address(),
+                any::<u64>(),
+                vec((any::<String>(), any::<u64>()), 0..3),
+            )
+                .prop_map(|(sender, receiver, amount, assets)| {
+                    let mut transaction = Transaction::new(sender, receiver, Amount(amount));
+                    transaction.assets = assets
+                        .into_iter()
+                        .map(|(asset, amount)| super::asset::AssetTransfer { asset, amount })
+                        .collect();
+                    transaction
+                })
+                .boxed(),
+        }
+    }
+}
+
+// A block following the genesis block of `blockchain()`.
+impl Arbitrary for Block {
+    type Parameters = Validity;
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(validity: Validity) -> Self::Strategy {
+        chain(validity, 1..2)
+            .prop_map(|mut chain| Arc::unwrap_or_clone(chain.0.swap_remove(1)))
+            .boxed()
+    }
+}
+
+impl Arbitrary for ArbitraryChain {
+    type Parameters = Validity;
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(validity: Validity) -> Self::Strategy {
+        chain(validity, 0..MAX_ARBITRARY_BLOCKS + 1)
+    }
+}
+
+// Chains extending the genesis block by a number of blocks in `blocks`.
+pub fn chain(validity: Validity, blocks: std::ops::Range<usize>) -> BoxedStrategy<ArbitraryChain> {
+    let contents = (
+        any::<u64>(),
+        address(),
+        vec(any_with::<Transaction>(validity), 0..4),
+    );
+
+    match validity {
+        Validity::Valid => vec(contents, blocks)
+            .prop_map(|contents| {
+                let mut blockchain = blockchain();
+                for (interval, payout, mut transactions) in contents {
+                    // Valid blocks follow each other by up to a minute, so they stay in order
+                    // and behind the clock.
+                    let latest = blockchain
+                        .chain
+                        .last()
+                        .expect("there is at least one block");
+                    let timestamp = latest.header.timestamp + interval % 60_000;
+                    number(&mut transactions, &blockchain.state);
+                    let mut block = next_block(&blockchain.chain, timestamp, transactions);
+                    block.header.difficulty = blockchain.next_difficulty();
+                    let payout = Payout::new(payout, Vec::new()).expect("payout has no splits");
+                    let reward = blockchain.params.reward_at(block.header.index);
+                    coinbase::apply_payout(&mut block, &payout, reward);
+                    blockchain.commit_accumulator(&mut block);
+                    mine(&mut block, &blockchain);
+
+                    blockchain.try_to_add_a_block(block);
+                }
+
+                ArbitraryChain(blockchain.chain)
+            })
+            .boxed(),
+        Validity::StructurallyValid => vec((contents, any::<u64>()), blocks)
+            .prop_map(|contents| {
+                let mut chain = blockchain().chain;
+                for ((timestamp, _, transactions), proof_of_work) in contents {
+                    let mut block = next_block(&chain, timestamp, transactions);
+                    block.header.proof_of_work = proof_of_work;
+                    block.header.hash = block.generate_block_hash();
+
+                    chain.push(Arc::new(block));
+                }
+
+                ArbitraryChain(chain)
+            })
+            .boxed(),
+        Validity::Garbage => vec(garbage_block(), blocks.start + 1..blocks.end + 1)
+            .prop_map(|blocks| ArbitraryChain(blocks.into_iter().map(Arc::new).collect()))
+            .boxed(),
+    }
+}
+
+fn address() -> impl Strategy<Value = Address> {
+    "[0-9a-f]{8}".prop_map(to_address)
+}
+
+// Give the transactions of every funded key the next nonces after `state` and sign them again.
+fn number(transactions: &mut [Transaction], state: &State) {
+    let mut nonces: HashMap<Address, u64> = HashMap::new();
+
+    for transaction in transactions.iter_mut() {
+        let Some(keypair) = (0..FUNDED_KEYS)
+            .map(funded_keypair)
+            .find(|keypair| Address::from_public_key(&keypair.public) == transaction.sender)
+        else {
+            continue;
+        };
+        let nonce = nonces
+            .entry(transaction.sender.clone())
+            .or_insert_with(|| state.next_nonce(transaction.sender.as_str()));
+        transaction.nonce = *nonce;
+        *nonce += 1;
+        transaction.sign(&keypair);
+    }
+}
+
+// Key number `key` of the keys valid transactions are sent from.
+fn funded_keypair(key: u8) -> Keypair {
+    let secret = SecretKey::from_bytes(&[key; 32]).expect("any 32 bytes are a secret key");
+    let public = PublicKey::from(&secret);
+    Keypair { secret, public }
+}
+
+// Any valid address, not just the well behaved ones valid chains use.
+fn any_address() -> impl Strategy<Value = Address> {
+    proptest::string::string_regex(&format!("[A-Za-z0-9._-]{{1,{}}}", MAX_ADDRESS_LENGTH))
+        .expect("can compile address pattern")
+        .prop_map(to_address)
+}
+
+fn to_address(address: impl Into<String>) -> Address {
+    Address::new(address).expect("strategy generates valid addresses")
+}
+
+fn hash() -> impl Strategy<Value = Hash256> {
+    any::<[u8; 32]>().prop_map(Hash256)
+}
+
+fn next_block(chain: &[Arc<Block>], timestamp: u64, transactions: Vec<Transaction>) -> Block {
+    let latest_block = chain.last().expect("there is at least one block");
+
+    let mut block = Block::new(
+        latest_block.header.index + 1,
+        latest_block.header.hash,
+        transactions,
+    );
+    block.header.timestamp = timestamp;
+    block
+}
+
+fn mine(block: &mut Block, blockchain: &Blockchain) {
+    let engine = blockchain.params.pow.engine();
+
+    loop {
+        block.header.hash = engine.hash(&block.header);
+        if block.is_mined(blockchain.block_work()) {
+            return;
+        }
+        block.header.proof_of_work += 1;
+    }
+}
+
+fn garbage_block() -> impl Strategy<Value = Block> {
+    (
+        any::<u64>(),
+        any::<u64>(),
+        any::<u64>(),
+        hash(),
+        vec(any_with::<Transaction>(Validity::Garbage), 0..4),
+        hash(),
+    )
+        .prop_map(
+            |(index, timestamp, proof_of_work, previous_hash, transactions, hash)| Block {
+                header: BlockHeader {
+                    index,
+                    timestamp,
+                    proof_of_work,
+                    difficulty: 0,
+                    previous_hash,
+                    merkle_root: Default::default(),
+                    hash,
+                    accumulator: Default::default(),
+                    history: Default::default(),
+                    aux_pow: None,
+                },
+                body: BlockBody { transactions },
+            },
+        )
+}
@@ -0,0 +1,36 @@
+use super::block::Block;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+// Id of the chain's own coin, moved through `Transaction::amount`.
+pub const NATIVE_ASSET: &str = "native";
+
+// `AssetTransfer` An amount of a token moved alongside the native amount of a transaction.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AssetTransfer {
+    pub asset: String,
+    pub amount: u64,
+}
+
+// Check that the asset transfers of every transaction in `block` are well formed: each
+// asset appears at most once, is not the native coin and moves a non-zero amount.
+pub fn are_transfers_valid(block: &Block) -> bool {
+    for transaction in block.body.transactions.iter() {
+        let mut seen = HashSet::new();
+
+        for transfer in transaction.assets.iter() {
+            if transfer.asset == NATIVE_ASSET
+                || transfer.amount == 0
+                || !seen.insert(transfer.asset.as_str())
+            {
+                println!(
+                    "Block with id: {} has an invalid transfer of asset {}",
+                    block.header.index, transfer.asset
+                );
+                return false;
+            }
+        }
+    }
+
+    true
+}
@@ -0,0 +1,124 @@
+use super::hash::Hash256;
+use super::work::Work;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// Size of a parent chain header: version (4 bytes), previous hash (32), merge mining commitment
+// (32), timestamp (8) and nonce (8).
+pub const PARENT_HEADER_SIZE: usize = 84;
+// Position of the merge mining commitment in a parent header, the merkle root of the merge
+// mined block hashes. A commitment anywhere else doesn't count, so a parent header can't carry
+// several of them.
+pub const COMMITMENT_OFFSET: usize = 36;
+
+// `AuxPow` Proof that a block hash was committed in a block of another (parent) chain whose
+// header satisfies our difficulty, letting the block borrow that chain's proof of work.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuxPow {
+    // Raw header of the parent chain block, `PARENT_HEADER_SIZE` bytes with the merkle root of
+    // merge mined hashes at `COMMITMENT_OFFSET`.
+    pub parent_header: Vec<u8>,
+    // Sibling hashes from our block hash up to the committed merkle root.
+    pub merkle_branch: Vec<Hash256>,
+    // Position of our block hash among the merge mined hashes.
+    pub merkle_index: u64,
+}
+
+impl AuxPow {
+    // Calculate the merkle root `block_hash` commits to through the branch.
+    pub fn merkle_root(&self, block_hash: &Hash256) -> Hash256 {
+        let mut root = *block_hash;
+        let mut index = self.merkle_index;
+
+        for sibling in self.merkle_branch.iter() {
+            root = if index & 1 == 0 {
+                hash_pair(&root, sibling)
+            } else {
+                hash_pair(sibling, &root)
+            };
+            index >>= 1;
+        }
+
+        root
+    }
+
+    // Calculate the hash of the parent chain header.
+    pub fn parent_hash(&self) -> Hash256 {
+        Hash256::digest(&self.parent_header)
+    }
+
+    // Merkle root the parent header commits to, `None` if it isn't a parent header.
+    pub fn commitment(&self) -> Option<&[u8]> {
+        if self.parent_header.len() != PARENT_HEADER_SIZE {
+            return None;
+        }
+
+        Some(&self.parent_header[COMMITMENT_OFFSET..COMMITMENT_OFFSET + 32])
+    }
+
+    // Check that the parent header commits to `block_hash` and proves `required` work.
+    pub fn is_valid(&self, block_hash: &Hash256, required: Work) -> bool {
+        self.commitment() == Some(&self.merkle_root(block_hash).0[..])
+            && Work::proven_by(&self.parent_hash()) >= required
+    }
+}
+
+fn hash_pair(left: &Hash256, right: &Hash256) -> Hash256 {
+    let mut hasher = Sha256::new();
+    hasher.update(left.0);
+    hasher.update(right.0);
+
+    Hash256(hasher.finalize().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Parent header with `commitment` at `offset`.
+    fn parent_header(commitment: &Hash256, offset: usize) -> Vec<u8> {
+        let mut header = vec![7; PARENT_HEADER_SIZE];
+        header[offset..offset + 32].copy_from_slice(&commitment.0);
+        header
+    }
+
+    fn aux_pow(block_hash: &Hash256, offset: usize) -> AuxPow {
+        let mut aux_pow = AuxPow {
+            parent_header: Vec::new(),
+            merkle_branch: vec![Hash256::digest("sibling")],
+            merkle_index: 1,
+        };
+        aux_pow.parent_header = parent_header(&aux_pow.merkle_root(block_hash), offset);
+        aux_pow
+    }
+
+    #[test]
+    fn commitment_at_its_offset_is_accepted() {
+        let block_hash = Hash256::digest("block");
+        let aux_pow = aux_pow(&block_hash, COMMITMENT_OFFSET);
+
+        assert!(aux_pow.is_valid(&block_hash, Work::from_difficulty(0)));
+        // The commitment is to this block only.
+        assert!(!aux_pow.is_valid(&Hash256::digest("other"), Work::from_difficulty(0)));
+        // And the parent header has to prove the work.
+        assert!(!aux_pow.is_valid(&block_hash, Work::from_difficulty(32)));
+    }
+
+    #[test]
+    fn misplaced_or_missing_commitment_is_rejected() {
+        let block_hash = Hash256::digest("block");
+        let required = Work::from_difficulty(0);
+
+        let misplaced = aux_pow(&block_hash, COMMITMENT_OFFSET + 4);
+        assert!(!misplaced.is_valid(&block_hash, required));
+
+        let mut missing = aux_pow(&block_hash, COMMITMENT_OFFSET);
+        missing.parent_header = vec![7; PARENT_HEADER_SIZE];
+        assert!(!missing.is_valid(&block_hash, required));
+
+        // Extra bytes could hide a second commitment, so the header has to have the exact size.
+        let mut padded = aux_pow(&block_hash, COMMITMENT_OFFSET);
+        padded.parent_header.push(0);
+        assert!(!padded.is_valid(&block_hash, required));
+    }
+}
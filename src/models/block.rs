@@ -1,65 +1,266 @@
-use super::blockchain::Blockchain;
+use super::accumulator::Accumulator;
+use super::auxpow::AuxPow;
+use super::hash::Hash256;
 use super::transaction::Transaction;
+use super::work::Work;
 use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 
+// `Block` A header and the transactions it commits to. Both are flattened into one object, so
+// blocks are encoded the way they were before the split.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
+    #[serde(flatten)]
+    pub header: BlockHeader,
+    #[serde(flatten)]
+    pub body: BlockBody,
+}
+
+// `BlockHeader` The fields placing a block in the chain. Its hash is computed over the header
+// alone, which commits to the transactions by their merkle root, so a header and the work it
+// proves can be checked without the body.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockHeader {
     pub index: u64,
     pub timestamp: u64,
     pub proof_of_work: u64,
-    pub previous_hash: String, // Hash of the previous block
+    // Number of leading zero hex digits the block was mined at, covered by its hash so the work
+    // it claims can't be changed after mining.
+    #[serde(default)]
+    pub difficulty: usize,
+    pub previous_hash: Hash256, // Hash of the previous block
+    // Root of the merkle tree over the ids of the transactions in the body.
+    #[serde(default)]
+    pub merkle_root: Hash256,
+    pub hash: Hash256, // Hash of the current block
+    // Accumulator of all transactions up to and including this block.
+    #[serde(default)]
+    pub accumulator: Accumulator,
+    // Accumulator of every block before this one along with the work up to it, see `history`.
+    #[serde(default)]
+    pub history: Accumulator,
+    // Merge mining proof, used instead of a native proof of work.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aux_pow: Option<AuxPow>,
+}
+
+// `BlockBody` The transactions of a block.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlockBody {
     pub transactions: Vec<Transaction>,
-    pub hash: String, // Hash of the current block
+}
+
+// `BlockPreview` The fields of a block needed to recognize it, parsed straight from a received
+// message. The hash is decoded from the borrowed buffer and the transactions are skipped, so
+// nothing is allocated.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct BlockPreview {
+    pub index: u64,
+    pub timestamp: u64,
+    pub hash: Hash256,
 }
 
 impl Block {
-    pub fn new(index: u64, previous_hash: String, transactions: Vec<Transaction>) -> Self {
+    pub fn new(index: u64, previous_hash: Hash256, transactions: Vec<Transaction>) -> Self {
+        let body = BlockBody { transactions };
+
         // Current block to be created.
-        let block = Block {
-            index,
-            timestamp: Utc::now().timestamp_millis() as u64,
-            proof_of_work: u64::default(),
-            previous_hash,
-            transactions,
-            hash: String::default(),
-        };
+        Block {
+            header: BlockHeader {
+                index,
+                timestamp: Utc::now().timestamp_millis() as u64,
+                proof_of_work: u64::default(),
+                difficulty: 0,
+                previous_hash,
+                merkle_root: body.merkle_root(),
+                hash: Hash256::ZERO,
+                accumulator: Accumulator::default(),
+                history: Accumulator::default(),
+                aux_pow: None,
+            },
+            body,
+        }
+    }
+
+    // Calculate block hash.
+    pub fn generate_block_hash(&self) -> Hash256 {
+        self.header.generate_hash()
+    }
+
+    // Check that the block proves at least `required` work.
+    pub fn is_mined(&self, required: Work) -> bool {
+        self.header.is_mined(required)
+    }
+
+    // Check that the header commits to the transactions of the body.
+    pub fn is_merkle_root_valid(&self) -> bool {
+        self.header.merkle_root == self.body.merkle_root()
+    }
+}
+
+impl BlockHeader {
+    // Position of the nonce in `hash_data`, which miners vary on their own.
+    pub const NONCE_OFFSET: usize = 16;
+
+    // Canonical binary encoding of the header data covered by its hash: every number as a
+    // fixed-width big-endian integer and every list prefixed by its length, in the order of the
+    // fields. Unlike JSON it doesn't change with the serializer. The hash itself is left out,
+    // as is the aux-pow, which commits to the hash.
+    pub fn hash_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(128);
+        data.extend_from_slice(&self.index.to_be_bytes());
+        data.extend_from_slice(&self.timestamp.to_be_bytes());
+        data.extend_from_slice(&self.proof_of_work.to_be_bytes());
+        data.extend_from_slice(&(self.difficulty as u64).to_be_bytes());
+        data.extend_from_slice(&self.previous_hash.0);
+        data.extend_from_slice(&self.merkle_root.0);
+        encode_accumulator(&mut data, &self.accumulator);
+        encode_accumulator(&mut data, &self.history);
+        data
+    }
+
+    // Calculate the hash of the header.
+    pub fn generate_hash(&self) -> Hash256 {
+        // Calculate and return SHA-256 hash value.
+        Hash256::digest(self.hash_data())
+    }
 
-        block
+    // Check that the header proves at least `required` work.
+    pub fn is_mined(&self, required: Work) -> bool {
+        match &self.aux_pow {
+            Some(aux_pow) => aux_pow.is_valid(&self.hash, required),
+            None => Work::proven_by(&self.hash) >= required,
+        }
     }
+}
 
-    // Mine block hash.
-    pub fn mine(&mut self, blockchain: Blockchain, mining_flag: &mut bool) {
-        while *mining_flag {
-            if !self.hash.starts_with(&"0".repeat(blockchain.difficulty)) {
-                self.proof_of_work += 1;
-                self.hash = self.generate_block_hash();
-                println!("Hash: {}", self.hash);
-            } else {
-                break;
+// Append `accumulator` to `data`: the number of leaves, then the number of roots and every root,
+// a zero byte standing for an empty one and a one byte followed by the hash for the others.
+fn encode_accumulator(data: &mut Vec<u8>, accumulator: &Accumulator) {
+    data.extend_from_slice(&accumulator.leaves.to_be_bytes());
+    data.extend_from_slice(&(accumulator.roots.len() as u32).to_be_bytes());
+    for root in accumulator.roots.iter() {
+        match root {
+            Some(hash) => {
+                data.push(1);
+                data.extend_from_slice(&hash.0);
             }
+            None => data.push(0),
         }
     }
+}
 
-    // Calculate block hash.
-    pub fn generate_block_hash(&self) -> String {
-        let mut block_data = self.clone();
-        block_data.hash = String::default();
-        // Convert block to JSON format.
-        let serialized_block_data = serde_json::to_string(&block_data).unwrap();
+impl BlockBody {
+    // Root of the merkle tree over the transaction ids, the last id of an odd level paired with
+    // itself. Bodies without transactions have a zero root.
+    pub fn merkle_root(&self) -> Hash256 {
+        let mut level: Vec<Hash256> = self
+            .transactions
+            .iter()
+            .map(|transaction| transaction.id())
+            .collect();
+        if level.is_empty() {
+            return Hash256::ZERO;
+        }
 
-        // println!("Serialized block data: {}", serialized_block_data);
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| {
+                    let right = pair.get(1).unwrap_or(&pair[0]);
+                    Hash256::digest([pair[0].0, right.0].concat())
+                })
+                .collect();
+        }
 
-        // Calculate and return SHA-256 hash value.
-        let mut hasher = Sha256::new();
-        hasher.update(serialized_block_data);
+        level[0]
+    }
+}
 
-        let result = hasher.finalize();
-        format!("{:x}", result)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::address::Address;
+    use crate::models::amount::Amount;
+
+    fn header() -> BlockHeader {
+        BlockHeader {
+            index: 1,
+            timestamp: 1_700_000_000_000,
+            proof_of_work: 42,
+            difficulty: 2,
+            previous_hash: Hash256([0x11; 32]),
+            merkle_root: Hash256([0x22; 32]),
+            hash: Hash256::ZERO,
+            accumulator: Accumulator {
+                roots: vec![None, Some(Hash256([0x33; 32]))],
+                leaves: 2,
+            },
+            history: Accumulator::default(),
+            aux_pow: None,
+        }
     }
 
-    pub fn is_mined(&self, difficulty: usize) -> bool {
-        self.hash.starts_with(&"0".repeat(difficulty))
+    #[test]
+    fn empty_header_hash() {
+        let header = Block::new(0, Hash256::ZERO, Vec::new()).header;
+        let header = BlockHeader {
+            timestamp: 0,
+            ..header
+        };
+
+        assert_eq!(header.hash_data(), vec![0; 120]);
+        assert_eq!(
+            header.generate_hash().to_string(),
+            "6edd9f6f9cc92cded36e6c4a580933f9c9f1b90562b46903b806f21902a1a54f"
+        );
+    }
+
+    #[test]
+    fn header_hash() {
+        assert_eq!(header().hash_data().len(), 154);
+        assert_eq!(
+            header().generate_hash().to_string(),
+            "d79d86cc572a74d32790560902b4145eb7863fdba0d0891018ad948f324d874c"
+        );
+    }
+
+    #[test]
+    fn hash_ignores_hash_and_aux_pow() {
+        let mut header = header();
+        header.hash = Hash256([0x44; 32]);
+        header.aux_pow = Some(AuxPow {
+            parent_header: vec![1; 84],
+            merkle_branch: Vec::new(),
+            merkle_index: 0,
+        });
+
+        assert_eq!(header.generate_hash(), self::header().generate_hash());
+    }
+
+    #[test]
+    fn nonce_offset() {
+        let data = header().hash_data();
+        let nonce = &data[BlockHeader::NONCE_OFFSET..BlockHeader::NONCE_OFFSET + 8];
+
+        assert_eq!(nonce, 42u64.to_be_bytes());
+    }
+
+    #[test]
+    fn merkle_root_pairs_the_last_odd_id_with_itself() {
+        let address = Address::new("12D3KooWAbd4Fbpor5RqFjVHquPwXMHs9iXEhrFhnsafYg4zWEYX")
+            .expect("address is valid");
+        let transactions: Vec<Transaction> = (1..=3)
+            .map(|amount| Transaction::new(address.clone(), address.clone(), Amount(amount)))
+            .collect();
+        let ids: Vec<Hash256> = transactions.iter().map(Transaction::id).collect();
+        let pair = |left: &Hash256, right: &Hash256| Hash256::digest([left.0, right.0].concat());
+
+        let body = BlockBody { transactions };
+        assert_eq!(
+            body.merkle_root(),
+            pair(&pair(&ids[0], &ids[1]), &pair(&ids[2], &ids[2]))
+        );
+        assert_eq!(BlockBody::default().merkle_root(), Hash256::ZERO);
     }
 }
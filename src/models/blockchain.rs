@@ -1,7 +1,132 @@
+use super::accumulator::{self, Accumulator};
+use super::amount::Amount;
+use super::anchor;
 use super::block::Block;
+use super::hash::Hash256;
+use super::index::{AddressIndex, AddressStats, IndexSettings, TransactionLinks};
+use super::journal::{self, Journal, JournalEvent, JournalRecord};
+use super::params::{ChainParams, MAX_DIFFICULTY};
+use super::state::State;
+use super::storage::ChainStore;
+use super::transaction::Transaction;
+use super::work::{self, Work};
+use super::{asset, channel, coinbase, history, htlc, timelock};
 use chrono::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+use std::slice;
+use std::sync::Arc;
 
-type Blocks = Vec<Block>;
+type Blocks = Vec<Arc<Block>>;
+
+// Number of blocks between state snapshots used for historical queries.
+const SNAPSHOT_INTERVAL: u64 = 100;
+// Number of blocks between prunings of the index, if its settings prune it.
+const INDEX_PRUNE_INTERVAL: u64 = 10;
+// Milliseconds a block's timestamp may be ahead of the local clock.
+const MAX_FUTURE_BLOCK_TIME: u64 = 2 * 60 * 60 * 1000;
+
+// `BlockValidationError` Why `is_block_valid` rejects a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockValidationError {
+    // The operator invalidated the block.
+    Invalidated,
+    PreviousHash { expected: Hash256, found: Hash256 },
+    Difficulty { expected: usize, found: usize },
+    // The hash of the block doesn't meet its difficulty.
+    NotMined,
+    Signature { transaction: Hash256 },
+    Index { expected: u64, found: u64 },
+    Hash { expected: Hash256, found: Hash256 },
+    // The header doesn't commit to the transactions of the block.
+    MerkleRoot { expected: Hash256, found: Hash256 },
+    // The block is older than the block before it.
+    TimestampBeforePrevious { previous: u64, found: u64 },
+    // The block is further ahead of the local clock than `MAX_FUTURE_BLOCK_TIME`.
+    TimestampInFuture { now: u64, found: u64 },
+}
+
+impl fmt::Display for BlockValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockValidationError::Invalidated => write!(f, "was invalidated"),
+            BlockValidationError::PreviousHash { expected, found } => {
+                write!(f, "has previous hash {} instead of {}", found, expected)
+            }
+            BlockValidationError::Difficulty { expected, found } => {
+                write!(f, "claims difficulty {} instead of {}", found, expected)
+            }
+            BlockValidationError::NotMined => write!(f, "doesn't meet its difficulty"),
+            BlockValidationError::Signature { transaction } => write!(
+                f,
+                "has transaction {} without a valid signature",
+                transaction
+            ),
+            BlockValidationError::Index { expected, found } => {
+                write!(f, "has index {} instead of {}", found, expected)
+            }
+            BlockValidationError::Hash { expected, found } => {
+                write!(f, "has hash {} instead of {}", found, expected)
+            }
+            BlockValidationError::MerkleRoot { expected, found } => {
+                write!(f, "has merkle root {} instead of {}", found, expected)
+            }
+            BlockValidationError::TimestampBeforePrevious { previous, found } => write!(
+                f,
+                "has timestamp {} before the previous block's {}",
+                found, previous
+            ),
+            BlockValidationError::TimestampInFuture { now, found } => {
+                write!(f, "has timestamp {} too far ahead of {}", found, now)
+            }
+        }
+    }
+}
+
+// `ChainError` Why `choose_chain` keeps the local chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainError {
+    // The remote chain is valid but has no more work.
+    NotEnoughWork,
+    InvalidRemote,
+    // The remote chain is better but switching to it would disconnect more than
+    // `max_reorg_depth` blocks.
+    TooDeep { depth: u64, max: u64 },
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainError::NotEnoughWork => {
+                f.write_str("the remote chain has no more work than the local one")
+            }
+            ChainError::InvalidRemote => f.write_str("the remote chain is invalid"),
+            ChainError::TooDeep { depth, max } => write!(
+                f,
+                "switching to the remote chain disconnects {} blocks, more than the {} allowed",
+                depth, max
+            ),
+        }
+    }
+}
+
+// `ForkCheck` Validation of a fork block by block as it is downloaded, on top of the state of
+// the block it forks off from. Checked blocks are staged in the store, or kept here for chains
+// that only live in memory, so a fork is never held in memory while it downloads.
+#[derive(Debug)]
+pub struct ForkCheck {
+    ancestor: u64,
+    state: State,
+    // Latest blocks of the fork and the ones below it, as many as retargets and time locks look
+    // back at.
+    recent: Blocks,
+    // Number of blocks below the next one, the length of the chain so far.
+    len: usize,
+    difficulty: usize,
+    // Checked blocks, unless they are staged in the store.
+    blocks: Blocks,
+}
 
 // `Blockchain` A struct that represents the blockchain.
 #[derive(Debug, Clone)]
@@ -10,99 +135,1199 @@ pub struct Blockchain {
     pub genesis_block: Block,
     // The storage for blocks.
     pub chain: Blocks,
-    // Minimum amount of work required to mine a block.
-    pub difficulty: usize,
+    // Consensus parameters blocks are mined and validated with.
+    pub params: ChainParams,
+    // State at every `SNAPSHOT_INTERVAL`th block, starting from the genesis block.
+    pub snapshots: Vec<State>,
+    // State after the latest block, kept in sync by the methods changing `chain`.
+    pub state: State,
+    // Difficulty the next block has to be mined at, kept in sync like `state`.
+    next_difficulty: usize,
+    // Per-address statistics of the chain.
+    pub index: AddressIndex,
+    // Position of every block in `chain` by hash, kept in sync by the methods changing `chain`.
+    positions: HashMap<Hash256, usize>,
+    // Where `chain` is persisted, also kept in sync by the methods changing it. The chain only
+    // lives in memory if unset.
+    store: Option<Arc<dyn ChainStore>>,
+    // Journal every change is recorded to before it reaches `store`, if set.
+    journal: Option<Arc<Journal>>,
+    // Blocks the operator invalidated, along with the blocks dropped from the chain with them.
+    invalidated: HashMap<Hash256, Blocks>,
 }
 
 impl Blockchain {
-    pub fn new(difficulty: usize) -> Self {
-        // First block in the chain.
-        let genesis_block = Block {
-            index: 0,
-            timestamp: Utc::now().timestamp_millis() as u64,
-            proof_of_work: u64::default(),
-            previous_hash: String::default(),
-            transactions: Vec::new(),
-            hash: String::default(),
-        };
+    pub fn new(params: ChainParams) -> Self {
+        // First block in the chain, paying out the genesis allocations.
+        let transactions = params
+            .genesis_allocations
+            .iter()
+            .map(|allocation| coinbase::coinbase(&allocation.address, allocation.amount))
+            .collect();
+        let mut genesis_block = Block::new(0, Hash256::ZERO, transactions);
+        genesis_block.header.difficulty = params.difficulty;
+        let mut accumulator = Accumulator::default();
+        accumulator.add_block(&genesis_block);
+        genesis_block.header.accumulator = accumulator;
 
         // Create chain starting from the genesis chain.
-        let mut chain = Vec::new();
-        chain.push(genesis_block.clone());
+        let chain = vec![Arc::new(genesis_block.clone())];
 
         // Create a blockchain Instance.
-        let blockchain = Blockchain {
+        let mut blockchain = Blockchain {
             genesis_block,
             chain,
-            difficulty,
+            params,
+            snapshots: Vec::new(),
+            state: State::default(),
+            next_difficulty: 0,
+            index: AddressIndex::default(),
+            positions: HashMap::new(),
+            store: None,
+            journal: None,
+            invalidated: HashMap::new(),
         };
+        blockchain.rebuild_snapshots();
+        blockchain.next_difficulty = blockchain.difficulty_after(&blockchain.chain);
+        blockchain.index = AddressIndex::from_chain(&blockchain.chain, IndexSettings::default());
+        blockchain.positions = positions(&blockchain.chain);
         blockchain
     }
 
-    pub fn is_block_valid(&self, block: &Block, previous_block: &Block) -> bool {
-        if block.previous_hash != previous_block.hash {
-            println!("Block with id: {} has wrong previous hash", block.index);
-            return false;
-        } else if !block.hash.starts_with(&"0".repeat(self.difficulty)) {
-            return false;
-        } else if block.index != previous_block.index + 1 {
-            println!(
-                "Block with id: {} is not the next block after the latest: {}",
-                block.index, previous_block.index
-            );
-        } else if block.generate_block_hash() != block.hash {
-            println!("Block with id: {} has invalid hash", block.index);
+    // Continue the chain kept in `store`, starting it with a new genesis block if it is empty.
+    pub fn open(params: ChainParams, store: Arc<dyn ChainStore>) -> Result<Self, String> {
+        let mut blockchain = Blockchain::new(params);
+
+        let chain = store.load()?;
+        if chain.is_empty() {
+            store.replace(&blockchain.chain)?;
+        } else if blockchain.is_chain_valid(&chain) {
+            blockchain.genesis_block = chain[0].as_ref().clone();
+            blockchain.replace_chain(chain);
+        } else {
+            return Err("stored chain is invalid".to_string());
         }
 
-        true
+        blockchain.store = Some(store);
+        Ok(blockchain)
     }
 
-    pub fn try_to_add_a_block(&mut self, block: Block) {
+    // Check `block` on top of `previous_block`, `difficulty` being the difficulty it has to be
+    // mined at. Its transactions are left to `are_transactions_valid`, apart from signatures.
+    pub fn is_block_valid(
+        &self,
+        block: &Block,
+        previous_block: &Block,
+        difficulty: usize,
+    ) -> Result<(), BlockValidationError> {
+        if self.invalidated.contains_key(&block.header.hash) {
+            return Err(BlockValidationError::Invalidated);
+        }
+        if block.header.previous_hash != previous_block.header.hash {
+            return Err(BlockValidationError::PreviousHash {
+                expected: previous_block.header.hash,
+                found: block.header.previous_hash,
+            });
+        }
+        if block.header.index != previous_block.header.index + 1 {
+            return Err(BlockValidationError::Index {
+                expected: previous_block.header.index + 1,
+                found: block.header.index,
+            });
+        }
+        // Every node creates its own genesis block, so its timestamp says nothing about the
+        // blocks on top of it.
+        if previous_block.header.index > 0
+            && block.header.timestamp < previous_block.header.timestamp
+        {
+            return Err(BlockValidationError::TimestampBeforePrevious {
+                previous: previous_block.header.timestamp,
+                found: block.header.timestamp,
+            });
+        }
+        let now = Utc::now().timestamp_millis() as u64;
+        if block.header.timestamp > now.saturating_add(MAX_FUTURE_BLOCK_TIME) {
+            return Err(BlockValidationError::TimestampInFuture {
+                now,
+                found: block.header.timestamp,
+            });
+        }
+        if block.header.difficulty != difficulty {
+            return Err(BlockValidationError::Difficulty {
+                expected: difficulty,
+                found: block.header.difficulty,
+            });
+        }
+        let hash = self.params.pow.engine().hash(&block.header);
+        if hash != block.header.hash {
+            return Err(BlockValidationError::Hash {
+                expected: hash,
+                found: block.header.hash,
+            });
+        }
+        let merkle_root = block.body.merkle_root();
+        if merkle_root != block.header.merkle_root {
+            return Err(BlockValidationError::MerkleRoot {
+                expected: merkle_root,
+                found: block.header.merkle_root,
+            });
+        }
+        if !block.is_mined(Work::from_difficulty(difficulty)) {
+            return Err(BlockValidationError::NotMined);
+        }
+        if let Some(transaction) = block.body.transactions.iter().find(|transaction| {
+            !coinbase::is_coinbase(transaction) && !transaction.is_signature_valid()
+        }) {
+            return Err(BlockValidationError::Signature {
+                transaction: transaction.id(),
+            });
+        }
+
+        Ok(())
+    }
+
+    // Work the next block on the chain has to prove.
+    pub fn block_work(&self) -> Work {
+        Work::from_difficulty(self.next_difficulty)
+    }
+
+    // Difficulty the next block on the chain has to be mined at.
+    pub fn next_difficulty(&self) -> usize {
+        self.next_difficulty
+    }
+
+    // Difficulty every block of `chain` had to be mined at, followed by the one the block after
+    // it has to be mined at.
+    pub fn difficulties(&self, chain: &[Arc<Block>]) -> Vec<usize> {
+        let mut difficulty = self.params.difficulty;
+        let mut difficulties = Vec::with_capacity(chain.len() + 1);
+        for height in 0..=chain.len() {
+            difficulty = self.retarget(&chain[..height], difficulty);
+            difficulties.push(difficulty);
+        }
+
+        difficulties
+    }
+
+    // Difficulty the block after `chain` has to be mined at.
+    pub fn difficulty_after(&self, chain: &[Arc<Block>]) -> usize {
+        self.difficulties(chain)
+            .pop()
+            .expect("there is a difficulty after every chain")
+    }
+
+    // Difficulty of the block after `chain`, the last block of which was mined at `difficulty`.
+    // Every `retarget_interval` blocks it moves one digit, 16 times the work, up or down if the
+    // blocks since the last retarget came more than 4 times faster or slower than the target
+    // block time. Smaller deviations stay, a digit would overshoot them.
+    fn retarget(&self, chain: &[Arc<Block>], difficulty: usize) -> usize {
+        self.retarget_after(chain.len(), chain, difficulty)
+    }
+
+    // `retarget` for a chain of `len` blocks, `recent` holding at least its latest
+    // `retarget_interval` blocks.
+    fn retarget_after(&self, len: usize, recent: &[Arc<Block>], difficulty: usize) -> usize {
+        let interval = self.params.retarget_interval as usize;
+        if interval < 2 || len == 0 || !len.is_multiple_of(interval) {
+            return difficulty;
+        }
+
+        let window = &recent[recent.len() - interval..];
+        let actual = window[interval - 1]
+            .header
+            .timestamp
+            .saturating_sub(window[0].header.timestamp);
+        let expected = (interval as u64 - 1)
+            .saturating_mul(self.params.target_block_time)
+            .saturating_mul(1000);
+
+        if actual.saturating_mul(4) < expected {
+            (difficulty + 1).min(MAX_DIFFICULTY)
+        } else if actual > expected.saturating_mul(4) && difficulty > 1 {
+            difficulty - 1
+        } else {
+            difficulty
+        }
+    }
+
+    // Check the transactions of `block` against `chain` and the `state` it built.
+    pub fn are_transactions_valid(
+        &self,
+        block: &Block,
+        chain: &[Arc<Block>],
+        state: &State,
+    ) -> bool {
+        coinbase::is_coinbase_valid(block, self.params.reward_at(block.header.index))
+            && state.can_pay_for(&block.body.transactions)
+            && state.are_nonces_valid(&block.body.transactions)
+            && asset::are_transfers_valid(block)
+            && htlc::are_conditions_valid(block, &state.locks)
+            && timelock::are_time_locks_valid(block, chain)
+            && channel::are_channel_updates_valid(block, &state.channels)
+            && anchor::are_anchors_valid(block, &self.params.chain_id)
+            && accumulator::is_commitment_valid(block, &state.accumulator)
+            && history::is_commitment_valid(block, &state.history)
+    }
+
+    // Commit `block` to the accumulator of the chain extended by its transactions and to the
+    // history of the chain, both kept in the state.
+    pub fn commit_accumulator(&self, block: &mut Block) {
+        let mut accumulator = self.state.accumulator.clone();
+        accumulator.add_block(block);
+
+        block.header.accumulator = accumulator;
+        block.header.history = self.state.history.clone();
+    }
+
+    pub fn try_to_add_a_block(&mut self, block: impl Into<Arc<Block>>) {
+        let block = block.into();
         let last_block = self
             .chain
             .last()
             .expect("There should be at least one block");
 
-        if self.is_block_valid(&block, last_block) {
+        if let Err(err) = self.is_block_valid(&block, last_block, self.next_difficulty) {
+            println!("Block with id: {} {}", block.header.index, err);
+        } else if self.are_transactions_valid(&block, &self.chain, &self.state) {
+            self.record(
+                "connect_block",
+                block.header.hash,
+                vec![JournalEvent::Connect {
+                    height: self.chain.len() as u64,
+                    block: block.clone(),
+                }],
+            );
+            if let Some(store) = &self.store
+                && let Err(err) = store.append(self.chain.len() as u64, &block)
+            {
+                println!("{}", err);
+            }
+
+            self.index.add_block(&block);
+            self.positions.insert(block.header.hash, self.chain.len());
+            self.state.apply_block(&block);
             self.chain.push(block);
+            self.next_difficulty = self.retarget(&self.chain, self.next_difficulty);
+            self.update_snapshots();
+            if (self.chain.len() as u64).is_multiple_of(INDEX_PRUNE_INTERVAL) {
+                self.prune_index();
+            }
         } else {
             println!("Could not add block");
         }
     }
 
-    pub fn is_chain_valid(&self, chain: &[Block]) -> bool {
-        for block_index in 0..chain.len() {
-            if block_index == 0 {
-                continue;
+    // Swap the local chain for `chain` as a whole, rebuilding everything derived from it.
+    pub fn replace_chain(&mut self, chain: Blocks) {
+        let fork = self.fork_point(&chain);
+        self.record_switch("replace_chain", fork, &chain);
+
+        if let Some(store) = &self.store
+            && let Err(err) = store.replace(&chain)
+        {
+            println!("{}", err);
+        }
+
+        self.chain = chain;
+        self.rebuild_snapshots();
+        self.next_difficulty = self.difficulty_after(&self.chain);
+        self.index = AddressIndex::from_chain(&self.chain, self.index.settings().clone());
+        self.prune_index();
+        self.positions = positions(&self.chain);
+    }
+
+    // Switch to `chain`, e.g. one returned by `choose_chain`. The local blocks after the last one
+    // both chains share are disconnected, rolling the state back to that block from the closest
+    // snapshot, and the blocks of `chain` after it are connected on top. Returns the
+    // disconnected blocks, oldest first.
+    pub fn reorganize(&mut self, chain: Blocks) -> Blocks {
+        let fork = self.fork_point(&chain);
+        // Chains starting from another genesis block share nothing to roll back to.
+        if fork == 0 {
+            let disconnected = self.chain.clone();
+            self.replace_chain(chain);
+            return disconnected;
+        }
+        self.record_switch("reorganize", fork, &chain);
+
+        if let Some(store) = &self.store {
+            let stored = store.truncate(fork as u64).and_then(|()| {
+                chain[fork..]
+                    .iter()
+                    .enumerate()
+                    .try_for_each(|(offset, block)| store.append((fork + offset) as u64, block))
+            });
+            if let Err(err) = stored {
+                println!("{}", err);
+            }
+        }
+
+        self.state = self
+            .get_state_at(fork as u64 - 1)
+            .expect("the fork point is on the chain");
+        let disconnected = self.chain.split_off(fork);
+        for block in disconnected.iter() {
+            self.positions.remove(&block.header.hash);
+        }
+        self.snapshots
+            .truncate(((fork as u64 - 1) / SNAPSHOT_INTERVAL) as usize + 1);
+
+        for block in chain[fork..].iter() {
+            self.positions.insert(block.header.hash, self.chain.len());
+            self.state.apply_block(block);
+            self.chain.push(block.clone());
+            self.update_snapshots();
+        }
+        self.next_difficulty = self.difficulty_after(&self.chain);
+        // Spend links can't be taken back block by block, so the index is built anew.
+        self.index = AddressIndex::from_chain(&self.chain, self.index.settings().clone());
+        self.prune_index();
+
+        disconnected
+    }
+
+    // Start checking a fork off the block at `ancestor`, dropping the blocks staged for any fork
+    // before.
+    pub fn check_fork(&self, ancestor: u64) -> Option<ForkCheck> {
+        let state = self.get_state_at(ancestor)?;
+        if let Some(store) = &self.store
+            && let Err(err) = store.clear_staged()
+        {
+            println!("{}", err);
+        }
+
+        let len = ancestor as usize + 1;
+        let recent = self.chain[len.saturating_sub(self.recent_blocks())..len].to_vec();
+        Some(ForkCheck {
+            ancestor,
+            state,
+            recent,
+            len,
+            difficulty: self.difficulty_after(&self.chain[..len]),
+            blocks: Vec::new(),
+        })
+    }
+
+    // Check `blocks` as the next ones of `fork` and stage them.
+    pub fn extend_fork(&self, fork: &mut ForkCheck, blocks: Blocks) -> Result<(), String> {
+        for block in blocks.iter() {
+            let previous = fork.recent.last().expect("forks start from a block");
+            if let Err(err) = self.is_block_valid(block, previous, fork.difficulty) {
+                return Err(format!("block {} {}", block.header.index, err));
+            }
+            if !self.are_transactions_valid(block, &fork.recent, &fork.state) {
+                return Err(format!(
+                    "block {} has invalid transactions",
+                    block.header.index
+                ));
+            }
+
+            fork.state.apply_block(block);
+            fork.recent.push(block.clone());
+            fork.len += 1;
+            fork.difficulty = self.retarget_after(fork.len, &fork.recent, fork.difficulty);
+            if fork.recent.len() > 2 * self.recent_blocks() {
+                fork.recent
+                    .drain(..fork.recent.len() - self.recent_blocks());
+            }
+        }
+
+        match &self.store {
+            Some(store) => store.stage(fork.len as u64 - blocks.len() as u64, &blocks),
+            None => {
+                fork.blocks.extend(blocks);
+                Ok(())
+            }
+        }
+    }
+
+    // The chain `fork` leads to, the local blocks up to its ancestor and the checked ones after.
+    pub fn fork_chain(&self, fork: ForkCheck) -> Result<Blocks, String> {
+        let blocks = match &self.store {
+            Some(store) => {
+                let blocks = store.staged()?;
+                store.clear_staged()?;
+                blocks
+            }
+            None => fork.blocks,
+        };
+
+        let mut chain: Blocks = self.iter_blocks(..=fork.ancestor).cloned().collect();
+        chain.extend(blocks);
+        Ok(chain)
+    }
+
+    // Blocks retargets and time locks look back at.
+    fn recent_blocks(&self) -> usize {
+        (self.params.retarget_interval as usize).max(timelock::MEDIAN_TIME_SPAN)
+    }
+
+    // Number of blocks the local chain shares with `chain`.
+    fn fork_point(&self, chain: &[Arc<Block>]) -> usize {
+        self.chain
+            .iter()
+            .zip(chain.iter())
+            .take_while(|(local, other)| local.header.hash == other.header.hash)
+            .count()
+    }
+
+    // Record the switch to `chain`, which shares `fork` blocks with the local one.
+    fn record_switch(&self, operation: &str, fork: usize, chain: &[Arc<Block>]) {
+        if self.journal.is_none() {
+            return;
+        }
+
+        let mut events = Vec::new();
+        if fork < self.chain.len() {
+            events.push(JournalEvent::Disconnect {
+                height: fork as u64,
+            });
+        }
+        events.extend(chain[fork..].iter().enumerate().map(|(offset, block)| {
+            JournalEvent::Connect {
+                height: (fork + offset) as u64,
+                block: block.clone(),
+            }
+        }));
+        let tip = chain.last().expect("chains aren't empty").header.hash;
+        self.record(operation, tip, events);
+    }
+
+    // Treat the block with `hash` as invalid, dropping it and its descendants from the chain.
+    // Blocks that aren't on the chain are rejected once they arrive. Returns the number of
+    // blocks dropped.
+    pub fn invalidate_block(&mut self, hash: Hash256) -> Result<usize, String> {
+        if self.invalidated.contains_key(&hash) {
+            return Err(format!("block {} is already invalidated", hash));
+        }
+
+        let dropped = match self.positions.get(&hash) {
+            Some(0) => return Err("can't invalidate the genesis block".to_string()),
+            Some(&position) => self.reorganize(self.chain[..position].to_vec()),
+            None => Vec::new(),
+        };
+
+        let count = dropped.len();
+        self.invalidated.insert(hash, dropped);
+        Ok(count)
+    }
+
+    // Undo `invalidate_block`. The blocks dropped with it return if they still make up the best
+    // chain. Returns whether they did.
+    pub fn reconsider_block(&mut self, hash: Hash256) -> Result<bool, String> {
+        let dropped = self
+            .invalidated
+            .remove(&hash)
+            .ok_or_else(|| format!("block {} isn't invalidated", hash))?;
+
+        let Some(position) = dropped
+            .first()
+            .and_then(|block| self.positions.get(&block.header.previous_hash))
+        else {
+            return Ok(false);
+        };
+        let mut chain = self.chain[..=*position].to_vec();
+        chain.extend(dropped);
+
+        match self.choose_chain_at_any_depth(chain) {
+            Ok(chain) => {
+                self.reorganize(chain);
+                Ok(true)
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    // Keep the indexes `settings` enable, rebuilding them from the chain.
+    pub fn set_index_settings(&mut self, settings: IndexSettings) {
+        self.index = AddressIndex::from_chain(&self.chain, settings);
+        self.prune_index();
+    }
+
+    // Drop index entries of blocks past the depth or age the index settings keep.
+    fn prune_index(&mut self) {
+        let settings = self.index.settings();
+        let mut height = match settings.depth {
+            Some(depth) => (self.chain.len() as u64).saturating_sub(depth),
+            None => 0,
+        };
+        if let Some(max_age) = settings.max_age {
+            let oldest = (Utc::now().timestamp_millis() as u64).saturating_sub(max_age * 1000);
+            let young = self
+                .chain
+                .partition_point(|block| block.header.timestamp < oldest);
+            height = height.max(young as u64);
+        }
+
+        self.index.prune(height);
+    }
+
+    // Check that the store can still be read. Chains kept in memory only have nothing to check.
+    pub fn check_store(&self) -> Result<(), String> {
+        match &self.store {
+            Some(store) => store.check(),
+            None => Ok(()),
+        }
+    }
+
+    // Record further changes to `journal` before they reach the store.
+    pub fn set_journal(&mut self, journal: Arc<Journal>) {
+        self.journal = Some(journal);
+    }
+
+    fn record(&self, operation: &str, tip: Hash256, events: Vec<JournalEvent>) {
+        if let Some(journal) = &self.journal {
+            journal.record(&JournalRecord::new(operation, Some(tip), events));
+        }
+    }
+
+    // Apply the changes of `records` that never reached the store, e.g. as the node crashed
+    // while writing them. Returns the number of blocks connected.
+    pub fn recover(&mut self, records: &[JournalRecord]) -> Result<usize, String> {
+        let tip = self.chain.last().expect("has to exist").header.hash;
+        let mut chain = self.chain.clone();
+        let mut connected = 0;
+
+        for event in journal::missing_chain_events(records, tip) {
+            match event {
+                JournalEvent::Connect { height, block } => {
+                    let height = *height as usize;
+                    if height > chain.len() {
+                        return Err(format!("journal connects block {} past the tip", height));
+                    }
+                    chain.truncate(height);
+                    chain.push(block.clone());
+                    connected += 1;
+                }
+                JournalEvent::Disconnect { height } => chain.truncate(*height as usize),
+                _ => {}
+            }
+        }
+
+        if connected == 0 && chain.len() == self.chain.len() {
+            return Ok(0);
+        }
+        if chain.is_empty() || !self.is_chain_valid(&chain) {
+            return Err("journal leads to an invalid chain".to_string());
+        }
+        self.replace_chain(chain);
+        Ok(connected)
+    }
+
+    pub fn invalidated_blocks(&self) -> impl Iterator<Item = &Hash256> {
+        self.invalidated.keys()
+    }
+
+    pub fn get_block_by_height(&self, height: u64) -> Option<&Arc<Block>> {
+        self.chain
+            .get(height as usize)
+            .filter(|block| block.header.index == height)
+    }
+
+    // Blocks at the heights in `range`, borrowed from the chain rather than copied out of it.
+    pub fn iter_blocks(&self, range: impl RangeBounds<u64>) -> slice::Iter<'_, Arc<Block>> {
+        let len = self.chain.len();
+        let start = match range.start_bound() {
+            Bound::Included(start) => *start as usize,
+            Bound::Excluded(start) => (*start as usize).saturating_add(1),
+            Bound::Unbounded => 0,
+        }
+        .min(len);
+        let end = match range.end_bound() {
+            Bound::Included(end) => (*end as usize).saturating_add(1),
+            Bound::Excluded(end) => *end as usize,
+            Bound::Unbounded => len,
+        }
+        .clamp(start, len);
+
+        self.chain[start..end].iter()
+    }
+
+    pub fn get_block_by_hash(&self, hash: &Hash256) -> Option<&Arc<Block>> {
+        self.positions
+            .get(hash)
+            .and_then(|position| self.chain.get(*position))
+    }
+
+    // Dry run of `transaction` against the tip of the chain, nothing is added anywhere. Returns
+    // the name of every check a block including it would have to pass, and whether it passes.
+    pub fn test_accept(&self, transaction: &Transaction) -> Vec<(&'static str, bool)> {
+        self.test_accept_package(std::slice::from_ref(transaction))
+    }
+
+    // Dry run of `transactions` in one block, in order, like `test_accept`.
+    pub fn test_accept_package(&self, transactions: &[Transaction]) -> Vec<(&'static str, bool)> {
+        let mut checks = vec![
+            (
+                "not a coinbase",
+                !transactions.iter().any(coinbase::is_coinbase),
+            ),
+            (
+                "signature",
+                transactions
+                    .iter()
+                    .all(|transaction| transaction.is_signature_valid()),
+            ),
+        ];
+        checks.extend(self.test_accept_at_tip(transactions));
+        checks
+    }
+
+    // The checks of `test_accept_package` that depend on the chain. Signatures stay valid
+    // whatever the tip, so pending transactions only need these once the tip moves. They run
+    // against the state of the tip rather than the blocks of the chain.
+    pub fn test_accept_at_tip(&self, transactions: &[Transaction]) -> Vec<(&'static str, bool)> {
+        let latest_block = self.chain.last().expect("there is at least one block");
+        let block = Block::new(
+            latest_block.header.index + 1,
+            latest_block.header.hash,
+            transactions.to_vec(),
+        );
+
+        vec![
+            ("balance", self.state.can_pay_for(&block.body.transactions)),
+            // Pending transactions of the sender may come first, so only nonces that were used
+            // already are turned away.
+            (
+                "nonce",
+                transactions.iter().all(|transaction| {
+                    transaction.nonce >= self.state.next_nonce(transaction.sender.as_str())
+                }),
+            ),
+            ("asset transfers", asset::are_transfers_valid(&block)),
+            (
+                "htlc conditions",
+                htlc::are_conditions_valid(&block, &self.state.locks),
+            ),
+            (
+                "time locks",
+                timelock::are_time_locks_valid(&block, &self.chain),
+            ),
+            (
+                "channel updates",
+                channel::are_channel_updates_valid(&block, &self.state.channels),
+            ),
+            (
+                "anchors",
+                anchor::are_anchors_valid(&block, &self.params.chain_id),
+            ),
+        ]
+    }
+
+    // Median time past of the chain up to the block at `height`, see `timelock`.
+    pub fn median_time_past(&self, height: u64) -> Option<u64> {
+        let end = usize::try_from(height).ok()?.checked_add(1)?;
+        Some(timelock::median_time_past(self.chain.get(..end)?))
+    }
+
+    // Work of the chain, see `work::cumulative_work`.
+    pub fn work(&self) -> Work {
+        self.state.work
+    }
+
+    // Balance of `address` after the latest block.
+    pub fn balance_of(&self, address: &str) -> Amount {
+        self.state.balance_of(address)
+    }
+
+    // Statistics of `address` along with its current balance.
+    pub fn address_stats(&self, address: &str) -> Option<(AddressStats, Amount)> {
+        let stats = self.index.get(address)?.clone();
+
+        Some((stats, self.balance_of(address)))
+    }
+
+    // The transaction with `id` along with its spend links.
+    pub fn find_transaction(&self, id: &Hash256) -> Option<(&Transaction, &TransactionLinks)> {
+        let links = self.index.links(id)?;
+        let transaction = self
+            .chain
+            .get(links.height as usize)?
+            .body
+            .transactions
+            .iter()
+            .find(|transaction| transaction.id() == *id)?;
+
+        Some((transaction, links))
+    }
+
+    // Build the state after block `height` from the closest snapshot before it.
+    pub fn get_state_at(&self, height: u64) -> Option<State> {
+        if height as usize >= self.chain.len() {
+            return None;
+        } else if height as usize == self.chain.len() - 1 {
+            return Some(self.state.clone());
+        }
+
+        let mut state = self
+            .snapshots
+            .get((height / SNAPSHOT_INTERVAL) as usize)?
+            .clone();
+        for block in self.iter_blocks(state.height + 1..=height) {
+            state.apply_block(block);
+        }
+
+        Some(state)
+    }
+
+    pub fn get_balance_at(&self, address: &str, height: u64) -> Option<Amount> {
+        self.get_state_at(height)
+            .map(|state| state.balance_of(address))
+    }
+
+    // Snapshot the state if the latest block is at a snapshot height.
+    fn update_snapshots(&mut self) {
+        let height = self.chain.len() as u64 - 1;
+
+        if height.is_multiple_of(SNAPSHOT_INTERVAL)
+            && self.snapshots.len() as u64 == height / SNAPSHOT_INTERVAL
+        {
+            self.snapshots.push(self.state.clone());
+        }
+    }
+
+    fn rebuild_snapshots(&mut self) {
+        let mut state = State::default();
+        self.snapshots.clear();
+
+        for (height, block) in self.chain.iter().enumerate() {
+            state.apply_block(block);
+            if (height as u64).is_multiple_of(SNAPSHOT_INTERVAL) {
+                self.snapshots.push(state.clone());
+            }
+        }
+
+        self.state = state;
+    }
+
+    // Check that `block` is a genesis block of this chain. Every node creates its own, so
+    // only what the parameters fix can be checked: it pays out the genesis allocations and
+    // nothing else, at the initial difficulty.
+    fn is_genesis_valid(&self, block: &Block) -> bool {
+        let allocations: Vec<Hash256> = self
+            .params
+            .genesis_allocations
+            .iter()
+            .map(|allocation| coinbase::coinbase(&allocation.address, allocation.amount).id())
+            .collect();
+        let transactions: Vec<Hash256> = block
+            .body
+            .transactions
+            .iter()
+            .map(Transaction::id)
+            .collect();
+
+        if block.header.index != 0
+            || block.header.previous_hash != Hash256::ZERO
+            || block.header.difficulty != self.params.difficulty
+            || transactions != allocations
+        {
+            println!("Genesis block doesn't match the chain parameters");
+            return false;
+        }
+
+        true
+    }
+
+    pub fn is_chain_valid(&self, chain: &[Arc<Block>]) -> bool {
+        let Some(genesis) = chain.first() else {
+            return true;
+        };
+        if !self.is_genesis_valid(genesis) {
+            return false;
+        }
+
+        let mut state = State::default();
+        state.apply_block(genesis);
+        self.are_blocks_valid(chain, 1, state)
+    }
+
+    // Check that `remote` is valid. The local chain was checked block by block as it grew, so
+    // only the blocks after the ones both chains share are, on top of the local state there.
+    fn is_remote_valid(&self, remote: &[Arc<Block>]) -> bool {
+        match self.fork_point(remote) {
+            0 => self.is_chain_valid(remote),
+            fork => {
+                let state = self
+                    .get_state_at(fork as u64 - 1)
+                    .expect("the fork point is on the chain");
+                self.are_blocks_valid(remote, fork, state)
             }
+        }
+    }
+
+    // Check the blocks of `chain` from `start` on, `state` being the state after the block
+    // before it.
+    fn are_blocks_valid(&self, chain: &[Arc<Block>], start: usize, mut state: State) -> bool {
+        let difficulties = self.difficulties(chain);
 
+        for block_index in start..chain.len() {
             let first = chain.get(block_index - 1).expect("has to exist");
             let second = chain.get(block_index).expect("has to exist");
 
-            if !self.is_block_valid(second, first) {
+            if let Err(err) = self.is_block_valid(second, first, difficulties[block_index]) {
+                println!("Block with id: {} {}", second.header.index, err);
+                return false;
+            }
+            if !self.are_transactions_valid(second, &chain[..block_index], &state) {
                 return false;
             }
+            state.apply_block(second);
         }
 
         true
     }
 
-    pub fn choose_chain(&mut self, local: Vec<Block>, remote: Vec<Block>) -> Vec<Block> {
-        let is_local_valid = self.is_chain_valid(&local);
-        let is_remote_valid = self.is_chain_valid(&remote);
+    // Compare `remote` with the local chain. Returns `remote` if it should replace the local
+    // chain, or why the local one is kept. Of two valid chains the one with more cumulative work
+    // wins rather than the longer one, ties keep the local chain. Chains forking off deeper than
+    // `max_reorg_depth` blocks are kept out until an operator approves them.
+    pub fn choose_chain(&self, remote: Blocks) -> Result<Blocks, ChainError> {
+        let remote = self.choose_chain_at_any_depth(remote)?;
+        let depth = self.reorg_depth(&remote);
+        match self.params.max_reorg_depth {
+            max if max > 0 && depth > max => Err(ChainError::TooDeep { depth, max }),
+            _ => Ok(remote),
+        }
+    }
+
+    // Number of local blocks switching to `chain` would disconnect.
+    pub fn reorg_depth(&self, chain: &[Arc<Block>]) -> u64 {
+        (self.chain.len() - self.fork_point(chain)) as u64
+    }
+
+    // `choose_chain` without the limit on the depth of reorgs, for switches an operator asked
+    // for.
+    pub fn choose_chain_at_any_depth(&self, remote: Blocks) -> Result<Blocks, ChainError> {
+        if !self.is_remote_valid(&remote) {
+            return Err(ChainError::InvalidRemote);
+        }
+        if self.work() >= work::cumulative_work(&remote) {
+            return Err(ChainError::NotEnoughWork);
+        }
+
+        Ok(remote)
+    }
+}
+
+fn positions(chain: &[Arc<Block>]) -> HashMap<Hash256, usize> {
+    chain
+        .iter()
+        .enumerate()
+        .map(|(position, block)| (block.header.hash, position))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::address::Address;
+    use crate::models::coinbase::Payout;
+    use crate::models::params::Allocation;
+    use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+
+    fn keypair() -> Keypair {
+        let secret = SecretKey::from_bytes(&[1; 32]).expect("any 32 bytes are a secret key");
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    // Difficulty 1 retargeting every 2 blocks, aiming for a block a minute.
+    fn params() -> ChainParams {
+        ChainParams {
+            difficulty: 1,
+            retarget_interval: 2,
+            target_block_time: 60,
+            ..ChainParams::default()
+        }
+    }
 
-        if is_local_valid && is_remote_valid {
-            if local.len() >= remote.len() {
-                local
-            } else {
-                remote
+    // Next block of `blockchain` paying `miner`, `after` milliseconds after the latest block and
+    // mined at `difficulty`.
+    fn next_block(blockchain: &Blockchain, miner: &str, after: u64, difficulty: usize) -> Block {
+        next_block_with(blockchain, miner, after, difficulty, Vec::new())
+    }
+
+    // `next_block` including `transactions`.
+    fn next_block_with(
+        blockchain: &Blockchain,
+        miner: &str,
+        after: u64,
+        difficulty: usize,
+        transactions: Vec<Transaction>,
+    ) -> Block {
+        let latest = blockchain.chain.last().expect("there is a genesis block");
+        let mut block = Block::new(latest.header.index + 1, latest.header.hash, transactions);
+        block.header.timestamp = latest.header.timestamp + after;
+        block.header.difficulty = difficulty;
+        let payout = Payout::new(Address::new(miner).expect("address is valid"), Vec::new())
+            .expect("payout has no splits");
+        let reward = blockchain.params.reward_at(block.header.index);
+        coinbase::apply_payout(&mut block, &payout, reward);
+        blockchain.commit_accumulator(&mut block);
+
+        let engine = blockchain.params.pow.engine();
+        loop {
+            block.header.hash = engine.hash(&block.header);
+            if block.is_mined(Work::from_difficulty(difficulty)) {
+                return block;
             }
-        } else if !is_local_valid && is_remote_valid {
-            remote
-        } else if is_local_valid && !is_remote_valid {
+            block.header.proof_of_work += 1;
+        }
+    }
+
+    // Add the next block of `blockchain` like `next_block`, at the difficulty it asks for.
+    fn add_block(blockchain: &mut Blockchain, miner: &str, after: u64) {
+        let len = blockchain.chain.len();
+        let block = next_block(blockchain, miner, after, blockchain.next_difficulty());
+        blockchain.try_to_add_a_block(block);
+        assert_eq!(blockchain.chain.len(), len + 1, "block is valid");
+    }
+
+    #[test]
+    fn fast_blocks_raise_the_difficulty() {
+        let mut blockchain = Blockchain::new(params());
+        add_block(&mut blockchain, "miner", 1_000);
+        assert_eq!(blockchain.next_difficulty(), 2);
+
+        // The next blocks are accepted at the new difficulty.
+        add_block(&mut blockchain, "miner", 60_000);
+        assert_eq!(blockchain.chain[2].header.difficulty, 2);
+        assert_eq!(blockchain.difficulties(&blockchain.chain), vec![1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn slow_blocks_lower_the_difficulty_and_close_ones_keep_it() {
+        let mut blockchain = Blockchain::new(ChainParams {
+            difficulty: 2,
+            ..params()
+        });
+        add_block(&mut blockchain, "miner", 5 * 60_000);
+        assert_eq!(blockchain.next_difficulty(), 1);
+
+        let mut blockchain = Blockchain::new(ChainParams {
+            difficulty: 2,
+            ..params()
+        });
+        add_block(&mut blockchain, "miner", 2 * 60_000);
+        assert_eq!(blockchain.next_difficulty(), 2);
+    }
+
+    #[test]
+    fn block_at_the_difficulty_before_a_retarget_is_rejected() {
+        let mut blockchain = Blockchain::new(params());
+        add_block(&mut blockchain, "miner", 1_000);
+
+        let block = next_block(&blockchain, "miner", 60_000, 1);
+        let latest = blockchain.chain.last().expect("there is a block").clone();
+        assert!(matches!(
+            blockchain.is_block_valid(&block, &latest, blockchain.next_difficulty()),
+            Err(BlockValidationError::Difficulty {
+                expected: 2,
+                found: 1
+            })
+        ));
+        blockchain.try_to_add_a_block(block);
+        assert_eq!(blockchain.chain.len(), 2);
+    }
+
+    // Chain of 3 blocks at difficulty 1 and one of 2 blocks, the second of them at difficulty 2
+    // after a fast first block, both off the same genesis block.
+    fn long_and_heavy_chains() -> (Blockchain, Blockchain) {
+        let genesis = Blockchain::new(params());
+
+        let mut long = genesis.clone();
+        for _ in 0..3 {
+            add_block(&mut long, "long", 60_000);
+        }
+        let mut heavy = genesis;
+        add_block(&mut heavy, "heavy", 1_000);
+        add_block(&mut heavy, "heavy", 60_000);
+
+        (long, heavy)
+    }
+
+    #[test]
+    fn chain_with_more_work_is_chosen_even_if_shorter() {
+        let (long, heavy) = long_and_heavy_chains();
+        assert!(heavy.work() > long.work());
+
+        let chosen = long
+            .choose_chain(heavy.chain.clone())
+            .expect("has more work");
+        assert_eq!(chosen.len(), 3);
+    }
+
+    #[test]
+    fn chain_without_more_work_is_rejected_even_if_longer() {
+        let (long, heavy) = long_and_heavy_chains();
+
+        assert!(matches!(
+            heavy.choose_chain(long.chain.clone()),
+            Err(ChainError::NotEnoughWork)
+        ));
+        // Ties keep the local chain.
+        assert!(matches!(
+            long.choose_chain(long.chain.clone()),
+            Err(ChainError::NotEnoughWork)
+        ));
+    }
+
+    #[test]
+    fn chain_claiming_work_it_didnt_prove_is_rejected() {
+        let (long, _) = long_and_heavy_chains();
+        let mut remote = long.chain.clone();
+        // A block declaring a higher difficulty than the chain asks for.
+        let block = next_block(&long, "long", 60_000, 3);
+        remote.push(Arc::new(block));
+
+        assert!(matches!(
+            long.choose_chain(remote),
+            Err(ChainError::InvalidRemote)
+        ));
+    }
+
+    #[test]
+    fn reorganize_rolls_back_to_the_fork_point() {
+        let mut local = Blockchain::new(params());
+        add_block(&mut local, "shared", 60_000);
+        let mut fork = local.clone();
+        add_block(&mut local, "local", 60_000);
+        add_block(&mut local, "local", 60_000);
+        for _ in 0..3 {
+            add_block(&mut fork, "fork", 60_000);
+        }
+        let chain = local
+            .choose_chain(fork.chain.clone())
+            .expect("has more work");
+
+        let disconnected = local.reorganize(chain);
+        assert_eq!(disconnected.len(), 2);
+        assert!(
             local
-        } else {
-            panic!("Both chains are invalid");
+                .get_block_by_hash(&disconnected[0].header.hash)
+                .is_none()
+        );
+        // The state is the one of the fork, built on the shared block.
+        assert_eq!(local.balance_of("shared"), Amount::from_coins(50));
+        assert_eq!(local.balance_of("local"), Amount::ZERO);
+        assert_eq!(local.balance_of("fork"), Amount::from_coins(150));
+        assert_eq!(local.state.root(), fork.state.root());
+        assert_eq!(local.work(), fork.work());
+        assert_eq!(local.next_difficulty(), fork.next_difficulty());
+
+        // Blocks on top of the new tip are accepted.
+        add_block(&mut local, "fork", 60_000);
+    }
+
+    #[test]
+    fn reorg_deeper_than_the_limit_is_rejected() {
+        let mut local = Blockchain::new(ChainParams {
+            max_reorg_depth: 1,
+            ..params()
+        });
+        let mut fork = local.clone();
+        add_block(&mut local, "local", 60_000);
+        add_block(&mut local, "local", 60_000);
+        add_block(&mut fork, "fork", 1_000);
+        add_block(&mut fork, "fork", 60_000);
+
+        assert!(matches!(
+            local.choose_chain(fork.chain.clone()),
+            Err(ChainError::TooDeep { depth: 2, max: 1 })
+        ));
+        // Unless an operator asks for it.
+        let chain = local
+            .choose_chain_at_any_depth(fork.chain.clone())
+            .expect("has more work");
+        local.reorganize(chain);
+        assert_eq!(local.balance_of("local"), Amount::ZERO);
+    }
+
+    #[test]
+    fn chain_with_another_genesis_block_is_rejected() {
+        let local = Blockchain::new(params());
+        // A peer's own genesis block paying it, behind more work than the local chain.
+        let mut forged = Blockchain::new(ChainParams {
+            genesis_allocations: vec![Allocation {
+                address: Address::new("thief").expect("address is valid"),
+                amount: Amount(1_000_000),
+            }],
+            ..params()
+        });
+        for _ in 0..3 {
+            add_block(&mut forged, "thief", 60_000);
         }
+
+        assert!(matches!(
+            local.choose_chain(forged.chain.clone()),
+            Err(ChainError::InvalidRemote)
+        ));
+
+        // The local genesis block at another difficulty doesn't pass either.
+        let mut genesis = local.chain[0].as_ref().clone();
+        genesis.header.difficulty += 1;
+        assert!(!local.is_chain_valid(&[Arc::new(genesis)]));
+        assert!(local.is_chain_valid(&local.chain));
+    }
+
+    #[test]
+    fn remote_chain_is_checked_on_the_state_at_the_fork_point() {
+        let keypair = keypair();
+        let sender = Address::from_public_key(&keypair.public);
+        let mut local = Blockchain::new(ChainParams {
+            genesis_allocations: vec![Allocation {
+                address: sender.clone(),
+                amount: Amount(100),
+            }],
+            ..params()
+        });
+        add_block(&mut local, "shared", 60_000);
+        let mut fork = local.clone();
+
+        let transfer = |nonce| {
+            let receiver = Address::new("receiver").expect("address is valid");
+            let mut transaction = Transaction::new(sender.clone(), receiver, Amount(10));
+            transaction.nonce = nonce;
+            transaction.sign(&keypair);
+            transaction
+        };
+        let block = next_block_with(
+            &local,
+            "local",
+            60_000,
+            local.next_difficulty(),
+            vec![transfer(0)],
+        );
+        local.try_to_add_a_block(block);
+        assert_eq!(local.chain.len(), 3, "block is valid");
+
+        // The second transfer of the sender only follows the first one on the local branch.
+        let block = next_block_with(
+            &fork,
+            "fork",
+            60_000,
+            fork.next_difficulty(),
+            vec![transfer(1)],
+        );
+        fork.chain.push(Arc::new(block));
+        assert!(matches!(
+            local.choose_chain(fork.chain.clone()),
+            Err(ChainError::InvalidRemote)
+        ));
+
+        // The first one is valid on top of the fork point as well.
+        fork.chain.pop();
+        add_block(&mut fork, "fork", 60_000);
+        let block = next_block_with(
+            &fork,
+            "fork",
+            60_000,
+            fork.next_difficulty(),
+            vec![transfer(0)],
+        );
+        fork.try_to_add_a_block(block);
+        assert_eq!(fork.chain.len(), 4, "block is valid");
+        assert!(local.choose_chain(fork.chain.clone()).is_ok());
     }
 }
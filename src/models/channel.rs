@@ -0,0 +1,298 @@
+use super::address::Address;
+use super::amount::Amount;
+use super::block::Block;
+use super::condition::Condition;
+use super::hash::Hash256;
+use super::transaction::Transaction;
+use ed25519_dalek::{Keypair, Signature, Signer};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// `Channel` A two-party payment channel funded by the opening transaction. The sender and
+// receiver of that transaction are the two parties and its amount is the channel capacity.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Channel {
+    // Number of blocks a unilateral close can be contested for.
+    pub dispute_period: u64,
+}
+
+// `ChannelState` An off-chain balance split exchanged between the parties. Only a state both
+// parties signed can close or contest a channel, so neither can claim a split the other didn't
+// agree to.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChannelState {
+    // Id of the transaction that opened the channel.
+    pub channel_id: Hash256,
+    // Increases with every update, the highest sequence wins a dispute.
+    pub sequence: u64,
+    // Balances of the funder and the counterparty.
+    pub balances: [Amount; 2],
+    // Signatures of `signing_data` by the keys controlling the funder and the counterparty
+    // addresses.
+    #[serde(default)]
+    pub signatures: [Vec<u8>; 2],
+}
+
+impl ChannelState {
+    // Data the parties sign, the channel id, sequence and balances.
+    pub fn signing_data(&self) -> Vec<u8> {
+        serde_json::to_vec(&(&self.channel_id, self.sequence, &self.balances))
+            .expect("can jsonify channel state")
+    }
+
+    // Sign as party `party`, 0 for the funder and 1 for the counterparty.
+    pub fn sign(&mut self, party: usize, keypair: &Keypair) {
+        self.signatures[party] = keypair.sign(&self.signing_data()).to_bytes().to_vec();
+    }
+
+    // Check that the keys controlling both `parties` signed the state.
+    pub fn is_signed_by(&self, parties: &[Address; 2]) -> bool {
+        let signing_data = self.signing_data();
+
+        parties
+            .iter()
+            .zip(self.signatures.iter())
+            .all(|(party, signature)| {
+                let (Some(public_key), Ok(signature)) = (
+                    party.public_key(),
+                    Signature::try_from(signature.as_slice()),
+                ) else {
+                    return false;
+                };
+                public_key.verify_strict(&signing_data, &signature).is_ok()
+            })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Status {
+    Open,
+    Closing { state: ChannelState, deadline: u64 },
+    Closed,
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    parties: [Address; 2],
+    capacity: Amount,
+    dispute_period: u64,
+    status: Status,
+}
+
+pub type Channels = HashMap<Hash256, Entry>;
+
+// Check that every channel update in `block` is valid given the `open` channels of the chain it
+// builds on. Only the channels the block updates are copied to apply it to.
+pub fn are_channel_updates_valid(block: &Block, open: &Channels) -> bool {
+    let mut channels = Channels::new();
+
+    for transaction in block.body.transactions.iter() {
+        if let Some(channel_id) = updated_channel(transaction)
+            && !channels.contains_key(channel_id)
+            && let Some(entry) = open.get(channel_id)
+        {
+            channels.insert(*channel_id, entry.clone());
+        }
+        if apply(&mut channels, transaction, block.header.index).is_none() {
+            println!(
+                "Block with id: {} has an invalid channel update",
+                block.header.index
+            );
+            return false;
+        }
+    }
+
+    true
+}
+
+// Apply a channel update included at block `height`. Returns the payouts released by the
+// update, or `None` if it is invalid.
+pub fn apply(
+    channels: &mut Channels,
+    transaction: &Transaction,
+    height: u64,
+) -> Option<Vec<(Address, Amount)>> {
+    match &transaction.condition {
+        Some(Condition::ChannelOpen(channel)) => {
+            if transaction.sender == transaction.receiver {
+                return None;
+            }
+
+            channels.insert(
+                transaction.id(),
+                Entry {
+                    parties: [transaction.sender.clone(), transaction.receiver.clone()],
+                    capacity: transaction.amount,
+                    dispute_period: channel.dispute_period,
+                    status: Status::Open,
+                },
+            );
+            Some(Vec::new())
+        }
+        Some(Condition::ChannelClose { state, cooperative }) => {
+            let entry = participant_entry(channels, transaction, state)?;
+            if !matches!(entry.status, Status::Open) {
+                return None;
+            }
+
+            if *cooperative {
+                entry.status = Status::Closed;
+                Some(payouts(entry, state))
+            } else {
+                entry.status = Status::Closing {
+                    state: state.clone(),
+                    deadline: height + entry.dispute_period,
+                };
+                Some(Vec::new())
+            }
+        }
+        Some(Condition::ChannelContest { state }) => {
+            let entry = participant_entry(channels, transaction, state)?;
+            let Status::Closing {
+                state: pending,
+                deadline,
+            } = &entry.status
+            else {
+                return None;
+            };
+            if height >= *deadline || state.sequence <= pending.sequence {
+                return None;
+            }
+
+            entry.status = Status::Closing {
+                state: state.clone(),
+                deadline: *deadline,
+            };
+            Some(Vec::new())
+        }
+        Some(Condition::ChannelSettle { channel_id }) => {
+            let entry = channels.get_mut(channel_id)?;
+            let Status::Closing { state, deadline } = &entry.status else {
+                return None;
+            };
+            if height < *deadline || !entry.parties.contains(&transaction.sender) {
+                return None;
+            }
+
+            let payouts = payouts(entry, state);
+            entry.status = Status::Closed;
+            Some(payouts)
+        }
+        _ => Some(Vec::new()),
+    }
+}
+
+// Channel opened before that `transaction` updates, if it is a channel update.
+fn updated_channel(transaction: &Transaction) -> Option<&Hash256> {
+    match &transaction.condition {
+        Some(Condition::ChannelClose { state, .. }) | Some(Condition::ChannelContest { state }) => {
+            Some(&state.channel_id)
+        }
+        Some(Condition::ChannelSettle { channel_id }) => Some(channel_id),
+        _ => None,
+    }
+}
+
+fn payouts(entry: &Entry, state: &ChannelState) -> Vec<(Address, Amount)> {
+    entry
+        .parties
+        .iter()
+        .cloned()
+        .zip(state.balances.iter().copied())
+        .collect()
+}
+
+// Look up the channel a state refers to, making sure the state fits the channel, both parties
+// signed it and the transaction comes from one of them.
+fn participant_entry<'a>(
+    channels: &'a mut Channels,
+    transaction: &Transaction,
+    state: &ChannelState,
+) -> Option<&'a mut Entry> {
+    let entry = channels.get_mut(&state.channel_id)?;
+
+    let total = state.balances[0].checked_add(state.balances[1])?;
+    if total != entry.capacity
+        || !entry.parties.contains(&transaction.sender)
+        || !state.is_signed_by(&entry.parties)
+    {
+        return None;
+    }
+
+    Some(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{PublicKey, SecretKey};
+
+    fn keypair(seed: u8) -> Keypair {
+        let secret = SecretKey::from_bytes(&[seed; 32]).expect("any 32 bytes are a secret key");
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    // Channels with a capacity of 30 between the keys of seeds 1 and 2, and the id of the one.
+    fn open_channel() -> (Channels, Hash256, [Keypair; 2]) {
+        let keypairs = [keypair(1), keypair(2)];
+        let mut open = Transaction::new(
+            Address::from_public_key(&keypairs[0].public),
+            Address::from_public_key(&keypairs[1].public),
+            Amount(30),
+        );
+        open.condition = Some(Condition::ChannelOpen(Channel { dispute_period: 5 }));
+
+        let mut channels = Channels::new();
+        apply(&mut channels, &open, 1).expect("channel opens");
+        (channels, open.id(), keypairs)
+    }
+
+    fn close(keypairs: &[Keypair; 2], state: ChannelState) -> Transaction {
+        let mut close = Transaction::new(
+            Address::from_public_key(&keypairs[1].public),
+            Address::from_public_key(&keypairs[0].public),
+            Amount::ZERO,
+        );
+        close.condition = Some(Condition::ChannelClose {
+            state,
+            cooperative: true,
+        });
+        close
+    }
+
+    #[test]
+    fn state_signed_by_both_parties_closes_channel() {
+        let (mut channels, channel_id, keypairs) = open_channel();
+        let mut state = ChannelState {
+            channel_id,
+            sequence: 1,
+            balances: [Amount(10), Amount(20)],
+            signatures: Default::default(),
+        };
+        state.sign(0, &keypairs[0]);
+        state.sign(1, &keypairs[1]);
+
+        let payouts = apply(&mut channels, &close(&keypairs, state), 2).expect("state is valid");
+        assert_eq!(payouts[0].1, Amount(10));
+        assert_eq!(payouts[1].1, Amount(20));
+    }
+
+    #[test]
+    fn one_sided_state_is_rejected() {
+        let (mut channels, channel_id, keypairs) = open_channel();
+        // The counterparty claims the whole capacity with a state only it signed.
+        let mut state = ChannelState {
+            channel_id,
+            sequence: 1,
+            balances: [Amount::ZERO, Amount(30)],
+            signatures: Default::default(),
+        };
+        state.sign(1, &keypairs[1]);
+        assert!(apply(&mut channels, &close(&keypairs, state.clone()), 2).is_none());
+
+        // Signing for the funder with its own key doesn't help either.
+        state.sign(0, &keypairs[1]);
+        assert!(apply(&mut channels, &close(&keypairs, state), 2).is_none());
+    }
+}
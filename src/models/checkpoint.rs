@@ -0,0 +1,116 @@
+use super::block::Block;
+use super::hash::Hash256;
+use super::schema::{self, Migration};
+use super::state::State;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+// Number of blocks between checkpoints.
+pub const CHECKPOINT_INTERVAL: u64 = 100;
+// Migrations of the checkpoints file, see `schema`.
+const MIGRATIONS: &[Migration] = &[schema::add_version_tag];
+
+// `Checkpoint` A signed commitment to the chain and its state at `height`, letting light clients
+// and fast-syncing peers start from it instead of the genesis block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub height: u64,
+    // Hash of the block at `height`.
+    pub hash: Hash256,
+    // Root of the state after the block at `height`.
+    pub state_root: Hash256,
+    // Protobuf encoded public key of the node that created the checkpoint.
+    pub signer: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl Checkpoint {
+    // Unsigned checkpoint of `block` and the state after it.
+    pub fn new(block: &Block, state: &State) -> Self {
+        Checkpoint {
+            height: block.header.index,
+            hash: block.header.hash,
+            state_root: state.root(),
+            signer: Vec::new(),
+            signature: Vec::new(),
+        }
+    }
+
+    // Data covered by the signature.
+    pub fn signing_data(&self) -> Vec<u8> {
+        serde_json::to_vec(&(self.height, &self.hash, &self.state_root))
+            .expect("can jsonify checkpoint")
+    }
+}
+
+// `Checkpoints` Checkpoints created by the local node, persisted to `path`.
+#[derive(Debug, Clone)]
+pub struct Checkpoints {
+    path: String,
+    checkpoints: Vec<Checkpoint>,
+}
+
+impl Checkpoints {
+    pub fn load(path: &str) -> Self {
+        let checkpoints = match schema::load_json(path, MIGRATIONS) {
+            Ok(Some(data)) => serde_json::from_value(data).unwrap_or_else(|err| {
+                println!("can't parse checkpoints in {}: {}", path, err);
+                Vec::new()
+            }),
+            Ok(None) => Vec::new(),
+            Err(err) => {
+                println!("can't load checkpoints from {}: {}", path, err);
+                Vec::new()
+            }
+        };
+
+        Checkpoints {
+            path: path.to_string(),
+            checkpoints,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Checkpoint> {
+        self.checkpoints.iter()
+    }
+
+    pub fn contains(&self, height: u64) -> bool {
+        self.checkpoints
+            .iter()
+            .any(|checkpoint| checkpoint.height == height)
+    }
+
+    // The most recent checkpoint at or below `height`.
+    pub fn latest(&self, height: u64) -> Option<&Checkpoint> {
+        self.checkpoints
+            .iter()
+            .filter(|checkpoint| checkpoint.height <= height)
+            .max_by_key(|checkpoint| checkpoint.height)
+    }
+
+    pub fn add(&mut self, checkpoint: Checkpoint) {
+        self.checkpoints.push(checkpoint);
+        self.save();
+    }
+
+    // Drop checkpoints of blocks that are no longer part of `chain`.
+    pub fn retain_chain(&mut self, chain: &[Arc<Block>]) {
+        let count = self.checkpoints.len();
+        self.checkpoints.retain(|checkpoint| {
+            chain
+                .get(checkpoint.height as usize)
+                .is_none_or(|block| block.header.hash == checkpoint.hash)
+        });
+
+        if self.checkpoints.len() != count {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let version = MIGRATIONS.len() as u32;
+        if let Err(err) = schema::save_json(&self.path, version, &self.checkpoints) {
+            println!("can't save checkpoints to {}: {}", self.path, err);
+        }
+    }
+}
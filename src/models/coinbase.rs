@@ -0,0 +1,220 @@
+use super::address::Address;
+use super::amount::Amount;
+use super::block::Block;
+use super::transaction::Transaction;
+use std::str::FromStr;
+
+// Sender of the transactions paying out the block reward.
+pub const COINBASE_SENDER: &str = "coinbase";
+
+// `PayoutSplit` A share of the block reward sent to another address.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayoutSplit {
+    pub address: Address,
+    pub percent: u64,
+}
+
+// `Payout` Where the rewards of blocks mined by this node go. Splits get their share first,
+// the rest goes to `address`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Payout {
+    pub address: Address,
+    pub splits: Vec<PayoutSplit>,
+}
+
+impl Payout {
+    pub fn new(address: Address, splits: Vec<PayoutSplit>) -> Result<Self, String> {
+        let total: u64 = splits.iter().map(|split| split.percent).sum();
+        if total > 100 {
+            return Err(format!("payout splits add up to {}%", total));
+        }
+
+        Ok(Payout { address, splits })
+    }
+
+    // Assemble the coinbase transactions paying out `reward`. The first one pays `address`,
+    // even if splits leave nothing for it, so every block has a coinbase.
+    pub fn coinbase_transactions(&self, reward: Amount) -> Vec<Transaction> {
+        let mut transactions = Vec::new();
+        let mut remaining = reward;
+
+        for split in self.splits.iter() {
+            let amount = reward.percent(split.percent);
+            if !amount.is_zero() {
+                transactions.push(coinbase(&split.address, amount));
+                remaining = remaining
+                    .checked_sub(amount)
+                    .expect("splits add up to at most 100%");
+            }
+        }
+
+        transactions.insert(0, coinbase(&self.address, remaining));
+
+        transactions
+    }
+}
+
+// Read from `<address>:<percent>`, as `--payout-split` takes it.
+impl FromStr for PayoutSplit {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, percent) = value
+            .rsplit_once(':')
+            .ok_or_else(|| format!("{} isn't <address>:<percent>", value))?;
+
+        Ok(PayoutSplit {
+            address: Address::new(address)?,
+            percent: percent
+                .parse()
+                .map_err(|_| format!("{} isn't a percentage", percent))?,
+        })
+    }
+}
+
+pub fn coinbase(address: &Address, amount: Amount) -> Transaction {
+    let sender = Address::new(COINBASE_SENDER).expect("coinbase sender is an address");
+    Transaction::new(sender, address.clone(), amount)
+}
+
+pub fn is_coinbase(transaction: &Transaction) -> bool {
+    transaction.sender.as_str() == COINBASE_SENDER
+}
+
+// Total fees paid by the transactions of `block`, which its coinbase may pay out on top of the
+// block reward.
+pub fn fees(block: &Block) -> Amount {
+    block
+        .body
+        .transactions
+        .iter()
+        .filter(|transaction| !is_coinbase(transaction))
+        .fold(Amount::ZERO, |fees, transaction| {
+            fees.saturating_add(transaction.fee)
+        })
+}
+
+// Replace the coinbase transactions at the front of `block` with the ones of `payout`, paying
+// out the block reward and the fees of the block, and commit the header to them.
+pub fn apply_payout(block: &mut Block, payout: &Payout, reward: Amount) {
+    block
+        .body
+        .transactions
+        .retain(|transaction| !is_coinbase(transaction));
+
+    let coinbase = payout.coinbase_transactions(reward.saturating_add(fees(block)));
+    block.body.transactions.splice(0..0, coinbase);
+    block.header.merkle_root = block.body.merkle_root();
+}
+
+// Check that `block` starts with a coinbase transaction, followed by those of any payout
+// splits, that coinbase transactions appear nowhere else and that they pay out no more than the
+// block reward and the fees of the block.
+pub fn is_coinbase_valid(block: &Block, reward: Amount) -> bool {
+    let payouts = block
+        .body
+        .transactions
+        .iter()
+        .take_while(|transaction| is_coinbase(transaction))
+        .count();
+
+    let misplaced = block.body.transactions[payouts..].iter().any(is_coinbase);
+    let total = block.body.transactions[..payouts]
+        .iter()
+        .try_fold(Amount::ZERO, |total, transaction| {
+            total.checked_add(transaction.amount)
+        });
+
+    let reward = reward.saturating_add(fees(block));
+    if payouts == 0 || misplaced || !matches!(total, Some(total) if total <= reward) {
+        println!(
+            "Block with id: {} has an invalid coinbase",
+            block.header.index
+        );
+        return false;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::hash::Hash256;
+
+    fn address(name: &str) -> Address {
+        Address::new(name).expect("address is valid")
+    }
+
+    fn split(name: &str, percent: u64) -> PayoutSplit {
+        PayoutSplit {
+            address: address(name),
+            percent,
+        }
+    }
+
+    // Block paying out `payout` with the fees of `transactions` on top of `reward`.
+    fn block(payout: &Payout, reward: Amount, transactions: Vec<Transaction>) -> Block {
+        let mut block = Block::new(1, Hash256::ZERO, transactions);
+        apply_payout(&mut block, payout, reward);
+        block
+    }
+
+    #[test]
+    fn payout_splits_share_the_reward() {
+        let payout = Payout::new(address("miner"), vec![split("pool", 30), split("dev", 10)])
+            .expect("splits add up to 40%");
+        let transactions = payout.coinbase_transactions(Amount(100));
+
+        let paid: Vec<(&str, Amount)> = transactions
+            .iter()
+            .map(|transaction| (transaction.receiver.as_str(), transaction.amount))
+            .collect();
+        assert_eq!(
+            paid,
+            vec![
+                ("miner", Amount(60)),
+                ("pool", Amount(30)),
+                ("dev", Amount(10))
+            ]
+        );
+        assert!(transactions.iter().all(is_coinbase));
+    }
+
+    #[test]
+    fn payout_splits_over_100_percent_are_rejected() {
+        assert!(Payout::new(address("miner"), vec![split("pool", 100)]).is_ok());
+        assert!(Payout::new(address("miner"), vec![split("pool", 60), split("dev", 41)]).is_err());
+    }
+
+    #[test]
+    fn coinbase_paying_reward_and_fees_is_accepted() {
+        let payout = Payout::new(address("miner"), vec![split("pool", 50)]).expect("valid");
+        let mut transaction = Transaction::new(address("alice"), address("bob"), Amount(5));
+        transaction.fee = Amount(2);
+
+        let block = block(&payout, Amount(50), vec![transaction]);
+        assert_eq!(fees(&block), Amount(2));
+        assert!(is_coinbase_valid(&block, Amount(50)));
+    }
+
+    #[test]
+    fn coinbase_paying_too_much_or_misplaced_is_rejected() {
+        let payout = Payout::new(address("miner"), Vec::new()).expect("valid");
+        let transaction = Transaction::new(address("alice"), address("bob"), Amount(5));
+
+        let greedy = block(&payout, Amount(51), vec![transaction.clone()]);
+        assert!(!is_coinbase_valid(&greedy, Amount(50)));
+
+        let mut missing = block(&payout, Amount(50), vec![transaction.clone()]);
+        missing.body.transactions.remove(0);
+        assert!(!is_coinbase_valid(&missing, Amount(50)));
+
+        let mut misplaced = block(&payout, Amount(25), vec![transaction]);
+        misplaced
+            .body
+            .transactions
+            .push(coinbase(&address("miner"), Amount(25)));
+        assert!(!is_coinbase_valid(&misplaced, Amount(50)));
+    }
+}
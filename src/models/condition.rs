@@ -0,0 +1,39 @@
+use super::anchor::Anchor;
+use super::channel::{Channel, ChannelState};
+use super::hash::Hash256;
+use super::htlc::HashTimeLock;
+use serde::{Deserialize, Serialize};
+
+// `Condition` Spending conditions a transaction can carry, validated by consensus.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Condition {
+    // Lock the transaction amount in a hashed timelock contract.
+    Lock(HashTimeLock),
+    // Settle a lock by revealing the preimage of its hashlock.
+    Claim {
+        lock_id: Hash256,
+        preimage: String,
+    },
+    // Return an expired lock to its refund address.
+    Refund {
+        lock_id: Hash256,
+    },
+    // Fund a two-party payment channel with the transaction amount.
+    ChannelOpen(Channel),
+    // Close a channel with its latest off-chain state. A cooperative close pays out
+    // immediately, a unilateral one starts the dispute window.
+    ChannelClose {
+        state: ChannelState,
+        cooperative: bool,
+    },
+    // Replace the state of a closing channel with a newer one during its dispute window.
+    ChannelContest {
+        state: ChannelState,
+    },
+    // Pay out a unilaterally closed channel once its dispute window has passed.
+    ChannelSettle {
+        channel_id: Hash256,
+    },
+    // Commit to a block of another chain, moving no coins.
+    Anchor(Anchor),
+}
@@ -0,0 +1,160 @@
+use super::block::BlockHeader;
+use super::hash::Hash256;
+use super::work::Work;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// `ConsensusEngine` The proof of work algorithm used to mine and validate blocks.
+pub trait ConsensusEngine {
+    // Calculate the proof of work hash of serialized block data.
+    fn hash_data(&self, data: &[u8]) -> Hash256;
+
+    // Calculate the proof of work hash of a block header.
+    fn hash(&self, header: &BlockHeader) -> Hash256 {
+        self.hash_data(&header.hash_data())
+    }
+
+    // Check whether a hash satisfies the difficulty.
+    fn meets_difficulty(&self, hash: &Hash256, difficulty: usize) -> bool {
+        Work::proven_by(hash) >= Work::from_difficulty(difficulty)
+    }
+}
+
+// `PowAlgorithm` Selects the consensus engine of a chain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum PowAlgorithm {
+    #[default]
+    Sha256,
+    MemoryHard,
+}
+
+impl PowAlgorithm {
+    pub fn engine(&self) -> Box<dyn ConsensusEngine> {
+        match self {
+            PowAlgorithm::Sha256 => Box::new(Sha256Pow),
+            PowAlgorithm::MemoryHard => Box::new(MemoryHardPow::default()),
+        }
+    }
+}
+
+// `Sha256Pow` Plain SHA-256 proof of work.
+pub struct Sha256Pow;
+
+impl ConsensusEngine for Sha256Pow {
+    fn hash_data(&self, data: &[u8]) -> Hash256 {
+        Hash256::digest(data)
+    }
+
+    fn hash(&self, header: &BlockHeader) -> Hash256 {
+        header.generate_hash()
+    }
+}
+
+// `MemoryHardPow` Memory-hard proof of work in the style of scrypt's ROMix. Every hash
+// fills `memory_kib` of memory with chained hashes and then reads it back in a data dependent
+// order, which limits the advantage of specialized mining hardware.
+pub struct MemoryHardPow {
+    pub memory_kib: usize,
+}
+
+impl Default for MemoryHardPow {
+    fn default() -> Self {
+        MemoryHardPow { memory_kib: 1024 }
+    }
+}
+
+impl ConsensusEngine for MemoryHardPow {
+    fn hash_data(&self, data: &[u8]) -> Hash256 {
+        let slots = self.memory_kib * 1024 / 32;
+
+        // Fill the memory with a hash chain seeded by the data.
+        let mut memory = Vec::with_capacity(slots);
+        let mut state: [u8; 32] = Sha256::digest(data).into();
+        for _ in 0..slots {
+            memory.push(state);
+            state = Sha256::digest(&state).into();
+        }
+
+        // Mix in slots picked by the current state, so the whole memory has to be kept.
+        for _ in 0..slots {
+            let slot = u64::from_le_bytes(state[..8].try_into().unwrap()) as usize % slots;
+
+            let mut hasher = Sha256::new();
+            hasher.update(state);
+            hasher.update(memory[slot]);
+            state = hasher.finalize().into();
+        }
+
+        Hash256(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::block::Block;
+    use crate::models::blockchain::{BlockValidationError, Blockchain};
+    use crate::models::params::ChainParams;
+
+    // Small enough memory to keep the tests fast.
+    fn engine() -> MemoryHardPow {
+        MemoryHardPow { memory_kib: 4 }
+    }
+
+    // Find a nonce for `header` at `difficulty` with `engine`.
+    fn mine(header: &mut BlockHeader, engine: &dyn ConsensusEngine, difficulty: usize) {
+        header.difficulty = difficulty;
+        while !engine.meets_difficulty(&engine.hash(header), difficulty) {
+            header.proof_of_work += 1;
+        }
+        header.hash = engine.hash(header);
+    }
+
+    #[test]
+    fn memory_hard_hash_is_deterministic_and_its_own() {
+        let data = b"block header";
+
+        assert_eq!(engine().hash_data(data), engine().hash_data(data));
+        assert_ne!(
+            engine().hash_data(data),
+            engine().hash_data(b"other header")
+        );
+        assert_ne!(engine().hash_data(data), Sha256Pow.hash_data(data));
+        // The memory size is part of the algorithm.
+        assert_ne!(
+            engine().hash_data(data),
+            MemoryHardPow { memory_kib: 8 }.hash_data(data)
+        );
+    }
+
+    #[test]
+    fn memory_hard_block_is_accepted_at_its_difficulty() {
+        let engine = engine();
+        let mut header = Block::new(1, Hash256::ZERO, Vec::new()).header;
+        mine(&mut header, &engine, 2);
+
+        assert_eq!(engine.hash(&header), header.hash);
+        assert!(engine.meets_difficulty(&header.hash, 2));
+        assert!(header.is_mined(Work::from_difficulty(2)));
+    }
+
+    #[test]
+    fn chain_rejects_blocks_mined_with_another_engine() {
+        let blockchain = Blockchain::new(ChainParams {
+            difficulty: 1,
+            pow: PowAlgorithm::MemoryHard,
+            ..ChainParams::default()
+        });
+        let genesis = &blockchain.chain[0];
+        let mut block = Block::new(1, genesis.header.hash, Vec::new());
+
+        mine(&mut block.header, &*PowAlgorithm::MemoryHard.engine(), 1);
+        assert!(blockchain.is_block_valid(&block, genesis, 1).is_ok());
+
+        mine(&mut block.header, &Sha256Pow, 1);
+        assert!(matches!(
+            blockchain.is_block_valid(&block, genesis, 1),
+            Err(BlockValidationError::Hash { .. })
+        ));
+    }
+}
@@ -0,0 +1,184 @@
+use super::block::Block;
+use super::hash::Hash256;
+use super::schema::{self, Migration};
+use super::state::State;
+use super::work::{self, Work};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+// Number of blocks an epoch summary covers.
+pub const EPOCH_LENGTH: u64 = 1000;
+// Migrations of the epochs file, see `schema`.
+const MIGRATIONS: &[Migration] = &[];
+
+// `EpochSummary` A signed summary of the blocks of epoch `epoch`, the heights `start` to `end`.
+// Summaries link up like blocks do, so light clients can follow a long chain one epoch at a time
+// and only fetch the blocks of the epochs they want to check in full.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EpochSummary {
+    pub epoch: u64,
+    pub start: u64,
+    pub end: u64,
+    // Hash of the block before `start`, the last block of the previous epoch.
+    pub previous_hash: Hash256,
+    // Hash of the block at `end`.
+    pub hash: Hash256,
+    // Work of the chain up to and including the block at `end`.
+    pub work: Work,
+    // Root of the state after the block at `end`.
+    pub state_root: Hash256,
+    pub transactions: u64,
+    // Protobuf encoded public key of the node that created the summary.
+    pub signer: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl EpochSummary {
+    // Unsigned summary of epoch `epoch` of `chain`, `state` being the state after its last block.
+    // `None` if `chain` doesn't reach the end of the epoch. Epochs are counted from 1, the
+    // genesis block isn't part of any.
+    pub fn new(chain: &[Arc<Block>], epoch: u64, state: &State) -> Option<Self> {
+        let (start, end) = epoch_range(epoch)?;
+        let blocks = chain.get(start as usize..=end as usize)?;
+        let last = blocks.last()?;
+
+        Some(EpochSummary {
+            epoch,
+            start,
+            end,
+            previous_hash: blocks[0].header.previous_hash,
+            hash: last.header.hash,
+            work: work::cumulative_work(&chain[..=end as usize]),
+            state_root: state.root(),
+            transactions: blocks
+                .iter()
+                .map(|block| block.body.transactions.len() as u64)
+                .sum(),
+            signer: Vec::new(),
+            signature: Vec::new(),
+        })
+    }
+
+    // Data covered by the signature.
+    pub fn signing_data(&self) -> Vec<u8> {
+        serde_json::to_vec(&(
+            self.epoch,
+            self.start,
+            self.end,
+            &self.previous_hash,
+            &self.hash,
+            self.work.0,
+            &self.state_root,
+            self.transactions,
+        ))
+        .expect("can jsonify epoch summary")
+    }
+}
+
+// First and last height of epoch `epoch`.
+pub fn epoch_range(epoch: u64) -> Option<(u64, u64)> {
+    let end = epoch.checked_mul(EPOCH_LENGTH).filter(|end| *end > 0)?;
+    Some((end - EPOCH_LENGTH + 1, end))
+}
+
+// Check that `summaries` follow each other without gaps, starting on top of the block
+// `previous_hash`, and that the work they claim grows. Signatures are left to the caller.
+pub fn verify_links(summaries: &[EpochSummary], previous_hash: Hash256) -> Result<(), String> {
+    let mut previous_hash = previous_hash;
+    let mut previous: Option<&EpochSummary> = None;
+
+    for summary in summaries {
+        if epoch_range(summary.epoch) != Some((summary.start, summary.end)) {
+            return Err(format!("epoch {} has the wrong range", summary.epoch));
+        }
+        if summary.previous_hash != previous_hash {
+            return Err(format!(
+                "epoch {} doesn't extend the block {}",
+                summary.epoch, previous_hash
+            ));
+        }
+        if let Some(previous) = previous
+            && (summary.epoch != previous.epoch + 1 || summary.work <= previous.work)
+        {
+            return Err(format!(
+                "epoch {} doesn't follow epoch {}",
+                summary.epoch, previous.epoch
+            ));
+        }
+
+        previous_hash = summary.hash;
+        previous = Some(summary);
+    }
+
+    Ok(())
+}
+
+// `EpochSummaries` Epoch summaries created by the local node, persisted to `path`.
+#[derive(Debug, Clone)]
+pub struct EpochSummaries {
+    path: String,
+    summaries: Vec<EpochSummary>,
+}
+
+impl EpochSummaries {
+    pub fn load(path: &str) -> Self {
+        let summaries = match schema::load_json(path, MIGRATIONS) {
+            Ok(Some(data)) => serde_json::from_value(data).unwrap_or_else(|err| {
+                println!("can't parse epoch summaries in {}: {}", path, err);
+                Vec::new()
+            }),
+            Ok(None) => Vec::new(),
+            Err(err) => {
+                println!("can't load epoch summaries from {}: {}", path, err);
+                Vec::new()
+            }
+        };
+
+        EpochSummaries {
+            path: path.to_string(),
+            summaries,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &EpochSummary> {
+        self.summaries.iter()
+    }
+
+    pub fn contains(&self, epoch: u64) -> bool {
+        self.summaries.iter().any(|summary| summary.epoch == epoch)
+    }
+
+    // Summaries of epoch `epoch` and the ones after it, in order.
+    pub fn since(&self, epoch: u64) -> impl Iterator<Item = &EpochSummary> {
+        self.summaries
+            .iter()
+            .filter(move |summary| summary.epoch >= epoch)
+    }
+
+    pub fn add(&mut self, summary: EpochSummary) {
+        self.summaries.push(summary);
+        self.summaries.sort_by_key(|summary| summary.epoch);
+        self.save();
+    }
+
+    // Drop summaries of epochs whose last block is no longer part of `chain`.
+    pub fn retain_chain(&mut self, chain: &[Arc<Block>]) {
+        let count = self.summaries.len();
+        self.summaries.retain(|summary| {
+            chain
+                .get(summary.end as usize)
+                .is_some_and(|block| block.header.hash == summary.hash)
+        });
+
+        if self.summaries.len() != count {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let version = MIGRATIONS.len() as u32;
+        if let Err(err) = schema::save_json(&self.path, version, &self.summaries) {
+            println!("can't save epoch summaries to {}: {}", self.path, err);
+        }
+    }
+}
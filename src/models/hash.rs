@@ -0,0 +1,147 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+// `Hash256` A SHA-256 hash, kept binary and only hex encoded for display and serialization.
+// The all zero hash stands for no hash, e.g. the previous hash of the genesis block, and is
+// encoded as an empty string.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Hash256(pub [u8; 32]);
+
+impl Hash256 {
+    pub const ZERO: Hash256 = Hash256([0; 32]);
+
+    pub fn digest(data: impl AsRef<[u8]>) -> Self {
+        Hash256(Sha256::digest(data.as_ref()).into())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Hash256::ZERO
+    }
+
+    // Number of leading zero hex digits, which is what difficulties count. The zero hash has no
+    // digits, so it never meets a difficulty.
+    pub fn leading_zero_digits(&self) -> usize {
+        if self.is_zero() {
+            return 0;
+        }
+
+        self.hex()
+            .iter()
+            .take_while(|digit| **digit == b'0')
+            .count()
+    }
+
+    // Hex digits of the hash, without allocating.
+    pub fn hex(&self) -> [u8; 64] {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+        let mut hex = [0; 64];
+        for (pair, byte) in hex.chunks_mut(2).zip(self.0.iter()) {
+            pair[0] = DIGITS[(byte >> 4) as usize];
+            pair[1] = DIGITS[(byte & 0xf) as usize];
+        }
+        hex
+    }
+}
+
+impl fmt::Display for Hash256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return Ok(());
+        }
+
+        let hex = self.hex();
+        f.write_str(std::str::from_utf8(&hex).expect("hex digits are ascii"))
+    }
+}
+
+impl fmt::Debug for Hash256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"{}\"", self)
+    }
+}
+
+impl FromStr for Hash256 {
+    type Err = String;
+
+    fn from_str(hex: &str) -> Result<Self, Self::Err> {
+        if hex.is_empty() {
+            return Ok(Hash256::ZERO);
+        }
+
+        let digits = hex.as_bytes();
+        if digits.len() != 64 {
+            return Err(format!("expected 64 hex digits, got {}", digits.len()));
+        }
+        // `from_str_radix` would take a leading sign, e.g. "+f".
+        if !digits.iter().all(u8::is_ascii_hexdigit) {
+            return Err(format!("{} is not a hex encoded hash", hex));
+        }
+
+        let mut hash = [0; 32];
+        for (byte, pair) in hash.iter_mut().zip(digits.chunks(2)) {
+            *byte = std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("{} is not a hex encoded hash", hex))?;
+        }
+        Ok(Hash256(hash))
+    }
+}
+
+impl Serialize for Hash256 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.is_zero() {
+            return serializer.serialize_str("");
+        }
+
+        let hex = self.hex();
+        serializer.serialize_str(std::str::from_utf8(&hex).expect("hex digits are ascii"))
+    }
+}
+
+impl<'de> Deserialize<'de> for Hash256 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(HexVisitor)
+    }
+}
+
+// Decodes hashes from borrowed or transient strings alike, so parsing them doesn't allocate.
+struct HexVisitor;
+
+impl de::Visitor<'_> for HexVisitor {
+    type Value = Hash256;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a hex encoded 32 byte hash")
+    }
+
+    fn visit_str<E: de::Error>(self, hex: &str) -> Result<Self::Value, E> {
+        hex.parse().map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_round_trips() {
+        let hash = Hash256([0xab; 32]);
+        assert_eq!(hash.to_string().parse::<Hash256>(), Ok(hash));
+    }
+
+    #[test]
+    fn signs_and_other_characters_are_rejected() {
+        let hex = "ab".repeat(32);
+        for bad in [
+            format!("+f{}", &hex[2..]),
+            format!("-0{}", &hex[2..]),
+            format!("zz{}", &hex[2..]),
+        ] {
+            assert!(bad.parse::<Hash256>().is_err(), "{} was accepted", bad);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use super::accumulator::{self, Accumulator, InclusionProof};
+use super::block::Block;
+use super::hash::Hash256;
+use super::params::ChainParams;
+use super::work::Work;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+// Blocks a history proof samples unless the client asks for another number.
+pub const DEFAULT_HISTORY_SAMPLES: usize = 32;
+// Most blocks a history proof samples.
+pub const MAX_HISTORY_SAMPLES: usize = 256;
+
+// `HistorySample` A block of the chain along with the proof that the tip commits to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistorySample {
+    pub block: Arc<Block>,
+    // Work of the chain up to and including `block`.
+    pub work: Work,
+    pub proof: InclusionProof,
+}
+
+// `HistoryProof` FlyClient-style proof of the work of a chain. Every block commits to the blocks
+// before it and the work up to each of them, so a client can check blocks sampled at random
+// points of the work against the tip alone. The sample points derive from the tip, which the
+// prover can't choose without mining it. A chain forged with less work than it claims has to
+// leave most of its work unmined, which a sample then likely hits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryProof {
+    pub tip: Arc<Block>,
+    // Work of the chain below `tip`, proven by the first sample, the block right below it.
+    pub work: Work,
+    pub samples: Vec<HistorySample>,
+}
+
+// Leaf of the history accumulator standing for `block` with `work` up to and including it.
+pub fn leaf(block: &Block, work: Work) -> Hash256 {
+    Hash256::digest(format!("{}:{}", block.header.hash, work))
+}
+
+// Check that `block` commits to `history`, the one of the chain it builds on, see
+// `State::history`.
+pub fn is_commitment_valid(block: &Block, history: &Accumulator) -> bool {
+    if block.header.history != *history {
+        println!("Block with id: {} has a wrong history", block.header.index);
+        return false;
+    }
+
+    true
+}
+
+// Prove the work of `chain` sampling `samples` of its blocks, `None` if only the genesis block
+// is below its tip.
+pub fn prove(chain: &[Arc<Block>], samples: usize) -> Option<HistoryProof> {
+    let (tip, below) = chain.split_last()?;
+    if below.len() < 2 {
+        return None;
+    }
+
+    let mut works = Vec::with_capacity(below.len());
+    let mut total = Work::default();
+    for (height, block) in below.iter().enumerate() {
+        if height > 0 {
+            total = total + Work::from_difficulty(block.header.difficulty);
+        }
+        works.push(total);
+    }
+    let leaves: Vec<Hash256> = below
+        .iter()
+        .zip(works.iter())
+        .map(|(block, work)| leaf(block, *work))
+        .collect();
+
+    let sample = |height: usize| HistorySample {
+        block: below[height].clone(),
+        work: works[height],
+        proof: accumulator::prove_leaf(&leaves, height),
+    };
+    let mut proof_samples = vec![sample(below.len() - 1)];
+    for target in sample_points(tip, total, samples) {
+        proof_samples.push(sample(works.partition_point(|work| *work <= target)));
+    }
+
+    Some(HistoryProof {
+        tip: tip.clone(),
+        work: total,
+        samples: proof_samples,
+    })
+}
+
+// Check `proof` with at least `samples` sampled blocks, returning the height and the work of the
+// chain it proves.
+pub fn verify(
+    proof: &HistoryProof,
+    params: &ChainParams,
+    samples: usize,
+) -> Result<(u64, Work), String> {
+    let engine = params.pow.engine();
+    let is_mined = |block: &Block| {
+        engine.hash(&block.header) == block.header.hash
+            && block.is_mined(Work::from_difficulty(block.header.difficulty))
+    };
+
+    let tip = &proof.tip;
+    if !is_mined(tip) {
+        return Err(format!("tip {} isn't mined", tip.header.hash));
+    }
+    if tip.header.history.leaves != tip.header.index {
+        return Err(format!(
+            "tip {} commits to the wrong history",
+            tip.header.hash
+        ));
+    }
+    let Some((last, sampled)) = proof.samples.split_first() else {
+        return Err("proof has no samples".to_string());
+    };
+    if last.block.header.index + 1 != tip.header.index || last.work != proof.work {
+        return Err("proof doesn't prove the work below its tip".to_string());
+    }
+    if sampled.len() < samples {
+        return Err(format!(
+            "proof samples {} blocks instead of {}",
+            sampled.len(),
+            samples
+        ));
+    }
+
+    for sample in proof.samples.iter() {
+        let block = &sample.block;
+        if !is_mined(block) {
+            return Err(format!("block {} isn't mined", block.header.index));
+        }
+        if sample.proof.position != block.header.index
+            || sample.proof.transaction_id != leaf(block, sample.work)
+            || !tip.header.history.verify(&sample.proof)
+        {
+            return Err(format!(
+                "tip doesn't commit to block {}",
+                block.header.index
+            ));
+        }
+    }
+
+    let points = sample_points(tip, proof.work, sampled.len());
+    for (target, sample) in points.into_iter().zip(sampled.iter()) {
+        let work = Work::from_difficulty(sample.block.header.difficulty);
+        if !(sample.work.0.saturating_sub(work.0) <= target.0 && target < sample.work) {
+            return Err(format!(
+                "block {} isn't the one sampled at work {}",
+                sample.block.header.index, target
+            ));
+        }
+    }
+
+    Ok((
+        tip.header.index,
+        proof.work + Work::from_difficulty(tip.header.difficulty),
+    ))
+}
+
+// Points of the work below `tip` to sample blocks at, `total` being that work.
+fn sample_points(tip: &Block, total: Work, samples: usize) -> Vec<Work> {
+    if total.0 == 0 {
+        return Vec::new();
+    }
+
+    (0..samples)
+        .map(|sample| {
+            let seed = Hash256::digest(format!("{}:{}", tip.header.hash, sample));
+            let mut bytes = [0; 16];
+            bytes.copy_from_slice(&seed.0[..16]);
+            Work(u128::from_be_bytes(bytes) % total.0)
+        })
+        .collect()
+}
@@ -0,0 +1,201 @@
+use super::address::Address;
+use super::block::Block;
+use super::condition::Condition;
+use super::hash::Hash256;
+use super::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+// `HashTimeLock` Funds that go to the receiver once the preimage of `hashlock` is revealed
+// before block height `timelock`, or back to `refund` after it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HashTimeLock {
+    // SHA-256 hash of the secret preimage.
+    pub hashlock: Hash256,
+    // Block height at which the lock expires.
+    pub timelock: u64,
+    // Address that can reclaim the funds once the lock expires.
+    pub refund: Address,
+}
+
+// Calculate the hashlock for a secret preimage.
+pub fn hashlock(preimage: &str) -> Hash256 {
+    Hash256::digest(preimage)
+}
+
+// Locks that weren't settled yet, by the id of the transaction creating them.
+pub type Locks = HashMap<Hash256, Transaction>;
+
+// Track the lock `transaction` creates or settles.
+pub fn apply(locks: &mut Locks, transaction: &Transaction) {
+    match &transaction.condition {
+        Some(Condition::Lock(_)) => {
+            locks.insert(transaction.id(), transaction.clone());
+        }
+        Some(Condition::Claim { lock_id, .. }) | Some(Condition::Refund { lock_id }) => {
+            locks.remove(lock_id);
+        }
+        _ => {}
+    }
+}
+
+// Check that every HTLC condition in `block` is valid given the `open` locks of the chain it
+// builds on.
+pub fn are_conditions_valid(block: &Block, open: &Locks) -> bool {
+    let mut locks = Locks::new();
+    let mut settled = HashSet::new();
+
+    for transaction in block.body.transactions.iter() {
+        match &transaction.condition {
+            Some(Condition::Lock(lock)) => {
+                if lock.timelock <= block.header.index {
+                    println!(
+                        "HTLC in block with id: {} is already expired",
+                        block.header.index
+                    );
+                    return false;
+                }
+                locks.insert(transaction.id(), transaction.clone());
+            }
+            Some(Condition::Claim { lock_id, preimage }) => {
+                let Some(lock_transaction) = open_lock(open, &locks, &settled, lock_id) else {
+                    println!("HTLC {} can not be claimed", lock_id);
+                    return false;
+                };
+                let Some(Condition::Lock(lock)) = &lock_transaction.condition else {
+                    return false;
+                };
+
+                if hashlock(preimage) != lock.hashlock
+                    || block.header.index >= lock.timelock
+                    || transaction.receiver != lock_transaction.receiver
+                    || transaction.amount != lock_transaction.amount
+                {
+                    println!("HTLC {} has an invalid claim", lock_id);
+                    return false;
+                }
+                settled.insert(*lock_id);
+            }
+            Some(Condition::Refund { lock_id }) => {
+                let Some(lock_transaction) = open_lock(open, &locks, &settled, lock_id) else {
+                    println!("HTLC {} can not be refunded", lock_id);
+                    return false;
+                };
+                let Some(Condition::Lock(lock)) = &lock_transaction.condition else {
+                    return false;
+                };
+
+                if block.header.index < lock.timelock
+                    || transaction.receiver != lock.refund
+                    || transaction.amount != lock_transaction.amount
+                {
+                    println!("HTLC {} has an invalid refund", lock_id);
+                    return false;
+                }
+                settled.insert(*lock_id);
+            }
+            _ => {}
+        }
+    }
+
+    true
+}
+
+// Look up a lock open before the block or created in it, unless the block settled it already.
+fn open_lock<'a>(
+    open: &'a Locks,
+    locks: &'a Locks,
+    settled: &HashSet<Hash256>,
+    lock_id: &Hash256,
+) -> Option<&'a Transaction> {
+    if settled.contains(lock_id) {
+        return None;
+    }
+
+    locks.get(lock_id).or_else(|| open.get(lock_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::amount::Amount;
+
+    fn address(name: &str) -> Address {
+        Address::new(name).expect("address is valid")
+    }
+
+    // Locks with 30 coins from "alice" to "bob" until block 10 and the id of the one.
+    fn open_lock() -> (Locks, Hash256) {
+        let mut lock = Transaction::new(address("alice"), address("bob"), Amount(30));
+        lock.condition = Some(Condition::Lock(HashTimeLock {
+            hashlock: hashlock("secret"),
+            timelock: 10,
+            refund: address("alice"),
+        }));
+
+        let mut locks = Locks::new();
+        apply(&mut locks, &lock);
+        (locks, lock.id())
+    }
+
+    fn settle(receiver: &str, condition: Condition) -> Transaction {
+        let mut settle = Transaction::new(address("anyone"), address(receiver), Amount(30));
+        settle.condition = Some(condition);
+        settle
+    }
+
+    fn block(index: u64, transaction: Transaction) -> Block {
+        Block::new(index, Hash256::ZERO, vec![transaction])
+    }
+
+    #[test]
+    fn claim_with_preimage_before_timelock_is_accepted() {
+        let (locks, lock_id) = open_lock();
+        let claim = settle(
+            "bob",
+            Condition::Claim {
+                lock_id,
+                preimage: "secret".to_string(),
+            },
+        );
+        assert!(are_conditions_valid(&block(5, claim.clone()), &locks));
+
+        // Claimed locks are settled for good.
+        let mut locks = locks;
+        apply(&mut locks, &claim);
+        assert!(!are_conditions_valid(&block(6, claim), &locks));
+    }
+
+    #[test]
+    fn claim_with_wrong_preimage_or_too_late_is_rejected() {
+        let (locks, lock_id) = open_lock();
+        let wrong = settle(
+            "bob",
+            Condition::Claim {
+                lock_id,
+                preimage: "guess".to_string(),
+            },
+        );
+        assert!(!are_conditions_valid(&block(5, wrong), &locks));
+
+        let late = settle(
+            "bob",
+            Condition::Claim {
+                lock_id,
+                preimage: "secret".to_string(),
+            },
+        );
+        assert!(!are_conditions_valid(&block(10, late), &locks));
+    }
+
+    #[test]
+    fn refund_only_after_timelock_and_to_refund_address() {
+        let (locks, lock_id) = open_lock();
+        let refund = settle("alice", Condition::Refund { lock_id });
+        assert!(!are_conditions_valid(&block(9, refund.clone()), &locks));
+        assert!(are_conditions_valid(&block(10, refund), &locks));
+
+        let stolen = settle("bob", Condition::Refund { lock_id });
+        assert!(!are_conditions_valid(&block(10, stolen), &locks));
+    }
+}
@@ -0,0 +1,268 @@
+use super::address::Address;
+use super::amount::Amount;
+use super::block::Block;
+use super::channel::{self, Channels};
+use super::coinbase;
+use super::condition::Condition;
+use super::hash::Hash256;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+// `AddressStats` Aggregate activity of one address.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AddressStats {
+    // Height of the first block involving the address.
+    pub first_seen: u64,
+    // Height of the latest block involving the address.
+    pub last_seen: u64,
+    pub total_received: Amount,
+    pub total_sent: Amount,
+    // Number of transactions involving the address.
+    pub transaction_count: u64,
+}
+
+// `SpendLink` Part of the coins one transaction paid to an address and a later transaction of
+// that address spent.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpendLink {
+    pub transaction: Hash256,
+    pub amount: Amount,
+}
+
+// `TransactionLinks` Where the coins a transaction spent came from and which transactions spent
+// the coins it paid out. Accounts have no outputs, so coins an address received are taken to be
+// spent first in, first out.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TransactionLinks {
+    // Height of the block including the transaction.
+    pub height: u64,
+    // Earlier transactions whose coins this one spent.
+    pub inputs: Vec<SpendLink>,
+    // Later transactions that spent the coins this one paid out.
+    pub spent_by: Vec<SpendLink>,
+}
+
+// `IndexSettings` Which indexes a node keeps and for how long, set with `--no-address-index`,
+// `--no-tx-index`, `--index-depth <blocks>` and `--index-max-age <seconds>`. Pruned entries are
+// only dropped from the indexes, the blocks they were built from stay on the chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexSettings {
+    // Keep per-address statistics, queried with `stats <address>`.
+    pub address_index: bool,
+    // Keep the spend links of every transaction, queried with `tx <id>`.
+    pub tx_index: bool,
+    // Drop entries of blocks more than `depth` blocks below the tip.
+    pub depth: Option<u64>,
+    // Drop entries of blocks older than `max_age` seconds.
+    pub max_age: Option<u64>,
+}
+
+impl Default for IndexSettings {
+    fn default() -> Self {
+        IndexSettings {
+            address_index: true,
+            tx_index: true,
+            depth: None,
+            max_age: None,
+        }
+    }
+}
+
+impl IndexSettings {
+    pub fn is_pruning(&self) -> bool {
+        self.depth.is_some() || self.max_age.is_some()
+    }
+}
+
+// `AddressIndex` Per-address statistics and spend links, updated with every block added to the
+// chain. Only the indexes enabled in its settings are kept.
+#[derive(Debug, Clone, Default)]
+pub struct AddressIndex {
+    settings: IndexSettings,
+    // Entries of blocks below this height were pruned.
+    pruned_below: u64,
+    stats: HashMap<Address, AddressStats>,
+    links: HashMap<Hash256, TransactionLinks>,
+    // Coins every address received and didn't spend yet, oldest first.
+    unspent: HashMap<Address, VecDeque<SpendLink>>,
+    // Open channels, needed to know what closing them pays out.
+    channels: Channels,
+}
+
+impl AddressIndex {
+    pub fn from_chain(chain: &[Arc<Block>], settings: IndexSettings) -> Self {
+        let mut index = AddressIndex {
+            settings,
+            ..AddressIndex::default()
+        };
+        for block in chain.iter() {
+            index.add_block(block);
+        }
+
+        index
+    }
+
+    pub fn settings(&self) -> &IndexSettings {
+        &self.settings
+    }
+
+    // Height of the oldest block the index still has entries of.
+    pub fn pruned_below(&self) -> u64 {
+        self.pruned_below
+    }
+
+    // Drop the entries of blocks below `height`. Coins received in those blocks and not spent
+    // yet are still tracked, so later spend links stay correct.
+    pub fn prune(&mut self, height: u64) {
+        if height <= self.pruned_below {
+            return;
+        }
+
+        self.stats.retain(|_, stats| stats.last_seen >= height);
+        self.links.retain(|_, links| links.height >= height);
+        self.pruned_below = height;
+    }
+
+    pub fn get(&self, address: &str) -> Option<&AddressStats> {
+        self.stats.get(address)
+    }
+
+    // Spend links of the transaction with `id`.
+    pub fn links(&self, id: &Hash256) -> Option<&TransactionLinks> {
+        self.links.get(id)
+    }
+
+    pub fn add_block(&mut self, block: &Block) {
+        for transaction in block.body.transactions.iter() {
+            let id = transaction.id();
+            if self.settings.tx_index {
+                self.links.insert(
+                    id,
+                    TransactionLinks {
+                        height: block.header.index,
+                        ..TransactionLinks::default()
+                    },
+                );
+            }
+            if !coinbase::is_coinbase(transaction) {
+                self.spend(transaction.fee_payer(), id, transaction.fee);
+            }
+
+            let payouts = channel::apply(&mut self.channels, transaction, block.header.index)
+                .unwrap_or_default();
+            for (address, amount) in payouts {
+                if let Some(stats) = self.entry(&address, block.header.index) {
+                    stats.total_received = stats.total_received.saturating_add(amount);
+                }
+                self.receive(&address, id, amount);
+            }
+
+            let (sent, received) = match &transaction.condition {
+                None => (!coinbase::is_coinbase(transaction), true),
+                Some(Condition::Lock(_)) | Some(Condition::ChannelOpen(_)) => (true, false),
+                Some(Condition::Claim { .. }) | Some(Condition::Refund { .. }) => (false, true),
+                _ => (false, false),
+            };
+
+            if !coinbase::is_coinbase(transaction)
+                && let Some(sender) = self.entry(&transaction.sender, block.header.index)
+            {
+                sender.transaction_count += 1;
+                if sent {
+                    sender.total_sent = sender.total_sent.saturating_add(transaction.amount);
+                }
+            }
+            if sent {
+                self.spend(&transaction.sender, id, transaction.amount);
+            }
+
+            if let Some(receiver) = self.entry(&transaction.receiver, block.header.index) {
+                if transaction.receiver != transaction.sender {
+                    receiver.transaction_count += 1;
+                }
+                if received {
+                    receiver.total_received =
+                        receiver.total_received.saturating_add(transaction.amount);
+                }
+            }
+            if received {
+                self.receive(&transaction.receiver, id, transaction.amount);
+            }
+        }
+    }
+
+    fn receive(&mut self, address: &Address, transaction: Hash256, amount: Amount) {
+        if self.settings.tx_index && !amount.is_zero() {
+            self.unspent
+                .entry(address.clone())
+                .or_default()
+                .push_back(SpendLink {
+                    transaction,
+                    amount,
+                });
+        }
+    }
+
+    // Link `amount` spent by `spender` to the oldest coins `address` didn't spend yet.
+    fn spend(&mut self, address: &Address, spender: Hash256, mut amount: Amount) {
+        let Some(unspent) = self.unspent.get_mut(address) else {
+            return;
+        };
+
+        while !amount.is_zero() {
+            let Some(oldest) = unspent.front_mut() else {
+                break;
+            };
+            let spent = amount.min(oldest.amount);
+            let source = oldest.transaction;
+            oldest.amount = oldest.amount.saturating_sub(spent);
+            if oldest.amount.is_zero() {
+                unspent.pop_front();
+            }
+            amount = amount.saturating_sub(spent);
+
+            if let Some(links) = self.links.get_mut(&source) {
+                add_link(&mut links.spent_by, spender, spent);
+            }
+            if let Some(links) = self.links.get_mut(&spender) {
+                add_link(&mut links.inputs, source, spent);
+            }
+        }
+
+        if unspent.is_empty() {
+            self.unspent.remove(address);
+        }
+    }
+
+    // Statistics of `address`, marked as seen at `height`. None if the address index is disabled.
+    fn entry(&mut self, address: &Address, height: u64) -> Option<&mut AddressStats> {
+        if !self.settings.address_index {
+            return None;
+        }
+
+        let stats = self
+            .stats
+            .entry(address.clone())
+            .or_insert_with(|| AddressStats {
+                first_seen: height,
+                ..AddressStats::default()
+            });
+        stats.last_seen = height;
+        Some(stats)
+    }
+}
+
+// Add a link to `links`, merging it with the last one if both are to `transaction`, e.g. for the
+// fee and the amount of a transaction.
+fn add_link(links: &mut Vec<SpendLink>, transaction: Hash256, amount: Amount) {
+    match links.last_mut() {
+        Some(last) if last.transaction == transaction => {
+            last.amount = last.amount.saturating_add(amount)
+        }
+        _ => links.push(SpendLink {
+            transaction,
+            amount,
+        }),
+    }
+}
@@ -0,0 +1,176 @@
+use super::block::Block;
+use super::hash::Hash256;
+use super::transaction::Transaction;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+// `JournalEvent` One change to the chain or the mempool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JournalEvent {
+    // `block` was added to the chain at `height`.
+    Connect {
+        height: u64,
+        block: Arc<Block>,
+    },
+    // The block at `height` and every block above it were dropped from the chain.
+    Disconnect {
+        height: u64,
+    },
+    MempoolAdd {
+        transaction: Box<Transaction>,
+        // First transaction of the package `transaction` was relayed in, if any.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        package: Option<Hash256>,
+    },
+    MempoolRemove {
+        id: Hash256,
+    },
+}
+
+// `JournalRecord` One operation of the node along with the events it is made of. Records are
+// written as one line before the operation changes anything, so after a crash an operation is
+// either found whole or not at all.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalRecord {
+    // Milliseconds since the epoch.
+    pub timestamp: u64,
+    pub operation: String,
+    // Tip of the chain once the operation is done, unset for operations not changing the chain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tip: Option<Hash256>,
+    pub events: Vec<JournalEvent>,
+}
+
+impl JournalRecord {
+    pub fn new(operation: &str, tip: Option<Hash256>, events: Vec<JournalEvent>) -> Self {
+        JournalRecord {
+            timestamp: Utc::now().timestamp_millis() as u64,
+            operation: operation.to_string(),
+            tip,
+            events,
+        }
+    }
+}
+
+// `Journal` Append-only file of the operations changing the chain and the mempool, written ahead
+// of the chain store. Replaying it after a crash finishes the operation the store missed and
+// restores the mempool, which isn't stored anywhere else.
+#[derive(Debug)]
+pub struct Journal {
+    path: String,
+    file: Mutex<File>,
+    // Records in the file.
+    records: AtomicUsize,
+}
+
+impl Journal {
+    // Open the journal at `path`, returning it along with the records it holds. A record cut
+    // short by a crash while it was written is dropped.
+    pub fn open(path: &str) -> Result<(Self, Vec<JournalRecord>), String> {
+        let mut records = Vec::new();
+
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line.map_err(|err| format!("can't read {}: {}", path, err))?;
+                    match serde_json::from_str(&line) {
+                        Ok(record) => records.push(record),
+                        Err(err) => {
+                            println!("dropping incomplete journal record: {}", err);
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(format!("can't read {}: {}", path, err)),
+        }
+
+        let journal = Journal {
+            path: path.to_string(),
+            file: Mutex::new(open_append(path)?),
+            records: AtomicUsize::new(records.len()),
+        };
+        Ok((journal, records))
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Append `record`, returning once it is on disk.
+    pub fn record(&self, record: &JournalRecord) {
+        let mut line = serde_json::to_vec(record).expect("can jsonify journal record");
+        line.push(b'\n');
+
+        let mut file = self.file.lock().unwrap();
+        if let Err(err) = file.write_all(&line).and_then(|_| file.sync_data()) {
+            println!("can't write journal {}: {}", self.path, err);
+            return;
+        }
+        self.records.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Replace the journal with `records`, e.g. a summary of what isn't stored anywhere else.
+    pub fn compact(&self, records: &[JournalRecord]) -> Result<(), String> {
+        let temporary = format!("{}.tmp", self.path);
+        let mut data = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut data, record).expect("can jsonify journal record");
+            data.push(b'\n');
+        }
+
+        let mut file = self.file.lock().unwrap();
+        File::create(&temporary)
+            .and_then(|mut compacted| {
+                compacted.write_all(&data)?;
+                compacted.sync_all()
+            })
+            .and_then(|_| fs::rename(&temporary, &self.path))
+            .map_err(|err| format!("can't compact journal {}: {}", self.path, err))?;
+
+        *file = open_append(&self.path)?;
+        self.records.store(records.len(), Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+fn open_append(path: &str) -> Result<File, String> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| format!("can't open journal {}: {}", path, err))
+}
+
+// Chain events of `records` a stored chain with tip `stored_tip` is missing. The store is
+// written right after the journal, so it has every operation up to the last one leaving the
+// chain at its tip. If no operation did, it has none of them.
+pub fn missing_chain_events(
+    records: &[JournalRecord],
+    stored_tip: Hash256,
+) -> impl Iterator<Item = &JournalEvent> {
+    let start = records
+        .iter()
+        .rposition(|record| record.tip == Some(stored_tip))
+        .map_or(0, |position| position + 1);
+
+    records[start..]
+        .iter()
+        .flat_map(|record| record.events.iter())
+        .filter(|event| {
+            matches!(
+                event,
+                JournalEvent::Connect { .. } | JournalEvent::Disconnect { .. }
+            )
+        })
+}
@@ -0,0 +1,529 @@
+use super::address::Address;
+use super::block::Block;
+use super::coinbase;
+use super::hash::Hash256;
+use super::journal::{Journal, JournalEvent, JournalRecord};
+use super::state::State;
+use super::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+// Most transactions kept pending, further ones are turned away until blocks include some.
+pub const MAX_MEMPOOL_SIZE: usize = 10_000;
+// Most transactions taken from the mempool for one block.
+pub const MAX_BLOCK_TRANSACTIONS: usize = 100;
+// Most transactions relayed in one package.
+pub const MAX_PACKAGE_SIZE: usize = 25;
+
+// `TransactionPackage` Transactions that are only valid together, in the order they apply: every
+// one after the first spends from an address an earlier one pays. Receivers check and keep them
+// as a whole, so a child isn't turned away because its parent hasn't arrived yet, and miners
+// weigh them by the fee rate of the whole package.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionPackage {
+    pub transactions: Vec<Transaction>,
+}
+
+impl TransactionPackage {
+    // Check that the package has between 2 and `MAX_PACKAGE_SIZE` transactions that depend on
+    // the ones before them.
+    pub fn check(&self) -> Result<(), String> {
+        if !(2..=MAX_PACKAGE_SIZE).contains(&self.transactions.len()) {
+            return Err(format!(
+                "packages have 2 to {} transactions",
+                MAX_PACKAGE_SIZE
+            ));
+        }
+
+        for (position, transaction) in self.transactions.iter().enumerate().skip(1) {
+            if !self.transactions[..position]
+                .iter()
+                .any(|earlier| earlier.receiver == transaction.sender)
+            {
+                return Err(format!(
+                    "transaction {} doesn't spend from an earlier one",
+                    transaction.id()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// `Mempool` Transactions waiting to be included in a block, keyed by id so every transaction is
+// kept once no matter how many peers relay it.
+#[derive(Debug, Default)]
+pub struct Mempool {
+    transactions: HashMap<Hash256, (u64, Transaction)>,
+    // Package of every transaction kept as part of one, by the id of its first transaction.
+    packages: HashMap<Hash256, Hash256>,
+    // Order of arrival, breaking ties between transactions paying the same fee.
+    next_sequence: u64,
+    // Journal recording every change, the mempool isn't stored anywhere else.
+    journal: Option<Arc<Journal>>,
+}
+
+impl Mempool {
+    // Add `transaction`, returning its id.
+    pub fn insert(&mut self, transaction: Transaction) -> Result<Hash256, String> {
+        if coinbase::is_coinbase(&transaction) {
+            return Err("coinbase transactions are only created by miners".to_string());
+        }
+
+        let id = transaction.id();
+        if self.transactions.contains_key(&id) {
+            return Err(format!("transaction {} is already pending", id));
+        }
+        if self.transactions.len() >= MAX_MEMPOOL_SIZE {
+            return Err("mempool is full".to_string());
+        }
+
+        self.add("add_transaction", vec![(transaction, None)]);
+        Ok(id)
+    }
+
+    // Add the transactions of `package` that aren't pending yet, returning their ids. Pending
+    // ones join the package, so it is taken for blocks as a whole.
+    pub fn insert_package(&mut self, package: TransactionPackage) -> Result<Vec<Hash256>, String> {
+        package.check()?;
+        if package.transactions.iter().any(coinbase::is_coinbase) {
+            return Err("coinbase transactions are only created by miners".to_string());
+        }
+
+        let first = package.transactions[0].id();
+        let package_id = self.packages.get(&first).copied().unwrap_or(first);
+        let mut added = Vec::new();
+        for transaction in package.transactions {
+            let id = transaction.id();
+            if self.transactions.contains_key(&id) {
+                self.packages.entry(id).or_insert(package_id);
+            } else {
+                added.push((transaction, Some(package_id)));
+            }
+        }
+        if added.is_empty() {
+            return Err(format!("package {} is already pending", package_id));
+        }
+        if self.transactions.len() + added.len() > MAX_MEMPOOL_SIZE {
+            return Err("mempool is full".to_string());
+        }
+
+        let ids = added
+            .iter()
+            .map(|(transaction, _)| transaction.id())
+            .collect();
+        self.add("add_package", added);
+        Ok(ids)
+    }
+
+    fn add(&mut self, operation: &str, transactions: Vec<(Transaction, Option<Hash256>)>) {
+        if let Some(journal) = &self.journal {
+            let events = transactions
+                .iter()
+                .map(|(transaction, package)| JournalEvent::MempoolAdd {
+                    transaction: Box::new(transaction.clone()),
+                    package: *package,
+                })
+                .collect();
+            journal.record(&JournalRecord::new(operation, None, events));
+        }
+        for (transaction, package) in transactions {
+            let id = transaction.id();
+            if let Some(package) = package {
+                self.packages.insert(id, package);
+            }
+            self.transactions
+                .insert(id, (self.next_sequence, transaction));
+            self.next_sequence += 1;
+        }
+    }
+
+    // Up to `max_count` pending transactions valid on top of `state`, highest fee first and in
+    // order of arrival for equal fees. Packages go by the fee rate of their transactions taken
+    // together, which keep their order. Transactions ahead of their sender's nonce wait for the
+    // ones before them, those their sender can't pay for are left out. They stay pending until a
+    // block on the chain includes them.
+    pub fn take_for_block(&self, state: &State, max_count: usize) -> Vec<Transaction> {
+        let mut pending = state.pending();
+        let mut taken = Vec::new();
+        let mut waiting: HashMap<(&Address, u64), &Transaction> = HashMap::new();
+
+        for transaction in self.by_fee_rate() {
+            let mut next = Some(transaction);
+            while let Some(transaction) = next.take() {
+                if taken.len() == max_count {
+                    return taken;
+                }
+
+                let sender = &transaction.sender;
+                if transaction.nonce > pending.next_nonce(sender) {
+                    waiting
+                        .entry((sender, transaction.nonce))
+                        .or_insert(transaction);
+                } else if pending.apply(transaction) {
+                    taken.push(transaction.clone());
+                    // The one of the sender waiting for this one may follow now.
+                    next = waiting.remove(&(sender, transaction.nonce + 1));
+                }
+            }
+        }
+
+        taken
+    }
+
+    // Every pending transaction in the order `take_for_block` considers them.
+    pub fn by_fee_rate(&self) -> Vec<&Transaction> {
+        let mut units = self.units();
+        units.sort_by(|a, b| compare_fee_rates(a, b).then(a[0].0.cmp(&b[0].0)));
+
+        units
+            .into_iter()
+            .flatten()
+            .map(|(_, transaction)| transaction)
+            .collect()
+    }
+
+    // Pending transactions kept on their own or in a package, every package in order of
+    // arrival. The first transaction of a package belongs to it even if it came on its own.
+    fn units(&self) -> Vec<Vec<&(u64, Transaction)>> {
+        let mut units: HashMap<Hash256, Vec<&(u64, Transaction)>> = HashMap::new();
+        for (id, pending) in self.transactions.iter() {
+            let package = self.packages.get(id).unwrap_or(id);
+            units.entry(*package).or_default().push(pending);
+        }
+
+        units
+            .into_values()
+            .map(|mut unit| {
+                unit.sort_by_key(|(sequence, _)| *sequence);
+                unit
+            })
+            .collect()
+    }
+
+    // Pending transactions kept on their own or in a package that have one of `ids`, the whole
+    // package for the ones in a package.
+    pub fn units_with(&self, ids: &[Hash256]) -> Vec<Vec<Transaction>> {
+        let wanted: HashSet<&Hash256> = ids
+            .iter()
+            .filter(|id| self.transactions.contains_key(id))
+            .map(|id| self.packages.get(id).unwrap_or(id))
+            .collect();
+        let mut units: HashMap<&Hash256, Vec<&(u64, Transaction)>> = HashMap::new();
+        for (id, pending) in self.transactions.iter() {
+            let package = self.packages.get(id).unwrap_or(id);
+            if wanted.contains(package) {
+                units.entry(package).or_default().push(pending);
+            }
+        }
+
+        let mut units: Vec<_> = units.into_values().collect();
+        for unit in units.iter_mut() {
+            unit.sort_by_key(|(sequence, _)| *sequence);
+        }
+        units.sort_by_key(|unit| unit[0].0);
+        units
+            .into_iter()
+            .map(|unit| {
+                unit.into_iter()
+                    .map(|(_, transaction)| transaction.clone())
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Drop the transactions included in `blocks`.
+    pub fn remove_included<'a>(&mut self, blocks: impl IntoIterator<Item = &'a Block>) {
+        let included: Vec<Hash256> = blocks
+            .into_iter()
+            .flat_map(|block| block.body.transactions.iter())
+            .map(|transaction| transaction.id())
+            .filter(|id| self.transactions.contains_key(id))
+            .collect();
+        self.remove("remove_included", included);
+    }
+
+    // Keep only the pending transactions `keep` returns true for, given the transactions of a
+    // package all at once.
+    pub fn retain(&mut self, mut keep: impl FnMut(&[Transaction]) -> bool) {
+        let dropped: Vec<Hash256> = self
+            .units()
+            .into_iter()
+            .map(|unit| {
+                unit.into_iter()
+                    .map(|(_, transaction)| transaction.clone())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|transactions| !keep(transactions))
+            .flatten()
+            .map(|transaction| transaction.id())
+            .collect();
+        self.remove("drop_invalid", dropped);
+    }
+
+    fn remove(&mut self, operation: &str, ids: Vec<Hash256>) {
+        if ids.is_empty() {
+            return;
+        }
+        if let Some(journal) = &self.journal {
+            let events = ids
+                .iter()
+                .map(|id| JournalEvent::MempoolRemove { id: *id })
+                .collect();
+            journal.record(&JournalRecord::new(operation, None, events));
+        }
+        for id in ids {
+            self.transactions.remove(&id);
+            self.packages.remove(&id);
+        }
+    }
+
+    // Restore the transactions pending according to `records`, in their order of arrival.
+    pub fn recover(&mut self, records: &[JournalRecord]) {
+        for event in records.iter().flat_map(|record| record.events.iter()) {
+            match event {
+                JournalEvent::MempoolAdd {
+                    transaction,
+                    package,
+                } => {
+                    let id = transaction.id();
+                    if !self.transactions.contains_key(&id) {
+                        self.transactions
+                            .insert(id, (self.next_sequence, *transaction.clone()));
+                        self.next_sequence += 1;
+                    }
+                    if let Some(package) = package {
+                        self.packages.insert(id, *package);
+                    }
+                }
+                JournalEvent::MempoolRemove { id } => {
+                    self.transactions.remove(id);
+                    self.packages.remove(id);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Record further changes to `journal`.
+    pub fn set_journal(&mut self, journal: Arc<Journal>) {
+        self.journal = Some(journal);
+    }
+
+    // Events adding the pending transactions in their order of arrival, all the journal needs
+    // to restore the mempool.
+    pub fn snapshot(&self) -> Vec<JournalEvent> {
+        let mut pending: Vec<&(u64, Transaction)> = self.transactions.values().collect();
+        pending.sort_by_key(|(sequence, _)| *sequence);
+
+        pending
+            .into_iter()
+            .map(|(_, transaction)| JournalEvent::MempoolAdd {
+                transaction: Box::new(transaction.clone()),
+                package: self.packages.get(&transaction.id()).copied(),
+            })
+            .collect()
+    }
+
+    pub fn contains(&self, id: &Hash256) -> bool {
+        self.transactions.contains_key(id)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &Hash256> {
+        self.transactions.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Transaction> {
+        self.transactions
+            .values()
+            .map(|(_, transaction)| transaction)
+    }
+}
+
+// Order of `a` and `b` by the fee rate of their transactions, highest first.
+fn compare_fee_rates(a: &[&(u64, Transaction)], b: &[&(u64, Transaction)]) -> Ordering {
+    let fees = |unit: &[&(u64, Transaction)]| -> u128 {
+        unit.iter()
+            .map(|(_, transaction)| transaction.fee.0 as u128)
+            .sum()
+    };
+    // a / len(a) against b / len(b), without rounding.
+    (fees(b) * a.len() as u128).cmp(&(fees(a) * b.len() as u128))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::address::Address;
+    use crate::models::amount::Amount;
+
+    fn transaction(sender: &str, receiver: &str, amount: u64, fee: u64) -> Transaction {
+        let mut transaction = Transaction::new(
+            Address::new(sender).expect("address is valid"),
+            Address::new(receiver).expect("address is valid"),
+            Amount(amount),
+        );
+        transaction.fee = Amount(fee);
+        transaction
+    }
+
+    // State in which each of `senders` has 100 units.
+    fn funded(senders: &[&str]) -> State {
+        let mut state = State::default();
+        for sender in senders {
+            let address = Address::new(*sender).expect("address is valid");
+            state.balances.insert(address, Amount(100));
+        }
+        state
+    }
+
+    fn ids(transactions: &[Transaction]) -> Vec<Hash256> {
+        transactions.iter().map(Transaction::id).collect()
+    }
+
+    fn fees(transactions: &[Transaction]) -> Vec<u64> {
+        transactions
+            .iter()
+            .map(|transaction| transaction.fee.0)
+            .collect()
+    }
+
+    #[test]
+    fn highest_fees_are_taken_first_then_oldest() {
+        let mut mempool = Mempool::default();
+        let first_cheap = transaction("alice", "erin", 1, 1);
+        let second_cheap = transaction("bob", "erin", 2, 1);
+        mempool.insert(first_cheap.clone()).expect("is new");
+        mempool
+            .insert(transaction("carol", "erin", 3, 5))
+            .expect("is new");
+        mempool.insert(second_cheap).expect("is new");
+        mempool
+            .insert(transaction("dave", "erin", 4, 3))
+            .expect("is new");
+
+        let state = funded(&["alice", "bob", "carol", "dave"]);
+        let taken = mempool.take_for_block(&state, 3);
+        assert_eq!(fees(&taken), vec![5, 3, 1]);
+        assert_eq!(taken[2].id(), first_cheap.id());
+        // Taken transactions stay pending until a block includes them.
+        assert_eq!(mempool.len(), 4);
+    }
+
+    #[test]
+    fn duplicates_coinbases_and_overflow_are_rejected() {
+        let mut mempool = Mempool::default();
+        let pending = transaction("alice", "bob", 1, 1);
+        mempool.insert(pending.clone()).expect("is new");
+
+        assert!(mempool.insert(pending).is_err());
+        assert!(
+            mempool
+                .insert(coinbase::coinbase(
+                    &Address::new("miner").expect("address is valid"),
+                    Amount(50)
+                ))
+                .is_err()
+        );
+
+        for amount in 2..=MAX_MEMPOOL_SIZE as u64 {
+            mempool
+                .insert(transaction("alice", "bob", amount, 0))
+                .expect("there is room");
+        }
+        assert!(mempool.insert(transaction("alice", "bob", 0, 100)).is_err());
+        assert_eq!(mempool.len(), MAX_MEMPOOL_SIZE);
+    }
+
+    #[test]
+    fn packages_go_by_their_fee_rate_and_keep_their_order() {
+        let mut mempool = Mempool::default();
+        // A child paying for its parent: 0 + 9 over two transactions beats 4 alone.
+        let parent = transaction("alice", "bob", 20, 0);
+        let child = transaction("bob", "carol", 5, 9);
+        mempool
+            .insert(transaction("dave", "erin", 1, 4))
+            .expect("is new");
+        mempool
+            .insert_package(TransactionPackage {
+                transactions: vec![parent.clone(), child.clone()],
+            })
+            .expect("child spends from its parent");
+
+        assert_eq!(
+            ids(&mempool.take_for_block(&funded(&["alice", "dave"]), 3)[..2]),
+            vec![parent.id(), child.id()]
+        );
+    }
+
+    #[test]
+    fn packages_of_unrelated_transactions_are_rejected() {
+        let mut mempool = Mempool::default();
+        let unrelated = TransactionPackage {
+            transactions: vec![
+                transaction("alice", "bob", 10, 0),
+                transaction("carol", "dave", 5, 9),
+            ],
+        };
+
+        assert!(mempool.insert_package(unrelated).is_err());
+        assert!(mempool.is_empty());
+    }
+
+    #[test]
+    fn included_and_invalid_transactions_are_evicted() {
+        let mut mempool = Mempool::default();
+        let included = transaction("alice", "bob", 1, 1);
+        let parent = transaction("alice", "bob", 10, 0);
+        let child = transaction("bob", "carol", 5, 9);
+        let kept = transaction("dave", "erin", 1, 4);
+        mempool.insert(included.clone()).expect("is new");
+        mempool
+            .insert_package(TransactionPackage {
+                transactions: vec![parent.clone(), child],
+            })
+            .expect("child spends from its parent");
+        mempool.insert(kept.clone()).expect("is new");
+
+        mempool.remove_included([&Block::new(1, Hash256::ZERO, vec![included.clone()])]);
+        assert!(!mempool.contains(&included.id()));
+        assert_eq!(mempool.len(), 3);
+
+        // Packages are dropped as a whole.
+        mempool.retain(|transactions| !ids(transactions).contains(&parent.id()));
+        let state = funded(&["alice", "dave"]);
+        assert_eq!(ids(&mempool.take_for_block(&state, 10)), vec![kept.id()]);
+    }
+
+    #[test]
+    fn transactions_wait_for_their_nonce_and_are_left_out_if_unpaid() {
+        let mut mempool = Mempool::default();
+        let with_nonce = |nonce, amount, fee| {
+            let mut transaction = transaction("alice", "bob", amount, fee);
+            transaction.nonce = nonce;
+            transaction
+        };
+        let first = with_nonce(0, 10, 2);
+        let second = with_nonce(1, 10, 5);
+        let unpaid = with_nonce(2, 100, 9);
+        let outbid = with_nonce(0, 1, 1);
+        for transaction in [&first, &second, &unpaid, &outbid] {
+            mempool.insert(transaction.clone()).expect("is new");
+        }
+
+        // The higher fees come first, but only fit in after the transactions before them. Of two
+        // with the same nonce the one paying more is taken.
+        let taken = mempool.take_for_block(&funded(&["alice"]), 10);
+        assert_eq!(ids(&taken), vec![first.id(), second.id()]);
+    }
+}
@@ -0,0 +1,36 @@
+use super::address::Address;
+use ed25519_dalek::{Keypair, Signature, Signer};
+
+// Prepended to every signed message, so a message signature never passes for the signature of
+// a transaction.
+const MESSAGE_PREFIX: &str = "rust-blockchain signed message:\n";
+
+// Data covered by the signature of `message`.
+fn signing_data(message: &str) -> Vec<u8> {
+    format!("{}{}", MESSAGE_PREFIX, message).into_bytes()
+}
+
+// Sign `message` with `keypair` to prove control of the address derived from it off-chain, e.g.
+// for airdrops. The signature is base58 encoded like addresses.
+pub fn sign(keypair: &Keypair, message: &str) -> String {
+    let signature = keypair.sign(&signing_data(message));
+
+    bs58::encode(signature.to_bytes()).into_string()
+}
+
+// Check that `signature` of `message` was made with the key controlling `address`. Addresses
+// embed their key, so nothing but the address is needed.
+pub fn verify(address: &Address, message: &str, signature: &str) -> Result<(), String> {
+    let public_key = address
+        .public_key()
+        .ok_or_else(|| format!("{} is not derived from a key", address))?;
+    let signature = bs58::decode(signature)
+        .into_vec()
+        .ok()
+        .and_then(|signature| Signature::try_from(signature.as_slice()).ok())
+        .ok_or_else(|| "can't decode signature".to_string())?;
+
+    public_key
+        .verify_strict(&signing_data(message), &signature)
+        .map_err(|_| format!("signature is not by the key of {}", address))
+}
@@ -1,3 +1,31 @@
+pub mod accumulator;
+pub mod address;
+pub mod amount;
+pub mod anchor;
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+pub mod asset;
+pub mod auxpow;
 pub mod block;
 pub mod blockchain;
+pub mod channel;
+pub mod checkpoint;
+pub mod coinbase;
+pub mod condition;
+pub mod consensus;
+pub mod epoch;
+pub mod hash;
+pub mod history;
+pub mod htlc;
+pub mod index;
+pub mod journal;
+pub mod mempool;
+pub mod message;
+pub mod params;
+pub mod schema;
+pub mod sponsor;
+pub mod state;
+pub mod storage;
+pub mod timelock;
 pub mod transaction;
+pub mod work;
@@ -0,0 +1,160 @@
+use super::address::Address;
+use super::amount::Amount;
+use super::consensus::PowAlgorithm;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+
+// Id of the chain a node hosts unless its config names another one.
+pub const MAIN_CHAIN_ID: &str = "main";
+// Most leading zero hex digits the difficulty can ask for. `Work` can't tell higher difficulties
+// apart, so chains mined at them couldn't be compared.
+pub const MAX_DIFFICULTY: usize = 31;
+
+// `ChainParams` Consensus parameters of a chain, nodes only agree on blocks if they use the same
+// ones. Loaded from a JSON chain config, parameters missing from it keep their defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainParams {
+    // Name telling the chains hosted by one node apart, it also separates their topics and files.
+    pub chain_id: String,
+    // Number of leading zero hex digits the hash of the genesis block and the blocks up to the
+    // first retarget needs.
+    pub difficulty: usize,
+    // Number of blocks between difficulty retargets, 0 keeps the difficulty fixed.
+    pub retarget_interval: u64,
+    // Seconds between blocks the retargets aim for.
+    pub target_block_time: u64,
+    // Proof of work algorithm blocks are mined and validated with.
+    pub pow: PowAlgorithm,
+    // Units of the native coin created by a mined block before the first halving.
+    pub block_reward: Amount,
+    // Number of blocks after which the block reward halves, 0 keeps it constant.
+    pub halving_interval: u64,
+    // Most blocks a reorg may disconnect without an operator approving it, 0 for no limit.
+    pub max_reorg_depth: u64,
+    // Balances the genesis block starts the chain with, e.g. migrated from another chain.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub genesis_allocations: Vec<Allocation>,
+}
+
+// `Allocation` Coins paid to an address by the genesis block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Allocation {
+    pub address: Address,
+    pub amount: Amount,
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        ChainParams {
+            chain_id: MAIN_CHAIN_ID.to_string(),
+            difficulty: 3,
+            retarget_interval: 0,
+            target_block_time: 60,
+            pow: PowAlgorithm::Sha256,
+            block_reward: Amount::from_coins(50),
+            halving_interval: 210_000,
+            max_reorg_depth: 0,
+            genesis_allocations: Vec::new(),
+        }
+    }
+}
+
+impl ChainParams {
+    // Units of the native coin created by the block at `height`, halved every
+    // `halving_interval` blocks until nothing is left.
+    pub fn reward_at(&self, height: u64) -> Amount {
+        let halvings = match self.halving_interval {
+            0 => 0,
+            interval => height / interval,
+        };
+
+        u32::try_from(halvings)
+            .ok()
+            .and_then(|halvings| self.block_reward.0.checked_shr(halvings))
+            .map_or(Amount::ZERO, Amount)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data =
+            fs::read_to_string(path).map_err(|err| format!("can't read {}: {}", path, err))?;
+
+        let params: ChainParams =
+            serde_json::from_str(&data).map_err(|err| format!("can't parse {}: {}", path, err))?;
+        let is_valid_id = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if params.chain_id.is_empty() || !params.chain_id.chars().all(is_valid_id) {
+            return Err(format!(
+                "chain id {:?} in {} isn't made of letters, digits, '-' and '_'",
+                params.chain_id, path
+            ));
+        }
+        if params.difficulty > MAX_DIFFICULTY {
+            return Err(format!(
+                "difficulty {} in {} is above the maximum of {}",
+                params.difficulty, path, MAX_DIFFICULTY
+            ));
+        }
+
+        Ok(params)
+    }
+
+    // Read the chain configs at `paths`, one for every chain the node hosts. The defaults if
+    // there is none. `difficulty` overrides the difficulty of every chain.
+    pub fn configured(paths: &[String], difficulty: Option<usize>) -> Result<Vec<Self>, String> {
+        let mut chains = Vec::new();
+        let mut ids = HashSet::new();
+
+        for path in paths {
+            let params = ChainParams::load(path)?;
+            if !ids.insert(params.chain_id.clone()) {
+                return Err(format!("chain {} is configured twice", params.chain_id));
+            }
+            chains.push(params);
+        }
+
+        if chains.is_empty() {
+            chains.push(ChainParams::default());
+        }
+        if let Some(difficulty) = difficulty {
+            if difficulty > MAX_DIFFICULTY {
+                return Err(format!(
+                    "difficulty {} is above the maximum of {}",
+                    difficulty, MAX_DIFFICULTY
+                ));
+            }
+            for params in chains.iter_mut() {
+                params.difficulty = difficulty;
+            }
+        }
+        Ok(chains)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn difficulties_above_the_maximum_are_rejected() {
+        let chains = ChainParams::configured(&[], Some(MAX_DIFFICULTY)).expect("valid difficulty");
+        assert_eq!(chains[0].difficulty, MAX_DIFFICULTY);
+
+        assert!(ChainParams::configured(&[], Some(MAX_DIFFICULTY + 1)).is_err());
+    }
+
+    #[test]
+    fn block_reward_is_50_coins_halved_every_interval() {
+        let params = ChainParams::default();
+        assert_eq!(params.reward_at(0).to_string(), "50.00000000");
+        assert_eq!(
+            params.reward_at(params.halving_interval - 1),
+            Amount::from_coins(50)
+        );
+        assert_eq!(
+            params.reward_at(params.halving_interval),
+            Amount::from_coins(25)
+        );
+        assert_eq!(params.reward_at(64 * params.halving_interval), Amount::ZERO);
+    }
+}
@@ -0,0 +1,183 @@
+// Schema versions of the files the node keeps on disk. Every file format has its own list of
+// migrations, the one at index `n` upgrades data from version `n` to `n + 1`, so the current
+// version of a format is the length of its list. Files written before versioning was introduced
+// carry no tag and are version 0. Files at an older version are upgraded in place on load, after
+// copying them to `<path>.v<version>.bak`.
+
+use std::{
+    fs,
+    io::{self, ErrorKind},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// `Migration` Upgrades data by one schema version.
+pub type Migration = fn(Value) -> Result<Value, String>;
+
+// `Versioned` Envelope of a versioned JSON file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Versioned<T> {
+    pub schema_version: u32,
+    pub data: T,
+}
+
+// `Header` First line of a versioned JSON lines file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Header {
+    pub schema_version: u32,
+}
+
+// Migration of formats whose first version only added the schema version tag.
+pub fn add_version_tag(data: Value) -> Result<Value, String> {
+    Ok(data)
+}
+
+// Load the data of the versioned JSON file at `path`, `None` if it doesn't exist.
+pub fn load_json(path: &str, migrations: &[Migration]) -> io::Result<Option<Value>> {
+    let Some(text) = read(path)? else {
+        return Ok(None);
+    };
+
+    let (version, data) = match serde_json::from_str(&text)? {
+        Value::Object(mut document) if document.contains_key("schema_version") => {
+            let version = document["schema_version"]
+                .as_u64()
+                .ok_or_else(|| invalid(format!("{} has an invalid schema version", path)))?;
+            (
+                version as u32,
+                document.remove("data").unwrap_or(Value::Null),
+            )
+        }
+        data => (0, data),
+    };
+
+    let current = migrations.len() as u32;
+    let data = migrate(path, version, data, migrations)?;
+    if version < current {
+        backup(path, version)?;
+        save_json(path, current, &data)?;
+        println!(
+            "upgraded {} from schema version {} to {}",
+            path, version, current
+        );
+    }
+
+    Ok(Some(data))
+}
+
+pub fn save_json<T: Serialize>(path: &str, version: u32, data: &T) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(&Versioned {
+        schema_version: version,
+        data,
+    })?;
+
+    fs::write(path, json)
+}
+
+// Load the records of the versioned JSON lines file at `path`, whose first line is a `Header`.
+// Migrations get and return the records as an array.
+pub fn load_lines(path: &str, migrations: &[Migration]) -> io::Result<Vec<Value>> {
+    let Some(text) = read(path)? else {
+        return Ok(Vec::new());
+    };
+
+    let mut lines = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .peekable();
+    let version = match lines
+        .peek()
+        .map(|line| serde_json::from_str::<Header>(line))
+    {
+        Some(Ok(header)) => {
+            lines.next();
+            header.schema_version
+        }
+        _ => 0,
+    };
+    // A torn last line of an append-only file is skipped rather than failing the whole load.
+    let records: Vec<Value> = lines
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+
+    let current = migrations.len() as u32;
+    let records = match migrate(path, version, Value::Array(records), migrations)? {
+        Value::Array(records) => records,
+        _ => {
+            return Err(invalid(format!(
+                "migrating {} didn't produce records",
+                path
+            )));
+        }
+    };
+    if version < current {
+        backup(path, version)?;
+        save_lines(path, current, &records)?;
+        println!(
+            "upgraded {} from schema version {} to {}",
+            path, version, current
+        );
+    }
+
+    Ok(records)
+}
+
+pub fn save_lines<T: Serialize>(path: &str, version: u32, records: &[T]) -> io::Result<()> {
+    let mut text = header_line(version);
+    for record in records.iter() {
+        text.push('\n');
+        text.push_str(&serde_json::to_string(record)?);
+    }
+    text.push('\n');
+
+    fs::write(path, text)
+}
+
+pub fn header_line(version: u32) -> String {
+    serde_json::to_string(&Header {
+        schema_version: version,
+    })
+    .expect("can jsonify header")
+}
+
+fn migrate(path: &str, version: u32, data: Value, migrations: &[Migration]) -> io::Result<Value> {
+    if version as usize > migrations.len() {
+        return Err(invalid(format!(
+            "{} has schema version {}, this build only knows up to {}",
+            path,
+            version,
+            migrations.len()
+        )));
+    }
+
+    migrations[version as usize..]
+        .iter()
+        .enumerate()
+        .try_fold(data, |data, (step, migration)| {
+            migration(data).map_err(|err| {
+                invalid(format!(
+                    "can't migrate {} from schema version {}: {}",
+                    path,
+                    version as usize + step,
+                    err
+                ))
+            })
+        })
+}
+
+fn read(path: &str) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+fn backup(path: &str, version: u32) -> io::Result<()> {
+    fs::copy(path, format!("{}.v{}.bak", path, version)).map(|_| ())
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
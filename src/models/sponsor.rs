@@ -0,0 +1,102 @@
+// Fee sponsorship, so an address holding tokens but none of the native currency can still move
+// them: a third party, the sponsor, pays the fee of the transaction in its place. The sender
+// names the sponsor in the transaction before signing it, and the sponsor signs the same data
+// with its own key, so neither can change what the other agreed to. The sponsor only pays the
+// fee, the amount still comes from the sender.
+
+use super::address::Address;
+use ed25519_dalek::{PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+
+// `Sponsor` Address paying the fee of a transaction, and its signature of the transaction's
+// `signing_data`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Sponsor {
+    pub address: Address,
+    // Raw ed25519 key controlling `address`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub public_key: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signature: Vec<u8>,
+}
+
+impl Sponsor {
+    pub fn new(address: Address) -> Self {
+        Sponsor {
+            address,
+            public_key: Vec::new(),
+            signature: Vec::new(),
+        }
+    }
+
+    // Check that the key controlling the sponsor address signed `signing_data`.
+    pub fn is_signature_valid(&self, signing_data: &[u8]) -> bool {
+        let Ok(public_key) = PublicKey::from_bytes(&self.public_key) else {
+            return false;
+        };
+        let Ok(signature) = Signature::try_from(self.signature.as_slice()) else {
+            return false;
+        };
+
+        Address::from_public_key(&public_key) == self.address
+            && public_key.verify_strict(signing_data, &signature).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::amount::Amount;
+    use crate::models::transaction::Transaction;
+    use ed25519_dalek::{Keypair, SecretKey};
+
+    fn keypair(seed: u8) -> Keypair {
+        let secret = SecretKey::from_bytes(&[seed; 32]).expect("any 32 bytes are a secret key");
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    // Transaction of the holder of key 1 with a fee sponsored by the holder of key 2, signed by
+    // the sender only.
+    fn sponsored() -> Transaction {
+        let sender = keypair(1);
+        let mut transaction = Transaction::new(
+            Address::from_public_key(&sender.public),
+            Address::new("receiver").expect("address is valid"),
+            Amount(10),
+        );
+        transaction.fee = Amount(2);
+        transaction.sponsor = Some(Sponsor::new(Address::from_public_key(&keypair(2).public)));
+        transaction.sign(&sender);
+        transaction
+    }
+
+    #[test]
+    fn transaction_signed_by_sender_and_sponsor_is_accepted() {
+        let mut transaction = sponsored();
+        transaction.sign_as_sponsor(&keypair(2));
+
+        assert!(transaction.is_signature_valid());
+        assert_eq!(
+            transaction.fee_payer(),
+            &Address::from_public_key(&keypair(2).public)
+        );
+    }
+
+    #[test]
+    fn sponsor_signature_missing_forged_or_outdated_is_rejected() {
+        assert!(!sponsored().is_signature_valid());
+
+        // A key that doesn't control the sponsor address.
+        let mut forged = sponsored();
+        forged.sign_as_sponsor(&keypair(3));
+        assert!(!forged.is_signature_valid());
+
+        // The sender can't raise the fee once the sponsor agreed to it.
+        let mut raised = sponsored();
+        raised.sign_as_sponsor(&keypair(2));
+        raised.fee = Amount(20);
+        raised.sign(&keypair(1));
+        assert!(!raised.is_signature_valid());
+    }
+}
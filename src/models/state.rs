@@ -0,0 +1,537 @@
+use super::accumulator::Accumulator;
+use super::address::Address;
+use super::amount::Amount;
+use super::block::Block;
+use super::channel::{self, Channels};
+use super::coinbase;
+use super::condition::Condition;
+use super::hash::Hash256;
+use super::history;
+use super::htlc::{self, Locks};
+use super::transaction::Transaction;
+use super::work::Work;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// `State` Account balances after applying the chain up to `height`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct State {
+    // Index of the last applied block.
+    pub height: u64,
+    pub balances: HashMap<Address, Amount>,
+    // Nonce the next transaction of every address that sent one has to carry.
+    #[serde(default)]
+    pub nonces: HashMap<Address, u64>,
+    // Token balances of every address holding any, by asset id.
+    #[serde(default)]
+    pub assets: HashMap<Address, HashMap<String, u64>>,
+    // Open channels, needed to know what closing them pays out.
+    #[serde(skip)]
+    pub channels: Channels,
+    // HTLC locks that weren't claimed or refunded yet.
+    #[serde(skip)]
+    pub locks: Locks,
+    // Accumulator of every transaction applied, see `accumulator`.
+    #[serde(skip)]
+    pub accumulator: Accumulator,
+    // History of every block applied, the one the next block commits to, see `history`.
+    #[serde(skip)]
+    pub history: Accumulator,
+    // Work of the blocks applied, see `work::cumulative_work`.
+    #[serde(skip)]
+    pub work: Work,
+}
+
+impl State {
+    pub fn apply_block(&mut self, block: &Block) {
+        for transaction in block.body.transactions.iter() {
+            self.apply_transaction(transaction, block.header.index);
+        }
+
+        self.height = block.header.index;
+        self.accumulator.add_block(block);
+        // The genesis block proves no work.
+        if block.header.index > 0 {
+            self.work = self.work + Work::from_difficulty(block.header.difficulty);
+        }
+        self.history.add(history::leaf(block, self.work));
+    }
+
+    pub fn balance_of(&self, address: &str) -> Amount {
+        self.balances.get(address).copied().unwrap_or_default()
+    }
+
+    pub fn asset_balance_of(&self, address: &str, asset: &str) -> u64 {
+        self.assets
+            .get(address)
+            .and_then(|assets| assets.get(asset))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // Nonce the next transaction of `address` has to carry, the number of transactions it sent.
+    pub fn next_nonce(&self, address: &str) -> u64 {
+        self.nonces.get(address).copied().unwrap_or_default()
+    }
+
+    // Check that the transactions in `transactions` carry the next nonces of their senders, in
+    // order, so none of them was included before.
+    pub fn are_nonces_valid(&self, transactions: &[Transaction]) -> bool {
+        let mut pending = self.pending();
+        transactions
+            .iter()
+            .all(|transaction| pending.take_nonce(transaction))
+    }
+
+    // Check that the sender of every transaction in `transactions` can pay for it, in order, and
+    // its sponsor for the fee if it has one. Senders may spend what earlier transactions paid
+    // them, except channel payouts, which are only known once the transactions are applied.
+    // Every asset transfer has to be covered by the sender's balance of that asset.
+    pub fn can_pay_for(&self, transactions: &[Transaction]) -> bool {
+        let mut pending = self.pending();
+        transactions
+            .iter()
+            .all(|transaction| pending.try_pay(transaction))
+    }
+
+    // Nonces and balances of the state, with transactions taken into account one at a time.
+    pub fn pending(&self) -> PendingState<'_> {
+        PendingState {
+            state: self,
+            nonces: HashMap::new(),
+            balances: HashMap::new(),
+            assets: HashMap::new(),
+        }
+    }
+
+    // Hash committing to the height, balances, nonces and token balances, independent of the map
+    // ordering.
+    pub fn root(&self) -> Hash256 {
+        let mut balances: Vec<(&Address, &Amount)> = self.balances.iter().collect();
+        balances.sort();
+        let mut nonces: Vec<(&Address, &u64)> = self.nonces.iter().collect();
+        nonces.sort();
+        let mut assets: Vec<(&Address, &String, &u64)> = self
+            .assets
+            .iter()
+            .flat_map(|(address, assets)| {
+                assets
+                    .iter()
+                    .map(move |(asset, amount)| (address, asset, amount))
+            })
+            .collect();
+        assets.sort();
+
+        let json = serde_json::to_vec(&(self.height, balances, nonces, assets))
+            .expect("can jsonify state");
+        Hash256::digest(&json)
+    }
+
+    fn apply_transaction(&mut self, transaction: &Transaction, height: u64) {
+        if !coinbase::is_coinbase(transaction) {
+            self.debit(transaction.fee_payer(), transaction.fee);
+            *self.nonces.entry(transaction.sender.clone()).or_default() += 1;
+        }
+
+        htlc::apply(&mut self.locks, transaction);
+        let payouts = channel::apply(&mut self.channels, transaction, height).unwrap_or_default();
+        for (address, amount) in payouts {
+            self.credit(&address, amount);
+        }
+
+        let (debits, credits) = transfers(transaction);
+        if debits {
+            self.debit(&transaction.sender, transaction.amount);
+        }
+        if credits {
+            self.credit(&transaction.receiver, transaction.amount);
+        }
+
+        // Assets always move from the sender to the receiver, whatever the condition.
+        for transfer in transaction.assets.iter() {
+            self.move_asset(
+                &transaction.sender,
+                &transaction.receiver,
+                &transfer.asset,
+                transfer.amount,
+            );
+        }
+    }
+
+    fn move_asset(&mut self, sender: &Address, receiver: &Address, asset: &str, amount: u64) {
+        if let Some(assets) = self.assets.get_mut(sender) {
+            if let Some(balance) = assets.get_mut(asset) {
+                *balance = balance.saturating_sub(amount);
+                if *balance == 0 {
+                    assets.remove(asset);
+                }
+            }
+            if assets.is_empty() {
+                self.assets.remove(sender);
+            }
+        }
+
+        let balance = self
+            .assets
+            .entry(receiver.clone())
+            .or_default()
+            .entry(asset.to_string())
+            .or_default();
+        *balance = balance.saturating_add(amount);
+    }
+
+    fn credit(&mut self, address: &Address, amount: Amount) {
+        let balance = self.balances.entry(address.clone()).or_default();
+        *balance = balance.saturating_add(amount);
+    }
+
+    fn debit(&mut self, address: &Address, amount: Amount) {
+        let balance = self.balances.entry(address.clone()).or_default();
+        *balance = balance.saturating_sub(amount);
+    }
+}
+
+// Whether `transaction` takes its amount from the sender and whether it pays it to the receiver.
+fn transfers(transaction: &Transaction) -> (bool, bool) {
+    match &transaction.condition {
+        None => (!coinbase::is_coinbase(transaction), true),
+        // Locked funds are held by the chain until they are settled.
+        Some(Condition::Lock(_)) | Some(Condition::ChannelOpen(_)) => (true, false),
+        Some(Condition::Claim { .. }) | Some(Condition::Refund { .. }) => (false, true),
+        // Channel updates only move what the channel pays out.
+        Some(Condition::ChannelClose { .. })
+        | Some(Condition::ChannelContest { .. })
+        | Some(Condition::ChannelSettle { .. }) => (false, false),
+        Some(Condition::Anchor(_)) => (false, false),
+    }
+}
+
+// `PendingState` Nonces and balances of a state after transactions that aren't applied to it,
+// e.g. the ones picked for a block so far. Only what they changed is kept.
+pub struct PendingState<'a> {
+    state: &'a State,
+    nonces: HashMap<&'a Address, u64>,
+    balances: HashMap<&'a Address, Amount>,
+    assets: HashMap<(&'a Address, &'a str), u64>,
+}
+
+impl<'a> PendingState<'a> {
+    // Nonce the next transaction of `address` has to carry.
+    pub fn next_nonce(&self, address: &Address) -> u64 {
+        self.nonces
+            .get(address)
+            .copied()
+            .unwrap_or_else(|| self.state.next_nonce(address.as_str()))
+    }
+
+    // Take `transaction` into account if it carries the next nonce of its sender and can be paid
+    // for, see `State::are_nonces_valid` and `State::can_pay_for`. Nothing changes otherwise.
+    pub fn apply(&mut self, transaction: &'a Transaction) -> bool {
+        if !coinbase::is_coinbase(transaction)
+            && transaction.nonce != self.next_nonce(&transaction.sender)
+        {
+            return false;
+        }
+        if !self.pay(transaction) {
+            return false;
+        }
+
+        self.take_nonce(transaction)
+    }
+
+    fn take_nonce(&mut self, transaction: &'a Transaction) -> bool {
+        if coinbase::is_coinbase(transaction) {
+            return true;
+        }
+
+        let nonce = self.next_nonce(&transaction.sender);
+        if transaction.nonce != nonce {
+            return false;
+        }
+        self.nonces.insert(&transaction.sender, nonce + 1);
+        true
+    }
+
+    // `try_pay`, putting the balances back if `transaction` can't be paid for.
+    fn pay(&mut self, transaction: &'a Transaction) -> bool {
+        let sponsor = transaction.sponsor.as_ref().map(|sponsor| &sponsor.address);
+        let addresses = [
+            Some(&transaction.sender),
+            sponsor,
+            Some(&transaction.receiver),
+        ];
+        let balances: Vec<(&'a Address, Option<Amount>)> = addresses
+            .into_iter()
+            .flatten()
+            .map(|address| (address, self.balances.get(address).copied()))
+            .collect();
+        let assets: Vec<((&'a Address, &'a str), Option<u64>)> = transaction
+            .assets
+            .iter()
+            .flat_map(|transfer| {
+                [&transaction.sender, &transaction.receiver]
+                    .map(|address| (address, transfer.asset.as_str()))
+            })
+            .map(|key| (key, self.assets.get(&key).copied()))
+            .collect();
+
+        if self.try_pay(transaction) {
+            return true;
+        }
+        for (address, balance) in balances {
+            match balance {
+                Some(balance) => self.balances.insert(address, balance),
+                None => self.balances.remove(address),
+            };
+        }
+        for (key, balance) in assets {
+            match balance {
+                Some(balance) => self.assets.insert(key, balance),
+                None => self.assets.remove(&key),
+            };
+        }
+        false
+    }
+
+    // Move the coins and assets `transaction` spends. Stops at the first balance that can't
+    // cover its part, returning `false`.
+    fn try_pay(&mut self, transaction: &'a Transaction) -> bool {
+        for transfer in transaction.assets.iter() {
+            let balance = self
+                .assets
+                .entry((&transaction.sender, transfer.asset.as_str()))
+                .or_insert_with(|| {
+                    self.state
+                        .asset_balance_of(transaction.sender.as_str(), &transfer.asset)
+                });
+            match balance.checked_sub(transfer.amount) {
+                Some(rest) => *balance = rest,
+                None => return false,
+            }
+
+            let balance = self
+                .assets
+                .entry((&transaction.receiver, transfer.asset.as_str()))
+                .or_insert_with(|| {
+                    self.state
+                        .asset_balance_of(transaction.receiver.as_str(), &transfer.asset)
+                });
+            *balance = balance.saturating_add(transfer.amount);
+        }
+
+        let (debits, credits) = transfers(transaction);
+
+        if !coinbase::is_coinbase(transaction) {
+            let amount = match debits {
+                true => transaction.amount,
+                false => Amount::ZERO,
+            };
+            let spends = match &transaction.sponsor {
+                Some(sponsor) => vec![
+                    (&transaction.sender, Some(amount)),
+                    (&sponsor.address, Some(transaction.fee)),
+                ],
+                None => vec![(&transaction.sender, amount.checked_add(transaction.fee))],
+            };
+
+            for (address, spent) in spends {
+                let balance = self
+                    .balances
+                    .entry(address)
+                    .or_insert_with(|| self.state.balance_of(address.as_str()));
+                match spent.and_then(|spent| balance.checked_sub(spent)) {
+                    Some(rest) => *balance = rest,
+                    None => return false,
+                }
+            }
+        }
+
+        if credits {
+            let balance = self
+                .balances
+                .entry(&transaction.receiver)
+                .or_insert_with(|| self.state.balance_of(transaction.receiver.as_str()));
+            *balance = balance.saturating_add(transaction.amount);
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::asset::AssetTransfer;
+    use crate::models::sponsor::Sponsor;
+    use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+
+    fn keypair() -> Keypair {
+        let secret = SecretKey::from_bytes(&[1; 32]).expect("any 32 bytes are a secret key");
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    #[test]
+    fn replayed_transaction_is_rejected() {
+        let keypair = keypair();
+        let sender = Address::from_public_key(&keypair.public);
+        let receiver = Address::new("receiver").expect("address is valid");
+        let mut state = State::default();
+        state.balances.insert(sender.clone(), Amount(100));
+
+        let mut transfer = Transaction::new(sender.clone(), receiver, Amount(10));
+        transfer.sign(&keypair);
+        let transfers = [transfer];
+        assert!(state.are_nonces_valid(&transfers));
+        assert!(state.can_pay_for(&transfers));
+
+        state.apply_block(&Block::new(1, Hash256::ZERO, transfers.to_vec()));
+        assert_eq!(state.next_nonce(sender.as_str()), 1);
+        // The sender could pay again, but the transfer was spent already.
+        assert!(state.can_pay_for(&transfers));
+        assert!(!state.are_nonces_valid(&transfers));
+
+        let mut next = transfers[0].clone();
+        next.nonce = 1;
+        next.sign(&keypair);
+        assert!(state.are_nonces_valid(std::slice::from_ref(&next)));
+        assert!(!state.are_nonces_valid(&[next.clone(), next]));
+    }
+
+    fn token_transfer(keypair: &Keypair, amount: u64) -> Transaction {
+        let sender = Address::from_public_key(&keypair.public);
+        let receiver = Address::new("receiver").expect("address is valid");
+        let mut transaction = Transaction::new(sender, receiver, Amount(5));
+        transaction.assets = vec![AssetTransfer {
+            asset: "token".to_string(),
+            amount,
+        }];
+        transaction.sign(keypair);
+        transaction
+    }
+
+    #[test]
+    fn asset_transfer_moves_tokens() {
+        let keypair = keypair();
+        let sender = Address::from_public_key(&keypair.public);
+        let mut state = State::default();
+        state.balances.insert(sender.clone(), Amount(100));
+        state
+            .assets
+            .insert(sender.clone(), HashMap::from([("token".to_string(), 40)]));
+
+        let transfers = [token_transfer(&keypair, 40)];
+        assert!(state.can_pay_for(&transfers));
+
+        state.apply_block(&Block::new(1, Hash256::ZERO, transfers.to_vec()));
+        assert_eq!(state.asset_balance_of(sender.as_str(), "token"), 0);
+        assert_eq!(state.asset_balance_of("receiver", "token"), 40);
+        assert_eq!(state.balance_of(sender.as_str()), Amount(95));
+        assert_eq!(state.balance_of("receiver"), Amount(5));
+    }
+
+    #[test]
+    fn asset_overspend_is_rejected() {
+        let keypair = keypair();
+        let sender = Address::from_public_key(&keypair.public);
+        let mut state = State::default();
+        state.balances.insert(sender.clone(), Amount(100));
+        state
+            .assets
+            .insert(sender.clone(), HashMap::from([("token".to_string(), 40)]));
+
+        // Enough of the native coin doesn't make up for missing tokens.
+        assert!(!state.can_pay_for(&[token_transfer(&keypair, 41)]));
+        // Neither does spending the same tokens twice within a block.
+        let mut second = token_transfer(&keypair, 1);
+        second.nonce = 1;
+        assert!(!state.can_pay_for(&[token_transfer(&keypair, 40), second]));
+        // Nor holding a different asset.
+        let mut other = token_transfer(&keypair, 1);
+        other.assets[0].asset = "other".to_string();
+        assert!(!state.can_pay_for(&[other]));
+    }
+
+    // Transfer of 10 from the holder of `keypair` to "receiver" with a fee of 2 paid by
+    // "sponsor", which is left unsigned as balances don't depend on it.
+    fn sponsored_transfer(keypair: &Keypair) -> Transaction {
+        let sender = Address::from_public_key(&keypair.public);
+        let receiver = Address::new("receiver").expect("address is valid");
+        let mut transaction = Transaction::new(sender, receiver, Amount(10));
+        transaction.fee = Amount(2);
+        transaction.sponsor = Some(Sponsor::new(
+            Address::new("sponsor").expect("address is valid"),
+        ));
+        transaction.sign(keypair);
+        transaction
+    }
+
+    #[test]
+    fn sponsor_pays_the_fee() {
+        let keypair = keypair();
+        let sender = Address::from_public_key(&keypair.public);
+        let mut state = State::default();
+        state.balances.insert(sender.clone(), Amount(10));
+        state.balances.insert(
+            Address::new("sponsor").expect("address is valid"),
+            Amount(2),
+        );
+
+        // The sender only has to cover the amount.
+        let transfers = [sponsored_transfer(&keypair)];
+        assert!(state.can_pay_for(&transfers));
+
+        state.apply_block(&Block::new(1, Hash256::ZERO, transfers.to_vec()));
+        assert_eq!(state.balance_of(sender.as_str()), Amount::ZERO);
+        assert_eq!(state.balance_of("sponsor"), Amount::ZERO);
+        assert_eq!(state.balance_of("receiver"), Amount(10));
+    }
+
+    #[test]
+    fn sponsored_transaction_is_rejected_if_either_party_is_short() {
+        let keypair = keypair();
+        let sender = Address::from_public_key(&keypair.public);
+        let sponsor = Address::new("sponsor").expect("address is valid");
+        let transfers = [sponsored_transfer(&keypair)];
+
+        // A sponsor short of the fee.
+        let mut state = State::default();
+        state.balances.insert(sender.clone(), Amount(100));
+        state.balances.insert(sponsor.clone(), Amount(1));
+        assert!(!state.can_pay_for(&transfers));
+
+        // A sponsor doesn't pay the amount.
+        let mut state = State::default();
+        state.balances.insert(sender, Amount(9));
+        state.balances.insert(sponsor, Amount(100));
+        assert!(!state.can_pay_for(&transfers));
+    }
+
+    #[test]
+    fn pending_transaction_that_cant_be_paid_for_changes_nothing() {
+        let keypair = keypair();
+        let sender = Address::from_public_key(&keypair.public);
+        let mut state = State::default();
+        state.balances.insert(sender.clone(), Amount(10));
+        state.balances.insert(
+            Address::new("sponsor").expect("address is valid"),
+            Amount(1),
+        );
+        let transfer = sponsored_transfer(&keypair);
+
+        // The sender covers the amount but the sponsor is short of the fee.
+        let mut pending = state.pending();
+        assert!(!pending.apply(&transfer));
+        assert_eq!(pending.next_nonce(&sender), 0);
+
+        // Nothing was taken from the sender, it can still pay on its own.
+        let mut unsponsored = Transaction::new(
+            sender.clone(),
+            Address::new("receiver").expect("address is valid"),
+            Amount(10),
+        );
+        unsponsored.sign(&keypair);
+        assert!(pending.apply(&unsponsored));
+        assert_eq!(pending.next_nonce(&sender), 1);
+    }
+}
@@ -0,0 +1,221 @@
+use super::block::Block;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+// `ChainStore` Persistent copy of the chain, kept in sync by the `Blockchain` methods changing
+// it so the chain survives restarts.
+pub trait ChainStore: Debug + Send + Sync {
+    // Blocks of the stored chain in order, empty if nothing has been stored yet.
+    fn load(&self) -> Result<Vec<Arc<Block>>, String>;
+
+    // Store `block` on top of the stored chain, at position `height`.
+    fn append(&self, height: u64, block: &Block) -> Result<(), String>;
+
+    // Store `chain` in place of the stored chain.
+    fn replace(&self, chain: &[Arc<Block>]) -> Result<(), String>;
+
+    // Drop the stored blocks from position `height` on.
+    fn truncate(&self, height: u64) -> Result<(), String>;
+
+    // Check that the store is still usable, without loading the chain.
+    fn check(&self) -> Result<(), String>;
+
+    // Keep the blocks of a fork from position `height` on aside from the stored chain, until
+    // the fork is complete.
+    fn stage(&self, height: u64, blocks: &[Arc<Block>]) -> Result<(), String>;
+
+    // Blocks of the fork staged so far, in order.
+    fn staged(&self) -> Result<Vec<Arc<Block>>, String>;
+
+    // Drop the staged blocks.
+    fn clear_staged(&self) -> Result<(), String>;
+}
+
+// `SledStore` Chain stored in a sled database, every block as JSON keyed by its big endian
+// position so iterating the keys yields the chain in order. Staged blocks are kept the same way
+// in a tree of their own.
+#[derive(Debug, Clone)]
+pub struct SledStore {
+    blocks: sled::Tree,
+    staged: sled::Tree,
+}
+
+impl SledStore {
+    pub fn open(path: &str) -> Result<Self, String> {
+        SledStore::open_with(sled::Config::new().path(path), path)
+    }
+
+    fn open_with(config: sled::Config, path: &str) -> Result<Self, String> {
+        let db = config
+            .open()
+            .map_err(|err| format!("can't open {}: {}", path, err))?;
+        let blocks = db
+            .open_tree("blocks")
+            .map_err(|err| format!("can't open blocks in {}: {}", path, err))?;
+        let staged = db
+            .open_tree("staged")
+            .map_err(|err| format!("can't open staged blocks in {}: {}", path, err))?;
+
+        Ok(SledStore { blocks, staged })
+    }
+}
+
+impl ChainStore for SledStore {
+    fn load(&self) -> Result<Vec<Arc<Block>>, String> {
+        load_tree(&self.blocks)
+    }
+
+    fn append(&self, height: u64, block: &Block) -> Result<(), String> {
+        let value = serde_json::to_vec(block).expect("can jsonify block");
+
+        self.blocks
+            .insert(height.to_be_bytes(), value)
+            .map_err(|err| format!("can't store block {}: {}", height, err))?;
+        flush(&self.blocks)
+    }
+
+    fn replace(&self, chain: &[Arc<Block>]) -> Result<(), String> {
+        let mut batch = sled::Batch::default();
+        for (height, block) in chain.iter().enumerate() {
+            let value = serde_json::to_vec(block.as_ref()).expect("can jsonify block");
+            batch.insert(&(height as u64).to_be_bytes(), value);
+        }
+
+        // Blocks above the new tip belong to the replaced chain.
+        let height = chain.len() as u64;
+        for key in self.blocks.range(height.to_be_bytes()..).keys() {
+            let key = key.map_err(|err| format!("can't read block: {}", err))?;
+            batch.remove(key);
+        }
+
+        self.blocks
+            .apply_batch(batch)
+            .map_err(|err| format!("can't store chain: {}", err))?;
+        flush(&self.blocks)
+    }
+
+    fn truncate(&self, height: u64) -> Result<(), String> {
+        let mut batch = sled::Batch::default();
+        for key in self.blocks.range(height.to_be_bytes()..).keys() {
+            let key = key.map_err(|err| format!("can't read block: {}", err))?;
+            batch.remove(key);
+        }
+
+        self.blocks
+            .apply_batch(batch)
+            .map_err(|err| format!("can't truncate chain: {}", err))?;
+        flush(&self.blocks)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        self.blocks
+            .last()
+            .map(|_| ())
+            .map_err(|err| format!("can't read chain store: {}", err))
+    }
+
+    fn stage(&self, height: u64, blocks: &[Arc<Block>]) -> Result<(), String> {
+        let mut batch = sled::Batch::default();
+        for (height, block) in (height..).zip(blocks.iter()) {
+            let value = serde_json::to_vec(block.as_ref()).expect("can jsonify block");
+            batch.insert(&height.to_be_bytes(), value);
+        }
+
+        self.staged
+            .apply_batch(batch)
+            .map_err(|err| format!("can't stage blocks: {}", err))?;
+        flush(&self.staged)
+    }
+
+    fn staged(&self) -> Result<Vec<Arc<Block>>, String> {
+        load_tree(&self.staged)
+    }
+
+    fn clear_staged(&self) -> Result<(), String> {
+        self.staged
+            .clear()
+            .map_err(|err| format!("can't clear staged blocks: {}", err))?;
+        flush(&self.staged)
+    }
+}
+
+fn load_tree(tree: &sled::Tree) -> Result<Vec<Arc<Block>>, String> {
+    tree.iter()
+        .values()
+        .map(|value| {
+            let value = value.map_err(|err| format!("can't read block: {}", err))?;
+            serde_json::from_slice(&value)
+                .map(Arc::new)
+                .map_err(|err| format!("can't parse stored block: {}", err))
+        })
+        .collect()
+}
+
+fn flush(tree: &sled::Tree) -> Result<(), String> {
+    tree.flush()
+        .map(|_| ())
+        .map_err(|err| format!("can't flush chain store: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::hash::Hash256;
+
+    // Chain of `length` blocks, each pointing at the one before it.
+    fn chain(length: u64) -> Vec<Arc<Block>> {
+        let mut previous_hash = Hash256::ZERO;
+        (0..length)
+            .map(|index| {
+                let mut block = Block::new(index, previous_hash, Vec::new());
+                block.header.hash = Hash256::digest(index.to_be_bytes());
+                previous_hash = block.header.hash;
+                Arc::new(block)
+            })
+            .collect()
+    }
+
+    // Open the store at `path` without the background flusher, which would keep it locked for a
+    // while after the store is dropped.
+    fn open(path: &str) -> SledStore {
+        let config = sled::Config::new().path(path).flush_every_ms(None);
+        SledStore::open_with(config, path).expect("can open store")
+    }
+
+    fn hashes(blocks: &[Arc<Block>]) -> Vec<Hash256> {
+        blocks.iter().map(|block| block.header.hash).collect()
+    }
+
+    #[test]
+    fn stored_chain_is_loaded_again_after_reopening_and_truncating() {
+        let path = std::env::temp_dir().join(format!("chain-store-{}", std::process::id()));
+        let path = path.to_str().expect("path is utf-8");
+        let blocks = chain(4);
+
+        let store = open(path);
+        assert!(store.load().expect("can load").is_empty());
+        store.replace(&blocks[..2]).expect("can replace");
+        store.append(2, &blocks[2]).expect("can append");
+        store.append(3, &blocks[3]).expect("can append");
+        drop(store);
+
+        let store = open(path);
+        assert_eq!(hashes(&store.load().expect("can load")), hashes(&blocks));
+
+        store.truncate(2).expect("can truncate");
+        assert_eq!(
+            hashes(&store.load().expect("can load")),
+            hashes(&blocks[..2])
+        );
+        drop(store);
+
+        let store = open(path);
+        assert_eq!(
+            hashes(&store.load().expect("can load")),
+            hashes(&blocks[..2])
+        );
+        drop(store);
+
+        std::fs::remove_dir_all(path).expect("can remove store");
+    }
+}
@@ -0,0 +1,115 @@
+// Time-based validity of transactions. A transaction may name the time it can be included from,
+// `lock_time`, and the time it expires at, `expiry`, both in ms since the epoch like block
+// timestamps. They are checked against the median time past of the chain a block builds on, the
+// median timestamp of its latest `MEDIAN_TIME_SPAN` blocks, rather than the timestamp of the
+// block itself: a miner sets the timestamp of its own block within wide limits, but moves the
+// median of blocks others mined by one block at most.
+
+use super::block::Block;
+use super::transaction::Transaction;
+use std::sync::Arc;
+
+// Blocks the median time past is taken over.
+pub const MEDIAN_TIME_SPAN: usize = 11;
+
+// Median timestamp of the latest `MEDIAN_TIME_SPAN` blocks of `chain`. Every node creates its own
+// genesis block, so it is left out, and a chain of the genesis block alone has a median time past
+// of 0.
+pub fn median_time_past(chain: &[Arc<Block>]) -> u64 {
+    let mut timestamps: Vec<u64> = chain
+        .iter()
+        .rev()
+        .take_while(|block| block.header.index > 0)
+        .take(MEDIAN_TIME_SPAN)
+        .map(|block| block.header.timestamp)
+        .collect();
+    if timestamps.is_empty() {
+        return 0;
+    }
+
+    timestamps.sort_unstable();
+    timestamps[timestamps.len() / 2]
+}
+
+// Whether `transaction` may be included in a block on top of a chain with the median time past
+// `time`: its lock time has passed and it hasn't expired yet.
+pub fn is_valid_at(transaction: &Transaction, time: u64) -> bool {
+    transaction
+        .lock_time
+        .is_none_or(|lock_time| time >= lock_time)
+        && transaction.expiry.is_none_or(|expiry| time < expiry)
+}
+
+// Check the lock times and expiries of the transactions in `block` on top of `chain`.
+pub fn are_time_locks_valid(block: &Block, chain: &[Arc<Block>]) -> bool {
+    let time = median_time_past(chain);
+
+    block
+        .body
+        .transactions
+        .iter()
+        .all(|transaction| is_valid_at(transaction, time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::address::Address;
+    use crate::models::amount::Amount;
+    use crate::models::hash::Hash256;
+
+    // Chain of a genesis block and blocks with `timestamps`.
+    fn chain(timestamps: &[u64]) -> Vec<Arc<Block>> {
+        let mut chain = vec![Arc::new(Block::new(0, Hash256::ZERO, Vec::new()))];
+        for (index, timestamp) in timestamps.iter().enumerate() {
+            let mut block = Block::new(index as u64 + 1, Hash256::ZERO, Vec::new());
+            block.header.timestamp = *timestamp;
+            chain.push(Arc::new(block));
+        }
+        chain
+    }
+
+    fn transaction(lock_time: Option<u64>, expiry: Option<u64>) -> Transaction {
+        let mut transaction = Transaction::new(
+            Address::new("alice").expect("address is valid"),
+            Address::new("bob").expect("address is valid"),
+            Amount(1),
+        );
+        transaction.lock_time = lock_time;
+        transaction.expiry = expiry;
+        transaction
+    }
+
+    #[test]
+    fn median_time_past_ignores_outliers_and_the_genesis_block() {
+        assert_eq!(median_time_past(&chain(&[])), 0);
+        assert_eq!(median_time_past(&chain(&[30, 10, 20])), 20);
+        // A miner setting its timestamp far ahead doesn't move the median.
+        assert_eq!(median_time_past(&chain(&[30, 10, 20, u64::MAX])), 30);
+
+        // Only the latest blocks count.
+        let timestamps: Vec<u64> = (1..=20).collect();
+        assert_eq!(median_time_past(&chain(&timestamps)), 15);
+    }
+
+    #[test]
+    fn transaction_within_its_time_window_is_accepted() {
+        assert!(is_valid_at(&transaction(None, None), 0));
+        assert!(is_valid_at(&transaction(Some(100), Some(200)), 100));
+        assert!(is_valid_at(&transaction(Some(100), Some(200)), 199));
+
+        let block = Block::new(4, Hash256::ZERO, vec![transaction(Some(20), Some(40))]);
+        assert!(are_time_locks_valid(&block, &chain(&[10, 20, 30])));
+    }
+
+    #[test]
+    fn locked_or_expired_transaction_is_rejected() {
+        assert!(!is_valid_at(&transaction(Some(100), None), 99));
+        assert!(!is_valid_at(&transaction(None, Some(200)), 200));
+
+        // The timestamp of the block itself doesn't unlock anything.
+        let mut block = Block::new(4, Hash256::ZERO, vec![transaction(Some(25), None)]);
+        block.header.timestamp = 1_000;
+        assert!(!are_time_locks_valid(&block, &chain(&[10, 20, 30])));
+    }
+}
@@ -1,14 +1,137 @@
+use super::address::Address;
+use super::amount::Amount;
+use super::asset::AssetTransfer;
+use super::condition::Condition;
+use super::hash::Hash256;
+use super::sponsor::Sponsor;
+use ed25519_dalek::{Keypair, PublicKey, Signature, Signer};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transaction {
-    pub sender: String,
-    pub receiver: String,
-    pub amount: u64,
+    pub sender: Address,
+    pub receiver: Address,
+    pub amount: Amount,
+    // Number of transactions the sender sent before this one, so a transaction is only valid
+    // once and can't be replayed. Left out of the encoding when zero.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub nonce: u64,
+    // Paid by the sender, or its sponsor if it has one, to the miner of the block including the
+    // transaction. Left out of the encoding when zero, so transactions without fees keep their
+    // ids.
+    #[serde(default, skip_serializing_if = "Amount::is_zero")]
+    pub fee: Amount,
+    // Token amounts moved atomically together with the native amount.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assets: Vec<AssetTransfer>,
+    // Optional spending condition (e.g. an HTLC lock or settlement).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<Condition>,
+    // Time in ms the transaction can be included from, see `timelock`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock_time: Option<u64>,
+    // Time in ms from which on the transaction can't be included anymore, see `timelock`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<u64>,
+    // Third party paying the fee in place of the sender, see `sponsor`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sponsor: Option<Sponsor>,
+    // Raw ed25519 key controlling the sender address, coinbase transactions have none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub public_key: Vec<u8>,
+    // Signature of `signing_data` by `public_key`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signature: Vec<u8>,
 }
 
 impl Transaction {
-    pub fn new(sender: String, receiver: String, amount: u64) -> Self {
-        Transaction { sender, receiver, amount }
+    pub fn new(sender: Address, receiver: Address, amount: Amount) -> Self {
+        Transaction {
+            sender,
+            receiver,
+            amount,
+            nonce: 0,
+            fee: Amount::ZERO,
+            assets: Vec::new(),
+            condition: None,
+            lock_time: None,
+            expiry: None,
+            sponsor: None,
+            public_key: Vec::new(),
+            signature: Vec::new(),
+        }
     }
-}
\ No newline at end of file
+
+    // Data covered by the signature, the transaction without it. The sponsor signs the same data,
+    // so its key and signature are left out as well, but not its address.
+    pub fn signing_data(&self) -> Vec<u8> {
+        let unsigned = Transaction {
+            signature: Vec::new(),
+            sponsor: self
+                .sponsor
+                .as_ref()
+                .map(|sponsor| Sponsor::new(sponsor.address.clone())),
+            ..self.clone()
+        };
+
+        serde_json::to_vec(&unsigned).expect("can jsonify transaction")
+    }
+
+    // Sign with `keypair`, which has to control the sender address for the signature to be
+    // valid.
+    pub fn sign(&mut self, keypair: &Keypair) {
+        self.public_key = keypair.public.to_bytes().to_vec();
+        self.signature = keypair.sign(&self.signing_data()).to_bytes().to_vec();
+    }
+
+    // Sign as the sponsor with `keypair`, which has to control the sponsor address for the
+    // signature to be valid.
+    pub fn sign_as_sponsor(&mut self, keypair: &Keypair) {
+        let signing_data = self.signing_data();
+        if let Some(sponsor) = &mut self.sponsor {
+            sponsor.public_key = keypair.public.to_bytes().to_vec();
+            sponsor.signature = keypair.sign(&signing_data).to_bytes().to_vec();
+        }
+    }
+
+    // Address paying the fee, the sponsor if there is one.
+    pub fn fee_payer(&self) -> &Address {
+        match &self.sponsor {
+            Some(sponsor) => &sponsor.address,
+            None => &self.sender,
+        }
+    }
+
+    // Check that the transaction is signed by the key controlling its sender address, and by the
+    // one controlling the sponsor address if it has a sponsor.
+    pub fn is_signature_valid(&self) -> bool {
+        if let Some(sponsor) = &self.sponsor
+            && !sponsor.is_signature_valid(&self.signing_data())
+        {
+            return false;
+        }
+
+        let Ok(public_key) = PublicKey::from_bytes(&self.public_key) else {
+            return false;
+        };
+        let Ok(signature) = Signature::try_from(self.signature.as_slice()) else {
+            return false;
+        };
+
+        Address::from_public_key(&public_key) == self.sender
+            && public_key
+                .verify_strict(&self.signing_data(), &signature)
+                .is_ok()
+    }
+
+    // Calculate transaction id.
+    pub fn id(&self) -> Hash256 {
+        let serialized_transaction = serde_json::to_string(self).unwrap();
+
+        Hash256::digest(serialized_transaction)
+    }
+}
+
+fn is_zero(nonce: &u64) -> bool {
+    *nonce == 0
+}
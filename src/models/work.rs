@@ -0,0 +1,66 @@
+use super::block::Block;
+use super::hash::Hash256;
+use super::params::MAX_DIFFICULTY;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::Add;
+use std::sync::Arc;
+
+// `Work` Expected number of hashes needed to find a block, which is how blocks and chains
+// are compared. A difficulty of `d` leading zero hex digits takes 16^d hashes. Sums saturate
+// rather than wrap around.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Work(pub u128);
+
+impl Work {
+    pub const ZERO: Work = Work(0);
+
+    // Work required of blocks mined at `difficulty`, saturating above `MAX_DIFFICULTY`.
+    pub fn from_difficulty(difficulty: usize) -> Self {
+        if difficulty > MAX_DIFFICULTY {
+            return Work(u128::MAX);
+        }
+
+        Work(1 << (4 * difficulty))
+    }
+
+    // Work a hash proves, counted by its leading zero digits.
+    pub fn proven_by(hash: &Hash256) -> Self {
+        Work::from_difficulty(hash.leading_zero_digits())
+    }
+}
+
+// Total work of the blocks on top of the genesis block of `chain`, by the difficulty every block
+// declares. Blocks are only valid at the difficulty in force at their height, so this is the
+// work of a valid chain.
+pub fn cumulative_work(chain: &[Arc<Block>]) -> Work {
+    chain
+        .iter()
+        .skip(1)
+        .map(|block| Work::from_difficulty(block.header.difficulty))
+        .sum()
+}
+
+impl Add for Work {
+    type Output = Work;
+
+    fn add(self, other: Work) -> Work {
+        Work(self.0.saturating_add(other.0))
+    }
+}
+
+impl Sum for Work {
+    fn sum<I: Iterator<Item = Work>>(works: I) -> Work {
+        works.fold(Work::ZERO, Add::add)
+    }
+}
+
+impl fmt::Display for Work {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
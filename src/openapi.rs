@@ -0,0 +1,231 @@
+// OpenAPI description of the JSON-RPC server, served at `GET /openapi.json` so client SDKs can
+// be generated from it. Methods come from `rpc::METHODS` and the schemas of the values they take
+// and return are inferred from how serde encodes example values of their Rust types, so both
+// follow the code without being kept up by hand. Fields serde leaves out when empty are only
+// described if the examples set them, fees and signatures are but conditions and assets aren't.
+
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+
+use crate::{
+    auth::{ApiKey, Scope},
+    health::{Check, Readiness},
+    metrics::MetricsSample,
+    models::{
+        accumulator::Accumulator, address::Address, amount::Amount, block::Block, hash::Hash256,
+        transaction::Transaction,
+    },
+    p2p::PendingReorg,
+    rpc::{METHODS, RpcMethod},
+};
+
+// The OpenAPI document of the server.
+pub fn spec() -> Value {
+    let calls: Vec<Value> = METHODS.iter().map(call_schema).collect();
+    let results: Vec<Value> = METHODS
+        .iter()
+        .map(|method| schema_ref(method.result))
+        .collect();
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "blockchain node JSON-RPC",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": methods_description(),
+        },
+        "paths": {
+            "/": {
+                "post": {
+                    "summary": "Run a JSON-RPC 2.0 call",
+                    "security": [{ "bearer": [] }, {}],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": { "oneOf": calls } } },
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "Result or error of the call",
+                            "content": {
+                                "application/json": {
+                                    "schema": {
+                                        "type": "object",
+                                        "properties": {
+                                            "jsonrpc": { "type": "string", "enum": ["2.0"] },
+                                            "id": {},
+                                            "result": { "oneOf": results },
+                                            "error": { "$ref": "#/components/schemas/Error" },
+                                        },
+                                    },
+                                },
+                            },
+                        },
+                        "429": { "description": "Over the request quota, see Retry-After" },
+                    },
+                },
+            },
+            "/health": {
+                "get": {
+                    "summary": "Check that the node is running",
+                    "responses": {
+                        "200": { "description": "The node is running" },
+                        "429": { "description": "Over the request quota, see Retry-After" },
+                    },
+                },
+            },
+            "/ready": {
+                "get": {
+                    "summary": "Check that the node is fit to serve",
+                    "responses": {
+                        "200": {
+                            "description": "Every readiness check passed",
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/Readiness" },
+                                },
+                            },
+                        },
+                        "503": { "description": "A readiness check failed, or the node is stopping" },
+                        "429": { "description": "Over the request quota, see Retry-After" },
+                    },
+                },
+            },
+        },
+        "components": {
+            "securitySchemes": { "bearer": { "type": "http", "scheme": "bearer" } },
+            "schemas": schemas(),
+        },
+    })
+}
+
+// Schema of a call of `method`, its parameters given by position.
+fn call_schema(method: &RpcMethod) -> Value {
+    let params: Vec<Value> = method
+        .params
+        .iter()
+        .map(|(name, kind)| {
+            let mut schema = schema_ref(kind);
+            schema["title"] = Value::from(*name);
+            schema
+        })
+        .collect();
+
+    json!({
+        "title": method.name,
+        "description": method.summary,
+        "type": "object",
+        "required": ["jsonrpc", "method", "id"],
+        "properties": {
+            "jsonrpc": { "type": "string", "enum": ["2.0"] },
+            "method": { "type": "string", "enum": [method.name] },
+            "params": {
+                "type": "array",
+                "items": { "anyOf": params },
+                "maxItems": method.params.len(),
+            },
+            "id": {},
+        },
+    })
+}
+
+fn methods_description() -> String {
+    let mut description = String::from("Methods along with the API key scope they need:\n");
+    for method in METHODS {
+        description.push_str(&format!(
+            "\n- `{}` ({}): {}",
+            method.name, method.scope, method.summary
+        ));
+    }
+    description
+}
+
+// Schema of `kind`, a JSON type or one of `schemas`.
+fn schema_ref(kind: &str) -> Value {
+    match kind {
+        "integer" | "string" | "boolean" => json!({ "type": kind }),
+        _ => json!({ "$ref": format!("#/components/schemas/{}", kind) }),
+    }
+}
+
+fn schemas() -> Value {
+    let address = Address::new("12D3KooWAbd4Fbpor5RqFjVHquPwXMHs9iXEhrFhnsafYg4zWEYX")
+        .expect("example address is valid");
+    let mut transaction = Transaction::new(address.clone(), address, Amount(1));
+    transaction.fee = Amount(1);
+    transaction.public_key = vec![0; 32];
+    transaction.signature = vec![0; 64];
+
+    let mut block = Block::new(1, Hash256::ZERO, vec![transaction.clone()]);
+    let mut accumulator = Accumulator::default();
+    accumulator.add(Hash256::ZERO);
+    block.header.accumulator = accumulator.clone();
+    block.header.history = accumulator;
+
+    let api_key = ApiKey {
+        key: Hash256::ZERO.to_string(),
+        name: "dashboard".to_string(),
+        scope: Scope::Read,
+    };
+
+    json!({
+        "Block": infer(&block),
+        "Transaction": infer(&transaction),
+        "TransactionList": infer(&vec![transaction.clone()]),
+        "Amount": infer(&Amount(1)),
+        "Hash": infer(&Hash256::ZERO),
+        "HashList": infer(&vec![Hash256::ZERO]),
+        "ApiKey": infer(&api_key),
+        "MetricsHistory": infer(&vec![MetricsSample {
+            timestamp: 0,
+            height: 0,
+            mempool: 0,
+            peers: 0,
+            hashrate: 0.0,
+        }]),
+        "Readiness": infer(&Readiness::new(vec![Check {
+            name: "store",
+            ok: true,
+            detail: "open".to_string(),
+        }])),
+        "PendingReorg": infer(&PendingReorg {
+            tip: Hash256::ZERO,
+            height: 1,
+            depth: 1,
+            chain: Vec::new(),
+        }),
+        "ApiKeyList": infer(&vec![json!({ "name": api_key.name, "scope": api_key.scope })]),
+        "Error": {
+            "type": "object",
+            "properties": {
+                "code": { "type": "integer" },
+                "message": { "type": "string" },
+            },
+        },
+    })
+}
+
+// Schema of the values encoded like `example`.
+fn infer(example: &impl Serialize) -> Value {
+    schema_of(&serde_json::to_value(example).expect("can jsonify example"))
+}
+
+fn schema_of(value: &Value) -> Value {
+    match value {
+        Value::Null => json!({ "nullable": true }),
+        Value::Bool(_) => json!({ "type": "boolean" }),
+        Value::Number(number) if number.is_f64() => json!({ "type": "number" }),
+        Value::Number(_) => json!({ "type": "integer" }),
+        Value::String(_) => json!({ "type": "string" }),
+        Value::Array(items) => json!({
+            "type": "array",
+            "items": items.first().map(schema_of).unwrap_or_else(|| json!({})),
+        }),
+        Value::Object(fields) => {
+            let properties: Map<String, Value> = fields
+                .iter()
+                .map(|(name, value)| (name.clone(), schema_of(value)))
+                .collect();
+            json!({ "type": "object", "properties": properties })
+        }
+    }
+}
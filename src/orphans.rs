@@ -0,0 +1,162 @@
+// Blocks whose parent isn't known yet, e.g. as gossip delivered them before it. Instead of being
+// dropped they wait in the pool while the peer that sent them is asked for the parent by hash,
+// and are connected once it is. A parent that turns out to be an orphan as well has its own
+// parent asked for in turn.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{block::Block, hash::Hash256};
+
+// Orphans kept at most, the oldest are dropped first.
+pub const MAX_ORPHANS: usize = 256;
+
+// Ask for the block with `hash`, e.g. the parent of an orphan.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ParentRequest {
+    pub hash: Hash256,
+}
+
+// The block asked for, if the peer has it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ParentResponse {
+    pub block: Option<Arc<Block>>,
+}
+
+// `OrphanPool` Blocks waiting for their parent, by the hash of the parent.
+pub struct OrphanPool {
+    capacity: usize,
+    // Oldest first.
+    order: VecDeque<Hash256>,
+    blocks: HashMap<Hash256, Arc<Block>>,
+    children: HashMap<Hash256, Vec<Hash256>>,
+}
+
+impl Default for OrphanPool {
+    fn default() -> Self {
+        OrphanPool::new(MAX_ORPHANS)
+    }
+}
+
+impl OrphanPool {
+    pub fn new(capacity: usize) -> Self {
+        OrphanPool {
+            capacity: capacity.max(1),
+            order: VecDeque::new(),
+            blocks: HashMap::new(),
+            children: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    // Keep `block` until its parent comes. Returns `false` if it was kept already.
+    pub fn insert(&mut self, block: Arc<Block>) -> bool {
+        if self.blocks.contains_key(&block.header.hash) {
+            return false;
+        }
+
+        if self.order.len() == self.capacity
+            && let Some(evicted) = self.order.pop_front()
+        {
+            self.remove(&evicted);
+        }
+        self.order.push_back(block.header.hash);
+        self.children
+            .entry(block.header.previous_hash)
+            .or_default()
+            .push(block.header.hash);
+        self.blocks.insert(block.header.hash, block);
+        true
+    }
+
+    // Whether an orphan waits for the block with `hash`.
+    pub fn is_parent(&self, hash: &Hash256) -> bool {
+        self.children.contains_key(hash)
+    }
+
+    // Take the orphans waiting for the block with `hash` out of the pool.
+    pub fn take_children(&mut self, hash: &Hash256) -> Vec<Arc<Block>> {
+        let children = self.children.remove(hash).unwrap_or_default();
+        self.order.retain(|orphan| !children.contains(orphan));
+        children
+            .iter()
+            .filter_map(|child| self.blocks.remove(child))
+            .collect()
+    }
+
+    fn remove(&mut self, hash: &Hash256) {
+        let Some(block) = self.blocks.remove(hash) else {
+            return;
+        };
+        if let Some(siblings) = self.children.get_mut(&block.header.previous_hash) {
+            siblings.retain(|sibling| sibling != hash);
+            if siblings.is_empty() {
+                self.children.remove(&block.header.previous_hash);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Block at `index` on top of the block with hash `previous_hash`.
+    fn block(index: u64, previous_hash: Hash256) -> Arc<Block> {
+        let mut block = Block::new(index, previous_hash, Vec::new());
+        block.header.hash = Hash256::digest(format!("{} {}", index, previous_hash));
+        Arc::new(block)
+    }
+
+    #[test]
+    fn orphans_are_connected_once_their_parent_arrives() {
+        let parent = block(1, Hash256::ZERO);
+        let child = block(2, parent.header.hash);
+        let grandchild = block(3, child.header.hash);
+        let mut pool = OrphanPool::default();
+
+        // Gossip delivered the blocks in reverse.
+        assert!(pool.insert(grandchild.clone()));
+        assert!(pool.insert(child.clone()));
+        assert!(!pool.insert(child.clone()));
+        assert!(pool.is_parent(&parent.header.hash));
+        assert!(!pool.is_parent(&grandchild.header.hash));
+
+        // Once the parent is connected its child follows, and the grandchild after it.
+        let mut connected = Vec::new();
+        let mut tip = parent.header.hash;
+        while let Some(next) = pool.take_children(&tip).first().cloned() {
+            tip = next.header.hash;
+            connected.push(next.header.index);
+        }
+        assert_eq!(connected, vec![2, 3]);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn oldest_orphan_is_dropped_when_the_pool_is_full() {
+        let first = block(1, Hash256::digest("first parent"));
+        let second = block(1, Hash256::digest("second parent"));
+        let third = block(1, Hash256::digest("third parent"));
+        let mut pool = OrphanPool::new(2);
+
+        for orphan in [&first, &second, &third] {
+            pool.insert(orphan.clone());
+        }
+        assert_eq!(pool.len(), 2);
+        assert!(!pool.is_parent(&first.header.previous_hash));
+        assert!(pool.take_children(&first.header.previous_hash).is_empty());
+        assert_eq!(pool.take_children(&third.header.previous_hash).len(), 1);
+    }
+}
@@ -1,197 +1,3013 @@
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io, iter,
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
 
+use async_trait::async_trait;
+use chrono::Utc;
 use libp2p::{
-    NetworkBehaviour, PeerId, Swarm,
-    floodsub::{Floodsub, FloodsubEvent, Topic},
+    Multiaddr, NetworkBehaviour, PeerId, Swarm,
+    core::upgrade::{ProtocolName, read_length_prefixed, write_length_prefixed},
+    futures::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    gossipsub::{
+        Gossipsub, GossipsubConfig, GossipsubEvent, MessageAuthenticity, error::PublishError,
+    },
     identity,
+    kad::{Kademlia, KademliaConfig, KademliaEvent, QueryResult, store::MemoryStore},
     mdns::{Mdns, MdnsEvent},
-    swarm::NetworkBehaviourEventProcess,
+    request_response::{
+        ProtocolSupport, RequestId, RequestResponse, RequestResponseCodec, RequestResponseConfig,
+        RequestResponseEvent, RequestResponseMessage,
+    },
+    swarm::{
+        CloseConnection, DialPeerCondition, NetworkBehaviourAction, NetworkBehaviourEventProcess,
+        PollParameters,
+    },
 };
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
-
-use crate::{blockchain::Blockchain, models::block, models::transaction::Transaction};
+use sha2::{Digest, Sha256};
+use tokio::sync::{broadcast, mpsc};
 
+use crate::{
+    broadcast::OutboundQueue,
+    chaos::Chaos,
+    coop::{
+        ASSIGNMENT_SIZE, CoopAssignment, CoopJob, CoopResult, CoopSearched, CoopVolunteer,
+        Cooperation,
+    },
+    discovery::{self, RoutingEntry},
+    download::{
+        self, BATCH_SIZE, BlockDownload, BlocksRequest, BlocksResponse, HeadersRequest,
+        HeadersResponse, MAX_HEADERS,
+    },
+    faucet::FaucetRequest,
+    gossip::{GossipMessage, Topic},
+    health::HealthSettings,
+    metrics::MetricsHistory,
+    miner::{
+        MinedBlock, MinerSettings, MiningJob, NonceSearcher, SearchJob, SharedSearcher, Work,
+        WorkResult,
+    },
+    models::address::Address,
+    models::amount::Amount,
+    models::anchor::Anchor,
+    models::block,
+    models::blockchain::{Blockchain, ChainError},
+    models::checkpoint::{CHECKPOINT_INTERVAL, Checkpoint, Checkpoints},
+    models::coinbase::{self, Payout, PayoutSplit},
+    models::condition::Condition,
+    models::epoch::{self, EPOCH_LENGTH, EpochSummaries, EpochSummary},
+    models::hash::Hash256,
+    models::history::{self, HistoryProof, MAX_HISTORY_SAMPLES},
+    models::journal::{Journal, JournalRecord},
+    models::mempool::{MAX_BLOCK_TRANSACTIONS, Mempool, TransactionPackage},
+    models::params::MAIN_CHAIN_ID,
+    models::state::State,
+    models::transaction::Transaction,
+    models::work,
+    orphans::{OrphanPool, ParentRequest, ParentResponse},
+    peers::PeerSelector,
+    reconcile::{self, MAX_FETCH, MempoolSketch, ReconcileRequest, ReconcileResponse},
+    relay::RelayLog,
+    rpc::{self, RpcCall},
+    schedule::BlockScheduler,
+    seen::{SEEN_CACHE_SIZE, SeenCache},
+    session::SessionRecorder,
+    trace::{Outcome, PropagationTrace},
+    vanity::VanitySearch,
+    ws::{EVENT_BUFFER, NodeEvent},
+};
 
-pub static KEYS: Lazy<identity::Keypair> = Lazy::new(identity::Keypair::generate_ed25519);
+// File the node's secret key is read from, set with `--key-file <path>` before `KEYS` is first
+// used. Nodes without one get a new key on every start.
+pub static KEY_FILE: OnceCell<String> = OnceCell::new();
+pub static KEYS: Lazy<identity::Keypair> = Lazy::new(|| match KEY_FILE.get() {
+    Some(path) => load_keys(path).unwrap_or_else(|err| {
+        println!("{}, using a new key", err);
+        identity::Keypair::generate_ed25519()
+    }),
+    None => identity::Keypair::generate_ed25519(),
+});
 pub static PEER_ID: Lazy<PeerId> = Lazy::new(|| PeerId::from(KEYS.public()));
-pub static CHAIN_TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("chains"));
-pub static BLOCK_TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("blocks"));
+
+// `Topics` Gossip topics of one chain. The main chain keeps the plain names, other chains
+// prefix them with their id, so peers only hear about the chains they host.
+#[derive(Debug, Clone)]
+pub struct Topics {
+    pub block: Topic,
+    pub coop: Topic,
+    pub tip: Topic,
+    pub transaction: Topic,
+}
+
+impl Topics {
+    pub fn for_chain(chain_id: &str) -> Self {
+        let topic = |name: &str| match chain_id {
+            MAIN_CHAIN_ID => Topic::new(name),
+            _ => Topic::new(format!("{}/{}", chain_id, name)),
+        };
+
+        Topics {
+            block: topic("blocks"),
+            coop: topic("coop"),
+            tip: topic("tips"),
+            transaction: topic("transactions"),
+        }
+    }
+}
+
+// Keys of the node on the chain with `chain_id`. The main chain uses `KEYS`, every other chain
+// a key derived from them, so each chain has its own peer id and default payout address.
+pub fn chain_keys(chain_id: &str) -> identity::Keypair {
+    if chain_id == MAIN_CHAIN_ID {
+        return KEYS.clone();
+    }
+
+    let identity::Keypair::Ed25519(keypair) = &*KEYS else {
+        return identity::Keypair::generate_ed25519();
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(keypair.secret().as_ref());
+    hasher.update(chain_id.as_bytes());
+    let mut secret = hasher.finalize().to_vec();
+
+    let secret =
+        identity::ed25519::SecretKey::from_bytes(&mut secret).expect("hashes are secret keys");
+    identity::Keypair::Ed25519(secret.into())
+}
+
+// Read the secret key from the file at `path`, e.g. one found with `wallet vanity`.
+fn load_keys(path: &str) -> Result<identity::Keypair, String> {
+    let data =
+        std::fs::read_to_string(path).map_err(|err| format!("can't read {}: {}", path, err))?;
+    let mut secret = bs58::decode(data.trim())
+        .into_vec()
+        .map_err(|err| format!("can't decode key in {}: {}", path, err))?;
+    let secret = identity::ed25519::SecretKey::from_bytes(&mut secret)
+        .map_err(|err| format!("{} holds no ed25519 secret key: {}", path, err))?;
+
+    Ok(identity::Keypair::Ed25519(secret.into()))
+}
+
+// Address rewards go to unless another one is configured.
+pub fn peer_address(peer_id: &PeerId) -> Address {
+    Address::new(peer_id.to_string()).expect("peer ids are valid addresses")
+}
+
+// Ask a peer for its chain, which replaces the local one if it is better.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChainRequest {}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ChainResponse {
-    pub blocks: Vec<block::Block>,
-    pub receiver: String,
+    pub blocks: Vec<Arc<block::Block>>,
+}
+
+// Tells the publisher of a block or transaction that it reached at least one peer. Only the
+// peers it was published to send one, straight to the publisher.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BroadcastAck {
+    pub hash: Hash256,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AckResponse {}
+
+// Announces the new tip of the publisher's chain along with the work of that chain, so peers
+// only fetch the block if it beats theirs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TipAnnouncement {
+    pub header: block::BlockHeader,
+    pub work: work::Work,
+}
+
+// Time between downloads a peer can start by announcing tips. The work of a tip is the peer's
+// own claim, so a peer making it up can't keep the node downloading from it.
+const TIP_DOWNLOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+// Records the journal grows to before it is compacted to the pending transactions.
+const MAX_JOURNAL_RECORDS: usize = 10_000;
+
+// Largest sync message accepted, checkpoint responses carry every block past the checkpoint.
+const MAX_SYNC_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+// Most epoch summaries sent in one response, clients ask again for the ones after them.
+const MAX_EPOCH_SUMMARIES: usize = 1000;
+
+// Ask for the latest checkpoint at or below `height`, or the latest one if unset.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CheckpointRequest {
+    pub height: Option<u64>,
+}
+
+// The checkpoint along with the state it commits to and the blocks on top of it, which is
+// everything needed to follow the chain without its earlier history.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CheckpointResponse {
+    pub checkpoint: Option<Checkpoint>,
+    pub state: Option<State>,
+    pub blocks: Vec<Arc<block::Block>>,
+}
+
+// Ask for the summaries of epoch `from` and the ones after it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EpochRequest {
+    pub from: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EpochResponse {
+    pub summaries: Vec<EpochSummary>,
+}
+
+// Ask for a proof of the work of the chain sampling `samples` blocks.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HistoryRequest {
+    pub samples: usize,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct LocalChainRequest {
-    pub from_peer_id: String,
+pub struct HistoryResponse {
+    pub proof: Option<HistoryProof>,
+}
+
+// Ask a peer that connected to the node for the addresses it listens on, so the DHT can route
+// to it.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AddressRequest {}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AddressResponse {
+    pub addresses: Vec<String>,
+}
+
+// `SyncProtocol` A request-response protocol of one chain, named after it like its topics.
+#[derive(Debug, Clone)]
+pub struct SyncProtocol(String);
+
+impl SyncProtocol {
+    pub fn for_chain(chain_id: &str, name: &str) -> Self {
+        SyncProtocol::with_version(chain_id, name, "1")
+    }
+
+    pub fn with_version(chain_id: &str, name: &str, version: &str) -> Self {
+        match chain_id {
+            MAIN_CHAIN_ID => SyncProtocol(format!("/blockchain/{}/{}", name, version)),
+            _ => SyncProtocol(format!("/blockchain/{}/{}/{}", chain_id, name, version)),
+        }
+    }
+}
+
+impl ProtocolName for SyncProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+// `JsonCodec` Length prefixed JSON encoding of the requests `Q` and responses `R` of a sync
+// protocol.
+pub struct JsonCodec<Q, R>(PhantomData<fn() -> (Q, R)>);
+
+pub type AckCodec = JsonCodec<BroadcastAck, AckResponse>;
+pub type ChainCodec = JsonCodec<ChainRequest, ChainResponse>;
+pub type CheckpointCodec = JsonCodec<CheckpointRequest, CheckpointResponse>;
+pub type EpochCodec = JsonCodec<EpochRequest, EpochResponse>;
+pub type HistoryCodec = JsonCodec<HistoryRequest, HistoryResponse>;
+pub type AddressCodec = JsonCodec<AddressRequest, AddressResponse>;
+pub type BlocksCodec = JsonCodec<BlocksRequest, BlocksResponse>;
+pub type HeadersCodec = JsonCodec<HeadersRequest, HeadersResponse>;
+pub type ParentCodec = JsonCodec<ParentRequest, ParentResponse>;
+pub type ReconcileCodec = JsonCodec<ReconcileRequest, ReconcileResponse>;
+
+impl<Q, R> Default for JsonCodec<Q, R> {
+    fn default() -> Self {
+        JsonCodec(PhantomData)
+    }
+}
+
+impl<Q, R> Clone for JsonCodec<Q, R> {
+    fn clone(&self) -> Self {
+        JsonCodec(PhantomData)
+    }
+}
+
+#[async_trait]
+impl<Q, R> RequestResponseCodec for JsonCodec<Q, R>
+where
+    Q: Serialize + serde::de::DeserializeOwned + Send + Sync,
+    R: Serialize + serde::de::DeserializeOwned + Send + Sync,
+{
+    type Protocol = SyncProtocol;
+    type Request = Q;
+    type Response = R;
+
+    async fn read_request<T>(&mut self, _: &SyncProtocol, io: &mut T) -> io::Result<Q>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_json(io).await
+    }
+
+    async fn read_response<T>(&mut self, _: &SyncProtocol, io: &mut T) -> io::Result<R>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_json(io).await
+    }
+
+    async fn write_request<T>(&mut self, _: &SyncProtocol, io: &mut T, request: Q) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_json(io, &request).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &SyncProtocol,
+        io: &mut T,
+        response: R,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_json(io, &response).await
+    }
+}
+
+async fn read_json<T, M>(io: &mut T) -> io::Result<M>
+where
+    T: AsyncRead + Unpin + Send,
+    M: serde::de::DeserializeOwned,
+{
+    let data = read_length_prefixed(io, MAX_SYNC_MESSAGE_SIZE).await?;
+    serde_json::from_slice(&data).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+async fn write_json<T, M>(io: &mut T, message: &M) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin + Send,
+    M: Serialize,
+{
+    let data = serde_json::to_vec(message).expect("can jsonify message");
+    write_length_prefixed(io, data).await?;
+    io.close().await
 }
 
 pub enum EventType {
-    LocalChainResponse(ChainResponse),
     Input(String),
     Init,
+    Retry,
+    RotatePeers,
+    RefreshDiscovery,
+    ReleaseDelayed,
+    FaucetRequest(FaucetRequest),
+    RpcCall(RpcCall),
+    SampleMetrics,
+    CheckStaleTip,
+    ScheduledBlock,
+    Mined(MinedBlock),
+    Searched(CoopSearched),
+}
+
+// `PendingReorg` A better chain forking off deeper than the chain params allow reorgs to go,
+// kept until an operator approves switching to it with the `approve_reorg` admin call.
+#[derive(Serialize, Debug, Clone)]
+pub struct PendingReorg {
+    pub tip: Hash256,
+    pub height: u64,
+    // Local blocks the switch disconnects.
+    pub depth: u64,
+    #[serde(skip)]
+    pub chain: Vec<Arc<block::Block>>,
+}
+
+// `PeerAction` A change of the connections to outbound peers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PeerAction {
+    Dial(PeerId),
+    Disconnect(PeerId),
 }
 
 #[derive(NetworkBehaviour)]
+#[behaviour(poll_method = "poll_actions")]
 pub struct BlockchainBehaviour {
-    pub floodsub: Floodsub,
+    pub gossipsub: Gossipsub,
     pub mdns: Mdns,
+    kademlia: Kademlia<MemoryStore>,
+    ack_sync: RequestResponse<AckCodec>,
+    address_sync: RequestResponse<AddressCodec>,
+    block_sync: RequestResponse<BlocksCodec>,
+    chain_sync: RequestResponse<ChainCodec>,
+    pub checkpoint_sync: RequestResponse<CheckpointCodec>,
+    pub epoch_sync: RequestResponse<EpochCodec>,
+    header_sync: RequestResponse<HeadersCodec>,
+    pub history_sync: RequestResponse<HistoryCodec>,
+    mempool_sync: RequestResponse<ReconcileCodec>,
+    parent_sync: RequestResponse<ParentCodec>,
+    #[behaviour(ignore)]
+    keys: identity::Keypair,
     #[behaviour(ignore)]
-    pub response_sender: mpsc::UnboundedSender<ChainResponse>,
+    pub peer_id: PeerId,
     #[behaviour(ignore)]
-    pub init_sender: mpsc::UnboundedSender<bool>,
+    topics: Topics,
     #[behaviour(ignore)]
     pub blockchain: Blockchain,
     #[behaviour(ignore)]
-    pub mining: bool,
+    mined_sender: mpsc::UnboundedSender<MinedBlock>,
+    #[behaviour(ignore)]
+    searched_sender: mpsc::UnboundedSender<CoopSearched>,
+    // Block mined in the background, if any.
+    #[behaviour(ignore)]
+    mining: Option<MiningJob>,
+    // Mining jobs started so far, the next job gets this id.
+    #[behaviour(ignore)]
+    mining_jobs: u64,
+    // Faucet requests waiting for a block to be paid in.
+    #[behaviour(ignore)]
+    faucet_queue: Vec<FaucetRequest>,
+    // Faucet requests paid by the block being mined.
+    #[behaviour(ignore)]
+    faucet_payouts: Vec<FaucetRequest>,
+    #[behaviour(ignore)]
+    searcher: SharedSearcher,
+    #[behaviour(ignore)]
+    pub miner_settings: Arc<MinerSettings>,
+    #[behaviour(ignore)]
+    pub payout: Payout,
+    #[behaviour(ignore)]
+    pub scheduler: BlockScheduler,
+    #[behaviour(ignore)]
+    pub mempool: Mempool,
+    #[behaviour(ignore)]
+    journal: Option<Arc<Journal>>,
+    #[behaviour(ignore)]
+    pub vanity: Option<Arc<VanitySearch>>,
+    #[behaviour(ignore)]
+    pub watching: bool,
+    #[behaviour(ignore)]
+    pub checkpoints: Checkpoints,
+    #[behaviour(ignore)]
+    pub epochs: EpochSummaries,
+    // Blocks the history proofs asked for and not answered yet have to sample.
+    #[behaviour(ignore)]
+    pub history_requests: HashMap<RequestId, usize>,
+    // Changes of the chain and the mempool, pushed to WebSocket subscribers.
+    #[behaviour(ignore)]
+    pub events: broadcast::Sender<NodeEvent>,
+    #[behaviour(ignore)]
+    pub metrics: MetricsHistory,
+    #[behaviour(ignore)]
+    pub health: HealthSettings,
+    // Highest block peers announced, for telling how far behind the chain is.
+    #[behaviour(ignore)]
+    pub best_known_height: u64,
+    #[behaviour(ignore)]
+    outbound: OutboundQueue,
+    #[behaviour(ignore)]
+    seen: SeenCache,
+    #[behaviour(ignore)]
+    seen_transactions: SeenCache,
+    #[behaviour(ignore)]
+    orphans: OrphanPool,
+    #[behaviour(ignore)]
+    pub coop: Cooperation,
+    #[behaviour(ignore)]
+    pub relay_log: RelayLog,
+    #[behaviour(ignore)]
+    pub peers: PeerSelector,
+    // Addresses the node listens on or is reachable at, as the swarm last reported them.
+    #[behaviour(ignore)]
+    listen_addresses: Vec<Multiaddr>,
+    // File the routing table of the DHT is saved to, if discovery was started.
+    #[behaviour(ignore)]
+    routing_file: Option<String>,
+    // Peers the node joined the DHT through, dialed again when its tip goes stale.
+    #[behaviour(ignore)]
+    bootstrap: Vec<(PeerId, Multiaddr)>,
+    // When the node last saw a new block or a message from a peer.
+    #[behaviour(ignore)]
+    last_activity: Instant,
+    // Blocks being downloaded from a peer the node connected to, if any.
+    #[behaviour(ignore)]
+    download: Option<BlockDownload>,
+    // File the download is saved to once its headers are in, if it can be resumed.
+    #[behaviour(ignore)]
+    download_file: Option<String>,
+    // Announced tips asked for and not received yet.
+    #[behaviour(ignore)]
+    tip_requests: HashMap<RequestId, Hash256>,
+    // When peers last started a download by announcing their tip, see `TIP_DOWNLOAD_INTERVAL`.
+    #[behaviour(ignore)]
+    tip_downloads: HashMap<PeerId, Instant>,
+    // Mined blocks are only announced by their header on the tips topic, see `GossipSettings`.
+    #[behaviour(ignore)]
+    pub header_relay: bool,
+    #[behaviour(ignore)]
+    pub pending_reorg: Option<PendingReorg>,
+    // Dials of selected peers and disconnects of dropped ones, for the swarm to carry out.
+    #[behaviour(ignore)]
+    peer_actions: VecDeque<PeerAction>,
+    #[behaviour(ignore)]
+    pub trace: PropagationTrace,
+    #[behaviour(ignore)]
+    session: SessionRecorder,
+    #[behaviour(ignore)]
+    chaos: Chaos,
+}
+
+// `NodeSettings` What the behaviour of a node is built from, everything else it keeps starts out
+// empty.
+pub struct NodeSettings {
+    pub keys: identity::Keypair,
+    pub blockchain: Blockchain,
+    pub searcher: Box<dyn NonceSearcher + Send>,
+    pub miner_settings: Arc<MinerSettings>,
+    pub payout: Payout,
+    pub scheduler: BlockScheduler,
+    pub checkpoints: Checkpoints,
+    pub epochs: EpochSummaries,
+    pub relay_log: RelayLog,
+    pub trace: PropagationTrace,
+    pub session: SessionRecorder,
+    pub chaos: Chaos,
+    pub gossip_config: GossipsubConfig,
+}
+
+// `NodeSenders` Channels the behaviour reports back to the node's event loop on.
+pub struct NodeSenders {
+    pub mined: mpsc::UnboundedSender<MinedBlock>,
+    pub searched: mpsc::UnboundedSender<CoopSearched>,
 }
 
 impl BlockchainBehaviour {
-    pub async fn new(
-        blockchain: Blockchain,
-        response_sender: mpsc::UnboundedSender<ChainResponse>,
-        init_sender: mpsc::UnboundedSender<bool>,
-    ) -> Self {
+    pub async fn new(settings: NodeSettings, senders: NodeSenders) -> Self {
+        let NodeSettings {
+            keys,
+            blockchain,
+            searcher,
+            miner_settings,
+            payout,
+            scheduler,
+            checkpoints,
+            epochs,
+            relay_log,
+            trace,
+            session,
+            chaos,
+            gossip_config,
+        } = settings;
+        let peer_id = PeerId::from(keys.public());
+        let topics = Topics::for_chain(&blockchain.params.chain_id);
+        let chain_id = &blockchain.params.chain_id;
+        let checkpoint_protocol = SyncProtocol::for_chain(chain_id, "checkpoints");
+        let epoch_protocol = SyncProtocol::for_chain(chain_id, "epochs");
+        let history_protocol = SyncProtocol::for_chain(chain_id, "history");
+        let parent_protocol = SyncProtocol::for_chain(chain_id, "parents");
+        let mempool_protocol = SyncProtocol::for_chain(chain_id, "mempool");
+        let address_protocol = SyncProtocol::for_chain(chain_id, "addresses");
+        let chain_protocol = SyncProtocol::with_version(chain_id, "sync", "1.0");
+        let blocks_protocol = SyncProtocol::for_chain(chain_id, "blocks");
+        let headers_protocol = SyncProtocol::for_chain(chain_id, "headers");
+        let ack_protocol = SyncProtocol::for_chain(chain_id, "acks");
+        let mut kademlia_config = KademliaConfig::default();
+        kademlia_config.set_protocol_name(SyncProtocol::for_chain(chain_id, "kad").0.into_bytes());
         let mut behaviour = Self {
             blockchain,
-            floodsub: Floodsub::new(*PEER_ID),
+            gossipsub: Gossipsub::new(MessageAuthenticity::Signed(keys.clone()), gossip_config)
+                .expect("can create gossipsub"),
             mdns: Mdns::new(Default::default())
                 .await
                 .expect("can create mdns"),
-            response_sender,
-            init_sender,
-            mining: false,
+            kademlia: Kademlia::with_config(peer_id, MemoryStore::new(peer_id), kademlia_config),
+            ack_sync: RequestResponse::new(
+                AckCodec::default(),
+                iter::once((ack_protocol, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            address_sync: RequestResponse::new(
+                AddressCodec::default(),
+                iter::once((address_protocol, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            block_sync: RequestResponse::new(
+                BlocksCodec::default(),
+                iter::once((blocks_protocol, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            chain_sync: RequestResponse::new(
+                ChainCodec::default(),
+                iter::once((chain_protocol, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            checkpoint_sync: RequestResponse::new(
+                CheckpointCodec::default(),
+                iter::once((checkpoint_protocol, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            epoch_sync: RequestResponse::new(
+                EpochCodec::default(),
+                iter::once((epoch_protocol, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            header_sync: RequestResponse::new(
+                HeadersCodec::default(),
+                iter::once((headers_protocol, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            history_sync: RequestResponse::new(
+                HistoryCodec::default(),
+                iter::once((history_protocol, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            mempool_sync: RequestResponse::new(
+                ReconcileCodec::default(),
+                iter::once((mempool_protocol, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            parent_sync: RequestResponse::new(
+                ParentCodec::default(),
+                iter::once((parent_protocol, ProtocolSupport::Full)),
+                RequestResponseConfig::default(),
+            ),
+            keys,
+            peer_id,
+            topics,
+            mined_sender: senders.mined,
+            searched_sender: senders.searched,
+            mining: None,
+            mining_jobs: 0,
+            faucet_queue: Vec::new(),
+            faucet_payouts: Vec::new(),
+            searcher: Arc::new(Mutex::new(searcher)),
+            miner_settings,
+            payout,
+            scheduler,
+            mempool: Mempool::default(),
+            journal: None,
+            vanity: None,
+            watching: false,
+            checkpoints,
+            epochs,
+            history_requests: HashMap::new(),
+            events: broadcast::channel(EVENT_BUFFER).0,
+            metrics: MetricsHistory::default(),
+            health: HealthSettings::default(),
+            best_known_height: 0,
+            outbound: OutboundQueue::default(),
+            seen: SeenCache::new(SEEN_CACHE_SIZE),
+            seen_transactions: SeenCache::new(SEEN_CACHE_SIZE),
+            orphans: OrphanPool::default(),
+            coop: Cooperation::default(),
+            relay_log,
+            peers: PeerSelector::default(),
+            listen_addresses: Vec::new(),
+            routing_file: None,
+            bootstrap: Vec::new(),
+            last_activity: Instant::now(),
+            download: None,
+            download_file: None,
+            tip_requests: HashMap::new(),
+            tip_downloads: HashMap::new(),
+            header_relay: false,
+            pending_reorg: None,
+            peer_actions: VecDeque::new(),
+            trace,
+            session,
+            chaos,
         };
 
-        behaviour.floodsub.subscribe(CHAIN_TOPIC.clone());
-        behaviour.floodsub.subscribe(BLOCK_TOPIC.clone());
+        let topics = &behaviour.topics;
+        for topic in [
+            &topics.block,
+            &topics.coop,
+            &topics.tip,
+            &topics.transaction,
+        ] {
+            if let Err(err) = behaviour.gossipsub.subscribe(topic) {
+                println!("can't subscribe to {}: {:?}", topic, err);
+            }
+        }
 
         behaviour
     }
+
+    // Restore what `records` hold beyond the stored chain, then record every further change of
+    // the chain and the mempool to `journal`.
+    pub fn attach_journal(&mut self, journal: Journal, records: Vec<JournalRecord>) {
+        match self.blockchain.recover(&records) {
+            Ok(0) => {}
+            Ok(connected) => println!("recovered {} blocks from the journal", connected),
+            Err(err) => println!("can't recover the chain from the journal: {}", err),
+        }
+
+        self.mempool.recover(&records);
+        self.update_mempool(0, None);
+        if !self.mempool.is_empty() {
+            println!(
+                "recovered {} pending transactions from the journal",
+                self.mempool.len()
+            );
+        }
+
+        let journal = Arc::new(journal);
+        self.blockchain.set_journal(journal.clone());
+        self.mempool.set_journal(journal.clone());
+        self.journal = Some(journal);
+        self.compact_journal();
+    }
+
+    // Rewrite the journal to what the store doesn't hold, the pending transactions.
+    fn compact_journal(&mut self) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+
+        let tip = self
+            .blockchain
+            .chain
+            .last()
+            .expect("has to exist")
+            .header
+            .hash;
+        let record = JournalRecord::new("compact", Some(tip), self.mempool.snapshot());
+        if let Err(err) = journal.compact(&[record]) {
+            println!("{}", err);
+        }
+    }
 }
 
 impl NetworkBehaviourEventProcess<MdnsEvent> for BlockchainBehaviour {
     fn inject_event(&mut self, event: MdnsEvent) {
         match event {
             MdnsEvent::Discovered(discovered_list) => {
-                for (peer, _addr) in discovered_list {
-                    self.floodsub.add_node_to_partial_view(peer);
+                for (peer, addr) in discovered_list {
+                    self.peers.add_candidate(peer, &addr);
                 }
             }
             MdnsEvent::Expired(expired_list) => {
                 for (peer, _addr) in expired_list {
-                    if !self.mdns.has_node(&peer) {
-                        self.floodsub.remove_node_from_partial_view(&peer);
+                    if !self.mdns.has_node(&peer) && self.peers.remove_candidate(&peer) {
+                        self.peer_actions.push_back(PeerAction::Disconnect(peer));
                     }
                 }
             }
         }
+
+        self.dial_selected();
     }
 }
 
-impl NetworkBehaviourEventProcess<FloodsubEvent> for BlockchainBehaviour {
-    fn inject_event(&mut self, event: FloodsubEvent) {
-        if let FloodsubEvent::Message(msg) = event {
-            if let Ok(resp) = serde_json::from_slice::<ChainResponse>(&msg.data) {
-                if resp.receiver == PEER_ID.to_string() {
-                    println!("response from {}", msg.source);
-
-                    resp.blocks.iter().for_each(|block| println!("{:?}", block));
-                    self.blockchain.chain = self
-                        .blockchain
-                        .choose_chain(self.blockchain.chain.clone(), resp.blocks);
+impl NetworkBehaviourEventProcess<KademliaEvent> for BlockchainBehaviour {
+    fn inject_event(&mut self, event: KademliaEvent) {
+        match event {
+            KademliaEvent::RoutingUpdated {
+                peer, addresses, ..
+            } => {
+                for address in addresses.iter() {
+                    self.peers.add_candidate(peer, address);
                 }
-            } else if let Ok(resp) = serde_json::from_slice::<LocalChainRequest>(&msg.data) {
-                println!("sending local chain to {}", msg.source);
+                self.dial_selected();
+            }
+            // The peer connected to the node, which doesn't know where it listens yet.
+            KademliaEvent::UnroutablePeer { peer } => {
+                self.address_sync.send_request(&peer, AddressRequest {});
+            }
+            KademliaEvent::OutboundQueryCompleted {
+                result: QueryResult::Bootstrap(result),
+                ..
+            } => match result {
+                Ok(bootstrap) if bootstrap.num_remaining == 0 => self.save_routing_table(),
+                Ok(_) => {}
+                Err(err) => println!("dht bootstrap failed: {:?}", err),
+            },
+            _ => {}
+        }
+    }
+}
 
-                let peer_id = resp.from_peer_id;
-                if PEER_ID.to_string() == peer_id {
-                    if let Err(err) = self.response_sender.send(ChainResponse {
-                        blocks: self.blockchain.chain.clone(),
-                        receiver: msg.source.to_string(),
-                    }) {
-                        println!("error sending response via channel {}", err);
+impl NetworkBehaviourEventProcess<RequestResponseEvent<AddressRequest, AddressResponse>>
+    for BlockchainBehaviour
+{
+    fn inject_event(&mut self, event: RequestResponseEvent<AddressRequest, AddressResponse>) {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Request { channel, .. },
+            } => {
+                let addresses = self
+                    .listen_addresses
+                    .iter()
+                    .map(|address| address.to_string())
+                    .collect();
+                if self
+                    .address_sync
+                    .send_response(channel, AddressResponse { addresses })
+                    .is_err()
+                {
+                    println!("can't send addresses to {}", peer);
+                }
+            }
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Response { response, .. },
+            } => {
+                for address in response.addresses {
+                    match address.parse() {
+                        Ok(address) => {
+                            self.kademlia.add_address(&peer, address);
+                        }
+                        Err(_) => println!("{} sent an invalid address: {}", peer, address),
                     }
                 }
-            } else if let Ok(block) = serde_json::from_slice::<block::Block>(&msg.data) {
-                println!("received new block from {}", msg.source);
+            }
+            RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                println!("address request to {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                println!("address request from {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
+}
 
-                if block.is_mined(self.blockchain.difficulty) {
-                    // Block is already mined, stop mining and try to add it to the blockchain
-                    self.mining = false;
-                    self.blockchain.try_to_add_a_block(block);
-                } else {
-                    // Block is not mined, start mining
-                    self.mining = true;
-                    let mut block = block.clone();
-                    block.mine(self.blockchain.clone(), &mut self.mining);
+impl NetworkBehaviourEventProcess<GossipsubEvent> for BlockchainBehaviour {
+    fn inject_event(&mut self, event: GossipsubEvent) {
+        match event {
+            GossipsubEvent::Message {
+                propagation_source,
+                message,
+                ..
+            } => {
+                self.last_activity = Instant::now();
+                self.handle_gossip(GossipMessage::new(propagation_source, message));
+            }
+            // Peers join the topic as they connect, e.g. once a split of the network healed.
+            GossipsubEvent::Subscribed { peer_id, topic }
+                if topic == self.topics.transaction.hash() =>
+            {
+                self.reconcile_mempool(&peer_id)
+            }
+            _ => {}
+        }
+    }
+}
 
-                    // Broadcast the mined block
-                    let json = serde_json::to_string(&block).expect("can jsonify request");
-                    self.floodsub.publish(BLOCK_TOPIC.clone(), json.as_bytes());
+impl NetworkBehaviourEventProcess<RequestResponseEvent<ChainRequest, ChainResponse>>
+    for BlockchainBehaviour
+{
+    fn inject_event(&mut self, event: RequestResponseEvent<ChainRequest, ChainResponse>) {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Request { channel, .. },
+            } => {
+                println!("sending local chain to {}", peer);
 
-                    self.blockchain.try_to_add_a_block(block);
+                let blocks = self.blockchain.chain.clone();
+                if self
+                    .chain_sync
+                    .send_response(channel, ChainResponse { blocks })
+                    .is_err()
+                {
+                    println!("can't send local chain to {}", peer);
                 }
             }
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Response { response, .. },
+            } => self.update_chain(|behaviour| behaviour.handle_chain_response(&peer, response)),
+            RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                println!("chain request to {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                println!("chain request from {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::ResponseSent { .. } => {}
         }
     }
 }
 
-pub fn get_list_peers(swarm: &Swarm<BlockchainBehaviour>) -> Vec<String> {
-    println!("discovered peers");
-
-    let nodes = swarm.behaviour().mdns.discovered_nodes();
-    let mut unique_peers = HashSet::new();
+impl NetworkBehaviourEventProcess<RequestResponseEvent<BlocksRequest, BlocksResponse>>
+    for BlockchainBehaviour
+{
+    fn inject_event(&mut self, event: RequestResponseEvent<BlocksRequest, BlocksResponse>) {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Request {
+                        request, channel, ..
+                    },
+            } => {
+                let end = request.from.saturating_add(request.count.min(BATCH_SIZE));
+                let blocks = self
+                    .blockchain
+                    .iter_blocks(request.from..end)
+                    .cloned()
+                    .collect();
+                if self
+                    .block_sync
+                    .send_response(channel, BlocksResponse { blocks })
+                    .is_err()
+                {
+                    println!("can't send blocks to {}", peer);
+                }
+            }
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Response {
+                        request_id,
+                        response,
+                    },
+            } => self.update_chain(|behaviour| {
+                behaviour.handle_blocks_response(&peer, &request_id, response)
+            }),
+            RequestResponseEvent::OutboundFailure {
+                peer,
+                request_id,
+                error,
+            } => {
+                println!("block request to {} failed: {:?}", peer, error);
+                if self
+                    .download
+                    .as_mut()
+                    .is_some_and(|download| download.failed(&request_id))
+                {
+                    self.drop_download_peer(&peer, "blocks didn't come");
+                }
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                println!("block request from {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
+}
 
-    for peer in nodes {
-        unique_peers.insert(peer);
+impl NetworkBehaviourEventProcess<RequestResponseEvent<HeadersRequest, HeadersResponse>>
+    for BlockchainBehaviour
+{
+    fn inject_event(&mut self, event: RequestResponseEvent<HeadersRequest, HeadersResponse>) {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Request {
+                        request, channel, ..
+                    },
+            } => {
+                // The genesis block ends every locator, so there is always a block in common.
+                let from = request
+                    .locator
+                    .iter()
+                    .find_map(|hash| self.blockchain.get_block_by_hash(hash))
+                    .map_or(0, |block| block.header.index)
+                    + 1;
+                let headers = self
+                    .blockchain
+                    .iter_blocks(from..from + MAX_HEADERS)
+                    .map(|block| block.header.clone())
+                    .collect();
+                let tip = self.blockchain.chain.len() as u64 - 1;
+                if self
+                    .header_sync
+                    .send_response(channel, HeadersResponse { headers, tip })
+                    .is_err()
+                {
+                    println!("can't send headers to {}", peer);
+                }
+            }
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Response { response, .. },
+            } => self.handle_headers_response(&peer, response),
+            RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                println!("header request to {} failed: {:?}", peer, error);
+                if self
+                    .download
+                    .as_ref()
+                    .is_some_and(|download| download.peer == peer)
+                {
+                    self.stop_download(&peer, "headers didn't come");
+                }
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                println!("header request from {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::ResponseSent { .. } => {}
+        }
     }
+}
 
-    unique_peers.iter().map(|peer| peer.to_string()).collect()
+impl NetworkBehaviourEventProcess<RequestResponseEvent<BroadcastAck, AckResponse>>
+    for BlockchainBehaviour
+{
+    fn inject_event(&mut self, event: RequestResponseEvent<BroadcastAck, AckResponse>) {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Request {
+                        request, channel, ..
+                    },
+            } => {
+                let elapsed = self.outbound.acknowledge(&request.hash);
+                let outcome = Outcome {
+                    hash: Some(request.hash),
+                    latency_ms: elapsed.map(|elapsed| elapsed.as_millis() as u64),
+                    ..Outcome::new(
+                        "ack",
+                        if elapsed.is_some() {
+                            "acknowledged"
+                        } else {
+                            "unknown"
+                        },
+                    )
+                };
+                self.trace.inbound(peer.to_string(), 0, outcome);
+                let _ = self.ack_sync.send_response(channel, AckResponse {});
+            }
+            RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                println!("ack to {} failed: {:?}", peer, error)
+            }
+            _ => {}
+        }
+    }
 }
 
-pub fn handle_print_peers(swarm: &Swarm<BlockchainBehaviour>) {
-    let peers = get_list_peers(&swarm);
-    peers.iter().for_each(|peer| println!("{}", peer));
+impl NetworkBehaviourEventProcess<RequestResponseEvent<ParentRequest, ParentResponse>>
+    for BlockchainBehaviour
+{
+    fn inject_event(&mut self, event: RequestResponseEvent<ParentRequest, ParentResponse>) {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Request {
+                        request, channel, ..
+                    },
+            } => {
+                let block = self.blockchain.get_block_by_hash(&request.hash).cloned();
+                if self
+                    .parent_sync
+                    .send_response(channel, ParentResponse { block })
+                    .is_err()
+                {
+                    println!("can't send block {} to {}", request.hash, peer);
+                }
+            }
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Response {
+                        request_id,
+                        response,
+                    },
+            } => self.update_chain(|behaviour| {
+                behaviour.handle_parent_response(&peer, &request_id, response)
+            }),
+            RequestResponseEvent::OutboundFailure {
+                peer,
+                request_id,
+                error,
+            } => {
+                self.tip_requests.remove(&request_id);
+                println!("parent request to {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                println!("parent request from {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
 }
 
-pub fn handle_print_chain(swarm: &Swarm<BlockchainBehaviour>) {
-    println!("local blockchain");
+impl NetworkBehaviourEventProcess<RequestResponseEvent<ReconcileRequest, ReconcileResponse>>
+    for BlockchainBehaviour
+{
+    fn inject_event(&mut self, event: RequestResponseEvent<ReconcileRequest, ReconcileResponse>) {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Request {
+                        request, channel, ..
+                    },
+            } => {
+                let response = self.reconcile_response(request);
+                if self.mempool_sync.send_response(channel, response).is_err() {
+                    println!("can't send mempool reconciliation to {}", peer);
+                }
+            }
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Response { response, .. },
+            } => self.handle_reconcile_response(&peer, response),
+            RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                println!("mempool request to {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                println!("mempool request from {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
+}
 
-    let pretty_json = serde_json::to_string_pretty(&swarm.behaviour().blockchain.chain)
-        .expect("can jsonify blocks");
+impl NetworkBehaviourEventProcess<RequestResponseEvent<CheckpointRequest, CheckpointResponse>>
+    for BlockchainBehaviour
+{
+    fn inject_event(&mut self, event: RequestResponseEvent<CheckpointRequest, CheckpointResponse>) {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Request {
+                        request, channel, ..
+                    },
+            } => {
+                println!("sending checkpoint to {}", peer);
 
-    println!("{}", pretty_json);
+                let response = self.checkpoint_response(request.height);
+                if self
+                    .checkpoint_sync
+                    .send_response(channel, response)
+                    .is_err()
+                {
+                    println!("can't send checkpoint to {}", peer);
+                }
+            }
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Response { response, .. },
+            } => print_checkpoint_response(&peer, &response),
+            RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                println!("checkpoint request to {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                println!("checkpoint request from {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
 }
 
-pub fn handle_create_block(cmd: &str, swarm: &mut Swarm<BlockchainBehaviour>) {
-    if let Some(data) = cmd.strip_prefix("create b") {
-        let behaviour = swarm.behaviour_mut();
-
-        let latest_block = behaviour
-            .blockchain
-            .chain
-            .last()
-            .expect("there is at least one block");
+impl NetworkBehaviourEventProcess<RequestResponseEvent<EpochRequest, EpochResponse>>
+    for BlockchainBehaviour
+{
+    fn inject_event(&mut self, event: RequestResponseEvent<EpochRequest, EpochResponse>) {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Request {
+                        request, channel, ..
+                    },
+            } => {
+                println!("sending epoch summaries to {}", peer);
 
-        let transactions: Vec<Transaction> = serde_json::from_str(data).expect("can parse transactions");
+                let summaries = self
+                    .epochs
+                    .since(request.from)
+                    .take(MAX_EPOCH_SUMMARIES)
+                    .cloned()
+                    .collect();
+                if self
+                    .epoch_sync
+                    .send_response(channel, EpochResponse { summaries })
+                    .is_err()
+                {
+                    println!("can't send epoch summaries to {}", peer);
+                }
+            }
+            RequestResponseEvent::Message {
+                peer,
+                message: RequestResponseMessage::Response { response, .. },
+            } => self.print_epoch_response(&peer, &response),
+            RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+                println!("epoch request to {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                println!("epoch request from {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
+}
 
-        let block = block::Block::new(
-            latest_block.index + 1,
-            latest_block.hash.clone(),
-            transactions,
-        );
+impl NetworkBehaviourEventProcess<RequestResponseEvent<HistoryRequest, HistoryResponse>>
+    for BlockchainBehaviour
+{
+    fn inject_event(&mut self, event: RequestResponseEvent<HistoryRequest, HistoryResponse>) {
+        match event {
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Request {
+                        request, channel, ..
+                    },
+            } => {
+                println!("sending history proof to {}", peer);
 
-        let json = serde_json::to_string(&block).expect("can jsonify request");
+                let samples = request.samples.min(MAX_HISTORY_SAMPLES);
+                let proof = history::prove(&self.blockchain.chain, samples);
+                if self
+                    .history_sync
+                    .send_response(channel, HistoryResponse { proof })
+                    .is_err()
+                {
+                    println!("can't send history proof to {}", peer);
+                }
+            }
+            RequestResponseEvent::Message {
+                peer,
+                message:
+                    RequestResponseMessage::Response {
+                        request_id,
+                        response,
+                    },
+            } => {
+                let samples = self.history_requests.remove(&request_id).unwrap_or(0);
+                self.print_history_response(&peer, &response, samples);
+            }
+            RequestResponseEvent::OutboundFailure {
+                peer,
+                request_id,
+                error,
+            } => {
+                self.history_requests.remove(&request_id);
+                println!("history request to {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::InboundFailure { peer, error, .. } => {
+                println!("history request from {} failed: {:?}", peer, error)
+            }
+            RequestResponseEvent::ResponseSent { .. } => {}
+        }
+    }
+}
 
-        println!("broadcasting new block for mining");
+impl BlockchainBehaviour {
+    fn handle_message(&mut self, msg: GossipMessage) -> Outcome {
+        if msg.topic == self.topics.coop.hash() {
+            self.handle_coop_message(msg)
+        } else if msg.topic == self.topics.transaction.hash() {
+            self.handle_transaction(msg)
+        } else if msg.topic == self.topics.tip.hash() {
+            self.handle_tip(msg)
+        } else if let Ok(header) = serde_json::from_slice::<block::BlockPreview>(&msg.data) {
+            self.handle_block(msg, header)
+        } else {
+            Outcome::new("unknown", "unparsed")
+        }
+    }
 
-        behaviour
-            .floodsub
-            .publish(BLOCK_TOPIC.clone(), json.as_bytes());
+    // Transactions are relayed by gossipsub itself, so they only need to be kept for blocks.
+    // Transactions and packages seen before are only acknowledged, not checked again.
+    fn handle_transaction(&mut self, msg: GossipMessage) -> Outcome {
+        if let Ok(package) = serde_json::from_slice::<TransactionPackage>(&msg.data) {
+            let id = package
+                .transactions
+                .first()
+                .map(|transaction| transaction.id());
+            if let Some(id) = id
+                && !self.seen_transactions.insert(id)
+            {
+                self.acknowledge(&msg.source, id);
+                return Outcome {
+                    hash: Some(id),
+                    ..Outcome::new("package", "duplicate")
+                };
+            }
+            let result = match self.submit_package(package) {
+                Ok(_) => "accepted",
+                Err(_) => "rejected",
+            };
+            if let (Some(id), "accepted") = (id, result) {
+                self.acknowledge(&msg.source, id);
+            }
+            return Outcome {
+                hash: id,
+                ..Outcome::new("package", result)
+            };
+        }
+        let Ok(transaction) = serde_json::from_slice::<Transaction>(&msg.data) else {
+            return Outcome::new("transaction", "unparsed");
+        };
+
+        let id = transaction.id();
+        if !self.seen_transactions.insert(id) {
+            self.acknowledge(&msg.source, id);
+            return Outcome {
+                hash: Some(id),
+                ..Outcome::new("transaction", "duplicate")
+            };
+        }
+        let result = match self.submit_transaction(transaction) {
+            Ok(_) => "accepted",
+            Err(_) if self.mempool.contains(&id) => "duplicate",
+            Err(_) => "rejected",
+        };
+        if result != "rejected" {
+            self.acknowledge(&msg.source, id);
+        }
+        Outcome {
+            hash: Some(id),
+            ..Outcome::new("transaction", result)
+        }
+    }
+
+    // Blocks are recognized by their header first, so the many copies of a block gossip
+    // delivers are acknowledged without decoding their transactions or hashing them again.
+    // Only hashes of blocks the chain accepted are remembered as seen, a header can claim any
+    // hash and unmined, orphaned or rejected blocks may be followed by a valid copy.
+    fn handle_block(&mut self, msg: GossipMessage, header: block::BlockPreview) -> Outcome {
+        let latency_ms = (Utc::now().timestamp_millis() as u64).saturating_sub(header.timestamp);
+        if self.seen.touch(header.hash) {
+            self.acknowledge(&msg.source, header.hash);
+
+            return Outcome {
+                hash: Some(header.hash),
+                latency_ms: Some(latency_ms),
+                ..Outcome::new("block", "duplicate")
+            };
+        }
+
+        let block = match serde_json::from_slice::<block::Block>(&msg.data) {
+            Ok(block) => block,
+            Err(_) => return Outcome::new("block", "unparsed"),
+        };
+        // Blocks are known by the hash of their header under the chain's proof of work.
+        let hash = block.header.hash;
+        if self.blockchain.params.pow.engine().hash(&block.header) != hash {
+            println!("ignoring block from {} with a wrong hash", msg.source);
+            return Outcome {
+                hash: Some(hash),
+                ..Outcome::new("block", "rejected")
+            };
+        }
+
+        // Acknowledge duplicates too, the publisher may have missed the first ack.
+        self.acknowledge(&msg.source, hash);
+
+        let mut outcome = Outcome {
+            hash: Some(hash),
+            latency_ms: Some(latency_ms),
+            ..Outcome::new("block", "duplicate")
+        };
+        if self.seen.touch(hash) {
+            return outcome;
+        }
+        println!("received new block {} from {}", header.index, msg.source);
+
+        if block.is_mined(self.blockchain.block_work()) {
+            self.best_known_height = self.best_known_height.max(header.index);
+            self.relay_log
+                .record(&block.header.hash, &msg.source.to_string());
+            outcome.result = self.add_block(&msg.source, block.into());
+            if outcome.result == "accepted" {
+                self.seen.insert(hash);
+            }
+        } else {
+            // Only blocks assembled locally are mined, peers can't hand us their work.
+            println!("ignoring unmined block from {}", msg.source);
+            outcome.result = "unmined";
+        }
+        outcome
+    }
+
+    // A peer's new tip is only fetched if its chain has more work than the local one. A block on
+    // top of the local tip is asked for by hash, a tip further away is downloaded like the chain
+    // of a peer that just connected, at most every `TIP_DOWNLOAD_INTERVAL` for every peer.
+    fn handle_tip(&mut self, msg: GossipMessage) -> Outcome {
+        let Ok(tip) = serde_json::from_slice::<TipAnnouncement>(&msg.data) else {
+            return Outcome::new("tip", "unparsed");
+        };
+        let header = tip.header;
+        let mut outcome = Outcome {
+            hash: Some(header.hash),
+            ..Outcome::new("tip", "duplicate")
+        };
+        if self.blockchain.get_block_by_hash(&header.hash).is_some()
+            || self.tip_requests.values().any(|hash| *hash == header.hash)
+        {
+            return outcome;
+        }
+        // Headers are hashed on their own, so the work of the tip is checked before fetching it.
+        if self.blockchain.params.pow.engine().hash(&header) != header.hash
+            || !header.is_mined(work::Work::from_difficulty(header.difficulty))
+        {
+            outcome.result = "unmined";
+            return outcome;
+        }
+        // Cheap headers could send the node after any fork, so tips have to be at least as hard
+        // to mine as the next local block. The height of the tip only counts once its blocks
+        // are verified, its announcement alone doesn't hold back readiness.
+        if header.difficulty < self.blockchain.next_difficulty() {
+            println!("tip from {} is mined below the difficulty", msg.source);
+            outcome.result = "rejected";
+            return outcome;
+        }
+        if tip.work <= self.blockchain.work() {
+            outcome.result = "ignored";
+            return outcome;
+        }
+
+        let local_tip = self
+            .blockchain
+            .chain
+            .last()
+            .expect("there is at least one block")
+            .header
+            .hash;
+        if header.previous_hash == local_tip {
+            println!("asking {} for its new tip {}", msg.source, header.index);
+            let request_id = self
+                .parent_sync
+                .send_request(&msg.source, ParentRequest { hash: header.hash });
+            self.tip_requests.insert(request_id, header.hash);
+            outcome.result = "requested";
+        } else if self
+            .tip_downloads
+            .get(&msg.source)
+            .is_some_and(|started| started.elapsed() < TIP_DOWNLOAD_INTERVAL)
+        {
+            outcome.result = "throttled";
+        } else {
+            self.tip_downloads
+                .retain(|_, started| started.elapsed() < TIP_DOWNLOAD_INTERVAL);
+            self.tip_downloads.insert(msg.source, Instant::now());
+            let others = self
+                .gossipsub
+                .all_peers()
+                .map(|(peer, _)| *peer)
+                .filter(|peer| *peer != msg.source)
+                .collect();
+            self.start_download(msg.source, others);
+            outcome.result = "download";
+        }
+        outcome
+    }
+
+    // Announce the tip of the chain on the tips topic.
+    fn announce_tip(&mut self) {
+        let tip = self
+            .blockchain
+            .chain
+            .last()
+            .expect("there is at least one block");
+        let announcement = TipAnnouncement {
+            header: tip.header.clone(),
+            work: self.blockchain.work(),
+        };
+        let hash = tip.header.hash;
+        let json = serde_json::to_string(&announcement).expect("can jsonify tip");
+        self.publish(self.topics.tip.clone(), "tip", Some(hash), json);
+    }
+
+    // Add `block` from `peer` to the chain, or keep it as an orphan and ask `peer` for its parent
+    // unless the parent is known. A running download brings the parent anyway. Returns what
+    // became of it.
+    fn add_block(&mut self, peer: &PeerId, block: Arc<block::Block>) -> &'static str {
+        if self
+            .blockchain
+            .get_block_by_hash(&block.header.previous_hash)
+            .is_none()
+        {
+            let hash = block.header.previous_hash;
+            if self.orphans.insert(block) && self.download.is_none() {
+                println!("asking {} for the parent {} of an orphan", peer, hash);
+                self.parent_sync.send_request(peer, ParentRequest { hash });
+            }
+            return "orphan";
+        }
+
+        let hash = block.header.hash;
+        self.blockchain.try_to_add_a_block(block);
+        match self.blockchain.chain.last().map(|block| block.header.hash) == Some(hash) {
+            true => "accepted",
+            false => "rejected",
+        }
+    }
+
+    // Connect the orphans waiting for the tip, and the ones waiting for them in turn.
+    fn connect_orphans(&mut self) {
+        while !self.orphans.is_empty() {
+            let tip = self
+                .blockchain
+                .chain
+                .last()
+                .expect("there is at least one block")
+                .header
+                .hash;
+            let children = self.orphans.take_children(&tip);
+            if children.is_empty() {
+                break;
+            }
+            for child in children {
+                println!("connecting orphan block {}", child.header.index);
+                self.blockchain.try_to_add_a_block(child);
+            }
+        }
+    }
+
+    // Tell `publisher` its broadcast `hash` arrived, if it published it to this node itself. Peers
+    // further away leave that to the ones in between, so a broadcast costs as many acks as the
+    // publisher has peers rather than a message to everyone for every node.
+    fn acknowledge(&mut self, publisher: &PeerId, hash: Hash256) {
+        if self
+            .gossipsub
+            .all_peers()
+            .any(|(peer, _)| peer == publisher)
+        {
+            self.ack_sync.send_request(publisher, BroadcastAck { hash });
+        }
+    }
+
+    fn handle_coop_message(&mut self, msg: GossipMessage) -> Outcome {
+        if let Ok(volunteer) = serde_json::from_slice::<CoopVolunteer>(&msg.data) {
+            // Volunteers are known by the peer that published the announcement, which has to
+            // sign the payout address itself.
+            if !volunteer.is_signed_by(&msg.source) {
+                println!(
+                    "volunteer announcement from {} isn't signed by it",
+                    msg.source
+                );
+                return Outcome::new("coop_volunteer", "rejected");
+            }
+            if volunteer.available {
+                self.coop
+                    .volunteers
+                    .insert(msg.source.to_string(), volunteer.address);
+            } else {
+                self.coop.volunteers.remove(&msg.source.to_string());
+            }
+            Outcome::new("coop_volunteer", "accepted")
+        } else if let Ok(assignment) = serde_json::from_slice::<CoopAssignment>(&msg.data) {
+            if !self.coop.volunteering || assignment.worker != self.peer_id.to_string() {
+                return Outcome::new("coop_assignment", "ignored");
+            }
+            let work = &assignment.work;
+            if work.end_nonce.saturating_sub(work.start_nonce) > ASSIGNMENT_SIZE {
+                println!("assignment from {} is too large", msg.source);
+                return Outcome::new("coop_assignment", "rejected");
+            }
+
+            // The searcher is busy with a block of our own, the coordinator hands the range
+            // to someone else once it times out.
+            if self.mining.is_some() {
+                println!("miner busy, skipping nonces for {}", msg.source);
+                return Outcome::new("coop_assignment", "busy");
+            }
+            self.search_assignment(assignment, &msg.source);
+            Outcome::new("coop_assignment", "searching")
+        } else if let Ok(result) = serde_json::from_slice::<CoopResult>(&msg.data) {
+            // Only the worker itself reports on its assignment.
+            if result.worker != msg.source.to_string() {
+                return Outcome::new("coop_result", "rejected");
+            }
+            Outcome {
+                hash: Some(result.job_id),
+                ..Outcome::new("coop_result", self.handle_coop_result(result))
+            }
+        } else {
+            Outcome::new("unknown", "unparsed")
+        }
+    }
+
+    // Search the nonces of `assignment` in the background, giving up the assignment searched so
+    // far as its coordinator moved on.
+    fn search_assignment(&mut self, assignment: CoopAssignment, coordinator: &PeerId) {
+        self.coop.cancel_search("a new assignment arrived");
+        println!(
+            "searching nonces {}..{} for {}",
+            assignment.work.start_nonce, assignment.work.end_nonce, coordinator
+        );
+
+        self.coop.searches += 1;
+        let search = self.coop.searches;
+        let sender = self.searched_sender.clone();
+        let CoopAssignment {
+            job_id,
+            worker,
+            work,
+        } = assignment;
+        self.coop.search = Some(SearchJob::spawn(
+            search,
+            work,
+            self.searcher.clone(),
+            // The node may be shutting down, nobody waits for the result then.
+            move |nonce| {
+                let result = CoopResult {
+                    job_id,
+                    worker,
+                    result: WorkResult { nonce },
+                };
+                let _ = sender.send(CoopSearched { search, result });
+            },
+        ));
+    }
+
+    // Report the result of an assignment searched in the background to its coordinator, unless
+    // the search was given up since.
+    pub fn finish_search(&mut self, searched: CoopSearched) {
+        if self
+            .coop
+            .search
+            .as_ref()
+            .is_none_or(|search| search.id != searched.search)
+        {
+            return;
+        }
+        self.coop.search = None;
+
+        let json = serde_json::to_string(&searched.result).expect("can jsonify result");
+        self.publish(
+            self.topics.coop.clone(),
+            "coop_result",
+            Some(searched.result.job_id),
+            json,
+        );
+    }
+
+    // Mine `block` cooperatively, crediting every known volunteer with an equal share of the
+    // reward.
+    pub fn start_coop_job(&mut self, mut block: block::Block) {
+        if self.coop.volunteers.is_empty() {
+            println!("no volunteers known, mine the block with `create b` instead");
+            return;
+        }
+
+        let share = 100 / self.coop.volunteers.len() as u64;
+        let splits = self
+            .coop
+            .volunteers
+            .values()
+            .map(|address| PayoutSplit {
+                address: address.clone(),
+                percent: share,
+            })
+            .collect();
+        let payout = Payout::new(self.payout.address.clone(), splits)
+            .expect("volunteer shares add up to at most 100%");
+
+        let reward = self.blockchain.params.reward_at(block.header.index);
+        coinbase::apply_payout(&mut block, &payout, reward);
+        self.blockchain.commit_accumulator(&mut block);
+
+        println!(
+            "mining block #{} with {} volunteers",
+            block.header.index,
+            self.coop.volunteers.len()
+        );
+        self.coop.job = Some(CoopJob {
+            id: block.generate_block_hash(),
+            block,
+            next_nonce: 0,
+        });
+
+        let workers: Vec<String> = self.coop.volunteers.keys().cloned().collect();
+        for worker in workers {
+            self.assign_work(&worker);
+        }
+    }
+
+    fn handle_coop_result(&mut self, result: CoopResult) -> &'static str {
+        let Some(job) = self.coop.job.as_ref().filter(|job| job.id == result.job_id) else {
+            return "stale";
+        };
+
+        if let Some(nonce) = result.result.nonce {
+            let mut block = job.block.clone();
+            block.header.proof_of_work = nonce;
+            block.header.hash = self.blockchain.params.pow.engine().hash(&block.header);
+
+            if !block.is_mined(self.blockchain.block_work()) {
+                println!("{} returned an invalid nonce: {}", result.worker, nonce);
+                return "rejected";
+            }
+
+            println!(
+                "{} found the nonce of block #{}",
+                result.worker, block.header.index
+            );
+            self.coop.job = None;
+            self.relay_log
+                .record(&block.header.hash, &self.peer_id.to_string());
+            self.broadcast_block(&block);
+            self.blockchain.try_to_add_a_block(block);
+            "accepted"
+        } else {
+            self.assign_work(&result.worker);
+            "exhausted"
+        }
+    }
+
+    // Hand `worker` the next nonce range of the current job.
+    fn assign_work(&mut self, worker: &str) {
+        let Some(job) = self.coop.job.as_mut() else {
+            return;
+        };
+        let Some((start, end)) = job.next_range() else {
+            println!(
+                "nonce space of block #{} is exhausted",
+                job.block.header.index
+            );
+            self.coop.job = None;
+            return;
+        };
+
+        let assignment = CoopAssignment {
+            job_id: job.id,
+            worker: worker.to_string(),
+            work: Work::new(&job.block, self.blockchain.params.pow, start..end),
+        };
+
+        let json = serde_json::to_string(&assignment).expect("can jsonify assignment");
+        self.publish(
+            self.topics.coop.clone(),
+            "coop_assignment",
+            Some(assignment.job_id),
+            json,
+        );
+    }
+
+    // Tell coordinators whether this node accepts work assignments.
+    pub fn announce_volunteer(&mut self) {
+        let volunteer = CoopVolunteer::new(
+            &self.keys,
+            self.payout.address.clone(),
+            self.coop.volunteering,
+        );
+
+        let json = serde_json::to_string(&volunteer).expect("can jsonify volunteer");
+        self.publish(self.topics.coop.clone(), "coop_volunteer", None, json);
+    }
+
+    // Swap some outbound peers for peers from other subnets.
+    pub fn rotate_peers(&mut self) {
+        let (added, removed) = self.peers.rotate();
+
+        for peer in removed {
+            self.peer_actions.push_back(PeerAction::Disconnect(peer));
+        }
+        for peer in added {
+            self.peer_actions.push_back(PeerAction::Dial(peer));
+        }
+    }
+
+    // Dial the outbound peers the selector picks among the candidates.
+    fn dial_selected(&mut self) {
+        for peer in self.peers.select() {
+            self.peer_actions.push_back(PeerAction::Dial(peer));
+        }
+    }
+
+    // Join the DHT through the `bootstrap` nodes and the peers of the routing table
+    // saved to `routing_file`, saving the table there from now on.
+    pub fn start_discovery(&mut self, bootstrap: &[(PeerId, Multiaddr)], routing_file: String) {
+        let saved = discovery::load_routing_table(&routing_file);
+        for (peer, address) in bootstrap.iter().cloned().chain(saved) {
+            if peer != self.peer_id {
+                self.kademlia.add_address(&peer, address);
+            }
+        }
+
+        self.bootstrap = bootstrap.to_vec();
+        self.routing_file = Some(routing_file);
+        self.refresh_discovery();
+    }
+
+    // Warn if the node saw neither a new block nor a peer message for the stale tip timeout,
+    // then try to reach the network again: dial the bootstrap peers, look the node up in the DHT
+    // and ask the connected peers for the blocks past the tip.
+    pub fn check_stale_tip(&mut self) {
+        let idle = self.last_activity.elapsed();
+        if idle < self.health.stale_timeout {
+            return;
+        }
+        // Recovery is only tried again after another timeout.
+        self.last_activity = Instant::now();
+
+        let height = self.blockchain.chain.len() - 1;
+        println!(
+            "warning: no new block or peer message for {}s, the tip at block {} may be stale",
+            idle.as_secs(),
+            height
+        );
+        for (peer, address) in self.bootstrap.clone() {
+            self.kademlia.add_address(&peer, address);
+            self.peer_actions.push_back(PeerAction::Dial(peer));
+        }
+        self.refresh_discovery();
+
+        let peers: Vec<PeerId> = self.gossipsub.all_peers().map(|(peer, _)| *peer).collect();
+        match peers.split_first() {
+            Some((peer, others)) => self.start_download(*peer, others.to_vec()),
+            None => println!("no peers to ask for their tips"),
+        }
+    }
+
+    // Look the node up in the DHT, which fills the routing table with the peers close to it,
+    // and save the table.
+    pub fn refresh_discovery(&mut self) {
+        if self.routing_file.is_none() {
+            return;
+        }
+        // There is nobody to ask until a peer connects or is discovered.
+        let _ = self.kademlia.bootstrap();
+        self.save_routing_table();
+    }
+
+    fn save_routing_table(&mut self) {
+        let Some(path) = self.routing_file.clone() else {
+            return;
+        };
+
+        let mut entries = Vec::new();
+        for bucket in self.kademlia.kbuckets() {
+            for entry in bucket.iter() {
+                entries.push(RoutingEntry {
+                    peer: entry.node.key.preimage().to_string(),
+                    addresses: entry
+                        .node
+                        .value
+                        .iter()
+                        .map(|address| address.to_string())
+                        .collect(),
+                });
+            }
+        }
+        discovery::save_routing_table(&path, &entries);
+    }
+
+    // Hand the swarm the next dial or disconnect, see `peer_actions`, and note the addresses
+    // the node listens on. Neither action goes to a handler, so they fit any handler event `E`.
+    fn poll_actions<E>(
+        &mut self,
+        _cx: &mut Context,
+        params: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<E, ()>> {
+        let addresses = || {
+            params
+                .listened_addresses()
+                .chain(params.external_addresses().map(|record| record.addr))
+        };
+        if !addresses().eq(self.listen_addresses.iter().cloned()) {
+            self.listen_addresses = addresses().collect();
+        }
+
+        match self.peer_actions.pop_front() {
+            Some(PeerAction::Dial(peer_id)) => Poll::Ready(NetworkBehaviourAction::DialPeer {
+                peer_id,
+                condition: DialPeerCondition::Disconnected,
+            }),
+            Some(PeerAction::Disconnect(peer_id)) => {
+                Poll::Ready(NetworkBehaviourAction::CloseConnection {
+                    peer_id,
+                    connection: CloseConnection::All,
+                })
+            }
+            None => Poll::Pending,
+        }
+    }
+
+    // Handle a gossip message, recording it and injecting faults first if enabled.
+    pub fn handle_gossip(&mut self, msg: GossipMessage) {
+        self.session.record(&msg);
+
+        for msg in self.chaos.inbound(msg) {
+            self.receive(msg);
+        }
+    }
+
+    // Handle the delayed gossip messages that are due.
+    pub fn release_delayed(&mut self) {
+        for msg in self.chaos.release() {
+            self.receive(msg);
+        }
+    }
+
+    fn receive(&mut self, msg: GossipMessage) {
+        let (peer, size) = (msg.source.to_string(), msg.data.len());
+
+        let outcome = self.update_chain(|behaviour| behaviour.handle_message(msg));
+        self.trace.inbound(peer, size, outcome);
     }
+
+    // Run `update` and follow up on the blocks it added to the chain.
+    pub fn update_chain<T>(&mut self, update: impl FnOnce(&mut Self) -> T) -> T {
+        let height = self.blockchain.chain.len();
+        let tip = self.blockchain.chain.last().map(|block| block.header.hash);
+        // Only kept for subscribers, to tell them what the update changed.
+        let previous = (self.events.receiver_count() > 0).then(|| self.blockchain.chain.clone());
+
+        let result = update(self);
+        self.connect_orphans();
+        self.update_checkpoints();
+        self.update_epochs();
+
+        let new_tip = self.blockchain.chain.last().map(|block| block.header.hash);
+        if new_tip != tip && !self.mempool.is_empty() {
+            self.update_mempool(height, tip);
+        }
+        if new_tip != tip
+            && let Some(previous) = previous
+        {
+            self.publish_chain_events(&previous);
+        }
+        // A download in progress announces the tip it reaches once it's done.
+        if new_tip != tip {
+            self.last_activity = Instant::now();
+        }
+        if new_tip != tip && self.download.is_none() {
+            self.announce_tip();
+        }
+        // A competing block took the place of the one being mined.
+        if self
+            .mining
+            .as_ref()
+            .is_some_and(|job| Some(job.previous_hash) != new_tip)
+        {
+            self.cancel_mining("the chain moved on");
+            self.pay_faucet();
+        }
+
+        if self.watching
+            && self.blockchain.chain.last().map(|block| &block.header.hash) != tip.as_ref()
+        {
+            self.print_new_blocks(height);
+        }
+
+        if self
+            .journal
+            .as_ref()
+            .is_some_and(|journal| journal.len() > MAX_JOURNAL_RECORDS)
+        {
+            self.compact_journal();
+        }
+
+        result
+    }
+
+    // Add a sample of the chain, the mempool, the peers and the miner to the metrics history.
+    pub fn sample_metrics(&mut self) {
+        let peers: HashSet<&PeerId> = self.mdns.discovered_nodes().collect();
+        self.metrics.record(
+            self.blockchain.chain.len() as u64 - 1,
+            self.mempool.len(),
+            peers.len(),
+            self.miner_settings.hashes(),
+        );
+    }
+
+    // Tell subscribers how the chain changed since it was `previous`.
+    fn publish_chain_events(&self, previous: &[Arc<block::Block>]) {
+        let chain = &self.blockchain.chain;
+        let fork = previous
+            .iter()
+            .zip(chain.iter())
+            .take_while(|(old, new)| old.header.hash == new.header.hash)
+            .count();
+
+        if let (Some(old_tip), Some(new_tip)) = (previous.last(), chain.last())
+            && fork < previous.len()
+        {
+            let _ = self.events.send(NodeEvent::ChainReorg {
+                fork_height: fork as u64,
+                old_tip: old_tip.header.hash,
+                new_tip: new_tip.header.hash,
+                disconnected: (previous.len() - fork) as u64,
+            });
+        }
+        for block in chain[fork..].iter() {
+            let _ = self.events.send(NodeEvent::NewBlock {
+                block: block.clone(),
+            });
+        }
+    }
+
+    // Drop pending transactions included in the blocks added since the chain had `height`
+    // blocks up to `tip`, or in any block if the chain was replaced, then the ones the chain
+    // doesn't accept anymore, e.g. since their sender spent the coins elsewhere.
+    fn update_mempool(&mut self, height: usize, tip: Option<Hash256>) {
+        let chain = &self.blockchain.chain;
+        let extended = height > 0 && chain.get(height - 1).map(|block| block.header.hash) == tip;
+        let added = if extended {
+            &chain[height..]
+        } else {
+            &chain[..]
+        };
+        self.mempool
+            .remove_included(added.iter().map(|block| block.as_ref()));
+
+        let blockchain = &self.blockchain;
+        self.mempool.retain(|transactions| {
+            blockchain
+                .test_accept_at_tip(transactions)
+                .iter()
+                .all(|(_, passed)| *passed)
+        });
+    }
+
+    // Key this node signs its transactions with, the one its peer id and default payout address
+    // are derived from.
+    pub fn signing_keypair(&self) -> Option<ed25519_dalek::Keypair> {
+        match &self.keys {
+            identity::Keypair::Ed25519(keypair) => {
+                ed25519_dalek::Keypair::from_bytes(&keypair.encode()).ok()
+            }
+            _ => None,
+        }
+    }
+
+    // Sign `transaction` if it is unsigned and spends from this node's address, and sign for its
+    // sponsor if this node's address sponsors it and didn't sign yet.
+    pub fn sign_own(&self, transaction: &mut Transaction) {
+        let Some(keypair) = self.signing_keypair() else {
+            return;
+        };
+        let address = Address::from_public_key(&keypair.public);
+
+        if transaction.signature.is_empty() && address == transaction.sender {
+            transaction.sign(&keypair);
+        }
+        if let Some(sponsor) = &transaction.sponsor
+            && sponsor.signature.is_empty()
+            && sponsor.address == address
+        {
+            transaction.sign_as_sponsor(&keypair);
+        }
+    }
+
+    // Pending transactions for the next block, see `Mempool::take_for_block`.
+    pub fn pending_transactions(&self) -> Vec<Transaction> {
+        self.mempool
+            .take_for_block(&self.blockchain.state, MAX_BLOCK_TRANSACTIONS)
+    }
+
+    // Nonce of the next transaction of `address`, after its pending ones.
+    pub fn next_nonce(&self, address: &Address) -> u64 {
+        self.mempool
+            .iter()
+            .filter(|transaction| transaction.sender == *address)
+            .map(|transaction| transaction.nonce + 1)
+            .fold(self.blockchain.state.next_nonce(address.as_str()), u64::max)
+    }
+
+    // Check `transaction` against the chain and keep it for the next blocks, returning its id.
+    // Subscribers are told about it.
+    pub fn submit_transaction(&mut self, transaction: Transaction) -> Result<Hash256, String> {
+        let failed: Vec<&str> = self
+            .blockchain
+            .test_accept(&transaction)
+            .into_iter()
+            .filter(|(_, passed)| !passed)
+            .map(|(check, _)| check)
+            .collect();
+        if !failed.is_empty() {
+            return Err(format!("transaction fails {} checks", failed.join(", ")));
+        }
+
+        let id = self.mempool.insert(transaction.clone())?;
+        let _ = self.events.send(NodeEvent::NewTransaction {
+            id,
+            transaction: Box::new(transaction),
+        });
+        Ok(id)
+    }
+
+    // Check the transactions of `package` against the chain together and keep them for the next
+    // blocks, returning the ids of the ones that weren't pending yet.
+    pub fn submit_package(&mut self, package: TransactionPackage) -> Result<Vec<Hash256>, String> {
+        package.check()?;
+        let failed: Vec<&str> = self
+            .blockchain
+            .test_accept_package(&package.transactions)
+            .into_iter()
+            .filter(|(_, passed)| !passed)
+            .map(|(check, _)| check)
+            .collect();
+        if !failed.is_empty() {
+            return Err(format!("package fails {} checks", failed.join(", ")));
+        }
+
+        let transactions = package.transactions.clone();
+        let ids = self.mempool.insert_package(package)?;
+        for transaction in transactions {
+            let id = transaction.id();
+            if ids.contains(&id) {
+                let _ = self.events.send(NodeEvent::NewTransaction {
+                    id,
+                    transaction: Box::new(transaction),
+                });
+            }
+        }
+        Ok(ids)
+    }
+
+    // Commit to `anchor` with a transaction from this node's address, mined with the next
+    // blocks of its chain.
+    pub fn submit_anchor(&mut self, anchor: Anchor) -> Result<Hash256, String> {
+        let address = peer_address(&self.peer_id);
+        let mut transaction = Transaction::new(address.clone(), address.clone(), Amount::ZERO);
+        transaction.nonce = self.next_nonce(&address);
+        transaction.condition = Some(Condition::Anchor(anchor));
+        self.sign_own(&mut transaction);
+
+        let json = serde_json::to_string(&transaction).expect("can jsonify transaction");
+        let id = self.submit_transaction(transaction)?;
+        self.broadcast_transaction("transaction", id, json);
+        Ok(id)
+    }
+
+    // Ask `peer` for its chain, which replaces the local one if it is better.
+    pub fn request_chain(&mut self, peer: &PeerId) {
+        self.chain_sync.send_request(peer, ChainRequest {});
+    }
+
+    // Download the blocks of `peer` the local chain is missing from it and `others`, unless a
+    // download is running already. Its headers are asked for first, starting from the last block
+    // both chains share.
+    pub fn start_download(&mut self, peer: PeerId, others: Vec<PeerId>) {
+        if let Some(download) = self.download.as_mut() {
+            // A download resumed after a restart waits for peers to ask for its blocks.
+            if download.resumed && download.peers().is_empty() {
+                let (height, tip) = download.progress();
+                println!("resuming download of blocks {} to {}", height + 1, tip);
+                download.add_peers(iter::once(peer).chain(others));
+                self.request_blocks();
+            }
+            return;
+        }
+
+        self.download = Some(BlockDownload::new(peer, others));
+        let locator = download::locator(&self.blockchain.chain);
+        self.header_sync
+            .send_request(&peer, HeadersRequest { locator });
+    }
+
+    // Resume the download saved to `download_file` before a restart, if the chain still holds
+    // the block it started from, and save the next ones there.
+    pub fn resume_download(&mut self, download_file: String) {
+        if let Some(saved) = download::load_download(&download_file) {
+            match BlockDownload::resume(saved, &self.blockchain) {
+                Some(download) if !download.is_done() => self.download = Some(download),
+                _ => download::remove_download(&download_file),
+            }
+        }
+        self.download_file = Some(download_file);
+    }
+
+    fn stop_download(&mut self, peer: &PeerId, reason: &str) {
+        println!("stopping download from {}: {}", peer, reason);
+        self.finish_download();
+    }
+
+    // Take the download out, it finished or can't go on.
+    fn finish_download(&mut self) -> Option<BlockDownload> {
+        if let Some(path) = &self.download_file {
+            download::remove_download(path);
+        }
+        self.download.take()
+    }
+
+    // Carry on without `peer`, the download dropped it and put its batches back in line.
+    fn drop_download_peer(&mut self, peer: &PeerId, reason: &str) {
+        println!("dropping {} from the download: {}", peer, reason);
+        match self.download.as_ref() {
+            Some(download) if download.peers().is_empty() => {
+                self.stop_download(peer, "no peers left")
+            }
+            _ => self.request_blocks(),
+        }
+    }
+
+    // Take in the headers of the download and ask for the next ones, or for the blocks once
+    // they are all in and have more work than the local blocks after the ancestor.
+    fn handle_headers_response(&mut self, peer: &PeerId, response: HeadersResponse) {
+        let Some(download) = self
+            .download
+            .as_mut()
+            .filter(|download| download.peer == *peer)
+        else {
+            return;
+        };
+
+        if download.ancestor().is_none() {
+            // No headers means the peer has no block after the local tip.
+            let ancestor = match response.headers.first() {
+                Some(header) => self.blockchain.get_block_by_hash(&header.previous_hash),
+                None => self.blockchain.chain.last(),
+            };
+            match ancestor {
+                Some(ancestor) => download.set_ancestor(ancestor),
+                None => return self.stop_download(peer, "its headers don't connect"),
+            }
+        }
+        if let Err(err) = download.add_headers(response.headers, self.blockchain.params.pow) {
+            return self.stop_download(peer, &err);
+        }
+
+        if let Some(locator) = download.next_locator() {
+            println!(
+                "received headers up to {} of {} from {}",
+                download.tip(),
+                response.tip,
+                peer
+            );
+            self.header_sync
+                .send_request(peer, HeadersRequest { locator });
+            return;
+        }
+
+        let ancestor = download.ancestor().expect("the ancestor is set") as usize;
+        let local = self.blockchain.chain.get(ancestor..).unwrap_or_default();
+        if download.work() <= work::cumulative_work(local) {
+            println!("synced with {}, its chain has no more work", peer);
+            self.finish_download();
+            return;
+        }
+
+        println!(
+            "downloading blocks {} to {} from {}",
+            ancestor + 1,
+            download.tip(),
+            peer
+        );
+        // Blocks of a fork replace the local ones after the ancestor only once they are all in.
+        if ancestor + 1 < self.blockchain.chain.len() {
+            download.branch = self.blockchain.check_fork(ancestor as u64);
+        }
+        if let (Some(path), Some(saved)) = (&self.download_file, download.saved()) {
+            download::save_download(path, &saved);
+        }
+        self.best_known_height = self.best_known_height.max(download.tip());
+        self.request_blocks();
+    }
+
+    // Ask for as many batches of the download as it has room for.
+    fn request_blocks(&mut self) {
+        let Some(download) = self.download.as_mut() else {
+            return;
+        };
+        while let Some((peer, request)) = download.next_request() {
+            let id = self.block_sync.send_request(&peer, request);
+            download.requested(id, peer, request);
+        }
+    }
+
+    // Connect the batches of the download that are next in line, or check and stage them if they
+    // belong to a fork, then ask for more.
+    fn handle_blocks_response(&mut self, peer: &PeerId, id: &RequestId, response: BlocksResponse) {
+        let Some(download) = self.download.as_mut() else {
+            return;
+        };
+        match download.downloaded(id, response) {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(err) => return self.drop_download_peer(peer, &err),
+        }
+
+        while let Some(blocks) = self.download.as_mut().and_then(BlockDownload::next_batch) {
+            for block in blocks.iter() {
+                self.relay_log.record(&block.header.hash, &peer.to_string());
+            }
+            if let Some(download) = self.download.as_mut()
+                && let Some(branch) = download.branch.as_mut()
+            {
+                if let Err(err) = self.blockchain.extend_fork(branch, blocks) {
+                    let peer = download.peer;
+                    return self.stop_download(&peer, &err);
+                }
+                continue;
+            }
+
+            for block in blocks {
+                // Gossip may have delivered it in the meantime.
+                if self
+                    .blockchain
+                    .get_block_by_hash(&block.header.hash)
+                    .is_some()
+                {
+                    continue;
+                }
+
+                let height = self.blockchain.chain.len();
+                self.blockchain.try_to_add_a_block(block);
+                if self.blockchain.chain.len() == height {
+                    let peer = self
+                        .download
+                        .as_ref()
+                        .map_or(*peer, |download| download.peer);
+                    return self.stop_download(&peer, &format!("block {} is invalid", height));
+                }
+            }
+        }
+
+        self.request_blocks();
+        let Some(download) = self.download.as_mut() else {
+            return;
+        };
+        let (height, tip) = download.progress();
+        if !download.is_done() {
+            println!(
+                "downloaded blocks up to {} of {} from {} peers",
+                height,
+                tip,
+                download.peers().len()
+            );
+            return;
+        }
+
+        println!("synced blocks up to {} from {}", height, download.peer);
+        let mut download = self.finish_download().expect("the download is running");
+        if let Some(branch) = download.branch.take() {
+            match self.blockchain.fork_chain(branch) {
+                Ok(chain) => self.adopt_chain(chain),
+                Err(err) => println!("can't load the blocks of the fork: {}", err),
+            }
+        }
+        // The transactions of the peer the node turned away before may spend from these blocks.
+        self.reconcile_mempool(&download.peer);
+        // Peers went on mining while the node was down.
+        if download.resumed
+            && let Some((peer, others)) = download.peers().split_first()
+        {
+            self.start_download(*peer, others.to_vec());
+        }
+    }
+
+    // Add the parent of an orphan or the announced tip `peer` sent, which may be an orphan
+    // itself. Only blocks some orphan waits for or that were asked for, and that prove their
+    // work are taken.
+    fn handle_parent_response(
+        &mut self,
+        peer: &PeerId,
+        request_id: &RequestId,
+        response: ParentResponse,
+    ) {
+        let tip = self.tip_requests.remove(request_id);
+        let Some(block) = response.block else {
+            println!("{} doesn't have the block asked for", peer);
+            return;
+        };
+        if !(self.orphans.is_parent(&block.header.hash) || tip == Some(block.header.hash))
+            || self.blockchain.params.pow.engine().hash(&block.header) != block.header.hash
+            || !block.is_mined(self.blockchain.block_work())
+        {
+            println!(
+                "ignoring block {} from {}, it wasn't asked for",
+                block.header.index, peer
+            );
+            return;
+        }
+
+        let hash = block.header.hash;
+        self.relay_log.record(&hash, &peer.to_string());
+        if self.add_block(peer, block) == "accepted" {
+            self.seen.insert(hash);
+        }
+    }
+
+    // Send `peer` a sketch of the mempool, so it tells which transactions either side misses.
+    pub fn reconcile_mempool(&mut self, peer: &PeerId) {
+        let sketch = MempoolSketch::new(self.mempool.ids());
+        self.mempool_sync
+            .send_request(peer, ReconcileRequest::Sketch { sketch });
+    }
+
+    // The pending ids in the buckets that differ from the sketch of the peer, or the pending
+    // transactions it asked for.
+    fn reconcile_response(&self, request: ReconcileRequest) -> ReconcileResponse {
+        match request {
+            ReconcileRequest::Sketch { sketch } => {
+                let buckets = MempoolSketch::new(self.mempool.ids()).differing(&sketch);
+                let ids = self
+                    .mempool
+                    .ids()
+                    .filter(|id| buckets.contains(&reconcile::bucket(id)))
+                    .copied()
+                    .collect();
+                ReconcileResponse::Ids { ids }
+            }
+            ReconcileRequest::Fetch { mut ids } => {
+                ids.truncate(MAX_FETCH);
+                ReconcileResponse::Transactions {
+                    units: self.mempool.units_with(&ids),
+                }
+            }
+        }
+    }
+
+    // Fetch the transactions `peer` has pending that the node has neither pending nor on its
+    // chain, and keep the ones that pass the checks. They aren't relayed, the peers of the node
+    // learn them by reconciling in turn.
+    fn handle_reconcile_response(&mut self, peer: &PeerId, response: ReconcileResponse) {
+        match response {
+            ReconcileResponse::Ids { ids } => {
+                let missing: Vec<Hash256> = ids
+                    .into_iter()
+                    .filter(|id| {
+                        !self.mempool.contains(id) && self.blockchain.find_transaction(id).is_none()
+                    })
+                    .collect();
+                if missing.is_empty() {
+                    return;
+                }
+
+                println!("fetching {} transactions from {}", missing.len(), peer);
+                for ids in missing.chunks(MAX_FETCH) {
+                    self.mempool_sync
+                        .send_request(peer, ReconcileRequest::Fetch { ids: ids.to_vec() });
+                }
+            }
+            ReconcileResponse::Transactions { units } => {
+                let mut added = 0;
+                for mut transactions in units {
+                    let result = match transactions.len() {
+                        1 => self.submit_transaction(transactions.remove(0)).map(|_| 1),
+                        _ => self
+                            .submit_package(TransactionPackage { transactions })
+                            .map(|ids| ids.len()),
+                    };
+                    match result {
+                        Ok(count) => added += count,
+                        Err(err) => println!("can't add transaction from {}: {}", peer, err),
+                    }
+                }
+                if added > 0 {
+                    println!(
+                        "reconciled mempool with {}: {} transactions added",
+                        peer, added
+                    );
+                }
+            }
+        }
+    }
+
+    // Adopt the chain `peer` sent if it is valid and has more work than the local one.
+    fn handle_chain_response(&mut self, peer: &PeerId, response: ChainResponse) {
+        match response.blocks.last() {
+            Some(tip) => println!(
+                "chain of {} blocks up to {} from {}",
+                response.blocks.len(),
+                tip.header.hash,
+                peer
+            ),
+            None => println!("empty chain from {}", peer),
+        }
+        for block in response.blocks.iter() {
+            self.relay_log.record(&block.header.hash, &peer.to_string());
+        }
+        self.adopt_chain(response.blocks);
+    }
+
+    // Reorganize onto `chain` if it is valid and has more work. Chains forking off deeper than
+    // `max_reorg_depth` blocks are held back for an operator to approve.
+    fn adopt_chain(&mut self, chain: Vec<Arc<block::Block>>) {
+        let chain = match self.blockchain.choose_chain(chain.clone()) {
+            Ok(chain) => chain,
+            Err(ChainError::NotEnoughWork) => return,
+            Err(ChainError::TooDeep { depth, max }) => return self.hold_reorg(chain, depth, max),
+            Err(err) => return println!("keeping the local chain: {}", err),
+        };
+        self.switch_chain(chain);
+    }
+
+    // Keep `chain` for an operator to approve, unless a chain with more work waits already.
+    fn hold_reorg(&mut self, chain: Vec<Arc<block::Block>>, depth: u64, max: u64) {
+        if self.pending_reorg.as_ref().is_some_and(|pending| {
+            work::cumulative_work(&pending.chain) >= work::cumulative_work(&chain)
+        }) {
+            return;
+        }
+
+        let tip = chain.last().expect("chains aren't empty").header.clone();
+        println!(
+            "not switching to chain {} up to block {}, it disconnects {} blocks and at most {} \
+             may be: approve with the approve_reorg admin call",
+            tip.hash, tip.index, depth, max
+        );
+        self.pending_reorg = Some(PendingReorg {
+            tip: tip.hash,
+            height: tip.index,
+            depth,
+            chain,
+        });
+    }
+
+    // Switch to the chain held back by `hold_reorg` that ends in `tip`, however deep it forks
+    // off, if it is still valid and has more work. Returns the number of blocks disconnected.
+    pub fn approve_reorg(&mut self, tip: &Hash256) -> Result<u64, String> {
+        let pending = self
+            .pending_reorg
+            .take_if(|pending| pending.tip == *tip)
+            .ok_or_else(|| format!("there is no pending reorg to {}", tip))?;
+
+        let chain = self
+            .blockchain
+            .choose_chain_at_any_depth(pending.chain)
+            .map_err(|err| err.to_string())?;
+        let depth = self.blockchain.reorg_depth(&chain);
+        self.switch_chain(chain);
+        Ok(depth)
+    }
+
+    // Reorganize onto `chain`, which `choose_chain` returned. The transactions of the blocks
+    // disconnected go back to the mempool, unless the new blocks include them or conflict with
+    // them, see `update_mempool`.
+    fn switch_chain(&mut self, chain: Vec<Arc<block::Block>>) {
+        let height = self.blockchain.chain.len();
+        let disconnected = self.blockchain.reorganize(chain);
+        if disconnected.is_empty() {
+            return;
+        }
+        println!(
+            "reorganized at block {}: disconnected {} blocks, connected {}",
+            height - disconnected.len(),
+            disconnected.len(),
+            self.blockchain.chain.len() - (height - disconnected.len())
+        );
+        for transaction in disconnected
+            .iter()
+            .flat_map(|block| block.body.transactions.iter())
+            .filter(|transaction| !coinbase::is_coinbase(transaction))
+        {
+            let _ = self.mempool.insert(transaction.clone());
+        }
+    }
+
+    // Publish a gossip message, tracing it if propagation tracing is enabled.
+    pub fn publish(
+        &mut self,
+        topic: Topic,
+        kind: &'static str,
+        hash: Option<Hash256>,
+        data: impl Into<Vec<u8>>,
+    ) {
+        let data = data.into();
+        self.trace.outbound(kind, hash, data.len());
+        match self.gossipsub.publish(topic, data) {
+            // Broadcasts nobody got are published again by the outbound queue.
+            Ok(_) | Err(PublishError::InsufficientPeers) => {}
+            Err(err) => println!("can't publish {}: {:?}", kind, err),
+        }
+    }
+
+    // Assemble an unmined block of `transactions` on top of the chain.
+    pub fn next_block(&self, transactions: Vec<Transaction>) -> block::Block {
+        let latest_block = self
+            .blockchain
+            .chain
+            .last()
+            .expect("there is at least one block");
+
+        let mut block = block::Block::new(
+            latest_block.header.index + 1,
+            latest_block.header.hash,
+            transactions,
+        );
+        block.header.difficulty = self.blockchain.next_difficulty();
+        block
+    }
+
+    // Mine a locally assembled block in the background. `finish_mining` publishes it and adds it
+    // to the chain once it is found. A block still being mined is given up for it.
+    pub fn mine_block(&mut self, block: block::Block) {
+        self.start_mining(block, Vec::new());
+    }
+
+    // Mine `block` in the background, answering `faucet_payouts` once it is done.
+    pub fn start_mining(&mut self, mut block: block::Block, faucet_payouts: Vec<FaucetRequest>) {
+        let reward = self.blockchain.params.reward_at(block.header.index);
+        coinbase::apply_payout(&mut block, &self.payout, reward);
+        self.blockchain.commit_accumulator(&mut block);
+
+        self.cancel_mining("a new block is mined instead");
+        self.coop.cancel_search("a block of our own is mined");
+        self.mining_jobs += 1;
+        let sender = self.mined_sender.clone();
+        self.mining = Some(MiningJob::spawn(
+            self.mining_jobs,
+            block,
+            self.blockchain.params.pow,
+            self.searcher.clone(),
+            // The node may be shutting down, nobody waits for the block then.
+            move |mined| {
+                let _ = sender.send(mined);
+            },
+        ));
+        self.faucet_payouts = faucet_payouts;
+    }
+
+    // Give up the block being mined. The faucet requests it pays wait for the next block.
+    pub fn cancel_mining(&mut self, reason: &str) {
+        if let Some(job) = self.mining.take() {
+            job.cancel();
+            println!("stopped mining block {}: {}", job.id, reason);
+        }
+        let mut payouts = std::mem::take(&mut self.faucet_payouts);
+        payouts.append(&mut self.faucet_queue);
+        self.faucet_queue = payouts;
+    }
+
+    // Mine the waiting faucet requests the faucet has the coins for in a block of their own,
+    // unless a block is mined already, failing the others.
+    pub fn pay_faucet(&mut self) {
+        if self.mining.is_some() || self.faucet_queue.is_empty() {
+            return;
+        }
+
+        // Payouts all come from the faucet address.
+        let sender = self.faucet_queue[0].sender.clone();
+        let mut nonce = self.blockchain.state.next_nonce(sender.as_str());
+        let mut balance = self
+            .blockchain
+            .get_balance_at(sender.as_str(), self.blockchain.chain.len() as u64 - 1)
+            .unwrap_or_default();
+        let mut transactions = Vec::new();
+        let mut payouts = Vec::new();
+
+        // Requests past a full block wait for the next one.
+        let mut queue = std::mem::take(&mut self.faucet_queue).into_iter();
+        while transactions.len() < MAX_BLOCK_TRANSACTIONS {
+            let Some(request) = queue.next() else {
+                break;
+            };
+            let Some(left) = balance.checked_sub(request.amount) else {
+                println!(
+                    "faucet can't pay {}, it has {} left",
+                    request.receiver, balance
+                );
+                let _ = request.reply.send(Err("faucet is empty".to_string()));
+                continue;
+            };
+
+            let mut transaction = Transaction::new(
+                request.sender.clone(),
+                request.receiver.clone(),
+                request.amount,
+            );
+            transaction.nonce = nonce;
+            self.sign_own(&mut transaction);
+            if !transaction.is_signature_valid() {
+                println!("faucet can only pay from this node's address");
+                let _ = request
+                    .reply
+                    .send(Err("faucet can't sign payouts".to_string()));
+                continue;
+            }
+
+            println!("mining faucet payout to {}", request.receiver);
+            balance = left;
+            nonce += 1;
+            transactions.push(transaction);
+            payouts.push(request);
+        }
+        self.faucet_queue = queue.collect();
+
+        if !payouts.is_empty() {
+            let block = self.next_block(transactions);
+            self.start_mining(block, payouts);
+        }
+    }
+
+    // When the schedule asks for the next block, `None` while one is mined as it is only due
+    // once that one is done.
+    pub fn next_scheduled_block(&self) -> Option<tokio::time::Instant> {
+        match self.mining {
+            Some(_) => None,
+            None => self.scheduler.next_block_at(),
+        }
+    }
+
+    // Publish and add a block mined in the background, unless its job was given up since.
+    pub fn finish_mining(&mut self, mined: MinedBlock) {
+        if self.mining.as_ref().is_none_or(|job| job.id != mined.job) {
+            return;
+        }
+        self.mining = None;
+        self.scheduler.produced();
+        let faucet_payouts = std::mem::take(&mut self.faucet_payouts);
+
+        let added = mined.block.is_some_and(|block| {
+            let hash = block.header.hash;
+            self.relay_log.record(&hash, &self.peer_id.to_string());
+            if !self.header_relay {
+                self.broadcast_block(&block);
+            }
+            self.blockchain.try_to_add_a_block(block);
+            self.blockchain.chain.last().map(|block| block.header.hash) == Some(hash)
+        });
+
+        for request in faucet_payouts {
+            let result = if added {
+                Ok(request.amount)
+            } else {
+                Err("can't mine faucet payout".to_string())
+            };
+            let _ = request.reply.send(result);
+        }
+        self.pay_faucet();
+    }
+
+    // Publish a block and keep publishing it until a peer acknowledges it.
+    pub fn broadcast_block(&mut self, block: &block::Block) {
+        let json = serde_json::to_string(block).expect("can jsonify request");
+        self.broadcast(self.topics.block.clone(), "block", block.header.hash, json);
+    }
+
+    // Publish a transaction or package, `id` being the id of its first transaction, and keep
+    // publishing it until a peer acknowledges it.
+    pub fn broadcast_transaction(&mut self, kind: &'static str, id: Hash256, json: String) {
+        self.broadcast(self.topics.transaction.clone(), kind, id, json);
+    }
+
+    fn broadcast(&mut self, topic: Topic, kind: &'static str, hash: Hash256, json: String) {
+        if self
+            .outbound
+            .push(hash, topic.clone(), kind, json.clone().into_bytes())
+        {
+            self.publish(topic, kind, Some(hash), json);
+        }
+    }
+
+    // Publish again every broadcast that wasn't acknowledged yet. Volunteers announce
+    // themselves again too, for coordinators that joined since.
+    pub fn rebroadcast(&mut self) {
+        for (hash, topic, kind, data) in self.outbound.retry() {
+            self.publish(topic, kind, Some(hash), data);
+        }
+
+        if self.coop.volunteering {
+            self.announce_volunteer();
+        }
+    }
+
+    // Sign a checkpoint for every checkpoint height of the chain that doesn't have one yet.
+    fn update_checkpoints(&mut self) {
+        self.checkpoints.retain_chain(&self.blockchain.chain);
+
+        let tip = self.blockchain.chain.len() as u64 - 1;
+        for height in (CHECKPOINT_INTERVAL..=tip).step_by(CHECKPOINT_INTERVAL as usize) {
+            if self.checkpoints.contains(height) {
+                continue;
+            }
+            let (Some(block), Some(state)) = (
+                self.blockchain.get_block_by_height(height),
+                self.blockchain.get_state_at(height),
+            ) else {
+                continue;
+            };
+
+            let mut checkpoint = Checkpoint::new(block, &state);
+            checkpoint.signer = self.keys.public().into_protobuf_encoding();
+            checkpoint.signature = self
+                .keys
+                .sign(&checkpoint.signing_data())
+                .expect("can sign checkpoint");
+            self.checkpoints.add(checkpoint);
+        }
+    }
+
+    // Sign a summary of every epoch the chain completed that doesn't have one yet.
+    fn update_epochs(&mut self) {
+        self.epochs.retain_chain(&self.blockchain.chain);
+
+        let tip = self.blockchain.chain.len() as u64 - 1;
+        for epoch in 1..=tip / EPOCH_LENGTH {
+            if self.epochs.contains(epoch) {
+                continue;
+            }
+            let Some(mut summary) = self
+                .blockchain
+                .get_state_at(epoch * EPOCH_LENGTH)
+                .and_then(|state| EpochSummary::new(&self.blockchain.chain, epoch, &state))
+            else {
+                continue;
+            };
+
+            summary.signer = self.keys.public().into_protobuf_encoding();
+            summary.signature = self
+                .keys
+                .sign(&summary.signing_data())
+                .expect("can sign epoch summary");
+            self.epochs.add(summary);
+        }
+    }
+
+    // Check epoch summaries received from `peer` and print how far they take the chain. Only the
+    // first summary of a chain can be linked to the local genesis block, later ones are taken
+    // as linked to the summary before them.
+    fn print_epoch_response(&self, peer: &PeerId, response: &EpochResponse) {
+        let Some(first) = response.summaries.first() else {
+            println!("{} has no epoch summaries", peer);
+            return;
+        };
+
+        let signed = response.summaries.iter().all(|summary| {
+            identity::PublicKey::from_protobuf_encoding(&summary.signer).is_ok_and(|key| {
+                PeerId::from(key.clone()) == *peer
+                    && key.verify(&summary.signing_data(), &summary.signature)
+            })
+        });
+        if !signed {
+            println!("epoch summaries from {} are not signed by it", peer);
+            return;
+        }
+
+        let previous_hash = match first.epoch {
+            1 => self.blockchain.chain[0].header.hash,
+            _ => first.previous_hash,
+        };
+        if let Err(err) = epoch::verify_links(&response.summaries, previous_hash) {
+            println!("epoch summaries from {} are invalid: {}", peer, err);
+            return;
+        }
+
+        let last = response.summaries.last().unwrap_or(first);
+        println!(
+            "{} epoch summaries from {} up to height {}: {} (work {}, state root {}, {} txs)",
+            response.summaries.len(),
+            peer,
+            last.end,
+            last.hash,
+            last.work,
+            last.state_root,
+            response
+                .summaries
+                .iter()
+                .map(|summary| summary.transactions)
+                .sum::<u64>()
+        );
+    }
+
+    // Check a history proof received from `peer` and compare the work it proves with the local
+    // chain. The proof has to sample at least `samples` blocks.
+    fn print_history_response(&self, peer: &PeerId, response: &HistoryResponse, samples: usize) {
+        let Some(proof) = &response.proof else {
+            println!("{} has no history to prove", peer);
+            return;
+        };
+
+        match history::verify(proof, &self.blockchain.params, samples) {
+            Ok((height, work)) => {
+                let local = self.blockchain.work();
+                println!(
+                    "chain of {} at height {} has work {} ({} blocks sampled), local work {}",
+                    peer, height, work, samples, local
+                );
+            }
+            Err(err) => println!("history proof from {} is invalid: {}", peer, err),
+        }
+    }
+
+    fn checkpoint_response(&self, height: Option<u64>) -> CheckpointResponse {
+        let checkpoint = self.checkpoints.latest(height.unwrap_or(u64::MAX)).cloned();
+        let state = checkpoint
+            .as_ref()
+            .and_then(|checkpoint| self.blockchain.get_state_at(checkpoint.height));
+        let blocks = match &checkpoint {
+            Some(checkpoint) => self
+                .blockchain
+                .iter_blocks(checkpoint.height + 1..)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+
+        CheckpointResponse {
+            checkpoint,
+            state,
+            blocks,
+        }
+    }
+
+    // Print a one line summary of every block past the first `height` ones.
+    fn print_new_blocks(&self, height: usize) {
+        let chain = &self.blockchain.chain;
+        if chain.len() < 2 {
+            return;
+        }
+        let start = height.clamp(1, chain.len() - 1);
+
+        for (previous, block) in chain[start - 1..].iter().zip(chain[start..].iter()) {
+            let miner = block
+                .body
+                .transactions
+                .first()
+                .filter(|transaction| coinbase::is_coinbase(transaction))
+                .map_or("-", |transaction| transaction.receiver.as_str());
+            let interval = block
+                .header
+                .timestamp
+                .saturating_sub(previous.header.timestamp) as f64
+                / 1000.0;
+
+            println!(
+                "#{} {} txs: {} miner: {} interval: {:.1}s",
+                block.header.index,
+                block.header.hash,
+                block.body.transactions.len(),
+                miner,
+                interval
+            );
+        }
+    }
+}
+
+// Check a checkpoint received from `peer` and print what it covers.
+fn print_checkpoint_response(peer: &PeerId, response: &CheckpointResponse) {
+    let (Some(checkpoint), Some(state)) = (&response.checkpoint, &response.state) else {
+        println!("{} has no checkpoint", peer);
+        return;
+    };
+
+    let signed = identity::PublicKey::from_protobuf_encoding(&checkpoint.signer).is_ok_and(|key| {
+        PeerId::from(key.clone()) == *peer
+            && key.verify(&checkpoint.signing_data(), &checkpoint.signature)
+    });
+    if !signed {
+        println!("checkpoint from {} is not signed by it", peer);
+        return;
+    }
+    if state.height != checkpoint.height || state.root() != checkpoint.state_root {
+        println!("checkpoint from {} doesn't match its state", peer);
+        return;
+    }
+
+    let mut previous_hash = &checkpoint.hash;
+    for (height, block) in (checkpoint.height + 1..).zip(response.blocks.iter()) {
+        if block.header.index != height || &block.header.previous_hash != previous_hash {
+            println!("blocks from {} don't extend its checkpoint", peer);
+            return;
+        }
+        previous_hash = &block.header.hash;
+    }
+
+    println!(
+        "checkpoint from {} at height {}: {} (state root {}, {} blocks on top)",
+        peer,
+        checkpoint.height,
+        checkpoint.hash,
+        checkpoint.state_root,
+        response.blocks.len()
+    );
+}
+
+pub fn get_list_peers(swarm: &Swarm<BlockchainBehaviour>) -> Vec<PeerId> {
+    println!("discovered peers");
+
+    let nodes = swarm.behaviour().mdns.discovered_nodes();
+    let mut unique_peers = HashSet::new();
+
+    for peer in nodes {
+        unique_peers.insert(peer);
+    }
+
+    unique_peers.into_iter().copied().collect()
+}
+
+// Queue a faucet request, paid along with the others waiting once no block is mined, replying
+// once its block is mined and on the chain.
+pub fn handle_faucet_request(request: FaucetRequest, swarm: &mut Swarm<BlockchainBehaviour>) {
+    let behaviour = swarm.behaviour_mut();
+    behaviour.faucet_queue.push(request);
+    behaviour.pay_faucet();
+}
+
+pub fn handle_rpc_call(call: RpcCall, swarm: &mut Swarm<BlockchainBehaviour>) {
+    let result = rpc::call(&call.method, &call.params, swarm.behaviour_mut());
+    let _ = call.reply.send(result);
+}
+
+// Produce the block the schedule asks for, unless one is mined already.
+pub fn handle_scheduled_block(swarm: &mut Swarm<BlockchainBehaviour>) {
+    let behaviour = swarm.behaviour_mut();
+    if behaviour.mining.is_some() {
+        return;
+    }
+    let transactions = behaviour.pending_transactions();
+    let block = behaviour.next_block(transactions);
+
+    if block.body.transactions.is_empty() {
+        let latest_timestamp = behaviour
+            .blockchain
+            .chain
+            .last()
+            .map_or(0, |block| block.header.timestamp);
+        if !behaviour.scheduler.is_empty_block_due(latest_timestamp) {
+            behaviour.scheduler.skipped();
+            return;
+        }
+    }
+
+    behaviour.update_chain(|behaviour| behaviour.mine_block(block));
 }
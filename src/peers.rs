@@ -0,0 +1,128 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use libp2p::{Multiaddr, PeerId, multiaddr::Protocol};
+
+// Most peers this node gossips with directly.
+pub const MAX_OUTBOUND_PEERS: usize = 8;
+// How often a share of the outbound peers is replaced.
+pub const ROTATION_INTERVAL: Duration = Duration::from_secs(600);
+// One in this many outbound peers is replaced on every rotation.
+const ROTATION_FRACTION: usize = 4;
+
+// `PeerSelector` Chooses outbound peers among the discovered ones, spreading them over as many
+// subnets as possible so an attacker running many nodes in one network can't take over all of
+// this node's connections.
+#[derive(Debug, Default)]
+pub struct PeerSelector {
+    // Subnet of every discovered peer.
+    candidates: HashMap<PeerId, String>,
+    outbound: HashSet<PeerId>,
+}
+
+impl PeerSelector {
+    pub fn add_candidate(&mut self, peer: PeerId, address: &Multiaddr) {
+        self.candidates.insert(peer, subnet(address));
+    }
+
+    // Forget an expired peer. Returns `true` if it was an outbound peer.
+    pub fn remove_candidate(&mut self, peer: &PeerId) -> bool {
+        self.candidates.remove(peer);
+        self.outbound.remove(peer)
+    }
+
+    pub fn is_outbound(&self, peer: &PeerId) -> bool {
+        self.outbound.contains(peer)
+    }
+
+    // Fill the free outbound slots, each time picking a peer from the least represented
+    // subnet. Returns the peers added.
+    pub fn select(&mut self) -> Vec<PeerId> {
+        self.select_excluding(&HashSet::new())
+    }
+
+    // Replace a share of the outbound peers, starting with the most crowded subnets. Returns
+    // the peers added and the ones removed.
+    pub fn rotate(&mut self) -> (Vec<PeerId>, Vec<PeerId>) {
+        if self.candidates.len() <= self.outbound.len() {
+            return (Vec::new(), Vec::new());
+        }
+
+        let count = (self.outbound.len() / ROTATION_FRACTION).max(1);
+        let mut removed = HashSet::new();
+        for _ in 0..count {
+            let counts = self.subnet_counts();
+            let crowded = self
+                .outbound
+                .iter()
+                .max_by_key(|peer| (counts[&self.candidates[peer]], peer.to_bytes()))
+                .copied();
+
+            if let Some(peer) = crowded {
+                self.outbound.remove(&peer);
+                removed.insert(peer);
+            }
+        }
+
+        let added = self.select_excluding(&removed);
+        (added, removed.into_iter().collect())
+    }
+
+    fn select_excluding(&mut self, excluded: &HashSet<PeerId>) -> Vec<PeerId> {
+        let mut added = Vec::new();
+
+        while self.outbound.len() < MAX_OUTBOUND_PEERS {
+            let counts = self.subnet_counts();
+            let next = self
+                .candidates
+                .iter()
+                .filter(|(peer, _)| !self.outbound.contains(peer) && !excluded.contains(peer))
+                .min_by_key(|(peer, subnet)| {
+                    (
+                        counts.get(*subnet).copied().unwrap_or_default(),
+                        peer.to_bytes(),
+                    )
+                })
+                .map(|(peer, _)| *peer);
+
+            let Some(peer) = next else {
+                break;
+            };
+            self.outbound.insert(peer);
+            added.push(peer);
+        }
+
+        added
+    }
+
+    // Number of outbound peers in every subnet.
+    fn subnet_counts(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for peer in self.outbound.iter() {
+            *counts.entry(self.candidates[peer].clone()).or_default() += 1;
+        }
+
+        counts
+    }
+}
+
+// The /16 of an IPv4 address or the /32 of an IPv6 address.
+fn subnet(address: &Multiaddr) -> String {
+    for protocol in address.iter() {
+        match protocol {
+            Protocol::Ip4(ip) => {
+                let octets = ip.octets();
+                return format!("{}.{}.0.0/16", octets[0], octets[1]);
+            }
+            Protocol::Ip6(ip) => {
+                let segments = ip.segments();
+                return format!("{:x}:{:x}::/32", segments[0], segments[1]);
+            }
+            _ => {}
+        }
+    }
+
+    address.to_string()
+}
@@ -0,0 +1,67 @@
+// Mempool reconciliation between peers, so transactions accepted on either side of a network
+// split reach the other once it heals without every transaction being broadcast again. Whenever
+// a peer joins the transactions topic, e.g. as it connected again, the node sends it a sketch of
+// its mempool: the ids of the pending transactions split into buckets by their first byte, each
+// bucket summed up by the XOR of its ids. The peer answers with its ids in the buckets that
+// differ, and the node fetches the ones it has neither pending nor on its chain. Both sides do
+// this, so each learns what the other has. Nodes reconcile again after downloading blocks, which
+// transactions turned away before may spend from. Transactions learned that way aren't gossiped
+// on.
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::{hash::Hash256, transaction::Transaction};
+
+// Buckets of a sketch.
+pub const SKETCH_BUCKETS: usize = 64;
+// Most transactions fetched with one request.
+pub const MAX_FETCH: usize = 1000;
+
+// `MempoolSketch` Summary of the ids of a mempool, the XOR of the ids in every bucket.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MempoolSketch {
+    pub buckets: Vec<Hash256>,
+}
+
+impl MempoolSketch {
+    pub fn new<'a>(ids: impl IntoIterator<Item = &'a Hash256>) -> Self {
+        let mut buckets = vec![Hash256::ZERO; SKETCH_BUCKETS];
+        for id in ids {
+            let digest = &mut buckets[bucket(id)].0;
+            for (byte, id_byte) in digest.iter_mut().zip(id.0) {
+                *byte ^= id_byte;
+            }
+        }
+        MempoolSketch { buckets }
+    }
+
+    // Buckets whose ids differ from the ones of `other`. Every bucket differs from a sketch of
+    // another size.
+    pub fn differing(&self, other: &MempoolSketch) -> Vec<usize> {
+        (0..SKETCH_BUCKETS)
+            .filter(|position| self.buckets.get(*position) != other.buckets.get(*position))
+            .collect()
+    }
+}
+
+// Bucket of the sketch `id` falls into.
+pub fn bucket(id: &Hash256) -> usize {
+    id.0[0] as usize % SKETCH_BUCKETS
+}
+
+// `ReconcileRequest` A sketch of the mempool of the sender, or the transactions it wants.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReconcileRequest {
+    Sketch { sketch: MempoolSketch },
+    Fetch { ids: Vec<Hash256> },
+}
+
+// `ReconcileResponse` The pending ids in the buckets that differ from the sketch, or the
+// transactions asked for. Transactions of a package come together with the rest of the package.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReconcileResponse {
+    Ids { ids: Vec<Hash256> },
+    Transactions { units: Vec<Vec<Transaction>> },
+}
@@ -0,0 +1,100 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::OpenOptions,
+    io::Write,
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    hash::Hash256,
+    schema::{self, Migration},
+};
+
+// Migrations of the relay log, see `schema`.
+const MIGRATIONS: &[Migration] = &[schema::add_version_tag];
+
+// `RelayRecord` Which peer first delivered a block and when.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayRecord {
+    pub hash: Hash256,
+    pub peer: String,
+    // Milliseconds since the epoch.
+    pub received_at: u64,
+}
+
+// `RelayLog` Chain of custody of the blocks this node received, appended to `path` as one JSON
+// record per line.
+#[derive(Debug)]
+pub struct RelayLog {
+    path: String,
+    records: HashMap<Hash256, RelayRecord>,
+}
+
+impl RelayLog {
+    pub fn load(path: &str) -> Self {
+        let records = schema::load_lines(path, MIGRATIONS)
+            .unwrap_or_else(|err| {
+                println!("can't load relay log from {}: {}", path, err);
+                Vec::new()
+            })
+            .into_iter()
+            .filter_map(|record| serde_json::from_value::<RelayRecord>(record).ok())
+            .map(|record| (record.hash, record))
+            .collect();
+
+        RelayLog {
+            path: path.to_string(),
+            records,
+        }
+    }
+
+    pub fn get(&self, hash: &Hash256) -> Option<&RelayRecord> {
+        self.records.get(hash)
+    }
+
+    // Record that `peer` delivered the block with `hash`, unless another peer did first.
+    pub fn record(&mut self, hash: &Hash256, peer: &str) {
+        if self.records.contains_key(hash) {
+            return;
+        }
+
+        let record = RelayRecord {
+            hash: *hash,
+            peer: peer.to_string(),
+            received_at: Utc::now().timestamp_millis() as u64,
+        };
+        self.append(&record);
+        self.records.insert(*hash, record);
+    }
+
+    // Number of blocks every peer delivered first. A single peer delivering nearly everything
+    // hints at an eclipse attempt.
+    pub fn first_deliveries(&self) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for record in self.records.values() {
+            *counts.entry(record.peer.as_str()).or_default() += 1;
+        }
+
+        counts
+    }
+
+    fn append(&self, record: &RelayRecord) {
+        let json = serde_json::to_string(record).expect("can jsonify relay record");
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| {
+                if file.metadata()?.len() == 0 {
+                    writeln!(file, "{}", schema::header_line(MIGRATIONS.len() as u32))?;
+                }
+                writeln!(file, "{}", json)
+            });
+
+        if let Err(err) = result {
+            println!("can't save relay record to {}: {}", self.path, err);
+        }
+    }
+}
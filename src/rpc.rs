@@ -0,0 +1,602 @@
+// JSON-RPC 2.0 server for tools that would rather not parse the REPL output, enabled with
+// `--rpc-port <port>`. It listens on every address once there are API keys and only on localhost
+// as long as anyone may call it, unless `--rpc-bind <address>` names one. Calls are `POST`ed to
+// `/` as `{"jsonrpc": "2.0", "method": ..., "params": [...], "id": ...}` and answered about the
+// first hosted chain. The methods are listed in
+// `METHODS` and described at `GET /openapi.json`, see `openapi`.
+//
+// Calls need an API key of the right scope, see `auth`. Requests count against the quotas of
+// `http`. The server also answers the probes of `health`.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    spawn,
+    sync::{mpsc, oneshot},
+};
+
+use crate::{
+    auth::{ApiKeys, Scope},
+    health,
+    http::{self, RateLimiter, RateLimits},
+    models::mempool::TransactionPackage,
+    models::transaction::Transaction,
+    openapi,
+    p2p::BlockchainBehaviour,
+};
+
+// Error codes of the JSON-RPC 2.0 specification.
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const INTERNAL_ERROR: i64 = -32603;
+// Code of calls the node turned down, e.g. a transaction it didn't accept.
+pub const REJECTED: i64 = -32000;
+// Code of calls without an API key good for them.
+pub const UNAUTHORIZED: i64 = -32001;
+
+// `RpcMethod` A method of the server as `openapi` describes it. Parameters and results name a
+// JSON type or one of the schemas of `openapi`.
+#[derive(Debug, Clone, Copy)]
+pub struct RpcMethod {
+    pub name: &'static str,
+    pub summary: &'static str,
+    pub scope: Scope,
+    pub params: &'static [(&'static str, &'static str)],
+    pub result: &'static str,
+}
+
+pub const METHODS: &[RpcMethod] = &[
+    RpcMethod {
+        name: "get_chain_length",
+        summary: "Number of blocks including the genesis block",
+        scope: Scope::Read,
+        params: &[],
+        result: "integer",
+    },
+    RpcMethod {
+        name: "get_latest_block",
+        summary: "Block at the tip of the chain",
+        scope: Scope::Read,
+        params: &[],
+        result: "Block",
+    },
+    RpcMethod {
+        name: "get_block_by_index",
+        summary: "Block at a height of the chain",
+        scope: Scope::Read,
+        params: &[("index", "integer")],
+        result: "Block",
+    },
+    RpcMethod {
+        name: "get_median_time_past",
+        summary: "Median timestamp of the 11 blocks up to a height, the tip unless given",
+        scope: Scope::Read,
+        params: &[("index", "integer")],
+        result: "integer",
+    },
+    RpcMethod {
+        name: "get_balance",
+        summary: "Balance of an address at a height, the tip unless given",
+        scope: Scope::Read,
+        params: &[("address", "string"), ("height", "integer")],
+        result: "Amount",
+    },
+    RpcMethod {
+        name: "send_transaction",
+        summary: "Submit a signed transaction, returning its id",
+        scope: Scope::Wallet,
+        params: &[("transaction", "Transaction")],
+        result: "Hash",
+    },
+    RpcMethod {
+        name: "send_package",
+        summary: "Submit signed transactions that depend on each other, returning the new ids",
+        scope: Scope::Wallet,
+        params: &[("transactions", "TransactionList")],
+        result: "HashList",
+    },
+    RpcMethod {
+        name: "get_metrics_history",
+        summary: "Metrics sampled over the last seconds given, every sample kept unless given",
+        scope: Scope::Read,
+        params: &[("window", "integer")],
+        result: "MetricsHistory",
+    },
+    RpcMethod {
+        name: "get_readiness",
+        summary: "Readiness checks of the node, as `GET /ready` runs them",
+        scope: Scope::Read,
+        params: &[],
+        result: "Readiness",
+    },
+    RpcMethod {
+        name: "get_pending_reorg",
+        summary: "Chain held back as it forks off deeper than max_reorg_depth, if any",
+        scope: Scope::Admin,
+        params: &[],
+        result: "PendingReorg",
+    },
+    RpcMethod {
+        name: "approve_reorg",
+        summary: "Switch to the pending reorg ending in a tip, returning the blocks disconnected",
+        scope: Scope::Admin,
+        params: &[("tip", "Hash")],
+        result: "integer",
+    },
+    RpcMethod {
+        name: "list_api_keys",
+        summary: "Names and scopes of the API keys",
+        scope: Scope::Admin,
+        params: &[],
+        result: "ApiKeyList",
+    },
+    RpcMethod {
+        name: "create_api_key",
+        summary: "Create an API key for a scope: read, wallet or admin",
+        scope: Scope::Admin,
+        params: &[("name", "string"), ("scope", "string")],
+        result: "ApiKey",
+    },
+    RpcMethod {
+        name: "revoke_api_key",
+        summary: "Revoke an API key",
+        scope: Scope::Admin,
+        params: &[("name", "string")],
+        result: "boolean",
+    },
+];
+
+// `RpcSettings` Where the JSON-RPC server listens.
+#[derive(Debug, Clone)]
+pub struct RpcSettings {
+    pub bind: IpAddr,
+    pub port: u16,
+}
+
+impl RpcSettings {
+    // Serve on `port` at `bind`, unless given every address if calls need one of `keys` and
+    // localhost otherwise.
+    pub fn new(port: u16, bind: Option<IpAddr>, keys: &ApiKeys) -> Self {
+        let bind = bind.unwrap_or_else(|| match keys.is_empty() {
+            true => IpAddr::from([127, 0, 0, 1]),
+            false => IpAddr::from([0, 0, 0, 0]),
+        });
+
+        RpcSettings { bind, port }
+    }
+}
+
+// `RpcError` A JSON-RPC error object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+// `RpcCall` A call to run on the event loop, which owns the chain. The outcome is sent back
+// through `reply`.
+#[derive(Debug)]
+pub struct RpcCall {
+    pub method: String,
+    pub params: Value,
+    pub reply: oneshot::Sender<Result<Value, RpcError>>,
+}
+
+// Accept calls until the listener fails, forwarding the ones within `limits` and allowed by
+// `keys` to `calls`. Calls managing the API keys are answered right away, they don't touch the
+// chain.
+pub async fn serve(
+    settings: RpcSettings,
+    limits: RateLimits,
+    keys: Arc<Mutex<ApiKeys>>,
+    calls: mpsc::UnboundedSender<RpcCall>,
+) {
+    let listener = match TcpListener::bind((settings.bind, settings.port)).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            println!("can't start rpc server on port {}: {}", settings.port, err);
+            return;
+        }
+    };
+    println!("rpc server listening on port {}", settings.port);
+
+    let limiter = Arc::new(Mutex::new(RateLimiter::new(limits)));
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let calls = calls.clone();
+                let (limiter, keys) = (limiter.clone(), keys.clone());
+                spawn(async move {
+                    if let Err(err) = handle(stream, peer, calls, limiter, keys).await {
+                        println!("rpc request from {} failed: {}", peer, err);
+                    }
+                });
+            }
+            Err(err) => println!("rpc server can't accept connection: {}", err),
+        }
+    }
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    calls: mpsc::UnboundedSender<RpcCall>,
+    limiter: Arc<Mutex<RateLimiter>>,
+    keys: Arc<Mutex<ApiKeys>>,
+) -> std::io::Result<()> {
+    let allowed = limiter
+        .lock()
+        .expect("rpc rate limiter isn't poisoned")
+        .check_ip(peer.ip());
+    if let Err(wait) = allowed {
+        return http::write_rate_limited(&mut stream, wait).await;
+    }
+
+    let request = http::read_request(&mut stream).await?;
+    let allowed = limiter
+        .lock()
+        .expect("rpc rate limiter isn't poisoned")
+        .check_token(request.token());
+    if let Err(wait) = allowed {
+        return http::write_rate_limited(&mut stream, wait).await;
+    }
+
+    if request.method == "GET" {
+        match request.path.as_str() {
+            "/openapi.json" => {
+                return http::write_response(&mut stream, "200 OK", &[], &openapi::spec()).await;
+            }
+            "/health" => {
+                let body = serde_json::json!({ "status": "alive" });
+                return http::write_response(&mut stream, "200 OK", &[], &body).await;
+            }
+            "/ready" => {
+                let (status, body) = ready(&calls).await;
+                return http::write_response(&mut stream, status, &[], &body).await;
+            }
+            _ => {}
+        }
+    }
+    if request.path != "/" {
+        let body = serde_json::json!({ "error": "404 Not Found" });
+        return http::write_response(&mut stream, "404 Not Found", &[], &body).await;
+    }
+    if request.method != "POST" {
+        let body = serde_json::json!({ "error": "405 Method Not Allowed" });
+        return http::write_response(&mut stream, "405 Method Not Allowed", &[], &body).await;
+    }
+
+    let body = match serde_json::from_slice(&request.body) {
+        Ok(call) => answer(call, request.token(), &keys, &calls).await,
+        Err(err) => response(
+            Value::Null,
+            Err(RpcError::new(
+                PARSE_ERROR,
+                format!("can't parse call: {}", err),
+            )),
+        ),
+    };
+    http::write_response(&mut stream, "200 OK", &[], &body).await
+}
+
+// Run `call` made with the API key `key` and build its response.
+async fn answer(
+    call: Value,
+    key: Option<&str>,
+    keys: &Mutex<ApiKeys>,
+    calls: &mpsc::UnboundedSender<RpcCall>,
+) -> Value {
+    let id = call.get("id").cloned().unwrap_or(Value::Null);
+    let method = match call.get("method").and_then(Value::as_str) {
+        Some(method) if call.get("jsonrpc") == Some(&Value::from("2.0")) => method,
+        _ => {
+            let err = RpcError::new(INVALID_REQUEST, "expected a JSON-RPC 2.0 call");
+            return response(id, Err(err));
+        }
+    };
+
+    let params = call.get("params").cloned().unwrap_or(Value::Null);
+
+    {
+        let scope = scope(method);
+        let mut keys = keys.lock().expect("api keys aren't poisoned");
+        if let Err(err) = keys.authorize(key, scope) {
+            return response(id, Err(RpcError::new(UNAUTHORIZED, err)));
+        }
+        if matches!(
+            method,
+            "list_api_keys" | "create_api_key" | "revoke_api_key"
+        ) {
+            return response(id, call_admin(method, &params, &mut keys));
+        }
+    }
+
+    let (reply, outcome) = oneshot::channel();
+    let call = RpcCall {
+        method: method.to_string(),
+        params,
+        reply,
+    };
+    if calls.send(call).is_err() {
+        return response(
+            id,
+            Err(RpcError::new(INTERNAL_ERROR, "node is shutting down")),
+        );
+    }
+
+    match outcome.await {
+        Ok(result) => response(id, result),
+        Err(_) => response(id, Err(RpcError::new(INTERNAL_ERROR, "call was dropped"))),
+    }
+}
+
+// Status and body answering `GET /ready`, the readiness checks run on the event loop.
+async fn ready(calls: &mpsc::UnboundedSender<RpcCall>) -> (&'static str, Value) {
+    let (reply, outcome) = oneshot::channel();
+    let call = RpcCall {
+        method: "get_readiness".to_string(),
+        params: Value::Null,
+        reply,
+    };
+    if calls.send(call).is_err() {
+        return (
+            "503 Service Unavailable",
+            serde_json::json!({ "ready": false, "error": "node is shutting down" }),
+        );
+    }
+
+    match outcome.await {
+        Ok(Ok(readiness)) if readiness["ready"] == Value::Bool(true) => ("200 OK", readiness),
+        Ok(Ok(readiness)) => ("503 Service Unavailable", readiness),
+        _ => (
+            "503 Service Unavailable",
+            serde_json::json!({ "ready": false, "error": "call was dropped" }),
+        ),
+    }
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => serde_json::json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err(err) => serde_json::json!({
+            "jsonrpc": "2.0",
+            "error": { "code": err.code, "message": err.message },
+            "id": id,
+        }),
+    }
+}
+
+// Scope of the API keys allowed to call `method`. Unknown methods are left to fail later.
+fn scope(method: &str) -> Scope {
+    METHODS
+        .iter()
+        .find(|described| described.name == method)
+        .map_or(Scope::Read, |described| described.scope)
+}
+
+// Run the call `method` managing the API keys with `params` against `keys`.
+fn call_admin(method: &str, params: &Value, keys: &mut ApiKeys) -> Result<Value, RpcError> {
+    let name = || {
+        param(params, 0, "name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, "expected a key name"))
+    };
+
+    match method {
+        "list_api_keys" => Ok(keys
+            .iter()
+            .map(|api_key| serde_json::json!({ "name": api_key.name, "scope": api_key.scope }))
+            .collect()),
+        "create_api_key" => {
+            let scope = param(params, 1, "scope")
+                .and_then(Value::as_str)
+                .and_then(Scope::parse)
+                .ok_or_else(|| {
+                    RpcError::new(INVALID_PARAMS, "expected a scope: read, wallet or admin")
+                })?;
+            keys.create(name()?, scope)
+                .map(|api_key| to_json(&api_key))
+                .map_err(|err| RpcError::new(REJECTED, err))
+        }
+        "revoke_api_key" => keys
+            .revoke(name()?)
+            .map(|_| Value::Bool(true))
+            .map_err(|err| RpcError::new(REJECTED, err)),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("there is no method {}", method),
+        )),
+    }
+}
+
+// Run the call `method` with `params` against the chain of `behaviour`.
+pub fn call(
+    method: &str,
+    params: &Value,
+    behaviour: &mut BlockchainBehaviour,
+) -> Result<Value, RpcError> {
+    let blockchain = &behaviour.blockchain;
+    let tip = blockchain.chain.len() as u64 - 1;
+
+    match method {
+        "get_chain_length" => Ok(Value::from(blockchain.chain.len())),
+        "get_latest_block" => Ok(to_json(blockchain.chain.last().expect("chain has genesis"))),
+        "get_block_by_index" => {
+            let index = param(params, 0, "index")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "expected a block index"))?;
+            blockchain
+                .chain
+                .get(index as usize)
+                .map(to_json)
+                .ok_or_else(|| RpcError::new(REJECTED, format!("there is no block {}", index)))
+        }
+        "get_median_time_past" => {
+            let height = match param(params, 0, "index") {
+                None | Some(Value::Null) => tip,
+                Some(index) => index
+                    .as_u64()
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "expected a block index"))?,
+            };
+            blockchain
+                .median_time_past(height)
+                .map(Value::from)
+                .ok_or_else(|| RpcError::new(REJECTED, format!("there is no block {}", height)))
+        }
+        "get_balance" => {
+            let address = param(params, 0, "address")
+                .and_then(Value::as_str)
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "expected an address"))?;
+            let height = match param(params, 1, "height") {
+                None | Some(Value::Null) => tip,
+                Some(height) => height
+                    .as_u64()
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "expected a height"))?,
+            };
+            blockchain
+                .get_balance_at(address, height)
+                .map(|balance| to_json(&balance))
+                .ok_or_else(|| RpcError::new(REJECTED, format!("there is no block {}", height)))
+        }
+        "get_metrics_history" => {
+            let window = match param(params, 0, "window") {
+                None | Some(Value::Null) => Duration::from_secs(u64::MAX),
+                Some(seconds) => seconds
+                    .as_u64()
+                    .map(Duration::from_secs)
+                    .ok_or_else(|| RpcError::new(INVALID_PARAMS, "expected seconds"))?,
+            };
+            Ok(to_json(
+                &behaviour.metrics.window(window).collect::<Vec<_>>(),
+            ))
+        }
+        "get_readiness" => Ok(to_json(&health::readiness(behaviour))),
+        "get_pending_reorg" => Ok(to_json(&behaviour.pending_reorg)),
+        "approve_reorg" => {
+            let tip = param(params, 0, "tip")
+                .and_then(Value::as_str)
+                .and_then(|tip| tip.parse().ok())
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "expected a block hash"))?;
+            behaviour
+                .update_chain(|behaviour| behaviour.approve_reorg(&tip))
+                .map(Value::from)
+                .map_err(|err| RpcError::new(REJECTED, err))
+        }
+        "send_transaction" => {
+            let transaction: Transaction = param(params, 0, "transaction")
+                .cloned()
+                .map(serde_json::from_value)
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "expected a transaction"))?
+                .map_err(|err| {
+                    RpcError::new(INVALID_PARAMS, format!("can't parse transaction: {}", err))
+                })?;
+
+            let json = serde_json::to_string(&transaction).expect("can jsonify transaction");
+            let id = behaviour
+                .submit_transaction(transaction)
+                .map_err(|err| RpcError::new(REJECTED, err))?;
+            behaviour.broadcast_transaction("transaction", id, json);
+            Ok(to_json(&id))
+        }
+        "send_package" => {
+            let transactions: Vec<Transaction> = param(params, 0, "transactions")
+                .cloned()
+                .map(serde_json::from_value)
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "expected transactions"))?
+                .map_err(|err| {
+                    RpcError::new(INVALID_PARAMS, format!("can't parse transactions: {}", err))
+                })?;
+
+            let package = TransactionPackage { transactions };
+            let json = serde_json::to_string(&package).expect("can jsonify package");
+            let id = package
+                .transactions
+                .first()
+                .map(|transaction| transaction.id());
+            let ids = behaviour
+                .submit_package(package)
+                .map_err(|err| RpcError::new(REJECTED, err))?;
+            if let Some(id) = id {
+                behaviour.broadcast_transaction("package", id, json);
+            }
+            Ok(to_json(&ids))
+        }
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("there is no method {}", method),
+        )),
+    }
+}
+
+fn to_json(value: &impl Serialize) -> Value {
+    serde_json::to_value(value).expect("can jsonify rpc result")
+}
+
+// Parameter `name` at `position`, given either by position or by name.
+fn param<'a>(params: &'a Value, position: usize, name: &str) -> Option<&'a Value> {
+    match params {
+        Value::Array(values) => values.get(position),
+        Value::Object(values) => values.get(name),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(method: &str) -> Value {
+        serde_json::json!({ "jsonrpc": "2.0", "method": method, "params": [], "id": 1 })
+    }
+
+    fn error_code(response: &Value) -> Option<i64> {
+        response["error"]["code"].as_i64()
+    }
+
+    #[tokio::test]
+    async fn calls_without_a_good_key_are_turned_down_before_reaching_the_node() {
+        let path = std::env::temp_dir().join(format!("api-keys-{}.json", std::process::id()));
+        let path = path.to_str().expect("path is utf-8");
+        let (calls, mut received) = mpsc::unbounded_channel();
+
+        let keys = Mutex::new(ApiKeys::load(path));
+        let response = answer(call("list_api_keys"), None, &keys, &calls).await;
+        assert_eq!(error_code(&response), Some(UNAUTHORIZED));
+
+        let reader = keys
+            .lock()
+            .expect("api keys aren't poisoned")
+            .create("reader", Scope::Read)
+            .expect("can create key");
+        for (method, key) in [
+            ("get_chain_length", None),
+            ("get_chain_length", Some("unknown")),
+            ("send_transaction", Some(reader.key.as_str())),
+            ("list_api_keys", Some(reader.key.as_str())),
+        ] {
+            let response = answer(call(method), key, &keys, &calls).await;
+            assert_eq!(error_code(&response), Some(UNAUTHORIZED), "{}", method);
+        }
+        assert!(received.try_recv().is_err());
+
+        std::fs::remove_file(path).expect("can remove api keys");
+    }
+}
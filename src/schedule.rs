@@ -0,0 +1,138 @@
+// When the node produces blocks by itself, besides the ones asked for with `create b`. Chosen
+// with `--block-schedule on-demand|continuous|interval:<seconds>` and changed while running
+// with the `schedule` command. With `--empty-block-heartbeat <seconds>` scheduled blocks without
+// transactions are skipped, unless the chain hasn't grown for that long.
+
+use std::{fmt, str::FromStr};
+
+use chrono::Utc;
+use tokio::time::{Duration, Instant};
+
+// How long to wait after skipping an empty block before checking for transactions again.
+const EMPTY_RECHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// `BlockSchedule` How often blocks are produced.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum BlockSchedule {
+    // Only when asked to.
+    #[default]
+    OnDemand,
+    // One after another, as fast as they can be mined.
+    Continuous,
+    // One every interval of wall-clock time, e.g. for demo chains.
+    Interval(Duration),
+}
+
+impl FromStr for BlockSchedule {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "on-demand" => Ok(BlockSchedule::OnDemand),
+            "continuous" => Ok(BlockSchedule::Continuous),
+            _ => match value.strip_prefix("interval:").map(str::parse::<u64>) {
+                Some(Ok(seconds)) if seconds > 0 => {
+                    Ok(BlockSchedule::Interval(Duration::from_secs(seconds)))
+                }
+                _ => Err(format!(
+                    "{} is not on-demand, continuous or interval:<seconds>",
+                    value
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for BlockSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockSchedule::OnDemand => write!(f, "on-demand"),
+            BlockSchedule::Continuous => write!(f, "continuous"),
+            BlockSchedule::Interval(interval) => write!(f, "interval:{}", interval.as_secs()),
+        }
+    }
+}
+
+// `BlockScheduler` Tracks when the next scheduled block is due.
+#[derive(Debug)]
+pub struct BlockScheduler {
+    pub schedule: BlockSchedule,
+    // Longest the chain may go without a block before an empty one is produced, empty blocks
+    // are never skipped if unset.
+    pub heartbeat: Option<Duration>,
+    // When the last block was mined or a scheduled one skipped, or the schedule was set.
+    last_block: Instant,
+    // Whether the last scheduled block was skipped for being empty.
+    skipped: bool,
+}
+
+impl Default for BlockScheduler {
+    fn default() -> Self {
+        BlockScheduler::new(BlockSchedule::default(), None)
+    }
+}
+
+impl BlockScheduler {
+    pub fn new(schedule: BlockSchedule, heartbeat: Option<Duration>) -> Self {
+        BlockScheduler {
+            schedule,
+            heartbeat,
+            last_block: Instant::now(),
+            skipped: false,
+        }
+    }
+
+    pub fn set(&mut self, schedule: BlockSchedule) {
+        *self = BlockScheduler::new(schedule, self.heartbeat);
+    }
+
+    // When the next block should be produced, `None` if only on demand.
+    pub fn next_block_at(&self) -> Option<Instant> {
+        match self.schedule {
+            BlockSchedule::OnDemand => None,
+            BlockSchedule::Continuous if self.skipped => {
+                Some(self.last_block + EMPTY_RECHECK_INTERVAL)
+            }
+            BlockSchedule::Continuous => Some(self.last_block),
+            BlockSchedule::Interval(interval) => Some(self.last_block + interval),
+        }
+    }
+
+    // Whether a block without transactions should be produced on top of a block with
+    // `latest_timestamp`.
+    pub fn is_empty_block_due(&self, latest_timestamp: u64) -> bool {
+        let Some(heartbeat) = self.heartbeat else {
+            return true;
+        };
+
+        let now = Utc::now().timestamp_millis() as u64;
+        now.saturating_sub(latest_timestamp) >= heartbeat.as_millis() as u64
+    }
+
+    // Record that mining a block finished, whether or not it made it onto the chain. The next
+    // block is due from then on, so blocks taking longer to mine than the interval don't pile up.
+    pub fn produced(&mut self) {
+        self.last_block = Instant::now();
+        self.skipped = false;
+    }
+
+    // Record that a scheduled block was skipped for being empty.
+    pub fn skipped(&mut self) {
+        self.last_block = Instant::now();
+        self.skipped = true;
+    }
+}
+
+impl fmt::Display for BlockScheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.heartbeat {
+            Some(heartbeat) => write!(
+                f,
+                "{}, empty blocks at most every {}s",
+                self.schedule,
+                heartbeat.as_secs()
+            ),
+            None => write!(f, "{}", self.schedule),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::models::hash::Hash256;
+
+// Number of recently seen items remembered.
+pub const SEEN_CACHE_SIZE: usize = 1024;
+
+// `SeenCache` Least recently used set of block hashes and transaction ids received from peers,
+// so items broadcast by several peers are only processed once.
+pub struct SeenCache {
+    capacity: usize,
+    // Least recently seen first.
+    order: VecDeque<Hash256>,
+    entries: HashSet<Hash256>,
+}
+
+impl SeenCache {
+    pub fn new(capacity: usize) -> Self {
+        SeenCache {
+            capacity,
+            order: VecDeque::with_capacity(capacity),
+            entries: HashSet::with_capacity(capacity),
+        }
+    }
+
+    // Record `id` as seen. Returns `true` if it wasn't seen before.
+    pub fn insert(&mut self, id: Hash256) -> bool {
+        if self.touch(id) {
+            return false;
+        }
+
+        if self.order.len() == self.capacity
+            && let Some(evicted) = self.order.pop_front()
+        {
+            self.entries.remove(&evicted);
+        }
+        self.entries.insert(id);
+        self.order.push_back(id);
+
+        true
+    }
+
+    // Mark `id` as recently seen if it was seen before. Returns whether it was.
+    pub fn touch(&mut self, id: Hash256) -> bool {
+        if !self.entries.contains(&id) {
+            return false;
+        }
+
+        if let Some(position) = self.order.iter().position(|seen| *seen == id) {
+            let id = self.order.remove(position).expect("position is in bounds");
+            self.order.push_back(id);
+        }
+        true
+    }
+}
@@ -0,0 +1,333 @@
+// Startup self-check of the serialization formats. Every network message and the canonical
+// encodings hashes and signatures are computed over must survive a round trip unchanged,
+// otherwise a struct change would silently split this node from the rest of the network.
+
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{
+    coop::{CoopAssignment, CoopResult, CoopVolunteer},
+    download::{BlocksRequest, BlocksResponse, HeadersRequest, HeadersResponse},
+    miner::{Work, WorkResult},
+    models::{
+        accumulator::Accumulator,
+        address::Address,
+        amount::Amount,
+        anchor::Anchor,
+        asset::AssetTransfer,
+        auxpow::AuxPow,
+        block::Block,
+        channel::{Channel, ChannelState},
+        checkpoint::Checkpoint,
+        coinbase::COINBASE_SENDER,
+        condition::Condition,
+        consensus::PowAlgorithm,
+        hash::Hash256,
+        htlc::{self, HashTimeLock},
+        mempool::TransactionPackage,
+        sponsor::Sponsor,
+        state::State,
+        transaction::Transaction,
+        work,
+    },
+    orphans::{ParentRequest, ParentResponse},
+    p2p::{
+        BroadcastAck, ChainRequest, ChainResponse, CheckpointRequest, CheckpointResponse,
+        TipAnnouncement,
+    },
+    reconcile::{MempoolSketch, ReconcileRequest, ReconcileResponse},
+};
+
+// Round-trip a sample of every message type, using every optional field.
+pub fn run() -> Result<(), String> {
+    let block = sample_block();
+    let transactions = block.body.transactions.clone();
+    let checkpoint = Checkpoint {
+        height: block.header.index,
+        hash: block.header.hash,
+        state_root: sample_state().root(),
+        signer: vec![1, 2, 3],
+        signature: vec![4, 5, 6],
+    };
+    let work = Work {
+        algorithm: PowAlgorithm::MemoryHard,
+        prefix: b"prefix".to_vec(),
+        suffix: b"suffix".to_vec(),
+        difficulty: 3,
+        start_nonce: 0,
+        end_nonce: u64::MAX,
+    };
+
+    let decoded = round_trip("block", &block)?;
+    canonical(
+        "block",
+        block.header.hash_data(),
+        decoded.header.hash_data(),
+    )?;
+
+    for transaction in transactions.iter() {
+        let decoded = round_trip("transaction", transaction)?;
+        canonical("transaction", transaction.id(), decoded.id())?;
+        canonical(
+            "transaction signing data",
+            transaction.signing_data(),
+            decoded.signing_data(),
+        )?;
+    }
+
+    let decoded = round_trip("checkpoint", &checkpoint)?;
+    canonical(
+        "checkpoint",
+        checkpoint.signing_data(),
+        decoded.signing_data(),
+    )?;
+
+    let decoded = round_trip("state", &sample_state())?;
+    canonical("state", sample_state().root(), decoded.root())?;
+
+    round_trip(
+        "chain response",
+        &ChainResponse {
+            blocks: vec![Arc::new(block.clone())],
+        },
+    )?;
+    round_trip("chain request", &ChainRequest {})?;
+    round_trip("blocks request", &BlocksRequest { from: 1, count: 2 })?;
+    round_trip(
+        "blocks response",
+        &BlocksResponse {
+            blocks: vec![Arc::new(block.clone())],
+        },
+    )?;
+    round_trip(
+        "parent request",
+        &ParentRequest {
+            hash: block.header.hash,
+        },
+    )?;
+    round_trip(
+        "parent response",
+        &ParentResponse {
+            block: Some(Arc::new(block.clone())),
+        },
+    )?;
+    round_trip(
+        "headers request",
+        &HeadersRequest {
+            locator: vec![block.header.hash],
+        },
+    )?;
+    round_trip(
+        "headers response",
+        &HeadersResponse {
+            headers: vec![block.header.clone()],
+            tip: 1,
+        },
+    )?;
+    round_trip(
+        "broadcast ack",
+        &BroadcastAck {
+            hash: block.header.hash,
+        },
+    )?;
+    round_trip(
+        "transaction package",
+        &TransactionPackage {
+            transactions: block.body.transactions.clone(),
+        },
+    )?;
+    round_trip(
+        "tip announcement",
+        &TipAnnouncement {
+            header: block.header.clone(),
+            work: work::Work::from_difficulty(block.header.difficulty),
+        },
+    )?;
+    let ids: Vec<Hash256> = transactions
+        .iter()
+        .map(|transaction| transaction.id())
+        .collect();
+    round_trip(
+        "mempool sketch",
+        &ReconcileRequest::Sketch {
+            sketch: MempoolSketch::new(&ids),
+        },
+    )?;
+    round_trip(
+        "mempool fetch",
+        &ReconcileRequest::Fetch { ids: ids.clone() },
+    )?;
+    round_trip("mempool ids", &ReconcileResponse::Ids { ids })?;
+    round_trip(
+        "mempool transactions",
+        &ReconcileResponse::Transactions {
+            units: vec![transactions.clone()],
+        },
+    )?;
+    round_trip("checkpoint request", &CheckpointRequest { height: Some(1) })?;
+    round_trip(
+        "checkpoint response",
+        &CheckpointResponse {
+            checkpoint: Some(checkpoint),
+            state: Some(sample_state()),
+            blocks: vec![Arc::new(block)],
+        },
+    )?;
+    round_trip(
+        "coop volunteer",
+        &CoopVolunteer {
+            address: address("address"),
+            available: true,
+            signer: vec![1; 36],
+            signature: vec![2; 64],
+        },
+    )?;
+    round_trip(
+        "coop assignment",
+        &CoopAssignment {
+            job_id: Hash256::digest("job"),
+            worker: "peer".to_string(),
+            work,
+        },
+    )?;
+    round_trip(
+        "coop result",
+        &CoopResult {
+            job_id: Hash256::digest("job"),
+            worker: "peer".to_string(),
+            result: WorkResult { nonce: Some(42) },
+        },
+    )?;
+
+    Ok(())
+}
+
+// Decode the JSON encoding of `value` and check that it encodes the same again. Values are
+// compared rather than text, since maps don't keep their order.
+fn round_trip<T: Serialize + DeserializeOwned>(name: &str, value: &T) -> Result<T, String> {
+    let json =
+        serde_json::to_string(value).map_err(|err| format!("can't encode {}: {}", name, err))?;
+    let decoded: T =
+        serde_json::from_str(&json).map_err(|err| format!("can't decode {}: {}", name, err))?;
+
+    let before = serde_json::to_value(value).expect("can jsonify value");
+    let after = serde_json::to_value(&decoded).expect("can jsonify value");
+    if before != after {
+        return Err(format!("{} changes after a JSON round trip", name));
+    }
+
+    Ok(decoded)
+}
+
+fn canonical<T: PartialEq>(name: &str, before: T, after: T) -> Result<(), String> {
+    if before != after {
+        return Err(format!(
+            "canonical encoding of {} changes after a JSON round trip",
+            name
+        ));
+    }
+
+    Ok(())
+}
+
+fn sample_block() -> Block {
+    let state = ChannelState {
+        channel_id: Hash256::digest("channel"),
+        sequence: 1,
+        balances: [Amount(10), Amount(20)],
+        signatures: [vec![3; 64], vec![4; 64]],
+    };
+    let conditions = [
+        Condition::Lock(HashTimeLock {
+            hashlock: htlc::hashlock("preimage"),
+            timelock: 10,
+            refund: address("refund"),
+        }),
+        Condition::Claim {
+            lock_id: Hash256::digest("lock"),
+            preimage: "preimage".to_string(),
+        },
+        Condition::Refund {
+            lock_id: Hash256::digest("lock"),
+        },
+        Condition::ChannelOpen(Channel { dispute_period: 5 }),
+        Condition::ChannelClose {
+            state: state.clone(),
+            cooperative: false,
+        },
+        Condition::ChannelContest { state },
+        Condition::ChannelSettle {
+            channel_id: Hash256::digest("channel"),
+        },
+        Condition::Anchor(Anchor {
+            chain_id: "dev".to_string(),
+            height: 7,
+            hash: Hash256::digest("anchored"),
+        }),
+    ];
+
+    let mut transactions = vec![Transaction::new(
+        address(COINBASE_SENDER),
+        address("miner"),
+        Amount(50),
+    )];
+    for condition in conditions {
+        let mut transaction = Transaction::new(address("sender"), address("receiver"), Amount(30));
+        transaction.nonce = 9;
+        transaction.fee = Amount(2);
+        transaction.assets = vec![AssetTransfer {
+            asset: "token".to_string(),
+            amount: 7,
+        }];
+        transaction.condition = Some(condition);
+        transaction.lock_time = Some(3);
+        transaction.expiry = Some(4);
+        transaction.sponsor = Some(Sponsor {
+            address: address("sponsor"),
+            public_key: vec![5; 32],
+            signature: vec![6; 64],
+        });
+        transaction.public_key = vec![7; 32];
+        transaction.signature = vec![8; 64];
+        transactions.push(transaction);
+    }
+
+    let mut block = Block::new(1, Hash256::digest("previous"), transactions);
+    block.header.timestamp = 1;
+    block.header.proof_of_work = 2;
+    block.header.accumulator = Accumulator {
+        roots: vec![Some(Hash256::digest("root")), None],
+        leaves: 2,
+    };
+    block.header.history = Accumulator {
+        roots: vec![Some(Hash256::digest("history"))],
+        leaves: 1,
+    };
+    block.header.aux_pow = Some(AuxPow {
+        parent_header: vec![1; 84],
+        merkle_branch: vec![Hash256::digest("sibling")],
+        merkle_index: 1,
+    });
+    block.header.hash = block.generate_block_hash();
+    block
+}
+
+fn sample_state() -> State {
+    State {
+        height: 1,
+        balances: HashMap::from([
+            (address("miner"), Amount(50)),
+            (address("receiver"), Amount(30)),
+        ]),
+        assets: HashMap::from([(
+            address("receiver"),
+            HashMap::from([("token".to_string(), 7)]),
+        )]),
+        ..State::default()
+    }
+}
+
+fn address(name: &str) -> Address {
+    Address::new(name).expect("sample addresses are valid")
+}
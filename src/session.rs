@@ -0,0 +1,155 @@
+// Recorded gossip sessions, for reproducing bugs seen on live networks offline.
+// `--record-session <path>` appends every inbound gossip message to `path`, `--replay-session
+// <path>` feeds a recorded session through a fresh node instead of joining the network.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+};
+
+use chrono::Utc;
+use libp2p::{PeerId, gossipsub::TopicHash};
+use serde::{Deserialize, Serialize};
+
+use crate::{gossip::GossipMessage, p2p::BlockchainBehaviour};
+
+// `SessionEntry` One line of a session file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionEntry {
+    // First entry of a recording. Replays take over the recorder's id, so messages addressed
+    // to it are handled the same way.
+    Start {
+        timestamp: u64,
+        peer_id: String,
+    },
+    Message {
+        // Milliseconds since the epoch.
+        timestamp: u64,
+        source: String,
+        topics: Vec<String>,
+        // Gossip is JSON, so the payload is kept as text.
+        data: String,
+    },
+}
+
+// `SessionRecorder` Appends inbound gossip to a session file, if recording is enabled.
+#[derive(Debug, Default)]
+pub struct SessionRecorder {
+    file: Option<File>,
+}
+
+impl SessionRecorder {
+    // Append the inbound gossip of the node with `peer_id` to `path`. Records nothing if the
+    // file can't be opened.
+    pub fn open(path: &str, peer_id: &PeerId) -> Self {
+        let mut recorder = match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => SessionRecorder { file: Some(file) },
+            Err(err) => {
+                println!("can't record session to {}: {}", path, err);
+                return SessionRecorder::default();
+            }
+        };
+        println!("recording session to {}", path);
+
+        recorder.append(&SessionEntry::Start {
+            timestamp: Utc::now().timestamp_millis() as u64,
+            peer_id: peer_id.to_string(),
+        });
+        recorder
+    }
+
+    pub fn record(&mut self, msg: &GossipMessage) {
+        if self.file.is_none() {
+            return;
+        }
+
+        self.append(&SessionEntry::Message {
+            timestamp: Utc::now().timestamp_millis() as u64,
+            source: msg.source.to_string(),
+            topics: vec![msg.topic.to_string()],
+            data: String::from_utf8_lossy(&msg.data).into_owned(),
+        });
+    }
+
+    fn append(&mut self, entry: &SessionEntry) {
+        let Some(file) = self.file.as_mut() else {
+            return;
+        };
+
+        let json = serde_json::to_string(entry).expect("can jsonify session entry");
+        if let Err(err) = writeln!(file, "{}", json) {
+            println!("can't record session entry: {}", err);
+        }
+    }
+}
+
+// Files a replay of `path` keeps its checkpoints and relay log in, emptied so every replay
+// starts from a fresh node and the live node's files are left alone.
+pub fn scratch_files(path: &str) -> (String, String, String) {
+    let files = (
+        format!("{}.checkpoints.json", path),
+        format!("{}.relay.jsonl", path),
+        format!("{}.epochs.json", path),
+    );
+    for file in [&files.0, &files.1, &files.2] {
+        let _ = fs::remove_file(file);
+    }
+
+    files
+}
+
+pub fn load(path: &str) -> io::Result<Vec<SessionEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+
+    let mut entries = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line)?);
+    }
+
+    Ok(entries)
+}
+
+// Feed the recorded messages through `behaviour` in the order they were received, the same
+// way the swarm delivers them. Returns the number of messages replayed.
+pub fn replay(entries: Vec<SessionEntry>, behaviour: &mut BlockchainBehaviour) -> usize {
+    let mut replayed = 0;
+    for entry in entries {
+        match entry {
+            SessionEntry::Start { peer_id, .. } => match peer_id.parse() {
+                Ok(peer_id) => behaviour.peer_id = peer_id,
+                Err(_) => println!("session has an invalid peer id: {}", peer_id),
+            },
+            SessionEntry::Message {
+                source,
+                topics,
+                data,
+                ..
+            } => {
+                let Ok(source) = source.parse::<PeerId>() else {
+                    println!("skipping message with an invalid source: {}", source);
+                    continue;
+                };
+
+                // Messages are published on a single topic.
+                let Some(topic) = topics.into_iter().next() else {
+                    println!("skipping message without a topic from {}", source);
+                    continue;
+                };
+
+                behaviour.handle_gossip(GossipMessage {
+                    source,
+                    topic: TopicHash::from_raw(topic),
+                    data: data.into_bytes(),
+                });
+                replayed += 1;
+            }
+        }
+    }
+
+    replayed
+}
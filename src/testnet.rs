@@ -0,0 +1,252 @@
+// In-process network of nodes for integration tests, enabled with the `test-network` feature.
+// Nodes talk over memory transports, so tests don't need free ports or mDNS.
+
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use libp2p::{
+    Multiaddr, PeerId, Swarm, Transport,
+    core::{transport::MemoryTransport, upgrade},
+    futures::{StreamExt, future},
+    identity, mplex,
+    noise::{Keypair, NoiseConfig, X25519Spec},
+    swarm::SwarmBuilder,
+};
+use tokio::{select, spawn, sync::mpsc, time};
+
+use crate::{
+    broadcast,
+    chaos::Chaos,
+    coop::CoopSearched,
+    gossip::GossipSettings,
+    miner::{MinedBlock, MinerSettings, ThreadedHasher},
+    models::{
+        blockchain::Blockchain, checkpoint::Checkpoints, coinbase::Payout, epoch::EpochSummaries,
+        hash::Hash256, params::ChainParams, transaction::Transaction,
+    },
+    p2p::{self, BlockchainBehaviour, NodeSenders, NodeSettings},
+    relay::RelayLog,
+    schedule::BlockScheduler,
+    session::SessionRecorder,
+    trace::PropagationTrace,
+};
+
+// Memory transport ports are shared by the whole process.
+static NEXT_PORT: AtomicU64 = AtomicU64::new(1);
+
+struct Node {
+    swarm: Swarm<BlockchainBehaviour>,
+    mined: mpsc::UnboundedReceiver<MinedBlock>,
+    searched: mpsc::UnboundedReceiver<CoopSearched>,
+    // Files the node persists its checkpoints and relay log to.
+    files: Vec<PathBuf>,
+}
+
+// `TestNetwork` Fully connected nodes running in this process.
+pub struct TestNetwork {
+    nodes: Vec<Node>,
+}
+
+// Boot `n_nodes` connected nodes.
+pub async fn test_network(n_nodes: usize) -> TestNetwork {
+    assert!(n_nodes > 0, "a test network needs at least one node");
+
+    let mut nodes = Vec::new();
+    let mut addresses = Vec::new();
+    for _ in 0..n_nodes {
+        let (node, address) = spawn_node().await;
+        nodes.push(node);
+        addresses.push(address);
+    }
+
+    for (index, node) in nodes.iter_mut().enumerate() {
+        for address in addresses[..index].iter() {
+            Swarm::dial_addr(&mut node.swarm, address.clone()).expect("can dial node");
+        }
+    }
+
+    TestNetwork { nodes }
+}
+
+async fn spawn_node() -> (Node, Multiaddr) {
+    let keys = identity::Keypair::generate_ed25519();
+    let peer_id = PeerId::from(keys.public());
+
+    let auth_keys = Keypair::<X25519Spec>::new()
+        .into_authentic(&keys)
+        .expect("can create auth keys");
+    let transport = MemoryTransport
+        .upgrade(upgrade::Version::V1)
+        .authenticate(NoiseConfig::xx(auth_keys).into_authenticated())
+        .multiplex(mplex::MplexConfig::new())
+        .boxed();
+
+    let files: Vec<PathBuf> = ["checkpoints.json", "relay.jsonl", "epochs.json"]
+        .iter()
+        .map(|name| std::env::temp_dir().join(format!("{}-{}", peer_id, name)))
+        .collect();
+    let path = |index: usize| files[index].to_str().expect("path is utf-8").to_string();
+
+    let miner_settings = Arc::new(MinerSettings::new(1, 100));
+    let (mined_sender, mined) = mpsc::unbounded_channel();
+    let (searched_sender, searched) = mpsc::unbounded_channel();
+
+    let settings = NodeSettings {
+        keys,
+        blockchain: Blockchain::new(ChainParams::default()),
+        searcher: Box::new(ThreadedHasher::new(miner_settings.clone())),
+        miner_settings,
+        payout: Payout::new(p2p::peer_address(&peer_id), Vec::new()).expect("no splits"),
+        scheduler: BlockScheduler::default(),
+        checkpoints: Checkpoints::load(&path(0)),
+        epochs: EpochSummaries::load(&path(2)),
+        relay_log: RelayLog::load(&path(1)),
+        trace: PropagationTrace::default(),
+        session: SessionRecorder::default(),
+        chaos: Chaos::default(),
+        gossip_config: GossipSettings::default()
+            .config()
+            .expect("default gossip settings are valid"),
+    };
+    let senders = NodeSenders {
+        mined: mined_sender,
+        searched: searched_sender,
+    };
+    let behaviour = BlockchainBehaviour::new(settings, senders).await;
+
+    let mut swarm = SwarmBuilder::new(transport, behaviour, peer_id)
+        .executor(Box::new(|fut| {
+            spawn(fut);
+        }))
+        .build();
+
+    let address: Multiaddr = format!("/memory/{}", NEXT_PORT.fetch_add(1, Ordering::Relaxed))
+        .parse()
+        .expect("can parse memory address");
+    Swarm::listen_on(&mut swarm, address.clone()).expect("can listen on memory transport");
+
+    let node = Node {
+        swarm,
+        mined,
+        searched,
+        files,
+    };
+    (node, address)
+}
+
+impl TestNetwork {
+    pub fn node(&mut self, index: usize) -> &mut Swarm<BlockchainBehaviour> {
+        &mut self.nodes[index].swarm
+    }
+
+    // Mine a block of `transactions` on node `index` and broadcast it.
+    pub async fn mine(&mut self, index: usize, transactions: Vec<Transaction>) {
+        let node = &mut self.nodes[index];
+        let behaviour = node.swarm.behaviour_mut();
+        let block = behaviour.next_block(transactions);
+        behaviour.mine_block(block);
+
+        let mined = node
+            .mined
+            .recv()
+            .await
+            .expect("the node keeps a mined sender");
+        let behaviour = node.swarm.behaviour_mut();
+        behaviour.update_chain(|behaviour| behaviour.finish_mining(mined));
+    }
+
+    // Hash of the latest block of every node.
+    pub fn tips(&self) -> Vec<Hash256> {
+        self.nodes
+            .iter()
+            .map(|node| {
+                let chain = &node.swarm.behaviour().blockchain.chain;
+                chain
+                    .last()
+                    .expect("there is at least one block")
+                    .header
+                    .hash
+            })
+            .collect()
+    }
+
+    pub fn is_converged(&self) -> bool {
+        let tips = self.tips();
+        tips.iter().all(|tip| *tip == tips[0])
+    }
+
+    // Run the network until every node has the same tip. Returns `false` if that doesn't
+    // happen within `timeout`.
+    pub async fn await_converged(&mut self, timeout: Duration) -> bool {
+        let mut retry = time::interval(broadcast::RETRY_INTERVAL);
+
+        let run = async {
+            while !self.is_converged() {
+                let (retry_due, searched) = {
+                    let (events, searches): (Vec<_>, Vec<_>) = self
+                        .nodes
+                        .iter_mut()
+                        .map(|node| {
+                            (
+                                node.swarm.select_next_some(),
+                                Box::pin(node.searched.recv()),
+                            )
+                        })
+                        .unzip();
+
+                    select! {
+                        _ = future::select_all(events) => (false, None),
+                        (searched, index, _) = future::select_all(searches) => {
+                            let searched = searched.expect("the behaviour keeps a searched sender");
+                            (false, Some((index, searched)))
+                        },
+                        _ = retry.tick() => (true, None),
+                    }
+                };
+
+                if let Some((index, searched)) = searched {
+                    self.nodes[index]
+                        .swarm
+                        .behaviour_mut()
+                        .finish_search(searched);
+                }
+                for node in self.nodes.iter_mut() {
+                    let behaviour = node.swarm.behaviour_mut();
+                    if retry_due {
+                        behaviour.rebroadcast();
+                    }
+                }
+            }
+        };
+
+        time::timeout(timeout, run).await.is_ok()
+    }
+}
+
+impl Drop for TestNetwork {
+    fn drop(&mut self) {
+        for path in self.nodes.iter().flat_map(|node| node.files.iter()) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// Boot a network, mine a block on the first node and check that every node converges on it.
+pub async fn self_test(n_nodes: usize) -> bool {
+    let mut network = test_network(n_nodes).await;
+
+    network.mine(0, Vec::new()).await;
+    let converged = network.await_converged(Duration::from_secs(30)).await;
+
+    for (index, tip) in network.tips().iter().enumerate() {
+        println!("node {}: {}", index, tip);
+    }
+    converged
+}
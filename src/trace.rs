@@ -0,0 +1,139 @@
+use std::{collections::VecDeque, fs, io};
+
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::models::hash::Hash256;
+
+// Most trace records kept in memory, older ones are dropped.
+const MAX_TRACE_RECORDS: usize = 100_000;
+// Most recent failures kept for debug dumps.
+const MAX_RECENT_FAILURES: usize = 50;
+// Outcomes of inbound messages that failed validation or parsing.
+const FAILED_OUTCOMES: [&str; 2] = ["rejected", "unparsed"];
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    In,
+    Out,
+}
+
+// `Outcome` What handling an inbound gossip message amounted to.
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub kind: &'static str,
+    pub hash: Option<Hash256>,
+    pub result: &'static str,
+    // Time since the item was created or, for acks, since it was first broadcast.
+    pub latency_ms: Option<u64>,
+}
+
+impl Outcome {
+    pub fn new(kind: &'static str, result: &'static str) -> Self {
+        Outcome {
+            kind,
+            hash: None,
+            result,
+            latency_ms: None,
+        }
+    }
+}
+
+// `TraceRecord` One gossip message sent or received.
+#[derive(Debug, Clone, Serialize)]
+pub struct TraceRecord {
+    // Milliseconds since the epoch.
+    pub timestamp: u64,
+    pub direction: Direction,
+    // Sender of inbound messages, outbound ones go to every subscribed peer.
+    pub peer: Option<String>,
+    pub kind: &'static str,
+    pub hash: Option<Hash256>,
+    pub size: usize,
+    pub outcome: Option<&'static str>,
+    pub latency_ms: Option<u64>,
+}
+
+// `PropagationTrace` Structured log of gossip traffic for analyzing propagation in testbeds,
+// enabled with `--trace-propagation`. The most recent failed inbound messages are kept either
+// way, for debug dumps.
+#[derive(Debug, Default)]
+pub struct PropagationTrace {
+    enabled: bool,
+    records: VecDeque<TraceRecord>,
+    failures: VecDeque<TraceRecord>,
+}
+
+impl PropagationTrace {
+    pub fn new(enabled: bool) -> Self {
+        PropagationTrace {
+            enabled,
+            records: VecDeque::new(),
+            failures: VecDeque::new(),
+        }
+    }
+
+    pub fn inbound(&mut self, peer: String, size: usize, outcome: Outcome) {
+        let record = TraceRecord {
+            timestamp: Utc::now().timestamp_millis() as u64,
+            direction: Direction::In,
+            peer: Some(peer),
+            kind: outcome.kind,
+            hash: outcome.hash,
+            size,
+            outcome: Some(outcome.result),
+            latency_ms: outcome.latency_ms,
+        };
+
+        if FAILED_OUTCOMES.contains(&outcome.result) {
+            if self.failures.len() == MAX_RECENT_FAILURES {
+                self.failures.pop_front();
+            }
+            self.failures.push_back(record.clone());
+        }
+        self.record(record);
+    }
+
+    // Inbound messages that most recently failed validation or parsing, oldest first.
+    pub fn recent_failures(&self) -> &VecDeque<TraceRecord> {
+        &self.failures
+    }
+
+    pub fn outbound(&mut self, kind: &'static str, hash: Option<Hash256>, size: usize) {
+        self.record(TraceRecord {
+            timestamp: Utc::now().timestamp_millis() as u64,
+            direction: Direction::Out,
+            peer: None,
+            kind,
+            hash,
+            size,
+            outcome: None,
+            latency_ms: None,
+        });
+    }
+
+    // Write every kept record to `path` as a JSON array.
+    pub fn export(&self, path: &str) -> io::Result<usize> {
+        let json = serde_json::to_string_pretty(&self.records)?;
+        fs::write(path, json)?;
+
+        Ok(self.records.len())
+    }
+
+    fn record(&mut self, record: TraceRecord) {
+        if !self.enabled {
+            return;
+        }
+
+        println!(
+            "trace {}",
+            serde_json::to_string(&record).expect("can jsonify trace record")
+        );
+
+        if self.records.len() == MAX_TRACE_RECORDS {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+}
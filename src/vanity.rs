@@ -0,0 +1,192 @@
+// Search for addresses starting with a chosen prefix, run with `wallet vanity <prefix> [seed]`.
+// Candidate keys are derived from a seed and a counter, so a search with the same seed finds
+// the same key and any key it finds can be derived again from the seed and its counter. The
+// search runs on the miner's worker threads, honouring its thread count and throttle.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    miner::{self, MinerSettings},
+    models::{address::Address, hash::Hash256},
+};
+
+// Every address derived from a key starts with the encoding of the same multihash prefix.
+pub const ADDRESS_START: &str = "12D3KooW";
+// How often a running search reports its progress.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
+// Characters of the base58 alphabet addresses are encoded in.
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+// Characters that can follow `ADDRESS_START`, the key only spans part of the range of the next
+// digit.
+const FIRST_CHARACTERS: &str = "9ABCDEFGHJKLMNPQRST";
+
+// `VanitySearch` A running search, shared with its worker threads.
+#[derive(Debug)]
+pub struct VanitySearch {
+    // Wanted start of the address after `ADDRESS_START`.
+    pub prefix: String,
+    pub seed: [u8; 32],
+    started: Instant,
+    attempts: AtomicU64,
+    stop: AtomicBool,
+    done: AtomicBool,
+}
+
+impl VanitySearch {
+    // Start searching for an address continuing `ADDRESS_START` with `prefix`.
+    pub fn start(
+        prefix: &str,
+        seed: [u8; 32],
+        settings: Arc<MinerSettings>,
+    ) -> Result<Arc<Self>, String> {
+        if prefix.is_empty() {
+            return Err("the prefix can't be empty".to_string());
+        }
+        if let Some(invalid) = prefix.chars().find(|c| !BASE58_ALPHABET.contains(*c)) {
+            return Err(format!(
+                "addresses never contain {:?}, they use {}",
+                invalid, BASE58_ALPHABET
+            ));
+        }
+        if !prefix.starts_with(|c| FIRST_CHARACTERS.contains(c)) {
+            return Err(format!(
+                "addresses continue {} with one of {}",
+                ADDRESS_START, FIRST_CHARACTERS
+            ));
+        }
+
+        let search = Arc::new(VanitySearch {
+            prefix: prefix.to_string(),
+            seed,
+            started: Instant::now(),
+            attempts: AtomicU64::new(0),
+            stop: AtomicBool::new(false),
+            done: AtomicBool::new(false),
+        });
+
+        let running = search.clone();
+        thread::spawn(move || running.run(&settings));
+
+        Ok(search)
+    }
+
+    fn run(&self, settings: &MinerSettings) {
+        let wanted = format!("{}{}", ADDRESS_START, self.prefix);
+
+        let found = thread::scope(|scope| {
+            scope.spawn(|| self.report_progress());
+
+            let found = miner::search_parallel(
+                settings,
+                0..u64::MAX,
+                &self.stop,
+                &self.attempts,
+                |counter| {
+                    let keypair = derive_keypair(&self.seed, counter);
+                    Address::from_public_key(&keypair.public)
+                        .as_str()
+                        .starts_with(&wanted)
+                },
+            );
+            self.done.store(true, Ordering::Relaxed);
+
+            found
+        });
+
+        match found {
+            Some(counter) => {
+                let keypair = derive_keypair(&self.seed, counter);
+                println!(
+                    "found {} after {} attempts",
+                    Address::from_public_key(&keypair.public),
+                    self.attempts()
+                );
+                println!(
+                    "secret key: {}",
+                    bs58::encode(keypair.secret.as_bytes()).into_string()
+                );
+                println!(
+                    "derived from seed {} counter {}",
+                    Hash256(self.seed),
+                    counter
+                );
+                println!("save the secret key to a file and start the node with --key-file <path>");
+            }
+            None => println!(
+                "vanity search for {} cancelled after {} attempts",
+                wanted,
+                self.attempts()
+            ),
+        }
+    }
+
+    fn report_progress(&self) {
+        let mut next_report = Instant::now() + PROGRESS_INTERVAL;
+
+        while !self.is_done() {
+            thread::sleep(Duration::from_millis(100));
+            if Instant::now() >= next_report {
+                println!("{}", self);
+                next_report += PROGRESS_INTERVAL;
+            }
+        }
+    }
+
+    // Stop the search, it reports that it was cancelled once its workers stopped.
+    pub fn cancel(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    pub fn attempts(&self) -> u64 {
+        self.attempts.load(Ordering::Relaxed)
+    }
+
+    // Attempts a search for the prefix takes on average.
+    pub fn expected_attempts(&self) -> f64 {
+        58f64.powi(self.prefix.len() as i32)
+    }
+}
+
+impl std::fmt::Display for VanitySearch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let attempts = self.attempts();
+
+        write!(
+            f,
+            "vanity search for {}{}: {} of ~{:.0} expected attempts in {:.0}s ({:.0}/s)",
+            ADDRESS_START,
+            self.prefix,
+            attempts,
+            self.expected_attempts(),
+            elapsed,
+            attempts as f64 / elapsed.max(f64::EPSILON)
+        )
+    }
+}
+
+// Key number `counter` of the keys derived from `seed`.
+pub fn derive_keypair(seed: &[u8; 32], counter: u64) -> Keypair {
+    let mut hasher = Sha256::new();
+    hasher.update(seed);
+    hasher.update(counter.to_be_bytes());
+
+    let secret = SecretKey::from_bytes(&hasher.finalize()).expect("hashes are secret keys");
+    let public = PublicKey::from(&secret);
+    Keypair { secret, public }
+}
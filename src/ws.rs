@@ -0,0 +1,274 @@
+// WebSocket server pushing what happens on the first hosted chain to subscribers, enabled with
+// `--ws-port <port>`. Clients connect to `/`, sending their API key as `Authorization: Bearer
+// <key>` or, from browsers, as `/?key=<key>`, and get every `NodeEvent` as a JSON text message.
+// Subscribers need a key of the read scope once there are keys, see `auth`. Subscribers too slow
+// to keep up with `EVENT_BUFFER` events miss the oldest ones and are told how many.
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, tcp::OwnedReadHalf},
+    spawn,
+    sync::{broadcast, mpsc},
+};
+
+use crate::{
+    auth::{ApiKeys, Scope},
+    http::{self, HttpRequest, RateLimiter, RateLimits},
+    models::{block::Block, hash::Hash256, transaction::Transaction},
+};
+
+// Events kept for subscribers that fall behind.
+pub const EVENT_BUFFER: usize = 1024;
+// Largest message read from a subscriber, they have nothing to say but control frames.
+const MAX_FRAME_SIZE: u64 = 64 * 1024;
+// Appended to the key of a handshake before hashing it, see RFC 6455.
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+// `NodeEvent` A change of the chain or the mempool pushed to subscribers.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum NodeEvent {
+    NewBlock {
+        block: Arc<Block>,
+    },
+    NewTransaction {
+        id: Hash256,
+        transaction: Box<Transaction>,
+    },
+    // The blocks above `fork_height` were swapped for the ones of another chain, which follow
+    // as `NewBlock` events.
+    ChainReorg {
+        fork_height: u64,
+        old_tip: Hash256,
+        new_tip: Hash256,
+        disconnected: u64,
+    },
+    // The subscriber fell behind and missed `missed` events.
+    Lagged {
+        missed: u64,
+    },
+}
+
+// `WsSettings` Where the WebSocket server listens.
+#[derive(Debug, Clone)]
+pub struct WsSettings {
+    pub port: u16,
+}
+
+// Accept subscribers until the listener fails, pushing them the events sent on `events`.
+pub async fn serve(
+    settings: WsSettings,
+    limits: RateLimits,
+    keys: Arc<Mutex<ApiKeys>>,
+    events: broadcast::Sender<NodeEvent>,
+) {
+    let listener = match TcpListener::bind(("0.0.0.0", settings.port)).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            println!(
+                "can't start websocket server on port {}: {}",
+                settings.port, err
+            );
+            return;
+        }
+    };
+    println!("websocket server listening on port {}", settings.port);
+
+    let limiter = Arc::new(Mutex::new(RateLimiter::new(limits)));
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let (limiter, keys) = (limiter.clone(), keys.clone());
+                let events = events.subscribe();
+                spawn(async move {
+                    if let Err(err) = handle(stream, peer, limiter, keys, events).await {
+                        println!("websocket subscriber {} failed: {}", peer, err);
+                    }
+                });
+            }
+            Err(err) => println!("websocket server can't accept connection: {}", err),
+        }
+    }
+}
+
+async fn handle(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    limiter: Arc<Mutex<RateLimiter>>,
+    keys: Arc<Mutex<ApiKeys>>,
+    mut events: broadcast::Receiver<NodeEvent>,
+) -> std::io::Result<()> {
+    let allowed = limiter
+        .lock()
+        .expect("websocket rate limiter isn't poisoned")
+        .check_ip(peer.ip());
+    if let Err(wait) = allowed {
+        return http::write_rate_limited(&mut stream, wait).await;
+    }
+
+    let request = http::read_request(&mut stream).await?;
+    let key = request_key(&request);
+    let allowed = limiter
+        .lock()
+        .expect("websocket rate limiter isn't poisoned")
+        .check_token(key);
+    if let Err(wait) = allowed {
+        return http::write_rate_limited(&mut stream, wait).await;
+    }
+    let authorized = keys
+        .lock()
+        .expect("api keys aren't poisoned")
+        .authorize(key, Scope::Read);
+    if let Err(err) = authorized {
+        let body = serde_json::json!({ "error": err });
+        return http::write_response(&mut stream, "401 Unauthorized", &[], &body).await;
+    }
+
+    let Some(accept) = handshake_accept(&request) else {
+        let body = serde_json::json!({ "error": "expected a websocket handshake" });
+        return http::write_response(&mut stream, "426 Upgrade Required", &[], &body).await;
+    };
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept
+    );
+    stream.write_all(response.as_bytes()).await?;
+
+    // Frames from the subscriber are read on their own task, reads can't be cancelled halfway
+    // through a frame when an event comes in.
+    let (reader, mut writer) = stream.into_split();
+    let (control_sender, mut control) = mpsc::unbounded_channel();
+    spawn(read_frames(reader, control_sender));
+
+    loop {
+        tokio::select! {
+            frame = control.recv() => match frame {
+                Some((OPCODE_PING, payload)) => {
+                    writer.write_all(&encode_frame(OPCODE_PONG, &payload)).await?;
+                }
+                // The subscriber closed the connection or it failed.
+                _ => {
+                    let _ = writer.write_all(&encode_frame(OPCODE_CLOSE, &[])).await;
+                    return Ok(());
+                }
+            },
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => NodeEvent::Lagged { missed },
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                };
+                let json = serde_json::to_vec(&event).expect("can jsonify node event");
+                writer.write_all(&encode_frame(OPCODE_TEXT, &json)).await?;
+            }
+        }
+    }
+}
+
+// API key of `request`, from its `Authorization` header or its `key` query parameter.
+fn request_key(request: &HttpRequest) -> Option<&str> {
+    request.token().or_else(|| {
+        let (_, query) = request.path.split_once('?')?;
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("key="))
+            .filter(|key| !key.is_empty())
+    })
+}
+
+// `Sec-WebSocket-Accept` answering the handshake `request`, `None` if it isn't one.
+fn handshake_accept(request: &HttpRequest) -> Option<String> {
+    let upgrade = request.header("upgrade")?;
+    if request.method != "GET" || !upgrade.eq_ignore_ascii_case("websocket") {
+        return None;
+    }
+    let key = request.header("sec-websocket-key")?;
+
+    let mut hasher = Sha1::new();
+    hasher.update(key.as_bytes());
+    hasher.update(HANDSHAKE_GUID.as_bytes());
+    Some(base64::encode(hasher.finalize()))
+}
+
+// Forward the pings of the subscriber to `control` along with their payload, and a close frame
+// once the connection ends. Other frames are dropped.
+async fn read_frames(mut reader: OwnedReadHalf, control: mpsc::UnboundedSender<(u8, Vec<u8>)>) {
+    loop {
+        match read_frame(&mut reader).await {
+            Ok((OPCODE_PING, payload)) => {
+                if control.send((OPCODE_PING, payload)).is_err() {
+                    return;
+                }
+            }
+            Ok((OPCODE_CLOSE, _)) | Err(_) => {
+                let _ = control.send((OPCODE_CLOSE, Vec::new()));
+                return;
+            }
+            Ok(_) => {}
+        }
+    }
+}
+
+// Read one frame, unmasking its payload.
+async fn read_frame(reader: &mut OwnedReadHalf) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut head = [0; 2];
+    reader.read_exact(&mut head).await?;
+    let opcode = head[0] & 0x0F;
+    let masked = head[1] & 0x80 != 0;
+
+    let length = match head[1] & 0x7F {
+        126 => reader.read_u16().await? as u64,
+        127 => reader.read_u64().await?,
+        length => length as u64,
+    };
+    if length > MAX_FRAME_SIZE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "websocket frame is too large",
+        ));
+    }
+
+    let mut mask = [0; 4];
+    if masked {
+        reader.read_exact(&mut mask).await?;
+    }
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload).await?;
+    if masked {
+        for (position, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[position % 4];
+        }
+    }
+
+    Ok((opcode, payload))
+}
+
+// A final, unmasked frame as servers send them.
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length if length < 126 => frame.push(length as u8),
+        length if length <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}